@@ -1,9 +1,22 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::steps::HashableJsonPath;
-use anyhow::Result;
-use aptos_indexer_processor_sdk::utils::convert::standardize_address;
+use crate::{
+    models::nft_models::{
+        MarketplaceField, CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME, DEFAULT_BUYER,
+        NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME,
+    },
+    steps::HashableJsonPath,
+    utils::{
+        convert::{
+            convert_bcs_hex, convert_bcs_hex_string, hex_to_utf8, standardize_address,
+            standardize_address_from_bytes, truncate_str,
+        },
+        marketplace_resource_utils::TokenStandard,
+    },
+};
+use anyhow::{Context, Result};
+use bigdecimal::{BigDecimal, Zero};
 use diesel::{
     deserialize::{self, FromSql, FromSqlRow},
     expression::AsExpression,
@@ -12,30 +25,344 @@ use diesel::{
     sql_types::Text,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, io::Write};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use strum::{Display, EnumString};
+use unicode_normalization::UnicodeNormalization;
 
 // event_type -> json_path, db_column
 pub type EventFieldRemappings = HashMap<EventType, HashMap<HashableJsonPath, Vec<DbColumn>>>;
 // resource_type -> json_path, db_column
-pub type ResourceFieldRemappings = HashMap<String, HashMap<HashableJsonPath, Vec<DbColumn>>>;
+pub type ResourceFieldRemappings = HashMap<EventType, HashMap<HashableJsonPath, Vec<DbColumn>>>;
 
 /// Maximum length of a token name in characters
 pub const MAX_TOKEN_NAME_LENGTH: usize = 128;
 
+/// How token and collection names are cleaned up before they're stored.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct NameNormalization {
+    /// Strips leading and trailing whitespace.
+    #[serde(default)]
+    pub trim: bool,
+    /// Applies Unicode NFC normalization, so the same name typed with combining characters or
+    /// precomposed ones is stored the same way.
+    #[serde(default)]
+    pub nfc: bool,
+    /// Names are truncated to this many characters.
+    #[serde(default = "NameNormalization::default_max_length")]
+    pub max_length: usize,
+}
+
+impl Default for NameNormalization {
+    fn default() -> Self {
+        Self {
+            trim: false,
+            nfc: false,
+            max_length: Self::default_max_length(),
+        }
+    }
+}
+
+impl NameNormalization {
+    pub const fn default_max_length() -> usize {
+        MAX_TOKEN_NAME_LENGTH
+    }
+
+    pub fn apply(&self, name: &str) -> String {
+        let name = if self.trim { name.trim() } else { name };
+        if self.nfc {
+            truncate_str(&name.nfc().collect::<String>(), self.max_length)
+        } else {
+            truncate_str(name, self.max_length)
+        }
+    }
+}
+
 pub type EventRemappingConfig = HashMap<String, EventRemapping>;
 pub type ResourceRemappingConfig = HashMap<String, ResourceRemapping>;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct DbColumn {
     pub table: String,
     pub column: String,
+    /// Move serialization wrapper to strip from the extracted value before it is stored.
+    #[serde(default)]
+    pub unwrap: Option<MoveWrapper>,
+    /// Conversion applied to the extracted value, after unwrapping, before it is stored.
+    #[serde(default)]
+    pub transform: Option<ValueTransform>,
+    /// Pads the value, after the transform, to a 32-byte `0x` address. Mapped values are
+    /// stored the way the event or resource has them otherwise, e.g. `0x1` rather than
+    /// `0x000...01`.
+    #[serde(default)]
+    pub standardize: bool,
+    /// Stores every match of the path, e.g. `$.royalties[*].recipient`, as a JSON array instead
+    /// of only the first one. Only custom activity columns, kept in `custom_fields`, hold arrays.
+    #[serde(default)]
+    pub all_matches: bool,
+}
+
+impl DbColumn {
+    /// Applies the `transform` and `standardize` options to an extracted value. Empty values
+    /// are returned unchanged.
+    pub fn convert(&self, value: String) -> Result<String> {
+        if value.is_empty() {
+            return Ok(value);
+        }
+        let value = match &self.transform {
+            Some(transform) => transform.apply(&value)?,
+            None => value,
+        };
+        if self.standardize {
+            return standardize_address(&value);
+        }
+        Ok(value)
+    }
+
+    /// Whether the column can hold the array an `all_matches` path produces.
+    pub fn stores_array(&self) -> bool {
+        self.table == NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME
+            && matches!(
+                MarketplaceField::for_column(&self.table, &self.column),
+                Ok(MarketplaceField::Custom(_))
+            )
+    }
+}
+
+/// What tells apart the rows of `current_nft_marketplace_token_offers` for the same token and
+/// marketplace.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenOfferKey {
+    /// The buyer: a buyer's new offer on a token replaces their previous one. Offers on
+    /// different property versions of a v1 token are kept apart.
+    #[default]
+    Buyer,
+    /// The offer id, for marketplaces where a buyer can hold several offers on the same token.
+    /// Cancel and fill events then have to map the offer id too.
+    OfferId,
+    /// The `bid_key`, for marketplaces that identify offers by bid key rather than offer id.
+    /// Cancel and fill events then have to map the bid key too.
+    BidKey,
+}
+
+/// What tells apart the rows of `current_nft_marketplace_collection_offers` whose id is
+/// generated, i.e. when the events don't carry a collection offer id.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionOfferKey {
+    /// The buyer: a buyer's new offer on a collection replaces their previous one.
+    #[default]
+    Buyer,
+    /// The buyer and the `bid_key`, so that each of a buyer's bids on a collection gets a row.
+    BidKey,
+}
+
+/// The Aptos network a marketplace config is indexing.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Devnet,
+}
+
+impl Network {
+    /// The chain id the network's gRPC stream serves. `None` for devnet, whose chain id changes
+    /// every time it's reset.
+    pub fn chain_id(&self) -> Option<u64> {
+        match self {
+            Network::Mainnet => Some(1),
+            Network::Testnet => Some(2),
+            Network::Devnet => None,
+        }
+    }
+}
+
+/// Wrappers that Move values are serialized with in event JSON.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveWrapper {
+    /// `0x1::option::Option<T>`, serialized as `{ "vec": [value] }` or `{ "vec": [] }`
+    Option,
+    /// Move enums, serialized as `{ "__variant__": "Name", ...fields }`
+    Variant,
+}
+
+impl MoveWrapper {
+    /// Strips the wrapper from `value`. An empty option becomes `Value::Null`; values that
+    /// are not wrapped are returned unchanged.
+    pub fn unwrap_value(&self, value: serde_json::Value) -> serde_json::Value {
+        match self {
+            MoveWrapper::Option => match value {
+                serde_json::Value::Object(mut map) => match map.remove("vec") {
+                    Some(serde_json::Value::Array(mut vec)) if map.is_empty() => {
+                        if vec.is_empty() {
+                            serde_json::Value::Null
+                        } else {
+                            vec.swap_remove(0)
+                        }
+                    },
+                    Some(inner) => {
+                        map.insert("vec".to_string(), inner);
+                        serde_json::Value::Object(map)
+                    },
+                    None => serde_json::Value::Object(map),
+                },
+                other => other,
+            },
+            MoveWrapper::Variant => match value {
+                serde_json::Value::Object(mut map) => match map.remove("__variant__") {
+                    // A variant with a single field unwraps to that field, otherwise
+                    // the variant name is the value.
+                    Some(variant) => {
+                        if map.len() == 1 {
+                            map.into_iter().next().map(|(_, v)| v).unwrap_or(variant)
+                        } else {
+                            variant
+                        }
+                    },
+                    None => serde_json::Value::Object(map),
+                },
+                other => other,
+            },
+        }
+    }
+}
+
+/// Move types of the values a `bcs:<type>` transform decodes, written as in Move.
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum BcsType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    /// Stored as a standardized address
+    Address,
+    #[strum(serialize = "0x1::string::String")]
+    String,
+    /// Stored as `0x` prefixed hex
+    #[strum(serialize = "vector<u8>")]
+    Bytes,
+}
+
+impl BcsType {
+    /// Decodes a hex encoded, BCS serialized value of this type into the string it's stored as.
+    pub fn decode(&self, value: &str) -> Result<String> {
+        let decoded = match self {
+            BcsType::Bool => convert_bcs_hex::<bool>(value).map(|v| v.to_string()),
+            BcsType::U8 => convert_bcs_hex::<u8>(value).map(|v| v.to_string()),
+            BcsType::U16 => convert_bcs_hex::<u16>(value).map(|v| v.to_string()),
+            BcsType::U32 => convert_bcs_hex::<u32>(value).map(|v| v.to_string()),
+            BcsType::U64 => convert_bcs_hex::<u64>(value).map(|v| v.to_string()),
+            BcsType::U128 => convert_bcs_hex::<u128>(value).map(|v| v.to_string()),
+            BcsType::Address => convert_bcs_hex::<[u8; 32]>(value)
+                .and_then(|bytes| standardize_address_from_bytes(&bytes)),
+            BcsType::String => convert_bcs_hex::<String>(value),
+            BcsType::Bytes => {
+                convert_bcs_hex::<Vec<u8>>(value).map(|bytes| format!("0x{}", hex::encode(bytes)))
+            },
+        };
+        decoded.with_context(|| format!("Value {value} is not a BCS encoded {self}"))
+    }
+}
+
+/// Conversions for extracted values that aren't stored verbatim, written in config as
+/// `bcs_string`, `bcs:<move type>`, `hex_decode` or `divide:<divisor>`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum ValueTransform {
+    /// A hex encoded, BCS serialized `0x1::string::String`
+    BcsString,
+    /// A hex encoded, BCS serialized value of a Move type, e.g. `bcs:u64`
+    Bcs(BcsType),
+    /// A hex encoded UTF-8 string
+    HexDecode,
+    /// A number to divide by the divisor, e.g. `divide:100000000` for octas to APT
+    Divide(BigDecimal),
+}
+
+impl ValueTransform {
+    /// Applies the transform to an extracted value.
+    pub fn apply(&self, value: &str) -> Result<String> {
+        match self {
+            ValueTransform::BcsString => convert_bcs_hex_string(value),
+            ValueTransform::Bcs(bcs_type) => bcs_type.decode(value),
+            ValueTransform::HexDecode => hex_to_utf8(value),
+            ValueTransform::Divide(divisor) => {
+                let value = BigDecimal::from_str(value)
+                    .with_context(|| format!("Value {value} is not a number"))?;
+                Ok((value / divisor).to_string())
+            },
+        }
+    }
+}
+
+impl FromStr for ValueTransform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "bcs_string" => Ok(ValueTransform::BcsString),
+            None if s == "hex_decode" => Ok(ValueTransform::HexDecode),
+            Some(("bcs", move_type)) => move_type
+                .parse()
+                .map(ValueTransform::Bcs)
+                .with_context(|| format!("Unsupported Move type in transform '{s}'")),
+            Some(("divide", divisor)) => {
+                let divisor = BigDecimal::from_str(divisor)
+                    .with_context(|| format!("Invalid divisor in transform '{s}'"))?;
+                if divisor.is_zero() {
+                    anyhow::bail!("Invalid divisor in transform '{s}': must not be zero");
+                }
+                Ok(ValueTransform::Divide(divisor))
+            },
+            _ => anyhow::bail!(
+                "Unsupported transform '{s}', expected bcs_string, bcs:<move type>, hex_decode or \
+                 divide:<divisor>"
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for ValueTransform {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<ValueTransform> for String {
+    fn from(transform: ValueTransform) -> Self {
+        match transform {
+            ValueTransform::BcsString => "bcs_string".to_string(),
+            ValueTransform::Bcs(bcs_type) => format!("bcs:{bcs_type}"),
+            ValueTransform::HexDecode => "hex_decode".to_string(),
+            ValueTransform::Divide(divisor) => format!("divide:{divisor}"),
+        }
+    }
 }
 
 /// Represents a marketplace and its configuration
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct NFTMarketplaceConfig {
     pub name: String,
+    /// Stored as the `marketplace` of every row instead of `name`, e.g. `wapal` for a
+    /// `wapal_v2` config indexing a redeployed contract, so that both contracts' activities and
+    /// listings end up under one marketplace. `name` still identifies the processor and its
+    /// checkpoints.
+    #[serde(default)]
+    pub canonical_name: Option<String>,
     /// Maps event type strings to their corresponding MarketplaceEventType enum values.
     /// This mapping is used to standardize different marketplace event types across
     /// different NFT marketplaces into a standarzied event types for processing.
@@ -43,31 +370,629 @@ pub struct NFTMarketplaceConfig {
     /// while another marketplace's "CreateListing" event would also map to PlaceListing.
     #[serde(default)]
     pub event_model_mapping: HashMap<String, MarketplaceEventType>,
+    /// Maps event types that combine several standard events into one, e.g. an instant sale
+    /// that places and fills a listing atomically (`[place_listing, fill_listing]`). The
+    /// activity takes the last entry's type, and so does each current state table when
+    /// several entries target it.
+    #[serde(default)]
+    pub combined_event_model_mapping: HashMap<String, Vec<MarketplaceEventType>>,
+    /// For marketplaces that emit no events, maps resource types to the standard event types
+    /// their changes stand for, e.g. a `Listing` resource written for `place_listing` and
+    /// deleted for `cancel_listing`. Each change is remapped with `events.<resource type>`.
+    #[serde(default)]
+    pub resource_event_model_mapping: HashMap<String, ResourceEventTypes>,
     #[serde(default)]
     pub events: EventRemappingConfig,
     #[serde(default)]
     pub resources: ResourceRemappingConfig,
+    /// Decimals of the coin prices are paid in (8 for APT). When set, `price_raw` and
+    /// `price_display` are populated alongside `price`.
+    #[serde(default)]
+    pub price_decimals: Option<u32>,
+    /// Contract addresses allowed to emit this marketplace's events. Events from any other
+    /// address are dropped, so spoofed events with a matching type never reach the database.
+    /// Empty allows every contract.
+    #[serde(default)]
+    pub allowed_contracts: Vec<String>,
+    /// Remaps the events of a transaction in parallel rather than one after the other. Worth it
+    /// for marketplaces with large bundles or many events per transaction.
+    #[serde(default)]
+    pub parallel_remap: bool,
+    /// Keeps the full event in the activities' `json_data`. Turning it off stores JSON `null`
+    /// instead, which shrinks the activities table considerably.
+    #[serde(default = "NFTMarketplaceConfig::default_store_raw_json")]
+    pub store_raw_json: bool,
+    /// Applied to every mapped `token_name` and `collection_name`.
+    #[serde(default)]
+    pub name_normalization: NameNormalization,
+    /// Counts the event types without a remapping per contract in the `discovered_event_types`
+    /// table, to help with writing the config of a new marketplace.
+    #[serde(default)]
+    pub discovery_mode: bool,
+    /// Standard event types to remap, e.g. only `fill_listing` for an integration that only
+    /// cares about sales. Events of other types are skipped. Empty remaps every type.
+    #[serde(default)]
+    pub enabled_event_types: Vec<MarketplaceEventType>,
+    /// Whether token offers are keyed by buyer, offer id or bid key.
+    #[serde(default)]
+    pub token_offer_key: TokenOfferKey,
+    /// Whether generated collection offer ids include the bid key.
+    #[serde(default)]
+    pub collection_offer_key: CollectionOfferKey,
+    /// Matches event types including their generic type parameters, e.g. to map
+    /// `Event<0x1::aptos_coin::AptosCoin>` differently from the same event in another coin.
+    /// By default they are dropped, so a config only names the bare event type.
+    #[serde(default)]
+    pub keep_event_type_generics: bool,
+    /// Buyer stored for token and collection offers whose event doesn't carry one, since the
+    /// buyer is part of their key. Set it to `null` to skip such offers instead. Optional
+    /// buyer and seller columns are left `NULL` when missing.
+    #[serde(default = "NFTMarketplaceConfig::default_buyer")]
+    pub default_buyer: Option<String>,
+    /// Follows the `0x1::object::ObjectCore` owner of listed v2 tokens. A listing whose token
+    /// moves to anyone other than its seller or listing object is flagged `is_stale`, since the
+    /// seller can no longer deliver it.
+    #[serde(default)]
+    pub track_listing_owners: bool,
+    /// The network indexed. Checked against the chain id of the gRPC stream at startup, and
+    /// selects the `contract_address_overrides` to apply. Unset skips both.
+    #[serde(default)]
+    pub network: Option<Network>,
+    /// Per network, the contract addresses to use in place of those the config is written
+    /// with, e.g. a testnet deployment of a mainnet contract. Applies to the event and resource
+    /// types and to `allowed_contracts`, so the same file works on every network.
+    #[serde(default)]
+    pub contract_address_overrides: HashMap<Network, HashMap<String, String>>,
+}
+
+impl Default for NFTMarketplaceConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            canonical_name: None,
+            event_model_mapping: HashMap::new(),
+            combined_event_model_mapping: HashMap::new(),
+            resource_event_model_mapping: HashMap::new(),
+            events: HashMap::new(),
+            resources: HashMap::new(),
+            price_decimals: None,
+            allowed_contracts: Vec::new(),
+            parallel_remap: false,
+            store_raw_json: Self::default_store_raw_json(),
+            name_normalization: NameNormalization::default(),
+            discovery_mode: false,
+            enabled_event_types: Vec::new(),
+            token_offer_key: TokenOfferKey::default(),
+            collection_offer_key: CollectionOfferKey::default(),
+            keep_event_type_generics: false,
+            default_buyer: Self::default_buyer(),
+            track_listing_owners: false,
+            network: None,
+            contract_address_overrides: HashMap::new(),
+        }
+    }
 }
 
 impl NFTMarketplaceConfig {
+    pub const fn default_store_raw_json() -> bool {
+        true
+    }
+
+    pub fn default_buyer() -> Option<String> {
+        Some(DEFAULT_BUYER.to_string())
+    }
+
+    /// A config with every option set, printed by `--print-config-template` as a starting point
+    /// for a new marketplace. The contract address and field paths are placeholders.
+    pub fn example() -> Self {
+        const CONTRACT: &str = "0xcafe";
+        let event_type = |name: &str| format!("{CONTRACT}::events::{name}");
+        let column = |table: &str, column: &str| DbColumn {
+            table: table.to_string(),
+            column: column.to_string(),
+            ..Default::default()
+        };
+        let activity = |name: &str| column(NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME, name);
+        let listing = |name: &str| column("current_nft_marketplace_listings", name);
+
+        Self {
+            name: "example_marketplace".to_string(),
+            canonical_name: Some("example".to_string()),
+            event_model_mapping: HashMap::from([
+                (
+                    event_type("ListingPlaced"),
+                    MarketplaceEventType::PlaceListing,
+                ),
+                (
+                    event_type("ListingCanceled"),
+                    MarketplaceEventType::CancelListing,
+                ),
+                (
+                    event_type("ListingFilled"),
+                    MarketplaceEventType::FillListing,
+                ),
+            ]),
+            combined_event_model_mapping: HashMap::from([(event_type("InstantSale"), vec![
+                MarketplaceEventType::PlaceListing,
+                MarketplaceEventType::FillListing,
+            ])]),
+            resource_event_model_mapping: HashMap::from([(
+                format!("{CONTRACT}::legacy::Listing"),
+                ResourceEventTypes {
+                    on_write: Some(MarketplaceEventType::PlaceListing),
+                    on_delete: Some(MarketplaceEventType::CancelListing),
+                    ..Default::default()
+                },
+            )]),
+            events: HashMap::from([
+                (event_type("ListingPlaced"), EventRemapping {
+                    event_fields: HashMap::from([
+                        ("$.listing".to_string(), vec![
+                            activity("listing_id"),
+                            listing("listing_id"),
+                        ]),
+                        ("$.seller".to_string(), vec![
+                            DbColumn {
+                                standardize: true,
+                                ..activity("seller")
+                            },
+                            DbColumn {
+                                standardize: true,
+                                ..listing("seller")
+                            },
+                        ]),
+                        ("$.price".to_string(), vec![
+                            activity("price"),
+                            listing("price"),
+                        ]),
+                        ("$.token_metadata.token".to_string(), vec![
+                            DbColumn {
+                                unwrap: Some(MoveWrapper::Option),
+                                ..activity("token_data_id")
+                            },
+                            DbColumn {
+                                unwrap: Some(MoveWrapper::Option),
+                                ..listing("token_data_id")
+                            },
+                        ]),
+                        ("$.token_metadata.token_name".to_string(), vec![DbColumn {
+                            transform: Some(ValueTransform::HexDecode),
+                            ..activity("token_name")
+                        }]),
+                        ("$.royalties[*].recipient".to_string(), vec![DbColumn {
+                            all_matches: true,
+                            ..activity("royalty_recipients")
+                        }]),
+                    ]),
+                    fan_out_arrays: false,
+                    token_standard: None,
+                    property_version_path: Some("$.token_metadata.property_version".to_string()),
+                    collection_inner_path: Some(
+                        "$.token_metadata.collection.vec[0].inner".to_string(),
+                    ),
+                }),
+                (format!("{CONTRACT}::legacy::Listing"), EventRemapping {
+                    event_fields: HashMap::from([
+                        ("$.address".to_string(), vec![
+                            activity("listing_id"),
+                            listing("listing_id"),
+                        ]),
+                        ("$.data.token".to_string(), vec![
+                            activity("token_data_id"),
+                            listing("token_data_id"),
+                        ]),
+                        ("$.data.price".to_string(), vec![
+                            activity("price"),
+                            listing("price"),
+                        ]),
+                    ]),
+                    fan_out_arrays: false,
+                    token_standard: Some(TokenStandard::V2),
+                    property_version_path: None,
+                    collection_inner_path: None,
+                }),
+            ]),
+            resources: HashMap::from([(
+                format!("{CONTRACT}::listing::Listing"),
+                ResourceRemapping {
+                    resource_fields: HashMap::from([("$.seller".to_string(), vec![DbColumn {
+                        standardize: true,
+                        ..listing("seller")
+                    }])]),
+                },
+            )]),
+            price_decimals: Some(8),
+            allowed_contracts: vec![CONTRACT.to_string()],
+            parallel_remap: false,
+            store_raw_json: Self::default_store_raw_json(),
+            name_normalization: NameNormalization {
+                trim: true,
+                nfc: true,
+                max_length: NameNormalization::default_max_length(),
+            },
+            discovery_mode: false,
+            enabled_event_types: Vec::new(),
+            token_offer_key: TokenOfferKey::default(),
+            collection_offer_key: CollectionOfferKey::default(),
+            keep_event_type_generics: false,
+            default_buyer: Self::default_buyer(),
+            track_listing_owners: false,
+            network: Some(Network::Mainnet),
+            contract_address_overrides: HashMap::from([(
+                Network::Testnet,
+                HashMap::from([(CONTRACT.to_string(), "0xbeef".to_string())]),
+            )]),
+        }
+    }
+
+    /// Parses an event type of the config the way this marketplace matches them, with the
+    /// contract address of the configured network.
+    pub fn parse_event_type(&self, event_type: &str) -> Result<EventType> {
+        let mut event_type = EventType::parse(event_type, self.keep_event_type_generics)?;
+        event_type.address = self.contract_address(&event_type.address)?;
+        Ok(event_type)
+    }
+
+    /// Standardizes a contract address of the config, and replaces it with its override on the
+    /// configured network if it has one.
+    pub fn contract_address(&self, address: &str) -> Result<String> {
+        let address = standardize_address(address)?;
+        let Some(overrides) = self
+            .network
+            .and_then(|network| self.contract_address_overrides.get(&network))
+        else {
+            return Ok(address);
+        };
+        for (from, to) in overrides {
+            if standardize_address(from)? == address {
+                return standardize_address(to);
+            }
+        }
+        Ok(address)
+    }
+
     /// Returns the name of the marketplace.
     pub fn get_name(&self) -> &'static str {
         // Intentionally leak the string to satisfy &'static str requirement
         // This is acceptable since processor names live for the application lifetime
         Box::leak(self.name.clone().into_boxed_str())
     }
+
+    /// Returns the `marketplace` stored in the rows this config produces: the canonical name if
+    /// set, otherwise the config's name.
+    pub fn marketplace(&self) -> &str {
+        self.canonical_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// The event and resource types of the config that map to a token offer event type, sorted.
+    fn token_offer_event_types(&self) -> Vec<&str> {
+        let mut token_offer_event_types = Vec::new();
+        for (event_type, standard_event_type) in &self.event_model_mapping {
+            if standard_event_type.is_token_offer() {
+                token_offer_event_types.push(event_type.as_str());
+            }
+        }
+        for (event_type, standard_event_types) in &self.combined_event_model_mapping {
+            if standard_event_types
+                .iter()
+                .any(MarketplaceEventType::is_token_offer)
+            {
+                token_offer_event_types.push(event_type.as_str());
+            }
+        }
+        for (resource_type, event_types) in &self.resource_event_model_mapping {
+            if [
+                &event_types.on_write,
+                &event_types.on_update,
+                &event_types.on_delete,
+            ]
+            .into_iter()
+            .flatten()
+            .any(MarketplaceEventType::is_token_offer)
+            {
+                token_offer_event_types.push(resource_type.as_str());
+            }
+        }
+        token_offer_event_types.sort();
+        token_offer_event_types.dedup();
+        token_offer_event_types
+    }
+
+    /// Whether the remapping of `event_type` sets the property version of token offers, with
+    /// `property_version_path` or a mapped `property_version` column.
+    fn maps_property_version(&self, event_type: &str) -> bool {
+        self.events.get(event_type).is_some_and(|event_remapping| {
+            event_remapping.property_version_path.is_some()
+                || event_remapping
+                    .event_fields
+                    .values()
+                    .flatten()
+                    .any(|db_column| {
+                        db_column.table == CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME
+                            && db_column.column == MarketplaceField::PropertyVersion.to_string()
+                    })
+        })
+    }
+
+    /// Checks that every event type and JSON path in the config can be parsed, without
+    /// needing any transactions. All failures are collected into a single error that
+    /// lists where each bad entry lives in the config.
+    pub fn validate_config(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        if self
+            .canonical_name
+            .as_ref()
+            .is_some_and(|canonical_name| canonical_name.trim().is_empty())
+        {
+            errors.push("canonical_name: must not be empty".to_string());
+        }
+
+        for event_type in self.event_model_mapping.keys() {
+            if let Err(e) = self.parse_event_type(event_type) {
+                errors.push(format!("event_model_mapping: {e}"));
+            }
+        }
+
+        for (event_type, standard_event_types) in &self.combined_event_model_mapping {
+            if let Err(e) = self.parse_event_type(event_type) {
+                errors.push(format!("combined_event_model_mapping: {e}"));
+            }
+            if standard_event_types.is_empty() {
+                errors.push(format!(
+                    "combined_event_model_mapping.{event_type}: must map to at least one event type"
+                ));
+            }
+        }
+
+        for (resource_type, resource_event_types) in &self.resource_event_model_mapping {
+            if let Err(e) = self.parse_event_type(resource_type) {
+                errors.push(format!("resource_event_model_mapping: {e}"));
+            }
+            if resource_event_types.on_write.is_none()
+                && resource_event_types.on_update.is_none()
+                && resource_event_types.on_delete.is_none()
+            {
+                errors.push(format!(
+                    "resource_event_model_mapping.{resource_type}: must set on_write, on_update \
+                     or on_delete"
+                ));
+            }
+            if !self.events.contains_key(resource_type) {
+                errors.push(format!(
+                    "resource_event_model_mapping.{resource_type}: no events.{resource_type} \
+                     remapping for the resource's fields"
+                ));
+            }
+        }
+
+        for (event_type, event_remapping) in &self.events {
+            if let Err(e) = self.parse_event_type(event_type) {
+                errors.push(format!("events: {e}"));
+            }
+            for (json_path, db_columns) in &event_remapping.event_fields {
+                if let Err(e) = HashableJsonPath::new(json_path) {
+                    errors.push(format!(
+                        "events.{event_type}: invalid path '{json_path}' for {}: {e}",
+                        format_db_columns(db_columns)
+                    ));
+                }
+                errors.extend(
+                    column_errors(db_columns)
+                        .map(|e| format!("events.{event_type}: path '{json_path}': {e}")),
+                );
+                for db_column in db_columns.iter().filter(|db_column| db_column.all_matches) {
+                    if !db_column.stores_array() {
+                        errors.push(format!(
+                            "events.{event_type}: all_matches for path '{json_path}' needs a \
+                             custom nft_marketplace_activities column, not {}.{}",
+                            db_column.table, db_column.column
+                        ));
+                    }
+                }
+            }
+            for (name, json_path) in [
+                (
+                    "property_version_path",
+                    &event_remapping.property_version_path,
+                ),
+                (
+                    "collection_inner_path",
+                    &event_remapping.collection_inner_path,
+                ),
+            ] {
+                if let Some(json_path) = json_path {
+                    if let Err(e) = HashableJsonPath::new(json_path) {
+                        errors.push(format!(
+                            "events.{event_type}: invalid {name} '{json_path}': {e}"
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Under the default key, a v1 token offer's row is keyed by its property version, so an
+        // event type that doesn't map it misses the rows the others write
+        if self.token_offer_key == TokenOfferKey::Buyer {
+            let (mapped, unmapped): (Vec<_>, Vec<_>) = self
+                .token_offer_event_types()
+                .into_iter()
+                .partition(|event_type| self.maps_property_version(event_type));
+            if !mapped.is_empty() && !unmapped.is_empty() {
+                errors.push(format!(
+                    "events: {} map the property version of token offers but {} don't, so \
+                     their offers are keyed apart. Set property_version_path or map a \
+                     property_version column on every token offer event type",
+                    mapped.join(", "),
+                    unmapped.join(", ")
+                ));
+            }
+        }
+
+        for contract in &self.allowed_contracts {
+            if let Err(e) = standardize_address(contract) {
+                errors.push(format!("allowed_contracts: {e}"));
+            }
+        }
+
+        for (network, overrides) in &self.contract_address_overrides {
+            for address in overrides.iter().flat_map(|(from, to)| [from, to]) {
+                if let Err(e) = standardize_address(address) {
+                    errors.push(format!("contract_address_overrides.{network}: {e}"));
+                }
+            }
+        }
+
+        for (resource_type, resource_remapping) in &self.resources {
+            if let Err(e) = self.parse_event_type(resource_type) {
+                errors.push(format!("resources.{resource_type}: {e}"));
+            }
+            for (json_path, db_columns) in &resource_remapping.resource_fields {
+                match HashableJsonPath::new(json_path) {
+                    Ok(path) if path.reads_txn() => errors.push(format!(
+                        "resources.{resource_type}: path '{json_path}' reads the transaction, \
+                         which is only supported for events"
+                    )),
+                    Ok(_) => {},
+                    Err(e) => errors.push(format!(
+                        "resources.{resource_type}: invalid path '{json_path}' for {}: {e}",
+                        format_db_columns(db_columns)
+                    )),
+                }
+                errors.extend(
+                    column_errors(db_columns)
+                        .map(|e| format!("resources.{resource_type}: path '{json_path}': {e}")),
+                );
+                if db_columns.iter().any(|db_column| db_column.all_matches) {
+                    errors.push(format!(
+                        "resources.{resource_type}: all_matches for path '{json_path}' is only \
+                         supported for events"
+                    ));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!(
+                "Invalid config for marketplace {}:\n{}",
+                self.name,
+                errors.join("\n")
+            )
+        }
+    }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+/// Marketplace configs read from a directory holding one YAML file per marketplace, so that
+/// adding a marketplace doesn't mean editing one ever-growing config.
+#[derive(Clone, Debug, Default)]
+pub struct NFTMarketplaceConfigs {
+    pub marketplaces: Vec<NFTMarketplaceConfig>,
+}
+
+impl NFTMarketplaceConfigs {
+    /// Reads every `*.yaml` file in `dir`, in file name order. Each file holds a single
+    /// `NFTMarketplaceConfig`, and two files defining the same marketplace name are an error.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut paths = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read marketplace config dir {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "yaml"));
+        paths.sort();
+
+        let mut marketplaces: Vec<NFTMarketplaceConfig> = Vec::with_capacity(paths.len());
+        let mut loaded_from: HashMap<String, PathBuf> = HashMap::new();
+        for path in paths {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let config: NFTMarketplaceConfig = serde_yaml::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            if let Some(previous) = loaded_from.get(&config.name) {
+                anyhow::bail!(
+                    "Marketplace {} is defined in both {} and {}",
+                    config.name,
+                    previous.display(),
+                    path.display()
+                );
+            }
+            loaded_from.insert(config.name.clone(), path);
+            marketplaces.push(config);
+        }
+        Ok(Self { marketplaces })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NFTMarketplaceConfig> {
+        self.marketplaces
+            .iter()
+            .find(|marketplace| marketplace.name == name)
+    }
+}
+
+/// Errors for the columns that aren't columns of their table, see
+/// [`MarketplaceField::for_column`].
+fn column_errors(db_columns: &[DbColumn]) -> impl Iterator<Item = anyhow::Error> + '_ {
+    db_columns.iter().filter_map(|db_column| {
+        MarketplaceField::for_column(&db_column.table, &db_column.column).err()
+    })
+}
+
+fn format_db_columns(db_columns: &[DbColumn]) -> String {
+    db_columns
+        .iter()
+        .map(|db_column| format!("{}.{}", db_column.table, db_column.column))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct EventRemapping {
     pub event_fields: HashMap<String, Vec<DbColumn>>,
+    /// For bundle events that carry several tokens in one event. When set, paths that
+    /// resolve to a JSON array are fanned out into one current state row per element, while
+    /// other paths apply to every row.
+    #[serde(default)]
+    pub fan_out_arrays: bool,
+    /// Token standard (`v1` or `v2`) of every token in this event. Takes precedence over a
+    /// `token_standard` mapped from the event and over detection from the event's shape.
+    #[serde(default)]
+    pub token_standard: Option<TokenStandard>,
+    /// JSON path of a v1 token's property version, stored with the event's token offers. Like
+    /// in aptos-core it isn't part of the `token_data_id`.
+    #[serde(default)]
+    pub property_version_path: Option<String>,
+    /// JSON path of a v2 collection's object address, e.g. `collection.vec[0].inner`. Models
+    /// left without a `collection_id` take it from there, and only fall back to hashing the
+    /// creator and collection name, the v1 collection id, when the path doesn't resolve.
+    #[serde(default)]
+    pub collection_inner_path: Option<String>,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct ResourceRemapping {
     pub resource_fields: HashMap<String, Vec<DbColumn>>,
 }
 
+/// Standard event types of the changes to a resource. Changes are remapped like events whose
+/// data is `{"address": <resource address>, "data": <resource>}`, so a listing object's
+/// address is mapped with `$.address` and its fields with `$.data.<field>`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ResourceEventTypes {
+    /// The first write of the resource, i.e. a write of a resource without a stored last write.
+    #[serde(default)]
+    pub on_write: Option<MarketplaceEventType>,
+    /// The later writes of the resource, e.g. `update_listing` for a price change. Skipped when
+    /// unset.
+    #[serde(default)]
+    pub on_update: Option<MarketplaceEventType>,
+    /// The deletion of the resource. A deletion carries no data, so it's remapped with the data
+    /// of the resource's stored last write, see [`crate::postgres::resource_last_writes`].
+    #[serde(default)]
+    pub on_delete: Option<MarketplaceEventType>,
+}
+
 #[derive(
     Debug,
     Clone,
@@ -90,14 +1015,22 @@ pub enum MarketplaceEventType {
     PlaceListing,
     CancelListing,
     FillListing,
+    /// Changes the price or other fields of a listing that stays open
+    UpdateListing,
     // Token offer events
     PlaceTokenOffer,
     CancelTokenOffer,
     FillTokenOffer,
+    /// Changes the price or other fields of a token offer that stays open
+    UpdateTokenOffer,
     // Collection offer events
     PlaceCollectionOffer,
     CancelCollectionOffer,
     FillCollectionOffer,
+    // Auction events
+    PlaceAuction,
+    BidAuction,
+    SettleAuction,
     #[default]
     Unknown,
 }
@@ -117,6 +1050,53 @@ impl FromSql<Text, Pg> for MarketplaceEventType {
     }
 }
 
+impl MarketplaceEventType {
+    /// Whether events of this type write `current_nft_marketplace_token_offers`.
+    pub fn is_token_offer(&self) -> bool {
+        matches!(
+            self,
+            Self::PlaceTokenOffer
+                | Self::CancelTokenOffer
+                | Self::FillTokenOffer
+                | Self::UpdateTokenOffer
+        )
+    }
+
+    /// Where the funds of an event of this type go, `None` for events that move none.
+    pub fn funds_direction(&self) -> Option<FundsDirection> {
+        match self {
+            Self::FillListing
+            | Self::FillTokenOffer
+            | Self::FillCollectionOffer
+            | Self::SettleAuction => Some(FundsDirection::ToSeller),
+            Self::CancelTokenOffer | Self::CancelCollectionOffer => Some(FundsDirection::ToBuyer),
+            Self::PlaceTokenOffer | Self::PlaceCollectionOffer | Self::BidAuction => {
+                Some(FundsDirection::ToEscrow)
+            },
+            Self::PlaceListing
+            | Self::CancelListing
+            | Self::UpdateListing
+            | Self::UpdateTokenOffer
+            | Self::PlaceAuction
+            | Self::Unknown => None,
+        }
+    }
+}
+
+/// Where the price of an activity moves, stored in the `direction` column of
+/// `nft_marketplace_activities`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum FundsDirection {
+    /// Paid to the seller, e.g. by a fill.
+    ToSeller,
+    /// Refunded to the buyer, e.g. when an offer is canceled.
+    ToBuyer,
+    /// Locked up by the buyer until a fill or a cancel, e.g. by an offer or a bid.
+    ToEscrow,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct EventType {
     address: String,
@@ -134,22 +1114,35 @@ impl TryFrom<&str> for EventType {
     type Error = anyhow::Error;
 
     fn try_from(event_type: &str) -> Result<Self> {
-        let parts: Vec<&str> = event_type.split("::").collect();
+        Self::parse(event_type, false)
+    }
+}
+
+impl EventType {
+    /// Parses `event_type`, dropping the generic type parameters of the struct unless
+    /// `keep_generics` is set. Kept parameters have their whitespace removed, so that the
+    /// same type always compares equal.
+    pub fn parse(event_type: &str, keep_generics: bool) -> Result<Self> {
+        let (base, generics) =
+            event_type.split_at(event_type.find('<').unwrap_or(event_type.len()));
+        let parts: Vec<&str> = base.split("::").collect();
         if parts.len() < 3 {
             // With v1 events it is possible to emit primitives as events, e.g. just
             // emit an address or u64 as an event. We don't support this.
             anyhow::bail!("Unsupported event type: {}", event_type);
         }
 
+        let mut r#struct = parts[2..].join("::");
+        if keep_generics {
+            r#struct.extend(generics.chars().filter(|c| !c.is_whitespace()));
+        }
         Ok(EventType {
-            address: standardize_address(parts[0]),
+            address: standardize_address(parts[0])?,
             module: parts[1].to_string(),
-            r#struct: parts[2..].join("::"), // Don't need to standardize generics because we won't support them
+            r#struct,
         })
     }
-}
 
-impl EventType {
     /// Returns true if the event type is a framework event. We don't always allow
     /// users to index framework events.
     //
@@ -166,7 +1159,293 @@ impl EventType {
         addr_bytes[..32 - 1].iter().all(|x| *x == 0) && addr_bytes[32 - 1] < 0b10000
     }
 
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
     pub fn get_struct(&self) -> &str {
         &self.r#struct
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENT_TYPE: &str = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+
+    fn build_config(json_path: &str) -> NFTMarketplaceConfig {
+        NFTMarketplaceConfig {
+            name: "test_marketplace".to_string(),
+            event_model_mapping: HashMap::from([(
+                EVENT_TYPE.to_string(),
+                MarketplaceEventType::PlaceListing,
+            )]),
+            events: HashMap::from([(EVENT_TYPE.to_string(), EventRemapping {
+                event_fields: HashMap::from([(json_path.to_string(), vec![DbColumn {
+                    table: "nft_marketplace_activities".to_string(),
+                    column: "price".to_string(),
+                    ..Default::default()
+                }])]),
+                ..Default::default()
+            })]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_config_valid_paths() {
+        assert!(build_config("$.token_metadata.token.vec[0].inner")
+            .validate_config()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_example_config_round_trips_through_yaml() {
+        let example = NFTMarketplaceConfig::example();
+        assert!(example.validate_config().is_ok());
+
+        let yaml = serde_yaml::to_string(&example).unwrap();
+        let parsed: NFTMarketplaceConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed, example);
+    }
+
+    #[test]
+    fn test_validate_config_reports_invalid_path_location() {
+        let err = build_config("$.price[").validate_config().unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("$.price["));
+        assert!(message.contains("nft_marketplace_activities.price"));
+    }
+
+    #[test]
+    fn test_validate_config_rejects_columns_missing_from_table() {
+        let with_column = |table: &str, column: &str| {
+            let mut config = build_config("$.price");
+            let remapping = config.events.get_mut(EVENT_TYPE).unwrap();
+            remapping
+                .event_fields
+                .insert("$.rarity".to_string(), vec![DbColumn {
+                    table: table.to_string(),
+                    column: column.to_string(),
+                    ..Default::default()
+                }]);
+            config
+        };
+
+        // Custom columns are stored on activities only
+        assert!(with_column("nft_marketplace_activities", "rarity_rank")
+            .validate_config()
+            .is_ok());
+        let message = with_column("current_nft_marketplace_listings", "rarity_rank")
+            .validate_config()
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("'$.rarity'"));
+        assert!(message.contains("current_nft_marketplace_listings.rarity_rank"));
+
+        assert!(
+            with_column("current_nft_marketplace_listings", "auction_id")
+                .validate_config()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate_config_requires_property_version_on_every_token_offer_event() {
+        const PLACE: &str = "0xcafe::events::OfferPlaced";
+        const CANCEL: &str = "0xcafe::events::OfferCanceled";
+        let mut config = NFTMarketplaceConfig {
+            name: "test_marketplace".to_string(),
+            event_model_mapping: HashMap::from([
+                (PLACE.to_string(), MarketplaceEventType::PlaceTokenOffer),
+                (CANCEL.to_string(), MarketplaceEventType::CancelTokenOffer),
+            ]),
+            events: HashMap::from([
+                (PLACE.to_string(), EventRemapping {
+                    property_version_path: Some("$.property_version".to_string()),
+                    ..Default::default()
+                }),
+                (CANCEL.to_string(), EventRemapping::default()),
+            ]),
+            ..Default::default()
+        };
+        let message = config.validate_config().unwrap_err().to_string();
+        assert!(message.contains(&format!("{PLACE} map the property version")));
+        assert!(message.contains(&format!("but {CANCEL} don't")));
+
+        // A mapped column counts as well
+        config.events.insert(CANCEL.to_string(), EventRemapping {
+            event_fields: HashMap::from([("$.property_version".to_string(), vec![DbColumn {
+                table: "current_nft_marketplace_token_offers".to_string(),
+                column: "property_version".to_string(),
+                ..Default::default()
+            }])]),
+            ..Default::default()
+        });
+        assert!(config.validate_config().is_ok());
+
+        // Offers keyed by their id don't depend on it
+        config
+            .events
+            .insert(CANCEL.to_string(), EventRemapping::default());
+        config.token_offer_key = TokenOfferKey::OfferId;
+        assert!(config.validate_config().is_ok());
+    }
+
+    #[test]
+    fn test_unwrap_option() {
+        assert_eq!(
+            MoveWrapper::Option.unwrap_value(serde_json::json!({ "vec": ["name"] })),
+            serde_json::json!("name")
+        );
+        assert_eq!(
+            MoveWrapper::Option.unwrap_value(serde_json::json!({ "vec": [] })),
+            serde_json::Value::Null
+        );
+        assert_eq!(
+            MoveWrapper::Option.unwrap_value(serde_json::json!("100")),
+            serde_json::json!("100")
+        );
+    }
+
+    #[test]
+    fn test_unwrap_variant() {
+        assert_eq!(
+            MoveWrapper::Variant
+                .unwrap_value(serde_json::json!({ "__variant__": "V1", "price": "100" })),
+            serde_json::json!("100")
+        );
+        assert_eq!(
+            MoveWrapper::Variant.unwrap_value(serde_json::json!({ "__variant__": "Fixed" })),
+            serde_json::json!("Fixed")
+        );
+    }
+
+    #[test]
+    fn test_transform_bcs_string() {
+        assert_eq!(
+            ValueTransform::BcsString.apply("0x05546f6b656e").unwrap(),
+            "Token"
+        );
+        assert!(ValueTransform::BcsString.apply("Token").is_err());
+    }
+
+    #[test]
+    fn test_transform_bcs_move_types() {
+        let apply = |transform: &str, value: &str| {
+            transform.parse::<ValueTransform>().unwrap().apply(value)
+        };
+        assert_eq!(
+            apply("bcs:0x1::string::String", "0x05546f6b656e").unwrap(),
+            "Token"
+        );
+        assert_eq!(apply("bcs:u64", "0x00e1f50500000000").unwrap(), "100000000");
+        assert_eq!(apply("bcs:bool", "0x01").unwrap(), "true");
+        assert_eq!(apply("bcs:vector<u8>", "0x02abcd").unwrap(), "0xabcd");
+        assert_eq!(
+            apply("bcs:address", &format!("0x{}01", "00".repeat(31))).unwrap(),
+            standardize_address("0x1").unwrap()
+        );
+        // A u64 is 8 bytes, a u128 16
+        assert!(apply("bcs:u128", "0x00e1f50500000000").is_err());
+        assert!("bcs:u256".parse::<ValueTransform>().is_err());
+        assert_eq!(
+            String::from(ValueTransform::Bcs(BcsType::Bytes)),
+            "bcs:vector<u8>"
+        );
+    }
+
+    #[test]
+    fn test_transform_hex_decode() {
+        assert_eq!(
+            ValueTransform::HexDecode.apply("0x546f6b656e").unwrap(),
+            "Token"
+        );
+        assert!(ValueTransform::HexDecode.apply("Token").is_err());
+    }
+
+    #[test]
+    fn test_transform_divide() {
+        let transform: ValueTransform = "divide:100000000".parse().unwrap();
+        let value = transform.apply("398000000").unwrap();
+        assert_eq!(
+            BigDecimal::from_str(&value).unwrap(),
+            BigDecimal::from_str("3.98").unwrap()
+        );
+        assert!(transform.apply("abc").is_err());
+        assert!("divide:0".parse::<ValueTransform>().is_err());
+        assert!("divide:abc".parse::<ValueTransform>().is_err());
+    }
+
+    #[test]
+    fn test_transform_from_config() {
+        let db_column: DbColumn = serde_yaml::from_str(
+            "table: nft_marketplace_activities\ncolumn: token_name\ntransform: bcs_string",
+        )
+        .unwrap();
+        assert_eq!(db_column.transform, Some(ValueTransform::BcsString));
+
+        let db_column: Result<DbColumn, _> = serde_yaml::from_str(
+            "table: nft_marketplace_activities\ncolumn: price\ntransform: multiply:2",
+        );
+        assert!(db_column.is_err());
+    }
+
+    #[test]
+    fn test_load_dir_merges_marketplaces() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("topaz.yaml"),
+            format!("name: topaz\nevent_model_mapping:\n  {EVENT_TYPE}: place_listing\n"),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("wapal.yaml"),
+            "name: wapal\nprice_decimals: 8\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("README.md"), "not a config").unwrap();
+
+        let configs = NFTMarketplaceConfigs::load_dir(dir.path()).unwrap();
+        assert_eq!(configs.marketplaces.len(), 2);
+        assert_eq!(
+            configs.get("topaz").unwrap().event_model_mapping[EVENT_TYPE],
+            MarketplaceEventType::PlaceListing
+        );
+        assert_eq!(configs.get("wapal").unwrap().price_decimals, Some(8));
+        assert!(configs.get("tradeport").is_none());
+    }
+
+    #[test]
+    fn test_event_type_generics() {
+        let generic_event_type = format!("{EVENT_TYPE}<0x1::aptos_coin::AptosCoin>");
+        let event_type = EventType::try_from(generic_event_type.as_str()).unwrap();
+        assert_eq!(event_type.to_string(), EVENT_TYPE);
+        assert_eq!(event_type.get_struct(), "ListingPlacedEvent");
+
+        let event_type = EventType::parse(
+            &format!("{EVENT_TYPE}<0x1::coin::CoinStore< 0x1::aptos_coin::AptosCoin >>"),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            event_type.get_struct(),
+            "ListingPlacedEvent<0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>>"
+        );
+
+        assert!(EventType::try_from("vector<0x1::aptos_coin::AptosCoin>").is_err());
+    }
+
+    #[test]
+    fn test_load_dir_rejects_duplicate_names() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("wapal.yaml"), "name: wapal\n").unwrap();
+        fs::write(dir.path().join("wapal_v2.yaml"), "name: wapal\n").unwrap();
+
+        let err = NFTMarketplaceConfigs::load_dir(dir.path()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Marketplace wapal is defined in both"));
+    }
+}
@@ -1,33 +1,264 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{config::marketplace_config::NFTMarketplaceConfig, processor::Processor};
-use anyhow::Result;
+use crate::{
+    config::marketplace_config::{NFTMarketplaceConfig, NFTMarketplaceConfigs},
+    health::HealthConfig,
+    postgres::postgres_utils::{PoolTimeouts, DEFAULT_MAX_POOL_SIZE},
+    processor::Processor,
+    steps::{
+        bigquery_step::BigQueryConfig, clickhouse_step::ClickHouseConfig, kafka_step::KafkaConfig,
+        webhook_step::WebhookConfig,
+    },
+    utils::{logging::LogConfig, retry::QueryRetryConfig},
+};
+use anyhow::{Context, Result};
 use aptos_indexer_processor_sdk::{
-    aptos_indexer_transaction_stream::TransactionStreamConfig,
-    postgres::subconfigs::postgres_config::PostgresConfig, server_framework::RunnableConfig,
+    aptos_indexer_transaction_stream::TransactionStreamConfig, server_framework::RunnableConfig,
     traits::processor_trait::ProcessorTrait,
 };
 use processor_mode::ProcessorMode;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 pub mod marketplace_config;
 pub mod processor_mode;
 pub const QUERY_DEFAULT_RETRIES: u32 = 5;
 pub const QUERY_DEFAULT_RETRY_DELAY_MS: u64 = 500;
+pub const DEFAULT_DB_CHUNK_SIZE: usize = 200;
+pub const DEFAULT_WEBHOOK_BATCH_SIZE: usize = 100;
+pub const DEFAULT_CLICKHOUSE_TABLE: &str = "nft_marketplace_activities";
+pub const DEFAULT_CLICKHOUSE_BATCH_SIZE: usize = 10_000;
+pub const DEFAULT_CLICKHOUSE_FLUSH_INTERVAL_MS: u64 = 1_000;
+pub const DEFAULT_KAFKA_MESSAGE_TIMEOUT_MS: u64 = 30_000;
+pub const DEFAULT_BIGQUERY_ACTIVITIES_TABLE: &str = "nft_marketplace_activities";
+/// `insertAll` requests are recommended to stay at 500 rows.
+pub const DEFAULT_BIGQUERY_BATCH_SIZE: usize = 500;
+pub const DEFAULT_BIGQUERY_FLUSH_INTERVAL_MS: u64 = 1_000;
 
+/// Unknown fields are rejected unless `strict` is turned off, see
+/// [`IndexerProcessorConfig::check_unknown_fields`].
 #[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
 pub struct IndexerProcessorConfig {
     pub transaction_stream_config: TransactionStreamConfig,
+    /// File holding the gRPC auth token. When set, it takes precedence over the
+    /// `auth_token` of the `transaction_stream_config` and is read again when the stream fails,
+    /// so a rotated token is picked up without a restart.
+    #[serde(default)]
+    pub auth_token_path: Option<String>,
     pub db_config: DbConfig,
     pub processor_mode: ProcessorMode,
     pub nft_marketplace_config: NFTMarketplaceConfig,
+    /// Directory with one YAML file per marketplace. When set, the marketplace named by
+    /// `nft_marketplace_config` is loaded from it, see
+    /// [`IndexerProcessorConfig::resolve_marketplace_config`].
+    #[serde(default)]
+    pub nft_marketplace_config_dir: Option<String>,
+    /// Retries for startup queries such as fetching the chain id from gRPC.
+    #[serde(default)]
+    pub query_retry_config: QueryRetryConfig,
+    /// Reconnects to the transaction stream when it drops, see
+    /// [`crate::utils::reconnect::run_with_reconnects`].
+    #[serde(default)]
+    pub stream_reconnect_config: QueryRetryConfig,
+    /// Webhook to push activities to once they are persisted.
+    #[serde(default)]
+    pub webhook_config: Option<WebhookConfig>,
+    /// ClickHouse to export activities to, next to Postgres.
+    #[serde(default)]
+    pub clickhouse_config: Option<ClickHouseConfig>,
+    /// BigQuery to stream activities, and optionally the current tables, to next to Postgres.
+    #[serde(default)]
+    pub bigquery_config: Option<BigQueryConfig>,
+    /// Kafka topic to publish activities to once they are persisted.
+    #[serde(default)]
+    pub kafka_config: Option<KafkaConfig>,
+    /// Serves `/health` and `/ready` with the processor's lag when set.
+    #[serde(default)]
+    pub health_config: Option<HealthConfig>,
+    /// Deletes the marketplace's activities once they are older than this many days. Kept
+    /// forever when unset.
+    #[serde(default)]
+    pub activity_retention_days: Option<u32>,
+    /// Activities deleted per statement when pruning, see
+    /// [`crate::postgres::activity_retention::DEFAULT_ACTIVITY_RETENTION_BATCH_SIZE`].
+    #[serde(default)]
+    pub activity_retention_batch_size: Option<i64>,
+    /// Splits the batches polled from the transaction stream into batches of at most this many
+    /// transactions, see [`crate::steps::batch_size_step::CapBatchSizeStep`]. Unbounded when unset.
+    #[serde(default)]
+    pub max_batch_size: Option<usize>,
+    /// Rejects unknown fields when set. Turning it off lets a config carry keys for a newer
+    /// binary while older ones still run.
+    #[serde(default = "IndexerProcessorConfig::default_strict")]
+    pub strict: bool,
+    /// Log level and format, `RUST_LOG` aside.
+    #[serde(default)]
+    pub log_config: LogConfig,
+    /// Set from the command line rather than the config file.
+    #[serde(skip)]
+    pub version_override: VersionRangeOverride,
+}
+
+impl IndexerProcessorConfig {
+    pub const fn default_strict() -> bool {
+        true
+    }
+
+    /// Name the processor's status is stored under. Every schema shares
+    /// `processor_metadata.processor_status`, so with [`PostgresConfig::schema`] set the
+    /// marketplace name is qualified with the schema, e.g. `tenant_a.wapal`.
+    pub fn processor_status_name(&self) -> String {
+        let DbConfig::PostgresConfig(ref postgres_config) = self.db_config;
+        match &postgres_config.schema {
+            Some(schema) => format!("{schema}.{}", self.nft_marketplace_config.name),
+            None => self.nft_marketplace_config.name.clone(),
+        }
+    }
+
+    /// Checks the settings serde can't, before anything connects.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(clickhouse_config) = &self.clickhouse_config {
+            clickhouse_config.validate()?;
+        }
+        if self
+            .activity_retention_batch_size
+            .is_some_and(|batch_size| batch_size < 1)
+        {
+            anyhow::bail!("activity_retention_batch_size must be greater than 0");
+        }
+        if self.max_batch_size == Some(0) {
+            anyhow::bail!("max_batch_size must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// Looks for fields in `raw`, the `server_config` section this config was parsed from, that
+    /// none of the config structs know. Errors listing them in strict mode, otherwise returns
+    /// them so they can be reported as ignored.
+    ///
+    /// The `transaction_stream_config` is left to the SDK and the `nft_marketplace_config` has
+    /// always ignored unknown fields, so neither is checked. Everything else is: the processor
+    /// mode, db, retry, webhook, ClickHouse, BigQuery, Kafka, health and log configs.
+    pub fn check_unknown_fields(&self, raw: &serde_yaml::Value) -> Result<Vec<String>> {
+        let mut raw = raw.clone();
+        if let Some(raw) = raw.as_mapping_mut() {
+            raw.remove("transaction_stream_config");
+            raw.remove("nft_marketplace_config");
+        }
+        let mut unknown_fields = Vec::new();
+        collect_unknown_fields(&raw, &serde_yaml::to_value(self)?, "", &mut unknown_fields);
+        if self.strict && !unknown_fields.is_empty() {
+            anyhow::bail!(
+                "Unknown config fields: {}. Set `strict: false` to ignore them",
+                unknown_fields.join(", ")
+            );
+        }
+        Ok(unknown_fields)
+    }
+
+    /// Replaces `nft_marketplace_config` with the config of the same name in
+    /// `nft_marketplace_config_dir`, if a directory is set. Only the name has to be given
+    /// inline then.
+    ///
+    /// `only_marketplace`, from `--only-marketplace`, runs that marketplace instead of the one
+    /// named inline, so a single marketplace of the directory can be isolated without editing
+    /// the file. Without a directory it has to match the inline config's name.
+    pub fn resolve_marketplace_config(&mut self, only_marketplace: Option<&str>) -> Result<()> {
+        let Some(dir) = &self.nft_marketplace_config_dir else {
+            if let Some(name) = only_marketplace {
+                if name != self.nft_marketplace_config.name {
+                    anyhow::bail!(
+                        "No config for marketplace {name}, the config only has {}",
+                        self.nft_marketplace_config.name
+                    );
+                }
+            }
+            return Ok(());
+        };
+        let configs = NFTMarketplaceConfigs::load_dir(dir)?;
+        let name = only_marketplace.unwrap_or(&self.nft_marketplace_config.name);
+        let Some(config) = configs.get(name) else {
+            anyhow::bail!("No config for marketplace {name} in {dir}");
+        };
+        self.nft_marketplace_config = config.clone();
+        Ok(())
+    }
+}
+
+/// Collects the keys of `raw` missing from `parsed`, the config re-serialized after parsing.
+fn collect_unknown_fields(
+    raw: &serde_yaml::Value,
+    parsed: &serde_yaml::Value,
+    path: &str,
+    unknown_fields: &mut Vec<String>,
+) {
+    let (Some(raw), Some(parsed)) = (raw.as_mapping(), parsed.as_mapping()) else {
+        return;
+    };
+    for (key, raw_value) in raw {
+        let name = key
+            .as_str()
+            .map_or_else(|| format!("{key:?}"), str::to_string);
+        let field_path = if path.is_empty() {
+            name
+        } else {
+            format!("{path}.{name}")
+        };
+        match parsed.get(key) {
+            Some(parsed_value) => {
+                collect_unknown_fields(raw_value, parsed_value, &field_path, unknown_fields)
+            },
+            None => unknown_fields.push(field_path),
+        }
+    }
+}
+
+/// Versions passed with `--starting-version`/`--ending-version`. They take precedence over the
+/// range derived from the processor mode and its checkpoints, so a range can be reprocessed
+/// without editing the config.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VersionRangeOverride {
+    pub starting_version: Option<u64>,
+    pub ending_version: Option<u64>,
+    /// From `--max-versions`: stops after this many versions from the starting version, or at
+    /// the ending version if that comes first. Meant for smoke tests and bounded backfills.
+    pub max_versions: Option<u64>,
+}
+
+impl VersionRangeOverride {
+    /// Returns the range to process given the one derived from the processor mode.
+    pub fn apply(
+        &self,
+        starting_version: Option<u64>,
+        ending_version: Option<u64>,
+    ) -> Result<(Option<u64>, Option<u64>)> {
+        let starting_version = self.starting_version.or(starting_version);
+        let mut ending_version = self.ending_version.or(ending_version);
+        if let Some(max_versions) = self.max_versions {
+            if max_versions == 0 {
+                anyhow::bail!("--max-versions must be at least 1");
+            }
+            let last_version = starting_version
+                .unwrap_or_default()
+                .saturating_add(max_versions - 1);
+            ending_version = Some(ending_version.map_or(last_version, |end| end.min(last_version)));
+        }
+        if let (Some(start), Some(end)) = (starting_version, ending_version) {
+            if start > end {
+                anyhow::bail!("Starting version {start} is after ending version {end}");
+            }
+        }
+        Ok((starting_version, ending_version))
+    }
 }
 
 #[async_trait::async_trait]
 impl RunnableConfig for IndexerProcessorConfig {
     async fn run(&self) -> Result<()> {
+        // Metrics are served by the server framework's `/metrics` endpoint on `health_check_port`
+        crate::metrics::init();
+        self.validate()?;
         let processor = Processor::new(self.clone()).await?;
         processor.run_processor().await
     }
@@ -40,6 +271,10 @@ impl RunnableConfig for IndexerProcessorConfig {
 /// This enum captures the configs for all the different db storages that are defined.
 /// The configs for each db storage should only contain configuration specific to that
 /// type.
+///
+/// Only Postgres is supported. The models store prices as `NUMERIC` (`BigDecimal`) and
+/// event data as `JSONB`, and diesel's SQLite backend supports neither. The SDK's pool,
+/// migration and processor status helpers are Postgres only as well.
 #[derive(Clone, Debug, Deserialize, Serialize, strum::IntoStaticStr, strum::EnumDiscriminants)]
 #[serde(tag = "type", rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
@@ -60,3 +295,304 @@ impl RunnableConfig for IndexerProcessorConfig {
 pub enum DbConfig {
     PostgresConfig(PostgresConfig),
 }
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostgresConfig {
+    pub connection_string: String,
+    #[serde(default = "PostgresConfig::default_db_pool_size")]
+    pub db_pool_size: u32,
+    /// Number of rows per insert statement. Larger chunks help under high throughput,
+    /// smaller ones are easier on constrained Postgres instances.
+    #[serde(default)]
+    pub db_chunk_size: Option<usize>,
+    /// Schema holding the processor's tables, including the migrations' bookkeeping, instead of
+    /// `public`. Lets several processors share a database without sharing tables. The schema is
+    /// created if it doesn't exist.
+    #[serde(default)]
+    pub schema: Option<String>,
+    /// How long to wait for a free connection before failing, 30 seconds when unset.
+    #[serde(default)]
+    pub connection_timeout_ms: Option<u64>,
+    /// Closes connections left unused this long, 10 minutes when unset.
+    #[serde(default)]
+    pub idle_timeout_ms: Option<u64>,
+    /// Aborts statements running longer than this. Postgres' own setting applies when unset.
+    #[serde(default)]
+    pub statement_timeout_ms: Option<u64>,
+}
+
+impl PostgresConfig {
+    pub const fn default_db_pool_size() -> u32 {
+        DEFAULT_MAX_POOL_SIZE
+    }
+
+    /// The connection string with the `search_path` of `schema`, so that every connection the
+    /// pool opens and the migrations resolve the tables in it.
+    pub fn connection_string_with_schema(&self) -> Result<String> {
+        let Some(schema) = &self.schema else {
+            return Ok(self.connection_string.clone());
+        };
+        // Unquoted identifiers are folded to lowercase, so only allow names that stay the same
+        let is_valid = schema.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && schema
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !is_valid {
+            anyhow::bail!(
+                "Invalid schema {schema:?}: use lowercase letters, digits and underscores"
+            );
+        }
+        let mut url = url::Url::parse(&self.connection_string)
+            .context("Failed to parse the Postgres connection string")?;
+        url.query_pairs_mut()
+            .append_pair("options", &format!("-csearch_path={schema}"));
+        Ok(url.to_string())
+    }
+
+    pub fn db_chunk_size(&self) -> usize {
+        self.db_chunk_size.unwrap_or(DEFAULT_DB_CHUNK_SIZE)
+    }
+
+    /// Errors on a zero connection or idle timeout, which the pool doesn't accept. A zero
+    /// statement timeout disables it, as it does in Postgres.
+    pub fn pool_timeouts(&self) -> Result<PoolTimeouts> {
+        for (name, timeout_ms) in [
+            ("connection_timeout_ms", self.connection_timeout_ms),
+            ("idle_timeout_ms", self.idle_timeout_ms),
+        ] {
+            if timeout_ms == Some(0) {
+                anyhow::bail!("{name} must be greater than 0");
+            }
+        }
+        Ok(PoolTimeouts {
+            connection_timeout: self.connection_timeout_ms.map(Duration::from_millis),
+            idle_timeout: self.idle_timeout_ms.map(Duration::from_millis),
+            statement_timeout: self.statement_timeout_ms.map(Duration::from_millis),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::marketplace_config::EventType,
+        models::EventModel,
+        steps::remappers::{event_remapper::EventRemapper, RemapOutput},
+    };
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn test_version_override_takes_precedence_over_processor_mode() {
+        let no_override = VersionRangeOverride::default();
+        assert_eq!(
+            no_override.apply(Some(10), Some(20)).unwrap(),
+            (Some(10), Some(20))
+        );
+
+        let full_override = VersionRangeOverride {
+            starting_version: Some(100),
+            ending_version: Some(200),
+            max_versions: None,
+        };
+        assert_eq!(
+            full_override.apply(Some(10), Some(20)).unwrap(),
+            (Some(100), Some(200))
+        );
+        assert_eq!(
+            full_override.apply(None, None).unwrap(),
+            (Some(100), Some(200))
+        );
+
+        // Unset flags keep the version derived from the processor mode
+        let start_override = VersionRangeOverride {
+            starting_version: Some(15),
+            ending_version: None,
+            max_versions: None,
+        };
+        assert_eq!(
+            start_override.apply(Some(10), Some(20)).unwrap(),
+            (Some(15), Some(20))
+        );
+        assert_eq!(
+            start_override.apply(Some(10), None).unwrap(),
+            (Some(15), None)
+        );
+    }
+
+    fn parse_config(strict: bool) -> (IndexerProcessorConfig, serde_yaml::Value) {
+        let raw: serde_yaml::Value = serde_yaml::from_str(&format!(
+            r#"
+            strict: {strict}
+            transaction_stream_config:
+              indexer_grpc_data_service_address: "https://grpc.mainnet.aptoslabs.com:443"
+              starting_version: 0
+              auth_token: "token"
+              request_name_header: "nft-aggregator"
+            db_config:
+              type: postgres_config
+              connection_string: postgresql://localhost:5432/postgres
+            processor_mode:
+              type: default
+              initial_starting_version: 0
+            nft_marketplace_config:
+              name: wapal
+            query_retry_config:
+              retries: 3
+              backoff: exponential
+            "#
+        ))
+        .unwrap();
+        (serde_yaml::from_value(raw.clone()).unwrap(), raw)
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected_in_strict_mode_only() {
+        let (config, raw) = parse_config(true);
+        let err = config.check_unknown_fields(&raw).unwrap_err();
+        assert!(err.to_string().contains("query_retry_config.backoff"));
+
+        let (config, raw) = parse_config(false);
+        assert_eq!(config.check_unknown_fields(&raw).unwrap(), vec![
+            "query_retry_config.backoff".to_string()
+        ]);
+        assert_eq!(config.query_retry_config.retries, 3);
+    }
+
+    #[test]
+    fn test_validate_rejects_empty_retention_batches() {
+        let (mut config, _) = parse_config(true);
+        config.activity_retention_batch_size = Some(0);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("activity_retention_batch_size"));
+
+        config.activity_retention_batch_size = Some(1);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_empty_stream_batches() {
+        let (mut config, _) = parse_config(true);
+        config.max_batch_size = Some(0);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("max_batch_size"));
+
+        config.max_batch_size = Some(1);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_only_marketplace_ignores_other_marketplaces() {
+        const TOPAZ_EVENT: &str = "0xc7::topaz::ListingEvent";
+        const WAPAL_EVENT: &str = "0x9b::wapal::ListingEvent";
+        let dir = tempfile::tempdir().unwrap();
+        for (name, event_type) in [("topaz", TOPAZ_EVENT), ("wapal", WAPAL_EVENT)] {
+            std::fs::write(
+                dir.path().join(format!("{name}.yaml")),
+                format!("name: {name}\nevent_model_mapping:\n  {event_type}: place_listing\n"),
+            )
+            .unwrap();
+        }
+        let (mut config, _) = parse_config(true);
+        config.nft_marketplace_config_dir = Some(dir.path().display().to_string());
+
+        let mut only_topaz = config.clone();
+        only_topaz
+            .resolve_marketplace_config(Some("topaz"))
+            .unwrap();
+        assert_eq!(only_topaz.nft_marketplace_config.name, "topaz");
+
+        let remapper = EventRemapper::new(&only_topaz.nft_marketplace_config).unwrap();
+        let wapal_event = EventModel {
+            sequence_number: 0,
+            creation_number: 0,
+            account_address: "0x2".to_string(),
+            transaction_version: 1,
+            transaction_block_height: 1,
+            event_type: EventType::try_from(WAPAL_EVENT).unwrap(),
+            data: serde_json::json!({ "price": "100" }),
+            event_index: 0,
+            block_timestamp: NaiveDateTime::default(),
+        };
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper
+            .remap_event_models(&[wapal_event], NaiveDateTime::default())
+            .unwrap();
+        assert!(activities.is_empty());
+        assert!(listings.is_empty());
+
+        let err = config
+            .resolve_marketplace_config(Some("tradeport"))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("No config for marketplace tradeport"));
+    }
+
+    #[test]
+    fn test_max_versions_caps_ending_version() {
+        let max_override = VersionRangeOverride {
+            max_versions: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            max_override.apply(Some(1000), None).unwrap(),
+            (Some(1000), Some(1099))
+        );
+        // An earlier ending version still wins
+        assert_eq!(
+            max_override.apply(Some(1000), Some(1010)).unwrap(),
+            (Some(1000), Some(1010))
+        );
+        assert_eq!(max_override.apply(None, None).unwrap(), (None, Some(99)));
+
+        let zero_override = VersionRangeOverride {
+            max_versions: Some(0),
+            ..Default::default()
+        };
+        assert!(zero_override.apply(Some(1000), None).is_err());
+    }
+
+    #[test]
+    fn test_version_override_rejects_inverted_range() {
+        let start_override = VersionRangeOverride {
+            starting_version: Some(30),
+            ending_version: None,
+            max_versions: None,
+        };
+        assert!(start_override.apply(Some(10), Some(20)).is_err());
+    }
+
+    #[test]
+    fn test_schema_sets_search_path() {
+        let build_config = |schema: Option<&str>| PostgresConfig {
+            connection_string: "postgresql://localhost:5432/postgres?sslmode=disable".to_string(),
+            db_pool_size: PostgresConfig::default_db_pool_size(),
+            db_chunk_size: None,
+            schema: schema.map(str::to_string),
+            connection_timeout_ms: None,
+            idle_timeout_ms: None,
+            statement_timeout_ms: None,
+        };
+
+        assert_eq!(
+            build_config(None).connection_string_with_schema().unwrap(),
+            "postgresql://localhost:5432/postgres?sslmode=disable"
+        );
+        assert_eq!(
+            build_config(Some("tenant_a"))
+                .connection_string_with_schema()
+                .unwrap(),
+            "postgresql://localhost:5432/postgres?sslmode=disable&options=-csearch_path%3Dtenant_a"
+        );
+        assert!(build_config(Some("Tenant"))
+            .connection_string_with_schema()
+            .is_err());
+        assert!(build_config(Some("a; DROP TABLE x"))
+            .connection_string_with_schema()
+            .is_err());
+    }
+}
@@ -23,7 +23,6 @@ use serde::{Deserialize, Serialize};
 ///   ending_version: 100
 /// ```
 #[derive(Clone, Debug, Deserialize, Serialize, strum::IntoStaticStr, strum::EnumDiscriminants)]
-#[serde(deny_unknown_fields)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ProcessorMode {
     Backfill(BackfillConfig),
@@ -38,7 +37,6 @@ impl Default for ProcessorMode {
     }
 }
 #[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
 pub struct BackfillConfig {
     pub backfill_id: String,
     pub initial_starting_version: u64,
@@ -47,14 +45,12 @@ pub struct BackfillConfig {
     pub overwrite_checkpoint: bool,
 }
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
-#[serde(deny_unknown_fields)]
 /// Initial starting version for non-backfill processors. Processors should pick up where it left off
 /// if restarted.
 pub struct BootStrapConfig {
     pub initial_starting_version: u64,
 }
 #[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
 /// Use this config for testing. Processors will not use checkpoint and will
 /// always start from `override_starting_version`.
 pub struct TestingConfig {
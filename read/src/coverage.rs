@@ -0,0 +1,264 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks a marketplace config against real transactions: which of the configured paths
+//! resolve for each event, and which columns end up with a value. Paths that match nothing are
+//! the usual reason for a column that stays empty.
+
+use crate::{
+    config::marketplace_config::{EventRemapping, NFTMarketplaceConfig},
+    models::{EventModel, TxnMetadata},
+    steps::HashableJsonPath,
+};
+use anyhow::Result;
+use aptos_indexer_processor_sdk::{
+    aptos_indexer_transaction_stream::utils::time::parse_timestamp,
+    aptos_protos::transaction::v1::{transaction::TxnData, Transaction},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+/// A configured path of an event and what it resolved to.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PathCoverage {
+    pub path: String,
+    /// `None` when the path matched nothing. Every match of an `all_matches` path, as an array.
+    pub value: Option<Value>,
+    /// The `table.column`s the path is mapped to.
+    pub columns: Vec<String>,
+}
+
+/// The coverage of the config for one event of a configured type.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct EventCoverage {
+    pub txn_version: i64,
+    pub event_index: i64,
+    pub event_type: String,
+    /// Ordered by path.
+    pub paths: Vec<PathCoverage>,
+    /// Columns mapped from at least one path that resolved.
+    pub populated_columns: BTreeSet<String>,
+    /// Columns whose paths all matched nothing.
+    pub missing_columns: BTreeSet<String>,
+}
+
+/// Resolves the paths of `config` for every event of `transactions` that has an event
+/// remapping. Events of other types are left out of the report.
+pub fn check_coverage(
+    config: &NFTMarketplaceConfig,
+    transactions: &[Transaction],
+) -> Result<Vec<EventCoverage>> {
+    let mut remappings = HashMap::new();
+    for (event_type, event_remapping) in &config.events {
+        remappings.insert(
+            config.parse_event_type(event_type)?,
+            parse_paths(event_remapping)?,
+        );
+    }
+
+    let mut coverage = Vec::new();
+    for transaction in transactions {
+        let Some(TxnData::User(user_txn)) = transaction.txn_data.as_ref() else {
+            continue;
+        };
+        let txn_version = transaction.version as i64;
+        let txn_timestamp = transaction
+            .timestamp
+            .as_ref()
+            .map(|timestamp| parse_timestamp(timestamp, txn_version).naive_utc())
+            .unwrap_or_default();
+        let txn = TxnMetadata::from_transaction(transaction).to_json();
+        let events = EventModel::from_events(
+            &user_txn.events,
+            txn_version,
+            transaction.block_height as i64,
+            txn_timestamp,
+            config.keep_event_type_generics,
+        );
+        for event in events {
+            if let Some(paths) = remappings.get(&event.event_type) {
+                coverage.push(event_coverage(&event, paths, &txn));
+            }
+        }
+    }
+    Ok(coverage)
+}
+
+/// The event remapping's paths, ordered by their raw form, with their columns and whether any
+/// of them stores every match.
+fn parse_paths(
+    event_remapping: &EventRemapping,
+) -> Result<Vec<(HashableJsonPath, Vec<String>, bool)>> {
+    let mut paths = event_remapping
+        .event_fields
+        .iter()
+        .map(|(raw, db_columns)| {
+            let columns = db_columns
+                .iter()
+                .map(|db_column| format!("{}.{}", db_column.table, db_column.column))
+                .collect();
+            let all_matches = db_columns.iter().any(|db_column| db_column.all_matches);
+            Ok((HashableJsonPath::new(raw)?, columns, all_matches))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    paths.sort_by(|(a, ..), (b, ..)| a.raw().cmp(b.raw()));
+    Ok(paths)
+}
+
+fn event_coverage(
+    event: &EventModel,
+    paths: &[(HashableJsonPath, Vec<String>, bool)],
+    txn: &Value,
+) -> EventCoverage {
+    let mut populated_columns = BTreeSet::new();
+    let mut mapped_columns = BTreeSet::new();
+    let paths = paths
+        .iter()
+        .map(|(json_path, columns, all_matches)| {
+            let data = if json_path.reads_txn() {
+                txn
+            } else {
+                &event.data
+            };
+            let value = if *all_matches {
+                Some(Value::Array(json_path.extract_all_from(data)))
+                    .filter(|matches| matches.as_array().is_some_and(|m| !m.is_empty()))
+            } else {
+                json_path.extract_from(data).ok().filter(|v| !v.is_null())
+            };
+            mapped_columns.extend(columns.iter().cloned());
+            if value.is_some() {
+                populated_columns.extend(columns.iter().cloned());
+            }
+            PathCoverage {
+                path: json_path.raw().to_string(),
+                value,
+                columns: columns.clone(),
+            }
+        })
+        .collect();
+    let missing_columns = mapped_columns
+        .difference(&populated_columns)
+        .cloned()
+        .collect();
+
+    EventCoverage {
+        txn_version: event.transaction_version,
+        event_index: event.event_index,
+        event_type: event.event_type.to_string(),
+        paths,
+        populated_columns,
+        missing_columns,
+    }
+}
+
+impl fmt::Display for EventCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (version {}, event {})",
+            self.event_type, self.txn_version, self.event_index
+        )?;
+        for path in &self.paths {
+            match &path.value {
+                Some(value) => writeln!(f, "  resolved  {} = {value}", path.path)?,
+                None => writeln!(f, "  no value  {}", path.path)?,
+            }
+            writeln!(f, "            -> {}", path.columns.join(", "))?;
+        }
+        let join =
+            |columns: &BTreeSet<String>| columns.iter().cloned().collect::<Vec<_>>().join(", ");
+        writeln!(f, "  populated: {}", join(&self.populated_columns))?;
+        writeln!(f, "  missing:   {}", join(&self.missing_columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::marketplace_config::DbColumn;
+    use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::{Event, UserTransaction};
+
+    const EVENT_TYPE: &str = "0x1::marketplace::ListingPlaced";
+
+    fn column(table: &str, column: &str) -> DbColumn {
+        DbColumn {
+            table: table.to_string(),
+            column: column.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reports_resolved_and_missing_paths() {
+        let config = NFTMarketplaceConfig {
+            name: "test_marketplace".to_string(),
+            events: HashMap::from([(EVENT_TYPE.to_string(), EventRemapping {
+                event_fields: HashMap::from([
+                    ("$.price".to_string(), vec![
+                        column("nft_marketplace_activities", "price"),
+                        column("current_nft_marketplace_listings", "price"),
+                    ]),
+                    ("$.token_metadata.token.vec[0].inner".to_string(), vec![
+                        column("current_nft_marketplace_listings", "token_data_id"),
+                    ]),
+                    ("$.royalties[*].recipient".to_string(), vec![DbColumn {
+                        all_matches: true,
+                        ..column("nft_marketplace_activities", "royalty_recipients")
+                    }]),
+                ]),
+                ..Default::default()
+            })]),
+            ..Default::default()
+        };
+        let event = |type_str: &str| Event {
+            key: Some(Default::default()),
+            type_str: type_str.to_string(),
+            data: serde_json::json!({
+                "price": "100",
+                "token_metadata": { "token": { "vec": [] } },
+                "royalties": [{ "recipient": "0xa" }, { "recipient": "0xb" }]
+            })
+            .to_string(),
+            ..Default::default()
+        };
+        let transaction = Transaction {
+            version: 7,
+            txn_data: Some(TxnData::User(UserTransaction {
+                request: None,
+                events: vec![event("0x1::other::Event"), event(EVENT_TYPE)],
+            })),
+            ..Default::default()
+        };
+
+        let coverage = check_coverage(&config, &[transaction]).unwrap();
+
+        assert_eq!(coverage.len(), 1);
+        let coverage = &coverage[0];
+        assert_eq!((coverage.txn_version, coverage.event_index), (7, 1));
+        let values: Vec<_> = coverage
+            .paths
+            .iter()
+            .map(|path| (path.path.as_str(), path.value.clone()))
+            .collect();
+        assert_eq!(values, vec![
+            ("$.price", Some(Value::from("100"))),
+            (
+                "$.royalties[*].recipient",
+                Some(serde_json::json!(["0xa", "0xb"]))
+            ),
+            ("$.token_metadata.token.vec[0].inner", None),
+        ]);
+        assert_eq!(
+            coverage.missing_columns,
+            BTreeSet::from(["current_nft_marketplace_listings.token_data_id".to_string()])
+        );
+        assert!(coverage
+            .populated_columns
+            .contains("current_nft_marketplace_listings.price"));
+    }
+}
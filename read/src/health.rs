@@ -0,0 +1,266 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! `/health` and `/ready` endpoints reporting how far the processor is behind the chain,
+//! served on their own port next to the SDK's `/metrics` server.
+
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::postgres::{
+    models::processor_status::ProcessorStatusQuery, utils::database::ArcDbPool,
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::info;
+use url::Url;
+
+pub const DEFAULT_MAX_LAG_VERSIONS: u64 = 100_000;
+pub const DEFAULT_MAX_STREAM_IDLE_SECS: u64 = 60;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HealthConfig {
+    pub port: u16,
+    /// Lag, in versions, above which `/ready` returns 503.
+    #[serde(default = "HealthConfig::default_max_lag_versions")]
+    pub max_lag_versions: u64,
+    /// REST API of a fullnode, e.g. `https://api.mainnet.aptoslabs.com`, whose ledger version
+    /// is the chain head the lag is measured against. Without it the lag is measured against
+    /// the latest version received from the transaction stream.
+    #[serde(default)]
+    pub fullnode_url: Option<Url>,
+    /// Seconds without a batch from the transaction stream after which `/ready` returns 503.
+    /// The chain commits transactions every second, so a quiet stream has stalled.
+    #[serde(default = "HealthConfig::default_max_stream_idle_secs")]
+    pub max_stream_idle_secs: u64,
+}
+
+impl HealthConfig {
+    pub const fn default_max_lag_versions() -> u64 {
+        DEFAULT_MAX_LAG_VERSIONS
+    }
+
+    pub const fn default_max_stream_idle_secs() -> u64 {
+        DEFAULT_MAX_STREAM_IDLE_SECS
+    }
+}
+
+/// Highest version received from the transaction stream, and when the last batch came in.
+#[derive(Debug, Default)]
+pub struct StreamProgress {
+    latest_version: AtomicU64,
+    last_received: Mutex<Option<Instant>>,
+}
+
+impl StreamProgress {
+    pub fn record(&self, version: u64) {
+        self.latest_version.fetch_max(version, Ordering::Relaxed);
+        *self.last_received.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn latest_version(&self) -> Option<u64> {
+        match self.latest_version.load(Ordering::Relaxed) {
+            0 => None,
+            version => Some(version),
+        }
+    }
+
+    /// Time since the last batch was received, `None` before the first one.
+    pub fn idle_for(&self) -> Option<Duration> {
+        self.last_received
+            .lock()
+            .unwrap()
+            .map(|last_received| last_received.elapsed())
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HealthStatus {
+    pub processor: String,
+    pub last_success_version: Option<u64>,
+    /// The fullnode's ledger version, or the latest version received from the stream.
+    pub latest_version: Option<u64>,
+    pub lag: Option<u64>,
+    pub stream_stalled: bool,
+    pub ready: bool,
+}
+
+impl HealthStatus {
+    /// The processor is ready once it has received transactions, is still receiving them and
+    /// has processed all but `max_lag_versions` of the chain.
+    pub fn new(
+        processor: String,
+        last_success_version: Option<u64>,
+        latest_version: Option<u64>,
+        stream_stalled: bool,
+        max_lag_versions: u64,
+    ) -> Self {
+        let lag =
+            latest_version.map(|latest| latest.saturating_sub(last_success_version.unwrap_or(0)));
+        Self {
+            processor,
+            last_success_version,
+            latest_version,
+            lag,
+            stream_stalled,
+            ready: !stream_stalled && lag.is_some_and(|lag| lag <= max_lag_versions),
+        }
+    }
+}
+
+/// Response of a fullnode's `/v1` ledger info endpoint, of which only the version is used.
+#[derive(Deserialize)]
+struct LedgerInfo {
+    ledger_version: String,
+}
+
+async fn get_ledger_version(client: &reqwest::Client, fullnode_url: &Url) -> Result<u64> {
+    let url = fullnode_url.join("v1").context("Invalid fullnode URL")?;
+    let ledger_info: LedgerInfo = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .context("Failed to get the ledger info from the fullnode")?
+        .json()
+        .await
+        .context("Failed to parse the fullnode's ledger info")?;
+    ledger_info
+        .ledger_version
+        .parse()
+        .context("Invalid ledger version from the fullnode")
+}
+
+#[derive(Clone)]
+struct HealthState {
+    processor: String,
+    config: HealthConfig,
+    client: reqwest::Client,
+    db_pool: ArcDbPool,
+    stream_progress: Arc<StreamProgress>,
+}
+
+impl HealthState {
+    async fn status(&self) -> Result<HealthStatus> {
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .context("Failed to get database connection")?;
+        let last_success_version =
+            ProcessorStatusQuery::get_by_processor(&self.processor, &mut conn)
+                .await
+                .context("Failed to query processor_status table")?
+                .map(|status| status.last_success_version as u64);
+        let latest_version = match &self.config.fullnode_url {
+            Some(fullnode_url) => Some(get_ledger_version(&self.client, fullnode_url).await?),
+            None => self.stream_progress.latest_version(),
+        };
+        let stream_stalled = self
+            .stream_progress
+            .idle_for()
+            .is_some_and(|idle| idle > Duration::from_secs(self.config.max_stream_idle_secs));
+        Ok(HealthStatus::new(
+            self.processor.clone(),
+            last_success_version,
+            latest_version,
+            stream_stalled,
+            self.config.max_lag_versions,
+        ))
+    }
+}
+
+/// Serves `/health` and `/ready` until the server fails. `/health` always answers with the
+/// status, `/ready` answers 503 while the lag is above `max_lag_versions` or unknown, or the
+/// stream has stalled.
+pub async fn run_health_server(
+    config: HealthConfig,
+    processor: String,
+    db_pool: ArcDbPool,
+    stream_progress: Arc<StreamProgress>,
+) -> Result<()> {
+    let port = config.port;
+    let state = HealthState {
+        processor,
+        config,
+        client: reqwest::Client::new(),
+        db_pool,
+        stream_progress,
+    };
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+        .await
+        .with_context(|| format!("Failed to bind health server to port {port}"))?;
+    info!(port, "Serving /health and /ready");
+    axum::serve(listener, app)
+        .await
+        .context("Health server failed")
+}
+
+async fn health(State(state): State<HealthState>) -> Response {
+    match state.status().await {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, format!("{e:#}")).into_response(),
+    }
+}
+
+async fn ready(State(state): State<HealthState>) -> Response {
+    match state.status().await {
+        Ok(status) if status.ready => Json(status).into_response(),
+        Ok(status) => (StatusCode::SERVICE_UNAVAILABLE, Json(status)).into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, format!("{e:#}")).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_status_lag_and_readiness() {
+        let status = HealthStatus::new("wapal".to_string(), Some(90), Some(100), false, 10);
+        assert_eq!(status.lag, Some(10));
+        assert!(status.ready);
+
+        let status = HealthStatus::new("wapal".to_string(), Some(80), Some(100), false, 10);
+        assert_eq!(status.lag, Some(20));
+        assert!(!status.ready);
+
+        // Nothing received from the stream yet
+        let status = HealthStatus::new("wapal".to_string(), Some(80), None, false, 10);
+        assert_eq!(status.lag, None);
+        assert!(!status.ready);
+
+        // Caught up with the last version received, but nothing came in since
+        let status = HealthStatus::new("wapal".to_string(), Some(100), Some(100), true, 10);
+        assert_eq!(status.lag, Some(0));
+        assert!(!status.ready);
+    }
+
+    #[test]
+    fn test_stream_progress_keeps_highest_version() {
+        let progress = StreamProgress::default();
+        assert_eq!(progress.latest_version(), None);
+
+        assert_eq!(progress.idle_for(), None);
+
+        progress.record(10);
+        progress.record(5);
+        assert_eq!(progress.latest_version(), Some(10));
+        assert!(progress.idle_for().is_some());
+    }
+}
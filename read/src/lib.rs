@@ -8,9 +8,33 @@ pub mod schema;
 pub mod steps;
 
 pub mod config;
+pub mod coverage;
+pub mod health;
+pub mod metrics;
 pub mod models;
 pub mod postgres;
 pub mod processor;
+pub mod replay;
 pub mod utils;
 
+// Re-exports for embedding the remapping logic in other indexers without running the
+// full processor. Build an `EventRemapper` from an `NFTMarketplaceConfig`, then call
+// `EventRemapper::remap_event_models` with decoded events. Marketplaces that need custom logic
+// can register a `MarketplacePlugin` and build the remapper with `EventRemapper::with_plugins`,
+// or run the full processor with them through `Processor::with_plugins`.
+pub use config::marketplace_config::{MarketplaceEventType, NFTMarketplaceConfig};
+pub use models::{
+    nft_models::{
+        CurrentNFTMarketplaceAuction, CurrentNFTMarketplaceCollectionOffer,
+        CurrentNFTMarketplaceListing, CurrentNFTMarketplaceTokenOffer, MarketplaceModel,
+        NftMarketplaceActivity,
+    },
+    EventModel, TxnMetadata,
+};
+pub use steps::remappers::{
+    event_remapper::EventRemapper,
+    plugins::{MarketplacePlugin, PluginRegistry},
+    RemapOutput,
+};
+
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./src/postgres/migrations");
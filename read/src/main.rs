@@ -1,9 +1,19 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 use anyhow::Result;
-use aptos_indexer_processor_sdk::server_framework::ServerArgs;
-use clap::Parser;
-use nft_aggregator::config::IndexerProcessorConfig;
+use aptos_indexer_processor_sdk::server_framework::{
+    load, run_server_with_config, setup_panic_handler, GenericConfig, ServerArgs,
+};
+use clap::{Parser, Subcommand};
+use nft_aggregator::{
+    config::{
+        marketplace_config::NFTMarketplaceConfig, IndexerProcessorConfig, VersionRangeOverride,
+    },
+    coverage::check_coverage,
+    replay::{load_transactions, replay, write_output},
+};
+use std::path::PathBuf;
+use tracing::warn;
 
 #[cfg(unix)]
 #[global_allocator]
@@ -11,7 +21,120 @@ static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 const RUNTIME_WORKER_MULTIPLIER: usize = 2;
 
+#[derive(Parser)]
+struct Args {
+    #[clap(flatten)]
+    server_args: ServerArgs,
+    /// Validate the config file and exit without connecting to gRPC or Postgres
+    #[clap(long)]
+    validate_config: bool,
+    /// Resolve the marketplace config's paths against the events of a transaction JSON file,
+    /// or a directory of them, and print which paths matched and which columns got a value
+    #[clap(long, value_name = "PATH")]
+    config_check_against_txn: Option<PathBuf>,
+    /// Print an example marketplace config with every option set, to start a new one from
+    #[clap(long, exclusive = true)]
+    print_config_template: bool,
+    /// Start processing at this version, regardless of the processor mode and checkpoints
+    #[clap(long)]
+    starting_version: Option<u64>,
+    /// Stop processing at this version, regardless of the processor mode
+    #[clap(long)]
+    ending_version: Option<u64>,
+    /// Stop after processing this many versions from the starting version, then log how many
+    /// rows were written to each table
+    #[clap(long)]
+    max_versions: Option<u64>,
+    /// Run only this marketplace of `nft_marketplace_config_dir`, instead of the one named by
+    /// `nft_marketplace_config`
+    #[clap(long)]
+    only_marketplace: Option<String>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Remap transactions exported to JSON and print the resulting rows, without gRPC
+    Replay {
+        /// A transaction JSON file, or a directory of them
+        path: PathBuf,
+        /// Also write the rows to the configured database
+        #[clap(long)]
+        write: bool,
+    },
+}
+
+/// Replays the transactions at `path` and prints the rows as JSON to stdout.
+fn run_replay(config: IndexerProcessorConfig, path: PathBuf, write: bool) -> Result<()> {
+    let transactions = load_transactions(&path)?;
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let output = replay(&config.nft_marketplace_config, transactions).await?;
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        if write {
+            write_output(&config, output).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Prints the coverage report of the marketplace config for the transactions at `path`.
+fn run_coverage_check(config: &NFTMarketplaceConfig, path: PathBuf) -> Result<()> {
+    let coverage = check_coverage(config, &load_transactions(&path)?)?;
+    if coverage.is_empty() {
+        println!("No events of a configured type in {}", path.display());
+    }
+    for event in coverage {
+        println!("{event}");
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.print_config_template {
+        print!(
+            "{}",
+            serde_yaml::to_string(&NFTMarketplaceConfig::example())?
+        );
+        return Ok(());
+    }
+    let mut config: GenericConfig<IndexerProcessorConfig> = load(&args.server_args.config_path)?;
+    config.server_config.log_config.init()?;
+    // Logs the panic of any step and exits, instead of leaving the other steps running
+    setup_panic_handler();
+    let raw_config: serde_yaml::Value =
+        serde_yaml::from_str(&std::fs::read_to_string(&args.server_args.config_path)?)?;
+    for field in config
+        .server_config
+        .check_unknown_fields(&raw_config["server_config"])?
+    {
+        warn!(%field, "Ignoring unknown field under server_config");
+    }
+    config
+        .server_config
+        .resolve_marketplace_config(args.only_marketplace.as_deref())?;
+    if args.validate_config {
+        config.server_config.validate()?;
+        config
+            .server_config
+            .nft_marketplace_config
+            .validate_config()?;
+        println!("Config is valid");
+        return Ok(());
+    }
+    if let Some(path) = args.config_check_against_txn {
+        return run_coverage_check(&config.server_config.nft_marketplace_config, path);
+    }
+    if let Some(Command::Replay { path, write }) = args.command {
+        return run_replay(config.server_config, path, write);
+    }
+    config.server_config.version_override = VersionRangeOverride {
+        starting_version: args.starting_version,
+        ending_version: args.ending_version,
+        max_versions: args.max_versions,
+    };
+
     let num_cpus = num_cpus::get();
     let worker_threads = (num_cpus * RUNTIME_WORKER_MULTIPLIER).max(16);
 
@@ -22,9 +145,5 @@ fn main() -> Result<()> {
         .worker_threads(worker_threads)
         .build()
         .unwrap()
-        .block_on(async {
-            let args = ServerArgs::parse();
-            args.run::<IndexerProcessorConfig>(tokio::runtime::Handle::current())
-                .await
-        })
+        .block_on(async { run_server_with_config(config, tokio::runtime::Handle::current()).await })
 }
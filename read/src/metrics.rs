@@ -0,0 +1,109 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Prometheus metrics for the NFT aggregator.
+//!
+//! All metrics are registered in the default prometheus registry, which is what the
+//! processor SDK's server framework serves on `/metrics` (on `health_check_port`).
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, HistogramVec,
+    IntCounterVec, IntGaugeVec,
+};
+
+/// Reasons an event can be skipped by the remappers, used as the `reason` label.
+pub const SKIP_REASON_UNKNOWN_EVENT_TYPE: &str = "unknown_event_type";
+pub const SKIP_REASON_NO_EVENT_MAPPING: &str = "no_event_mapping";
+pub const SKIP_REASON_EXTRACTION_FAILED: &str = "extraction_failed";
+pub const SKIP_REASON_INVALID_FIELD: &str = "invalid_field";
+pub const SKIP_REASON_UNKNOWN_TABLE: &str = "unknown_table";
+pub const SKIP_REASON_INVALID_MODEL: &str = "invalid_model";
+pub const SKIP_REASON_DISALLOWED_CONTRACT: &str = "disallowed_contract";
+pub const SKIP_REASON_TRANSFORM_FAILED: &str = "transform_failed";
+pub const SKIP_REASON_DISABLED_EVENT_TYPE: &str = "disabled_event_type";
+pub const SKIP_REASON_INVALID_PRICE: &str = "invalid_price";
+pub const SKIP_REASON_INVALID_EVENT: &str = "invalid_event";
+pub const SKIP_REASON_UNKNOWN_DELETED_RESOURCE: &str = "unknown_deleted_resource";
+
+lazy_static! {
+    /// Number of events successfully remapped into marketplace models.
+    pub static ref NFT_EVENTS_REMAPPED: IntCounterVec = register_int_counter_vec!(
+        "nft_events_remapped_total",
+        "Number of events remapped into marketplace models",
+        &["marketplace", "event_type"]
+    )
+    .unwrap();
+
+    /// Number of events (or event fields) dropped during remapping, by reason.
+    pub static ref NFT_EVENTS_SKIPPED: IntCounterVec = register_int_counter_vec!(
+        "nft_events_skipped_total",
+        "Number of events or event fields skipped during remapping",
+        &["reason"]
+    )
+    .unwrap();
+
+    /// Number of resources remapped into partial model updates.
+    pub static ref NFT_RESOURCES_REMAPPED: IntCounterVec = register_int_counter_vec!(
+        "nft_resources_remapped_total",
+        "Number of write resources remapped into partial model updates",
+        &["resource_type"]
+    )
+    .unwrap();
+
+    /// Number of rows handed to the database per table.
+    pub static ref NFT_DB_ROWS_WRITTEN: IntCounterVec = register_int_counter_vec!(
+        "nft_db_rows_written_total",
+        "Number of deduplicated rows written to the database",
+        &["table"]
+    )
+    .unwrap();
+
+    /// Number of remapping errors that failed a batch, by `RemapError::reason`.
+    pub static ref NFT_REMAP_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "nft_remap_errors_total",
+        "Number of remapping errors that failed a batch",
+        &["reason"]
+    )
+    .unwrap();
+
+    /// Time spent remapping a single transaction, per remapper.
+    pub static ref NFT_REMAP_DURATION: HistogramVec = register_histogram_vec!(
+        "nft_remap_duration_seconds",
+        "Time spent remapping a single transaction",
+        &["remapper"]
+    )
+    .unwrap();
+
+    /// Number of contract addresses a marketplace's activities came from for the first time.
+    /// A new one usually means the marketplace was redeployed and its config needs updating.
+    pub static ref NFT_CONTRACTS_FIRST_SEEN: IntCounterVec = register_int_counter_vec!(
+        "nft_contracts_first_seen_total",
+        "Number of contract addresses seen for the first time per marketplace",
+        &["marketplace"]
+    )
+    .unwrap();
+
+    /// Number of batches queued for a stage of the pipeline or being processed by it, see
+    /// [`crate::steps::channel_depth_step::ChannelDepthStep`]. Stays near the channel size when
+    /// the stage is the bottleneck.
+    pub static ref NFT_CHANNEL_DEPTH: IntGaugeVec = register_int_gauge_vec!(
+        "nft_channel_depth",
+        "Number of batches queued for or processed by a stage of the pipeline",
+        &["step"]
+    )
+    .unwrap();
+}
+
+/// Forces registration of all metrics so they show up on `/metrics` before the first
+/// event is processed.
+pub fn init() {
+    lazy_static::initialize(&NFT_EVENTS_REMAPPED);
+    lazy_static::initialize(&NFT_EVENTS_SKIPPED);
+    lazy_static::initialize(&NFT_RESOURCES_REMAPPED);
+    lazy_static::initialize(&NFT_DB_ROWS_WRITTEN);
+    lazy_static::initialize(&NFT_REMAP_ERRORS);
+    lazy_static::initialize(&NFT_REMAP_DURATION);
+    lazy_static::initialize(&NFT_CONTRACTS_FIRST_SEEN);
+    lazy_static::initialize(&NFT_CHANNEL_DEPTH);
+}
@@ -1,12 +1,17 @@
 pub mod nft_models;
 
-use crate::config::marketplace_config::EventType;
+use crate::{
+    config::marketplace_config::EventType,
+    metrics::{NFT_EVENTS_SKIPPED, SKIP_REASON_INVALID_EVENT},
+    utils::convert::standardize_address,
+};
 use anyhow::{Context, Result};
-use aptos_indexer_processor_sdk::{
-    aptos_protos::transaction::v1::Event as EventPB, utils::convert::standardize_address,
+use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::{
+    transaction::TxnData, transaction_payload::Payload, Event as EventPB, Transaction,
 };
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct EventModel {
@@ -26,15 +31,19 @@ impl EventModel {
     /// data in a recoverable way we shouldn't ignore, e.g. the event data is not valid
     /// JSON. It can return None if the event data is something we purposely don't
     /// handle, for example if the event type is a primitive like `address`.
+    ///
+    /// The generic type parameters of the event type are dropped unless `keep_generics` is set,
+    /// see [`EventType::parse`].
     pub fn from_event(
         event: &EventPB,
         transaction_version: i64,
         transaction_block_height: i64,
         event_index: i64,
         block_timestamp: NaiveDateTime,
+        keep_generics: bool,
     ) -> Result<Option<Self>> {
         let t: &str = event.type_str.as_ref();
-        let event_type = match EventType::try_from(t) {
+        let event_type = match EventType::parse(t, keep_generics) {
             Ok(event_type) => event_type,
             Err(_) => {
                 // It is fine to skip these events without logging because we explicitly
@@ -46,7 +55,7 @@ impl EventModel {
         let event_key = event.key.as_ref().context("Event should have a key")?;
 
         Ok(Some(EventModel {
-            account_address: standardize_address(event_key.account_address.as_str()),
+            account_address: standardize_address(event_key.account_address.as_str())?,
             creation_number: event_key.creation_number as i64,
             sequence_number: event.sequence_number as i64,
             transaction_version,
@@ -61,13 +70,16 @@ impl EventModel {
         }))
     }
 
-    /// If we fail to parse an event, we log and skip it. So this function can't fail.
+    /// If we fail to parse an event, we log and skip it. So this function can't fail: one
+    /// malformed event, e.g. from a contract the marketplace doesn't even index, must not halt
+    /// the processor.
     pub fn from_events(
         events: &[EventPB],
         transaction_version: i64,
         transaction_block_height: i64,
         block_timestamp: NaiveDateTime,
-    ) -> Result<Vec<Self>> {
+        keep_generics: bool,
+    ) -> Vec<Self> {
         let mut result = Vec::new();
         for (index, event) in events.iter().enumerate() {
             match Self::from_event(
@@ -76,17 +88,99 @@ impl EventModel {
                 transaction_block_height,
                 index as i64,
                 block_timestamp,
+                keep_generics,
             ) {
                 Ok(Some(event_model)) => result.push(event_model),
                 Ok(None) => continue,
                 Err(e) => {
-                    return Err(e.context(format!(
-                        "Failed to parse event type {} at version {}",
-                        event.type_str, transaction_version
-                    )));
+                    warn!(
+                        txn_version = transaction_version,
+                        event_index = index,
+                        event_type = %event.type_str,
+                        "Skipping event that failed to parse: {e:#}"
+                    );
+                    NFT_EVENTS_SKIPPED
+                        .with_label_values(&[SKIP_REASON_INVALID_EVENT])
+                        .inc();
                 },
             }
         }
-        Ok(result)
+        result
+    }
+}
+
+/// Values of the transaction an event was emitted in, which event remappings can map with
+/// `$txn` paths, e.g. `$txn.sender` or `$txn.entry_function`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TxnMetadata {
+    /// The standardized address of the account that sent the transaction.
+    pub sender: Option<String>,
+    /// The entry function the transaction called, e.g. `0x1::marketplace::buy`.
+    pub entry_function: Option<String>,
+}
+
+impl TxnMetadata {
+    /// Both fields are `None` for transactions other than user transactions. The entry function
+    /// is also `None` for transactions that run a script or a multisig payload.
+    pub fn from_transaction(transaction: &Transaction) -> Self {
+        let Some(TxnData::User(user_txn)) = transaction.txn_data.as_ref() else {
+            return Self::default();
+        };
+        let Some(request) = user_txn.request.as_ref() else {
+            return Self::default();
+        };
+        let entry_function = match request
+            .payload
+            .as_ref()
+            .and_then(|payload| payload.payload.as_ref())
+        {
+            Some(Payload::EntryFunctionPayload(payload)) => {
+                Some(payload.entry_function_id_str.clone())
+            },
+            _ => None,
+        };
+        Self {
+            sender: standardize_address(&request.sender).ok(),
+            entry_function,
+        }
+    }
+
+    /// The JSON that `$txn` paths are resolved against.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::EventKey;
+
+    fn build_event(data: &str) -> EventPB {
+        EventPB {
+            key: Some(EventKey {
+                creation_number: 0,
+                account_address: "0x1".to_string(),
+            }),
+            sequence_number: 0,
+            type_str: "0x1::marketplace::ListingPlacedEvent".to_string(),
+            data: data.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_from_events_skips_malformed_event() {
+        let events = EventModel::from_events(
+            &[build_event("{not json"), build_event(r#"{"price":"100"}"#)],
+            5,
+            1,
+            NaiveDateTime::default(),
+            false,
+        );
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_index, 1);
+        assert_eq!(events[0].data["price"], "100");
     }
 }
@@ -1,19 +1,29 @@
 use crate::{
+    config::marketplace_config::MarketplaceEventType,
     models::EventModel,
     schema::{
+        collection_offer_fills, current_nft_marketplace_auctions,
         current_nft_marketplace_collection_offers, current_nft_marketplace_listings,
         current_nft_marketplace_token_offers, nft_marketplace_activities,
     },
+    utils::{
+        convert::standardize_address, marketplace_resource_utils::TokenStandard, MAX_TIMESTAMP_SECS,
+    },
 };
-use aptos_indexer_processor_sdk::aptos_indexer_transaction_stream::utils::time::parse_timestamp_secs;
-use chrono::NaiveDateTime;
+use anyhow::Context;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, NaiveDateTime};
 use diesel::prelude::*;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{num::IntErrorKind, str::FromStr};
 use strum::{Display, EnumString};
 
-pub const DEFAULT_SELLER: &str = "unknown";
+/// Buyer of offers whose event doesn't carry one, unless the marketplace config sets another.
 pub const DEFAULT_BUYER: &str = "unknown";
+/// Prices are assumed to be paid in APT unless the marketplace config maps a payment token.
+pub const DEFAULT_PAYMENT_TOKEN: &str = "0x1::aptos_coin::AptosCoin";
 
 pub const NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME: &str = "nft_marketplace_activities";
 pub const CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME: &str = "current_nft_marketplace_listings";
@@ -21,6 +31,8 @@ pub const CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME: &str =
     "current_nft_marketplace_token_offers";
 pub const CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME: &str =
     "current_nft_marketplace_collection_offers";
+pub const CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME: &str = "current_nft_marketplace_auctions";
+pub const COLLECTION_OFFER_FILLS_TABLE_NAME: &str = "collection_offer_fills";
 
 /**
  * NftMarketplaceActivity is the main model for storing NFT marketplace activities.
@@ -41,8 +53,8 @@ pub struct NftMarketplaceActivity {
     pub collection_name: Option<String>,
     pub token_data_id: Option<String>,
     pub token_name: Option<String>,
-    pub price: i64,
-    pub token_amount: Option<i64>,
+    pub price: BigDecimal,
+    pub token_amount: Option<BigDecimal>,
     pub buyer: Option<String>,
     pub seller: Option<String>,
     pub listing_id: Option<String>,
@@ -53,6 +65,77 @@ pub struct NftMarketplaceActivity {
     pub block_timestamp: NaiveDateTime,
     pub expiration_time: Option<NaiveDateTime>,
     pub bid_key: Option<i64>,
+    pub price_raw: Option<BigDecimal>,
+    pub price_display: Option<BigDecimal>,
+    pub payment_token: String,
+    pub token_standard: Option<String>,
+    /// Values mapped to columns that aren't a [`MarketplaceField`], as a JSON object keyed by
+    /// column. `None` when the config maps no custom column.
+    pub custom_fields: Option<serde_json::Value>,
+    /// Hex encoded SHA-256 of the other fields, see [`Self::compute_content_hash`].
+    pub content_hash: Option<String>,
+    /// Set by the database when the row is first written. `None` for rows that weren't read
+    /// back from it, which inserts the column's default.
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inserted_at: Option<NaiveDateTime>,
+    /// Set by the database whenever an upsert rewrites the row.
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<NaiveDateTime>,
+    /// Commission the marketplace took from the price, in the same units.
+    pub marketplace_fee: Option<BigDecimal>,
+    /// Royalties paid to the creator out of the price, in the same units.
+    pub royalty_fee: Option<BigDecimal>,
+    /// The buyer and the seller are the same address. Such trades are kept like any other so
+    /// that wash trading can be looked for.
+    pub is_self_trade: bool,
+    /// Whether the price goes to the seller, back to the buyer or into escrow, derived from the
+    /// standard event type. `None` for events that move no funds, e.g. listings.
+    pub direction: Option<String>,
+}
+
+impl NftMarketplaceActivity {
+    /// Hashes every field but `content_hash` itself. Reprocessing a version only rewrites its
+    /// activities when the hash changed, e.g. because the remapping config did.
+    pub fn compute_content_hash(&self) -> String {
+        let activity = Self {
+            content_hash: None,
+            inserted_at: None,
+            updated_at: None,
+            ..self.clone()
+        };
+        let bytes = serde_json::to_vec(&activity).unwrap_or_default();
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    fn set_custom_field(&mut self, column: String, value: serde_json::Value) {
+        let custom_fields = self
+            .custom_fields
+            .get_or_insert_with(|| serde_json::Value::Object(Default::default()));
+        if let Some(custom_fields) = custom_fields.as_object_mut() {
+            custom_fields.insert(column, value);
+        }
+    }
+
+    /// Whether the event named a buyer and a seller, and they're the same address.
+    pub fn buyer_is_seller(&self) -> bool {
+        match (&self.buyer, &self.seller) {
+            (Some(buyer), Some(seller)) => {
+                buyer == seller
+                    || standardize_address(buyer)
+                        .is_ok_and(|buyer| standardize_address(seller).ok() == Some(buyer))
+            },
+            _ => false,
+        }
+    }
+
+    /// Stores `values` as a JSON array in the custom `column`, e.g. every royalty recipient of
+    /// an event.
+    pub fn set_custom_array(&mut self, column: String, values: Vec<String>) {
+        let values = values.into_iter().map(serde_json::Value::String).collect();
+        self.set_custom_field(column, serde_json::Value::Array(values));
+    }
 }
 
 impl MarketplaceModel for NftMarketplaceActivity {
@@ -68,18 +151,17 @@ impl MarketplaceModel for NftMarketplaceActivity {
             MarketplaceField::TokenName => self.token_name = Some(value),
             MarketplaceField::CreatorAddress => self.creator_address = Some(value),
             MarketplaceField::CollectionName => self.collection_name = Some(value),
-            MarketplaceField::Price => self.price = value.parse().unwrap_or(0),
+            MarketplaceField::Price => {
+                if let Ok(price) = value.parse::<Price>() {
+                    self.price = price.into();
+                }
+            },
             MarketplaceField::TokenAmount => self.token_amount = value.parse().ok(),
+            MarketplaceField::MarketplaceFee => self.marketplace_fee = value.parse().ok(),
+            MarketplaceField::RoyaltyFee => self.royalty_fee = value.parse().ok(),
             MarketplaceField::Buyer => self.buyer = Some(value),
             MarketplaceField::Seller => self.seller = Some(value),
-            MarketplaceField::ExpirationTime => {
-                if let Ok(timestamp_secs) = value.parse::<u64>() {
-                    self.expiration_time =
-                        Some(parse_timestamp_secs(timestamp_secs, 0).naive_utc());
-                } else {
-                    self.expiration_time = None;
-                }
-            },
+            MarketplaceField::ExpirationTime => self.expiration_time = parse_unix_timestamp(&value),
             MarketplaceField::ListingId => self.listing_id = Some(value),
             MarketplaceField::OfferId | MarketplaceField::CollectionOfferId => {
                 self.offer_id = Some(value)
@@ -90,6 +172,15 @@ impl MarketplaceModel for NftMarketplaceActivity {
                 self.block_timestamp = value.parse().unwrap_or(NaiveDateTime::default())
             },
             MarketplaceField::BidKey => self.bid_key = value.parse().ok(),
+            MarketplaceField::PaymentToken => self.payment_token = value,
+            MarketplaceField::TokenStandard => self.token_standard = parse_token_standard(&value),
+            // Activities have no column for it, so it's kept with the custom fields
+            MarketplaceField::PropertyVersion => {
+                self.set_custom_field(field.to_string(), serde_json::Value::String(value))
+            },
+            MarketplaceField::Custom(column) => {
+                self.set_custom_field(column, serde_json::Value::String(value))
+            },
             _ => tracing::debug!("Unknown field: {:?}", field),
         }
     }
@@ -97,7 +188,7 @@ impl MarketplaceModel for NftMarketplaceActivity {
     // This is a function that is used to check if we have all the necessary fields to insert the model into the database.
     // Activity table uses txn_version, index, and marketplace as the primary key, so it's rare that we need to check if it's valid.
     // So we use this function to check if has the contract_address and marketplace. to make sure we can easily filter out marketplaces that don't exist.
-    // TODO: if we want to be more strict, we can have a whitelist of marketplaces that are allowed to be inserted into the database.
+    // Activities from contracts outside a marketplace's `allowed_contracts` are dropped earlier, in the event remapper.
     fn is_valid(&self) -> bool {
         !self.marketplace.is_empty() && !self.contract_address.is_empty()
     }
@@ -106,6 +197,10 @@ impl MarketplaceModel for NftMarketplaceActivity {
         NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME
     }
 
+    fn primary_key(&self) -> String {
+        format!("{}::{}::{}", self.txn_version, self.index, self.marketplace)
+    }
+
     fn updated_at(&self) -> i64 {
         self.block_timestamp.and_utc().timestamp()
     }
@@ -123,8 +218,12 @@ impl MarketplaceModel for NftMarketplaceActivity {
             },
             MarketplaceField::Price => Some(self.price.to_string()),
             MarketplaceField::TokenAmount => {
-                Some(self.token_amount.unwrap_or_default().to_string())
+                Some(self.token_amount.clone().unwrap_or_default().to_string())
+            },
+            MarketplaceField::MarketplaceFee => {
+                self.marketplace_fee.as_ref().map(|fee| fee.to_string())
             },
+            MarketplaceField::RoyaltyFee => self.royalty_fee.as_ref().map(|fee| fee.to_string()),
             MarketplaceField::Buyer => Some(self.buyer.clone().unwrap_or_default()),
             MarketplaceField::Seller => Some(self.seller.clone().unwrap_or_default()),
             MarketplaceField::ExpirationTime => self
@@ -136,6 +235,20 @@ impl MarketplaceModel for NftMarketplaceActivity {
             MarketplaceField::ContractAddress => Some(self.contract_address.clone()),
             MarketplaceField::BlockTimestamp => Some(self.block_timestamp.to_string()),
             MarketplaceField::BidKey => self.bid_key.map(|val| val.to_string()),
+            MarketplaceField::PaymentToken => Some(self.payment_token.clone()),
+            MarketplaceField::TokenStandard => self.token_standard.clone(),
+            MarketplaceField::PropertyVersion => self
+                .custom_fields
+                .as_ref()?
+                .get(&field.to_string())?
+                .as_str()
+                .map(String::from),
+            MarketplaceField::Custom(column) => self
+                .custom_fields
+                .as_ref()?
+                .get(&column)?
+                .as_str()
+                .map(String::from),
             _ => None,
         }
     }
@@ -159,8 +272,8 @@ pub struct CurrentNFTMarketplaceListing {
     pub listing_id: Option<String>,
     pub collection_id: Option<String>,
     pub seller: Option<String>,
-    pub price: i64,
-    pub token_amount: Option<i64>,
+    pub price: BigDecimal,
+    pub token_amount: Option<BigDecimal>,
     pub token_name: Option<String>,
     pub is_deleted: bool,
     pub marketplace: String,
@@ -168,6 +281,27 @@ pub struct CurrentNFTMarketplaceListing {
     pub last_transaction_version: i64,
     pub last_transaction_timestamp: NaiveDateTime,
     pub standard_event_type: String,
+    pub price_raw: Option<BigDecimal>,
+    pub price_display: Option<BigDecimal>,
+    pub payment_token: String,
+    pub creator_address: Option<String>,
+    pub collection_name: Option<String>,
+    pub token_standard: Option<String>,
+    /// Tokens taken by a fill of a semi-fungible listing, see
+    /// [`CurrentNFTMarketplaceListing::apply_partial_fill`].
+    pub filled_token_amount: Option<BigDecimal>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inserted_at: Option<NaiveDateTime>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<NaiveDateTime>,
+    /// Current owner of the token's object, when the config tracks listing owners. Only ever
+    /// set from `0x1::object::ObjectCore` writes, see [`crate::postgres::token_owners`].
+    pub owner: Option<String>,
+    /// The token moved to someone other than the seller after the listing's last event, so the
+    /// listing can't be filled anymore. Cleared by the listing's next event.
+    pub is_stale: bool,
 }
 
 impl MarketplaceModel for CurrentNFTMarketplaceListing {
@@ -177,7 +311,11 @@ impl MarketplaceModel for CurrentNFTMarketplaceListing {
             MarketplaceField::ListingId => self.listing_id = Some(value),
             MarketplaceField::CollectionId => self.collection_id = Some(value),
             MarketplaceField::Seller => self.seller = Some(value),
-            MarketplaceField::Price => self.price = value.parse().unwrap_or(0),
+            MarketplaceField::Price => {
+                if let Ok(price) = value.parse::<Price>() {
+                    self.price = price.into();
+                }
+            },
             MarketplaceField::TokenAmount => self.token_amount = value.parse().ok(),
             MarketplaceField::TokenName => self.token_name = Some(value),
             MarketplaceField::Marketplace => self.marketplace = value,
@@ -188,6 +326,11 @@ impl MarketplaceModel for CurrentNFTMarketplaceListing {
             MarketplaceField::LastTransactionTimestamp => {
                 self.last_transaction_timestamp = value.parse().unwrap_or(NaiveDateTime::default())
             },
+            MarketplaceField::PaymentToken => self.payment_token = value,
+            MarketplaceField::TokenStandard => self.token_standard = parse_token_standard(&value),
+            MarketplaceField::CreatorAddress => self.creator_address = Some(value),
+            MarketplaceField::CollectionName => self.collection_name = Some(value),
+            MarketplaceField::FilledTokenAmount => self.filled_token_amount = value.parse().ok(),
             _ => tracing::debug!("Unknown field: {:?}", field),
         }
     }
@@ -200,6 +343,10 @@ impl MarketplaceModel for CurrentNFTMarketplaceListing {
         CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME
     }
 
+    fn primary_key(&self) -> String {
+        format!("{}::{}", self.token_data_id, self.marketplace)
+    }
+
     fn updated_at(&self) -> i64 {
         self.last_transaction_timestamp.and_utc().timestamp()
     }
@@ -212,7 +359,7 @@ impl MarketplaceModel for CurrentNFTMarketplaceListing {
             MarketplaceField::Seller => Some(self.seller.clone().unwrap_or_default()),
             MarketplaceField::Price => Some(self.price.to_string()),
             MarketplaceField::TokenAmount => {
-                Some(self.token_amount.unwrap_or_default().to_string())
+                Some(self.token_amount.clone().unwrap_or_default().to_string())
             },
             MarketplaceField::TokenName => Some(self.token_name.clone().unwrap_or_default()),
             MarketplaceField::Marketplace => Some(self.marketplace.clone()),
@@ -223,6 +370,18 @@ impl MarketplaceModel for CurrentNFTMarketplaceListing {
             MarketplaceField::LastTransactionTimestamp => {
                 Some(self.last_transaction_timestamp.to_string())
             },
+            MarketplaceField::PaymentToken => Some(self.payment_token.clone()),
+            MarketplaceField::TokenStandard => self.token_standard.clone(),
+            MarketplaceField::CreatorAddress => {
+                Some(self.creator_address.clone().unwrap_or_default())
+            },
+            MarketplaceField::CollectionName => {
+                Some(self.collection_name.clone().unwrap_or_default())
+            },
+            MarketplaceField::FilledTokenAmount => self
+                .filled_token_amount
+                .as_ref()
+                .map(|amount| amount.to_string()),
             _ => None,
         }
     }
@@ -237,6 +396,46 @@ impl MarketplaceModel for CurrentNFTMarketplaceListing {
 }
 
 impl CurrentNFTMarketplaceListing {
+    /// Rebuilds the listing state left behind by a listing activity. Returns `None` for
+    /// activities that aren't about a listing or lack a token data id.
+    pub fn from_activity(activity: &NftMarketplaceActivity) -> Option<Self> {
+        let is_deleted = match activity
+            .standard_event_type
+            .parse::<MarketplaceEventType>()
+            .ok()?
+        {
+            MarketplaceEventType::PlaceListing | MarketplaceEventType::UpdateListing => false,
+            MarketplaceEventType::CancelListing | MarketplaceEventType::FillListing => true,
+            _ => return None,
+        };
+        Some(Self {
+            token_data_id: activity.token_data_id.clone()?,
+            listing_id: activity.listing_id.clone(),
+            collection_id: activity.collection_id.clone(),
+            seller: activity.seller.clone(),
+            price: activity.price.clone(),
+            token_amount: activity.token_amount.clone(),
+            token_name: activity.token_name.clone(),
+            is_deleted,
+            marketplace: activity.marketplace.clone(),
+            contract_address: activity.contract_address.clone(),
+            last_transaction_version: activity.txn_version,
+            last_transaction_timestamp: activity.block_timestamp,
+            standard_event_type: activity.standard_event_type.clone(),
+            price_raw: activity.price_raw.clone(),
+            price_display: activity.price_display.clone(),
+            payment_token: activity.payment_token.clone(),
+            creator_address: activity.creator_address.clone(),
+            collection_name: activity.collection_name.clone(),
+            token_standard: activity.token_standard.clone(),
+            filled_token_amount: None,
+            inserted_at: None,
+            updated_at: None,
+            owner: None,
+            is_stale: false,
+        })
+    }
+
     pub fn build_default(
         marketplace_name: String,
         event: &EventModel,
@@ -248,7 +447,7 @@ impl CurrentNFTMarketplaceListing {
             listing_id: None,
             collection_id: None,
             seller: None,
-            price: 0,
+            price: BigDecimal::default(),
             token_amount: None,
             token_name: None,
             is_deleted: is_filled_or_cancelled,
@@ -257,6 +456,43 @@ impl CurrentNFTMarketplaceListing {
             last_transaction_version: event.transaction_version,
             last_transaction_timestamp: event.block_timestamp,
             standard_event_type: event_type,
+            price_raw: None,
+            price_display: None,
+            payment_token: DEFAULT_PAYMENT_TOKEN.to_string(),
+            creator_address: None,
+            collection_name: None,
+            token_standard: None,
+            filled_token_amount: None,
+            inserted_at: None,
+            updated_at: None,
+            owner: None,
+            is_stale: false,
+        }
+    }
+
+    /// A fill that reports how many tokens it took only closes the listing once none remain.
+    /// `previous` is the listing before the fill: the tokens it had left are reduced by the
+    /// filled amount. Without a known amount the filled tokens add up over consecutive fills
+    /// until the upsert subtracts them from the stored listing.
+    pub fn apply_partial_fill(&mut self, previous: &Self) {
+        if self.standard_event_type != MarketplaceEventType::FillListing.to_string()
+            || self.token_amount.is_some()
+        {
+            return;
+        }
+        let Some(filled) = &self.filled_token_amount else {
+            return;
+        };
+        match (&previous.token_amount, &previous.filled_token_amount) {
+            (Some(listed), _) => {
+                let remaining = (listed - filled).max(BigDecimal::zero());
+                self.is_deleted = remaining.is_zero();
+                self.token_amount = Some(remaining);
+            },
+            (None, Some(previously_filled)) => {
+                self.filled_token_amount = Some(previously_filled + filled);
+            },
+            (None, None) => {},
         }
     }
 }
@@ -264,7 +500,7 @@ impl CurrentNFTMarketplaceListing {
 #[derive(
     Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
 )]
-#[diesel(primary_key(token_data_id, buyer, marketplace))]
+#[diesel(primary_key(token_data_id, buyer, marketplace, offer_key))]
 #[diesel(table_name = current_nft_marketplace_token_offers)]
 pub struct CurrentNFTMarketplaceTokenOffer {
     pub token_data_id: String,
@@ -272,8 +508,8 @@ pub struct CurrentNFTMarketplaceTokenOffer {
     pub marketplace: String,
     pub collection_id: Option<String>,
     pub buyer: String,
-    pub price: i64,
-    pub token_amount: Option<i64>,
+    pub price: BigDecimal,
+    pub token_amount: Option<BigDecimal>,
     pub token_name: Option<String>,
     pub is_deleted: bool,
     pub contract_address: String,
@@ -282,6 +518,26 @@ pub struct CurrentNFTMarketplaceTokenOffer {
     pub standard_event_type: String,
     pub expiration_time: Option<NaiveDateTime>,
     pub bid_key: Option<i64>,
+    pub price_raw: Option<BigDecimal>,
+    pub price_display: Option<BigDecimal>,
+    pub payment_token: String,
+    pub creator_address: Option<String>,
+    pub collection_name: Option<String>,
+    pub token_standard: Option<String>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inserted_at: Option<NaiveDateTime>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<NaiveDateTime>,
+    /// The `offer_id` or `bid_key` when the marketplace keys its token offers by one of them,
+    /// see [`TokenOfferKey`](crate::config::marketplace_config::TokenOfferKey). Otherwise the
+    /// non-zero property version of a v1 token, and empty for the rest, so a buyer has one offer
+    /// per token.
+    pub offer_key: String,
+    /// Property version of a v1 token, which tells the tokens of a semi-fungible token data
+    /// apart. Unset for v2 tokens.
+    pub property_version: Option<BigDecimal>,
 }
 
 impl MarketplaceModel for CurrentNFTMarketplaceTokenOffer {
@@ -292,7 +548,11 @@ impl MarketplaceModel for CurrentNFTMarketplaceTokenOffer {
             MarketplaceField::Marketplace => self.marketplace = value,
             MarketplaceField::CollectionId => self.collection_id = Some(value),
             MarketplaceField::Buyer => self.buyer = value,
-            MarketplaceField::Price => self.price = value.parse().unwrap_or(0),
+            MarketplaceField::Price => {
+                if let Ok(price) = value.parse::<Price>() {
+                    self.price = price.into();
+                }
+            },
             MarketplaceField::TokenAmount => self.token_amount = value.parse().ok(),
             MarketplaceField::TokenName => self.token_name = Some(value),
             MarketplaceField::ContractAddress => self.contract_address = value,
@@ -302,15 +562,13 @@ impl MarketplaceModel for CurrentNFTMarketplaceTokenOffer {
             MarketplaceField::LastTransactionTimestamp => {
                 self.last_transaction_timestamp = value.parse().unwrap_or(NaiveDateTime::default())
             },
-            MarketplaceField::ExpirationTime => {
-                if let Ok(timestamp_secs) = value.parse::<u64>() {
-                    self.expiration_time =
-                        Some(parse_timestamp_secs(timestamp_secs, 0).naive_utc());
-                } else {
-                    self.expiration_time = None;
-                }
-            },
+            MarketplaceField::ExpirationTime => self.expiration_time = parse_unix_timestamp(&value),
             MarketplaceField::BidKey => self.bid_key = value.parse().ok(),
+            MarketplaceField::PaymentToken => self.payment_token = value,
+            MarketplaceField::TokenStandard => self.token_standard = parse_token_standard(&value),
+            MarketplaceField::CreatorAddress => self.creator_address = Some(value),
+            MarketplaceField::CollectionName => self.collection_name = Some(value),
+            MarketplaceField::PropertyVersion => self.property_version = value.parse().ok(),
             _ => tracing::debug!("Unknown field: {:?}", field),
         }
     }
@@ -323,6 +581,13 @@ impl MarketplaceModel for CurrentNFTMarketplaceTokenOffer {
         CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME
     }
 
+    fn primary_key(&self) -> String {
+        format!(
+            "{}::{}::{}::{}",
+            self.token_data_id, self.buyer, self.marketplace, self.offer_key
+        )
+    }
+
     fn updated_at(&self) -> i64 {
         self.last_transaction_timestamp.and_utc().timestamp()
     }
@@ -336,7 +601,7 @@ impl MarketplaceModel for CurrentNFTMarketplaceTokenOffer {
             MarketplaceField::Buyer => Some(self.buyer.clone()),
             MarketplaceField::Price => Some(self.price.to_string()),
             MarketplaceField::TokenAmount => {
-                Some(self.token_amount.unwrap_or_default().to_string())
+                Some(self.token_amount.clone().unwrap_or_default().to_string())
             },
             MarketplaceField::TokenName => Some(self.token_name.clone().unwrap_or_default()),
             MarketplaceField::ContractAddress => Some(self.contract_address.clone()),
@@ -347,6 +612,17 @@ impl MarketplaceModel for CurrentNFTMarketplaceTokenOffer {
                 Some(self.last_transaction_timestamp.to_string())
             },
             MarketplaceField::BidKey => self.bid_key.map(|val| val.to_string()),
+            MarketplaceField::PaymentToken => Some(self.payment_token.clone()),
+            MarketplaceField::TokenStandard => self.token_standard.clone(),
+            MarketplaceField::CreatorAddress => {
+                Some(self.creator_address.clone().unwrap_or_default())
+            },
+            MarketplaceField::CollectionName => {
+                Some(self.collection_name.clone().unwrap_or_default())
+            },
+            MarketplaceField::PropertyVersion => {
+                self.property_version.as_ref().map(|val| val.to_string())
+            },
             _ => None,
         }
     }
@@ -373,7 +649,7 @@ impl CurrentNFTMarketplaceTokenOffer {
             marketplace: marketplace_name,
             collection_id: None,
             buyer: String::new(),
-            price: 0,
+            price: BigDecimal::default(),
             token_amount: None,
             token_name: None,
             is_deleted: is_filled_or_cancelled,
@@ -383,6 +659,16 @@ impl CurrentNFTMarketplaceTokenOffer {
             standard_event_type: event_type,
             expiration_time: None,
             bid_key: None,
+            price_raw: None,
+            price_display: None,
+            payment_token: DEFAULT_PAYMENT_TOKEN.to_string(),
+            creator_address: None,
+            collection_name: None,
+            token_standard: None,
+            inserted_at: None,
+            updated_at: None,
+            offer_key: String::new(),
+            property_version: None,
         }
     }
 }
@@ -396,8 +682,8 @@ pub struct CurrentNFTMarketplaceCollectionOffer {
     pub collection_offer_id: String,
     pub collection_id: Option<String>,
     pub buyer: String,
-    pub price: i64,
-    pub remaining_token_amount: Option<i64>,
+    pub price: BigDecimal,
+    pub remaining_token_amount: Option<BigDecimal>,
     pub is_deleted: bool,
     pub marketplace: String,
     pub contract_address: String,
@@ -407,6 +693,18 @@ pub struct CurrentNFTMarketplaceCollectionOffer {
     pub token_data_id: Option<String>,
     pub expiration_time: Option<NaiveDateTime>,
     pub bid_key: Option<i64>,
+    pub price_raw: Option<BigDecimal>,
+    pub price_display: Option<BigDecimal>,
+    pub payment_token: String,
+    pub creator_address: Option<String>,
+    pub collection_name: Option<String>,
+    pub token_standard: Option<String>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inserted_at: Option<NaiveDateTime>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<NaiveDateTime>,
 }
 
 impl MarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
@@ -415,7 +713,11 @@ impl MarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
             MarketplaceField::CollectionOfferId => self.collection_offer_id = value,
             MarketplaceField::CollectionId => self.collection_id = Some(value),
             MarketplaceField::Buyer => self.buyer = value,
-            MarketplaceField::Price => self.price = value.parse().unwrap_or(0),
+            MarketplaceField::Price => {
+                if let Ok(price) = value.parse::<Price>() {
+                    self.price = price.into();
+                }
+            },
             MarketplaceField::RemainingTokenAmount => {
                 self.remaining_token_amount = value.parse().ok()
             },
@@ -428,15 +730,12 @@ impl MarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
                 self.last_transaction_timestamp = value.parse().unwrap_or(NaiveDateTime::default())
             },
             MarketplaceField::TokenDataId => self.token_data_id = Some(value),
-            MarketplaceField::ExpirationTime => {
-                if let Ok(timestamp_secs) = value.parse::<u64>() {
-                    self.expiration_time =
-                        Some(parse_timestamp_secs(timestamp_secs, 0).naive_utc());
-                } else {
-                    self.expiration_time = None;
-                }
-            },
+            MarketplaceField::ExpirationTime => self.expiration_time = parse_unix_timestamp(&value),
             MarketplaceField::BidKey => self.bid_key = value.parse().ok(),
+            MarketplaceField::PaymentToken => self.payment_token = value,
+            MarketplaceField::TokenStandard => self.token_standard = parse_token_standard(&value),
+            MarketplaceField::CreatorAddress => self.creator_address = Some(value),
+            MarketplaceField::CollectionName => self.collection_name = Some(value),
             _ => tracing::debug!("Unknown field: {:?}", field),
         }
     }
@@ -449,6 +748,10 @@ impl MarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
         CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME
     }
 
+    fn primary_key(&self) -> String {
+        format!("{}::{}", self.collection_offer_id, self.marketplace)
+    }
+
     fn updated_at(&self) -> i64 {
         self.last_transaction_timestamp.and_utc().timestamp()
     }
@@ -459,9 +762,12 @@ impl MarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
             MarketplaceField::CollectionId => Some(self.collection_id.clone().unwrap_or_default()),
             MarketplaceField::Buyer => Some(self.buyer.clone()),
             MarketplaceField::Price => Some(self.price.to_string()),
-            MarketplaceField::RemainingTokenAmount => {
-                Some(self.remaining_token_amount.unwrap_or_default().to_string())
-            },
+            MarketplaceField::RemainingTokenAmount => Some(
+                self.remaining_token_amount
+                    .clone()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
             MarketplaceField::Marketplace => Some(self.marketplace.clone()),
             MarketplaceField::ContractAddress => Some(self.contract_address.clone()),
             MarketplaceField::LastTransactionVersion => {
@@ -472,6 +778,14 @@ impl MarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
             },
             MarketplaceField::TokenDataId => Some(self.token_data_id.clone().unwrap_or_default()),
             MarketplaceField::BidKey => self.bid_key.map(|val| val.to_string()),
+            MarketplaceField::PaymentToken => Some(self.payment_token.clone()),
+            MarketplaceField::TokenStandard => self.token_standard.clone(),
+            MarketplaceField::CreatorAddress => {
+                Some(self.creator_address.clone().unwrap_or_default())
+            },
+            MarketplaceField::CollectionName => {
+                Some(self.collection_name.clone().unwrap_or_default())
+            },
             _ => None,
         }
     }
@@ -486,6 +800,17 @@ impl MarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
 }
 
 impl CurrentNFTMarketplaceCollectionOffer {
+    /// A fill only closes the offer once no tokens remain. Fills start out with nothing
+    /// remaining, so this only keeps the offer open when the event reported a remaining amount.
+    pub fn apply_remaining_token_amount(&mut self) {
+        if self.standard_event_type == MarketplaceEventType::FillCollectionOffer.to_string() {
+            self.is_deleted = match &self.remaining_token_amount {
+                Some(amount) => amount.is_zero(),
+                None => true,
+            };
+        }
+    }
+
     pub fn build_default(
         marketplace_name: String,
         event: &EventModel,
@@ -496,9 +821,9 @@ impl CurrentNFTMarketplaceCollectionOffer {
             collection_offer_id: String::new(),
             collection_id: None,
             buyer: String::new(),
-            price: 0,
+            price: BigDecimal::default(),
             remaining_token_amount: if is_filled_or_cancelled {
-                Some(0)
+                Some(BigDecimal::default())
             } else {
                 None
             },
@@ -511,10 +836,289 @@ impl CurrentNFTMarketplaceCollectionOffer {
             standard_event_type: event_type,
             expiration_time: None,
             bid_key: None,
+            price_raw: None,
+            price_display: None,
+            payment_token: DEFAULT_PAYMENT_TOKEN.to_string(),
+            creator_address: None,
+            collection_name: None,
+            token_standard: None,
+            inserted_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+/**
+ * CollectionOfferFill records each token sold against a collection offer. Unlike the current
+ * tables, which only keep the offer's latest state, rows are never replaced by later fills.
+*/
+#[derive(
+    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+)]
+#[diesel(primary_key(txn_version, index, marketplace))]
+#[diesel(table_name = collection_offer_fills)]
+pub struct CollectionOfferFill {
+    pub txn_version: i64,
+    pub index: i64,
+    pub marketplace: String,
+    pub collection_offer_id: String,
+    pub token_data_id: String,
+    pub buyer: Option<String>,
+    pub seller: Option<String>,
+    pub price: BigDecimal,
+    pub block_timestamp: NaiveDateTime,
+}
+
+impl CollectionOfferFill {
+    /// Returns the fill recorded by a collection offer fill activity. Returns `None` for other
+    /// activities and for fills that lack the offer id or the token accepted.
+    pub fn from_activity(activity: &NftMarketplaceActivity) -> Option<Self> {
+        if activity.standard_event_type != MarketplaceEventType::FillCollectionOffer.to_string() {
+            return None;
+        }
+        Some(Self {
+            txn_version: activity.txn_version,
+            index: activity.index,
+            marketplace: activity.marketplace.clone(),
+            collection_offer_id: activity.offer_id.clone()?,
+            token_data_id: activity.token_data_id.clone()?,
+            buyer: activity.buyer.clone(),
+            seller: activity.seller.clone(),
+            price: activity.price.clone(),
+            block_timestamp: activity.block_timestamp,
+        })
+    }
+}
+
+/**
+ * CurrentNFTMarketplaceAuction tracks the latest state of a (possibly declining-price) auction.
+*/
+#[derive(
+    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+)]
+#[diesel(primary_key(token_data_id, marketplace))]
+#[diesel(table_name = current_nft_marketplace_auctions)]
+pub struct CurrentNFTMarketplaceAuction {
+    pub token_data_id: String,
+    pub marketplace: String,
+    pub auction_id: Option<String>,
+    pub collection_id: Option<String>,
+    pub token_name: Option<String>,
+    pub seller: Option<String>,
+    pub current_bidder: Option<String>,
+    pub starting_price: Option<BigDecimal>,
+    pub ending_price: Option<BigDecimal>,
+    pub current_bid: Option<BigDecimal>,
+    pub start_time: Option<NaiveDateTime>,
+    pub end_time: Option<NaiveDateTime>,
+    pub is_deleted: bool,
+    pub contract_address: String,
+    pub last_transaction_version: i64,
+    pub last_transaction_timestamp: NaiveDateTime,
+    pub standard_event_type: String,
+    pub payment_token: String,
+    pub token_standard: Option<String>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inserted_at: Option<NaiveDateTime>,
+    #[diesel(deserialize_as = NaiveDateTime)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl MarketplaceModel for CurrentNFTMarketplaceAuction {
+    fn set_field(&mut self, field: MarketplaceField, value: String) {
+        match field {
+            MarketplaceField::TokenDataId => self.token_data_id = value,
+            MarketplaceField::AuctionId => self.auction_id = Some(value),
+            MarketplaceField::CollectionId => self.collection_id = Some(value),
+            MarketplaceField::TokenName => self.token_name = Some(value),
+            MarketplaceField::Seller => self.seller = Some(value),
+            MarketplaceField::Buyer => self.current_bidder = Some(value),
+            MarketplaceField::StartingPrice => {
+                self.starting_price = value.parse::<Price>().ok().map(BigDecimal::from)
+            },
+            MarketplaceField::EndingPrice => {
+                self.ending_price = value.parse::<Price>().ok().map(BigDecimal::from)
+            },
+            MarketplaceField::CurrentBid => {
+                self.current_bid = value.parse::<Price>().ok().map(BigDecimal::from)
+            },
+            MarketplaceField::StartTime => self.start_time = parse_unix_timestamp(&value),
+            MarketplaceField::EndTime => self.end_time = parse_unix_timestamp(&value),
+            MarketplaceField::Marketplace => self.marketplace = value,
+            MarketplaceField::ContractAddress => self.contract_address = value,
+            MarketplaceField::LastTransactionVersion => {
+                self.last_transaction_version = value.parse().unwrap_or(0)
+            },
+            MarketplaceField::LastTransactionTimestamp => {
+                self.last_transaction_timestamp = value.parse().unwrap_or(NaiveDateTime::default())
+            },
+            MarketplaceField::PaymentToken => self.payment_token = value,
+            MarketplaceField::TokenStandard => self.token_standard = parse_token_standard(&value),
+            _ => tracing::debug!("Unknown field: {:?}", field),
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        !self.token_data_id.is_empty()
+    }
+
+    fn table_name(&self) -> &'static str {
+        CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME
+    }
+
+    fn primary_key(&self) -> String {
+        format!("{}::{}", self.token_data_id, self.marketplace)
+    }
+
+    fn updated_at(&self) -> i64 {
+        self.last_transaction_timestamp.and_utc().timestamp()
+    }
+
+    fn get_field(&self, field: MarketplaceField) -> Option<String> {
+        match field {
+            MarketplaceField::TokenDataId => Some(self.token_data_id.clone()),
+            MarketplaceField::AuctionId => Some(self.auction_id.clone().unwrap_or_default()),
+            MarketplaceField::CollectionId => Some(self.collection_id.clone().unwrap_or_default()),
+            MarketplaceField::TokenName => Some(self.token_name.clone().unwrap_or_default()),
+            MarketplaceField::Seller => Some(self.seller.clone().unwrap_or_default()),
+            MarketplaceField::Buyer => Some(self.current_bidder.clone().unwrap_or_default()),
+            MarketplaceField::StartingPrice => self.starting_price.as_ref().map(|p| p.to_string()),
+            MarketplaceField::EndingPrice => self.ending_price.as_ref().map(|p| p.to_string()),
+            MarketplaceField::CurrentBid => self.current_bid.as_ref().map(|p| p.to_string()),
+            MarketplaceField::StartTime => self
+                .start_time
+                .map(|ts| ts.and_utc().timestamp().to_string()),
+            MarketplaceField::EndTime => {
+                self.end_time.map(|ts| ts.and_utc().timestamp().to_string())
+            },
+            MarketplaceField::Marketplace => Some(self.marketplace.clone()),
+            MarketplaceField::ContractAddress => Some(self.contract_address.clone()),
+            MarketplaceField::LastTransactionVersion => {
+                Some(self.last_transaction_version.to_string())
+            },
+            MarketplaceField::LastTransactionTimestamp => {
+                Some(self.last_transaction_timestamp.to_string())
+            },
+            MarketplaceField::PaymentToken => Some(self.payment_token.clone()),
+            MarketplaceField::TokenStandard => self.token_standard.clone(),
+            _ => None,
+        }
+    }
+
+    fn get_txn_version(&self) -> i64 {
+        self.last_transaction_version
+    }
+
+    fn get_standard_event_type(&self) -> &str {
+        &self.standard_event_type
+    }
+}
+
+impl CurrentNFTMarketplaceAuction {
+    pub fn build_default(
+        marketplace_name: String,
+        event: &EventModel,
+        is_settled: bool,
+        event_type: String,
+    ) -> Self {
+        Self {
+            token_data_id: String::new(),
+            marketplace: marketplace_name,
+            auction_id: None,
+            collection_id: None,
+            token_name: None,
+            seller: None,
+            current_bidder: None,
+            starting_price: None,
+            ending_price: None,
+            current_bid: None,
+            start_time: None,
+            end_time: None,
+            is_deleted: is_settled,
+            contract_address: event.account_address.clone(),
+            last_transaction_version: event.transaction_version,
+            last_transaction_timestamp: event.block_timestamp,
+            standard_event_type: event_type,
+            payment_token: DEFAULT_PAYMENT_TOKEN.to_string(),
+            token_standard: None,
+            inserted_at: None,
+            updated_at: None,
         }
     }
 }
 
+/// A price mapped from an event or resource. Malformed prices fail to parse instead of becoming
+/// `0`, which would read as a free sale, and negative ones are rejected too.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Price(BigDecimal);
+
+impl FromStr for Price {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let price =
+            BigDecimal::from_str(s.trim()).with_context(|| format!("Invalid price '{s}'"))?;
+        if price < BigDecimal::zero() {
+            anyhow::bail!("Invalid price '{s}': must not be negative");
+        }
+        Ok(Self(price))
+    }
+}
+
+impl From<Price> for BigDecimal {
+    fn from(price: Price) -> Self {
+        price.0
+    }
+}
+
+/// Converts a price in the coin's smallest unit into a display price using the coin's decimals,
+/// e.g. 3400000000 octas with 8 decimals is 34 APT.
+pub fn to_display_price(price: &BigDecimal, decimals: u32) -> BigDecimal {
+    let (digits, scale) = price.as_bigint_and_exponent();
+    BigDecimal::new(digits, scale + decimals as i64)
+}
+
+/// Unix timestamps up to [`MAX_TIMESTAMP_SECS`] are in seconds. Ones below this are in
+/// milliseconds, the rest in microseconds.
+const MAX_TIMESTAMP_MILLIS: u64 = 100_000_000_000_000;
+
+/// Parses a unix timestamp that marketplaces may emit in seconds, milliseconds or microseconds.
+/// The unit is detected from the magnitude of the value.
+///
+/// The value comes from event data anyone can emit, so this never panics: values that aren't
+/// a non-negative integer are dropped, and ones past [`MAX_TIMESTAMP_SECS`] are clamped
+/// to it, like the chain's timestamps.
+pub fn parse_unix_timestamp(value: &str) -> Option<NaiveDateTime> {
+    let timestamp = match value.parse::<u64>() {
+        Ok(timestamp) => timestamp,
+        Err(e) if *e.kind() == IntErrorKind::PosOverflow => u64::MAX,
+        Err(_) => return None,
+    };
+    let micros = if timestamp <= MAX_TIMESTAMP_SECS as u64 {
+        timestamp * 1_000_000
+    } else if timestamp < MAX_TIMESTAMP_MILLIS {
+        timestamp * 1_000
+    } else {
+        timestamp
+    };
+    let max_micros = MAX_TIMESTAMP_SECS * 1_000_000;
+    let micros = i64::try_from(micros).map_or(max_micros, |micros| micros.min(max_micros));
+    DateTime::from_timestamp_micros(micros).map(|ts| ts.naive_utc())
+}
+
+/// Normalizes a token standard to `v1` or `v2`. Any other value is dropped.
+pub fn parse_token_standard(value: &str) -> Option<String> {
+    match value.parse::<TokenStandard>() {
+        Ok(token_standard) => Some(token_standard.to_string()),
+        Err(_) => {
+            tracing::debug!("Unknown token standard: {}", value);
+            None
+        },
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Display, EnumString)]
 #[strum(serialize_all = "snake_case")]
 pub enum MarketplaceField {
@@ -536,26 +1140,383 @@ pub enum MarketplaceField {
     LastTransactionVersion,
     LastTransactionTimestamp,
     RemainingTokenAmount,
+    /// Tokens taken by a fill of a semi-fungible listing. Only listings store it.
+    FilledTokenAmount,
     BlockTimestamp,
     BidKey,
+    AuctionId,
+    StartingPrice,
+    EndingPrice,
+    StartTime,
+    EndTime,
+    CurrentBid,
+    PaymentToken,
+    TokenStandard,
+    /// Fees taken out of the price of a sale. Only activities store them.
+    MarketplaceFee,
+    RoyaltyFee,
+    /// Property version of a v1 token. Token offers store it in a column, activities in
+    /// `custom_fields`.
+    PropertyVersion,
+    /// Any other column. Only activities store it, in `custom_fields`, see
+    /// [`MarketplaceField::for_column`].
+    #[strum(disabled)]
+    Custom(String),
+}
+
+impl MarketplaceField {
+    /// The field a config maps to `column` of `table_name`. Activities keep a column that isn't
+    /// a known field in `custom_fields`. Errors for a column the table doesn't have.
+    pub fn for_column(table_name: &str, column: &str) -> anyhow::Result<Self> {
+        let field = match Self::from_str(column) {
+            Ok(field) => field,
+            Err(_) if table_name == NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME => {
+                return Ok(Self::Custom(column.to_string()));
+            },
+            Err(_) => anyhow::bail!(
+                "unknown column {table_name}.{column}, custom columns are only stored on \
+                 {NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME}"
+            ),
+        };
+        if !field.is_stored_in(table_name) {
+            anyhow::bail!("{table_name} has no column {column}");
+        }
+        Ok(field)
+    }
+
+    /// Whether the model of `table_name` stores the field, see [`MarketplaceModel::set_field`].
+    fn is_stored_in(&self, table_name: &str) -> bool {
+        match table_name {
+            NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME => matches!(
+                self,
+                Self::CollectionId
+                    | Self::TokenDataId
+                    | Self::TokenName
+                    | Self::CreatorAddress
+                    | Self::CollectionName
+                    | Self::Price
+                    | Self::TokenAmount
+                    | Self::Buyer
+                    | Self::Seller
+                    | Self::ExpirationTime
+                    | Self::ListingId
+                    | Self::OfferId
+                    | Self::CollectionOfferId
+                    | Self::Marketplace
+                    | Self::ContractAddress
+                    | Self::BlockTimestamp
+                    | Self::BidKey
+                    | Self::PaymentToken
+                    | Self::TokenStandard
+                    | Self::MarketplaceFee
+                    | Self::RoyaltyFee
+                    | Self::PropertyVersion
+                    | Self::Custom(_)
+            ),
+            CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME => matches!(
+                self,
+                Self::CollectionId
+                    | Self::TokenDataId
+                    | Self::TokenName
+                    | Self::CreatorAddress
+                    | Self::CollectionName
+                    | Self::Price
+                    | Self::TokenAmount
+                    | Self::FilledTokenAmount
+                    | Self::Seller
+                    | Self::ListingId
+                    | Self::Marketplace
+                    | Self::ContractAddress
+                    | Self::LastTransactionVersion
+                    | Self::LastTransactionTimestamp
+                    | Self::PaymentToken
+                    | Self::TokenStandard
+            ),
+            CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME => matches!(
+                self,
+                Self::CollectionId
+                    | Self::TokenDataId
+                    | Self::TokenName
+                    | Self::CreatorAddress
+                    | Self::CollectionName
+                    | Self::Price
+                    | Self::TokenAmount
+                    | Self::Buyer
+                    | Self::ExpirationTime
+                    | Self::OfferId
+                    | Self::BidKey
+                    | Self::Marketplace
+                    | Self::ContractAddress
+                    | Self::LastTransactionVersion
+                    | Self::LastTransactionTimestamp
+                    | Self::PaymentToken
+                    | Self::TokenStandard
+                    | Self::PropertyVersion
+            ),
+            CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME => matches!(
+                self,
+                Self::CollectionId
+                    | Self::TokenDataId
+                    | Self::CreatorAddress
+                    | Self::CollectionName
+                    | Self::Price
+                    | Self::RemainingTokenAmount
+                    | Self::Buyer
+                    | Self::ExpirationTime
+                    | Self::CollectionOfferId
+                    | Self::BidKey
+                    | Self::Marketplace
+                    | Self::ContractAddress
+                    | Self::LastTransactionVersion
+                    | Self::LastTransactionTimestamp
+                    | Self::PaymentToken
+                    | Self::TokenStandard
+            ),
+            CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME => matches!(
+                self,
+                Self::CollectionId
+                    | Self::TokenDataId
+                    | Self::TokenName
+                    | Self::Buyer
+                    | Self::Seller
+                    | Self::AuctionId
+                    | Self::StartingPrice
+                    | Self::EndingPrice
+                    | Self::StartTime
+                    | Self::EndTime
+                    | Self::CurrentBid
+                    | Self::Marketplace
+                    | Self::ContractAddress
+                    | Self::LastTransactionVersion
+                    | Self::LastTransactionTimestamp
+                    | Self::PaymentToken
+                    | Self::TokenStandard
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the field holds a price, which is parsed as a [`Price`].
+    pub fn is_price(&self) -> bool {
+        matches!(
+            self,
+            MarketplaceField::Price
+                | MarketplaceField::StartingPrice
+                | MarketplaceField::EndingPrice
+                | MarketplaceField::CurrentBid
+        )
+    }
 }
 
 pub trait MarketplaceModel {
     fn set_field(&mut self, field: MarketplaceField, value: String);
     fn is_valid(&self) -> bool;
     fn table_name(&self) -> &'static str;
+    /// The columns of the table's primary key, in the table's order, joined with `::`. Models
+    /// with the same key are the same row, which is what the reduction and the DB writing step
+    /// deduplicate on.
+    fn primary_key(&self) -> String;
     fn updated_at(&self) -> i64;
     fn get_field(&self, field: MarketplaceField) -> Option<String>;
     fn get_txn_version(&self) -> i64;
     fn get_standard_event_type(&self) -> &str;
 }
 
+/// Current-state models, upserted by key. Fills and cancels often don't repeat what the place
+/// event carried, e.g. the seller or the price, so a field they leave unset keeps the value
+/// already known for the key instead of wiping it. The upserts do the same with `COALESCE`.
+/// Update events also keep whether the model was deleted. A place event starts over, so a
+/// re-listed token doesn't inherit e.g. the expiration of the canceled listing.
+pub trait CurrentMarketplaceModel: MarketplaceModel {
+    /// Takes the fields left unset on `self` from `previous`, an older model with the same key.
+    fn fill_missing_fields(&mut self, previous: &Self);
+}
+
+fn fill_missing<T: Clone>(field: &mut Option<T>, previous: &Option<T>) {
+    if field.is_none() {
+        field.clone_from(previous);
+    }
+}
+
+/// Prices default to zero when the event doesn't carry one.
+fn fill_missing_price(price: &mut BigDecimal, previous: &BigDecimal) {
+    if price.is_zero() {
+        price.clone_from(previous);
+    }
+}
+
+impl CurrentMarketplaceModel for CurrentNFTMarketplaceListing {
+    fn fill_missing_fields(&mut self, previous: &Self) {
+        if self.standard_event_type == MarketplaceEventType::PlaceListing.to_string() {
+            return;
+        }
+        if self.standard_event_type == MarketplaceEventType::UpdateListing.to_string() {
+            self.is_deleted = previous.is_deleted;
+        }
+        self.apply_partial_fill(previous);
+        fill_missing(&mut self.listing_id, &previous.listing_id);
+        fill_missing(&mut self.collection_id, &previous.collection_id);
+        fill_missing(&mut self.seller, &previous.seller);
+        fill_missing_price(&mut self.price, &previous.price);
+        fill_missing(&mut self.token_amount, &previous.token_amount);
+        fill_missing(&mut self.token_name, &previous.token_name);
+        fill_missing(&mut self.price_raw, &previous.price_raw);
+        fill_missing(&mut self.price_display, &previous.price_display);
+        fill_missing(&mut self.creator_address, &previous.creator_address);
+        fill_missing(&mut self.collection_name, &previous.collection_name);
+        fill_missing(&mut self.token_standard, &previous.token_standard);
+    }
+}
+
+impl CurrentMarketplaceModel for CurrentNFTMarketplaceTokenOffer {
+    fn fill_missing_fields(&mut self, previous: &Self) {
+        if self.standard_event_type == MarketplaceEventType::PlaceTokenOffer.to_string() {
+            return;
+        }
+        if self.standard_event_type == MarketplaceEventType::UpdateTokenOffer.to_string() {
+            self.is_deleted = previous.is_deleted;
+        }
+        fill_missing(&mut self.offer_id, &previous.offer_id);
+        fill_missing(&mut self.collection_id, &previous.collection_id);
+        fill_missing_price(&mut self.price, &previous.price);
+        fill_missing(&mut self.token_amount, &previous.token_amount);
+        fill_missing(&mut self.token_name, &previous.token_name);
+        fill_missing(&mut self.expiration_time, &previous.expiration_time);
+        fill_missing(&mut self.bid_key, &previous.bid_key);
+        fill_missing(&mut self.price_raw, &previous.price_raw);
+        fill_missing(&mut self.price_display, &previous.price_display);
+        fill_missing(&mut self.creator_address, &previous.creator_address);
+        fill_missing(&mut self.collection_name, &previous.collection_name);
+        fill_missing(&mut self.token_standard, &previous.token_standard);
+        fill_missing(&mut self.property_version, &previous.property_version);
+    }
+}
+
+impl CurrentMarketplaceModel for CurrentNFTMarketplaceCollectionOffer {
+    fn fill_missing_fields(&mut self, previous: &Self) {
+        if self.standard_event_type == MarketplaceEventType::PlaceCollectionOffer.to_string() {
+            return;
+        }
+        fill_missing(&mut self.collection_id, &previous.collection_id);
+        fill_missing_price(&mut self.price, &previous.price);
+        fill_missing(&mut self.expiration_time, &previous.expiration_time);
+        fill_missing(&mut self.bid_key, &previous.bid_key);
+        fill_missing(&mut self.price_raw, &previous.price_raw);
+        fill_missing(&mut self.price_display, &previous.price_display);
+        fill_missing(&mut self.creator_address, &previous.creator_address);
+        fill_missing(&mut self.collection_name, &previous.collection_name);
+        fill_missing(&mut self.token_standard, &previous.token_standard);
+    }
+}
+
+impl CurrentMarketplaceModel for CurrentNFTMarketplaceAuction {
+    fn fill_missing_fields(&mut self, previous: &Self) {
+        if self.standard_event_type == MarketplaceEventType::PlaceAuction.to_string() {
+            return;
+        }
+        fill_missing(&mut self.auction_id, &previous.auction_id);
+        fill_missing(&mut self.collection_id, &previous.collection_id);
+        fill_missing(&mut self.token_name, &previous.token_name);
+        fill_missing(&mut self.seller, &previous.seller);
+        fill_missing(&mut self.starting_price, &previous.starting_price);
+        fill_missing(&mut self.ending_price, &previous.ending_price);
+        fill_missing(&mut self.start_time, &previous.start_time);
+        fill_missing(&mut self.end_time, &previous.end_time);
+        fill_missing(&mut self.token_standard, &previous.token_standard);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::str::FromStr;
     use strum::ParseError;
 
+    #[test]
+    fn test_parse_unix_timestamp_units() {
+        let expected = DateTime::from_timestamp_micros(1_739_908_340_290_288)
+            .unwrap()
+            .naive_utc();
+        let expected_secs = DateTime::from_timestamp(1_739_908_340, 0)
+            .unwrap()
+            .naive_utc();
+        let expected_millis = DateTime::from_timestamp_millis(1_739_908_340_290)
+            .unwrap()
+            .naive_utc();
+
+        assert_eq!(parse_unix_timestamp("1739908340"), Some(expected_secs));
+        assert_eq!(parse_unix_timestamp("1739908340290"), Some(expected_millis));
+        assert_eq!(parse_unix_timestamp("1739908340290288"), Some(expected));
+        assert_eq!(parse_unix_timestamp("not a timestamp"), None);
+    }
+
+    #[test]
+    fn test_parse_unix_timestamp_out_of_range() {
+        let max = DateTime::from_timestamp(MAX_TIMESTAMP_SECS, 0)
+            .unwrap()
+            .naive_utc();
+
+        assert_eq!(parse_unix_timestamp("-1"), None);
+        assert_eq!(
+            parse_unix_timestamp("0"),
+            Some(DateTime::from_timestamp(0, 0).unwrap().naive_utc())
+        );
+        assert_eq!(parse_unix_timestamp(&u64::MAX.to_string()), Some(max));
+        assert_eq!(parse_unix_timestamp("99999999999999999999999"), Some(max));
+    }
+
+    #[test]
+    fn test_malformed_price_is_not_zero() {
+        assert_eq!(
+            BigDecimal::from("3400000000".parse::<Price>().unwrap()),
+            BigDecimal::from(3400000000i64)
+        );
+        assert!("not a price".parse::<Price>().is_err());
+        assert!("-1".parse::<Price>().is_err());
+
+        let mut listing = CurrentNFTMarketplaceListing {
+            price: BigDecimal::from(5),
+            ..Default::default()
+        };
+        listing.set_field(MarketplaceField::Price, "not a price".to_string());
+        assert_eq!(listing.price, BigDecimal::from(5));
+    }
+
+    #[test]
+    fn test_expiration_time_in_micros() {
+        let mut activity = NftMarketplaceActivity::default();
+        activity.set_field(
+            MarketplaceField::ExpirationTime,
+            "1739908340290288".to_string(),
+        );
+        assert_eq!(
+            activity.get_field(MarketplaceField::ExpirationTime),
+            Some("1739908340".to_string())
+        );
+    }
+
+    #[test]
+    fn test_content_hash_covers_fields_but_not_itself() {
+        let activity = NftMarketplaceActivity {
+            txn_version: 1,
+            token_name: Some("Token #1".to_string()),
+            ..Default::default()
+        };
+        let hash = activity.compute_content_hash();
+        assert_eq!(hash.len(), 64);
+
+        let hashed = NftMarketplaceActivity {
+            content_hash: Some(hash.clone()),
+            ..activity.clone()
+        };
+        assert_eq!(hashed.compute_content_hash(), hash);
+
+        let renamed = NftMarketplaceActivity {
+            token_name: Some("Token #2".to_string()),
+            ..activity
+        };
+        assert_ne!(renamed.compute_content_hash(), hash);
+    }
+
     #[test]
     fn test_invalid_field() {
         // This will return Err(ParseError::VariantNotFound)
@@ -571,6 +1532,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_unknown_activity_column_is_custom() {
+        let field =
+            MarketplaceField::for_column(NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME, "rarity_rank")
+                .unwrap();
+        assert_eq!(field, MarketplaceField::Custom("rarity_rank".to_string()));
+
+        // The current tables have no room for custom columns, nor for fields they don't store
+        assert!(MarketplaceField::for_column(
+            CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+            "rarity_rank"
+        )
+        .is_err());
+        assert!(MarketplaceField::for_column(
+            CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+            "auction_id"
+        )
+        .is_err());
+        assert_eq!(
+            MarketplaceField::for_column(CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME, "seller")
+                .unwrap(),
+            MarketplaceField::Seller
+        );
+
+        let mut activity = NftMarketplaceActivity::default();
+        activity.set_field(field, "12".to_string());
+        assert_eq!(
+            activity.custom_fields,
+            Some(serde_json::json!({ "rarity_rank": "12" }))
+        );
+        assert_eq!(
+            activity.get_field(MarketplaceField::Custom("rarity_rank".to_string())),
+            Some("12".to_string())
+        );
+    }
+
+    #[test]
+    fn test_activity_keeps_property_version_in_custom_fields() {
+        let field =
+            MarketplaceField::for_column(NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME, "property_version")
+                .unwrap();
+        assert_eq!(field, MarketplaceField::PropertyVersion);
+
+        let mut activity = NftMarketplaceActivity::default();
+        activity.set_field(field, "1".to_string());
+        assert_eq!(
+            activity.custom_fields,
+            Some(serde_json::json!({ "property_version": "1" }))
+        );
+        assert_eq!(
+            activity.get_field(MarketplaceField::PropertyVersion),
+            Some("1".to_string())
+        );
+    }
+
     #[test]
     fn test_valid_fields() {
         // Test a few valid field names
@@ -588,4 +1604,16 @@ mod tests {
             assert_eq!(result, expected);
         }
     }
+
+    #[test]
+    fn test_price_above_i64_max_is_kept() {
+        let price = u64::MAX.to_string();
+        let mut activity = NftMarketplaceActivity::default();
+        activity.set_field(MarketplaceField::Price, price.clone());
+        activity.set_field(MarketplaceField::TokenAmount, price.clone());
+
+        assert_eq!(activity.price, BigDecimal::from(u64::MAX));
+        assert_eq!(activity.token_amount, Some(BigDecimal::from(u64::MAX)));
+        assert_eq!(activity.get_field(MarketplaceField::Price), Some(price));
+    }
 }
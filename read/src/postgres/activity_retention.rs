@@ -0,0 +1,95 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Optional retention for `nft_marketplace_activities`, which otherwise grows without bound.
+//! Activities older than the retention window are deleted in batches, so no single statement
+//! holds its locks for long.
+
+use crate::{
+    postgres::postgres_utils::{ArcDbPool, DbPoolConnection},
+    schema::nft_marketplace_activities,
+};
+use anyhow::{Context, Result};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// How often activities past the retention window are pruned.
+pub const ACTIVITY_RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_ACTIVITY_RETENTION_BATCH_SIZE: i64 = 10_000;
+
+/// Deletes a batch of the activities of `marketplace` from before `cutoff`, the oldest first,
+/// and returns how many were deleted. A batch is about `batch_size` rows: it ends on a
+/// transaction version, so the rest of the last version's activities go with it.
+pub async fn delete_activities_before(
+    conn: &mut DbPoolConnection<'_>,
+    marketplace: &str,
+    cutoff: NaiveDateTime,
+    batch_size: i64,
+) -> diesel::QueryResult<usize> {
+    let last_version: Option<i64> = nft_marketplace_activities::table
+        .select(nft_marketplace_activities::txn_version)
+        .filter(nft_marketplace_activities::marketplace.eq(marketplace))
+        .filter(nft_marketplace_activities::block_timestamp.lt(cutoff))
+        .order_by(nft_marketplace_activities::txn_version)
+        .offset(batch_size - 1)
+        .first::<i64>(conn)
+        .await
+        .optional()?;
+
+    // With less than a batch left, the rest is deleted
+    diesel::delete(nft_marketplace_activities::table)
+        .filter(nft_marketplace_activities::marketplace.eq(marketplace))
+        .filter(nft_marketplace_activities::block_timestamp.lt(cutoff))
+        .filter(nft_marketplace_activities::txn_version.le(last_version.unwrap_or(i64::MAX)))
+        .execute(conn)
+        .await
+}
+
+/// Deletes every activity of `marketplace` older than `retention_days`, one batch at a time.
+/// Returns how many were deleted.
+pub async fn prune_activities(
+    db_pool: ArcDbPool,
+    marketplace: &str,
+    retention_days: u32,
+    batch_size: i64,
+) -> Result<usize> {
+    let cutoff = Utc::now().naive_utc() - TimeDelta::days(retention_days.into());
+    let mut conn = db_pool
+        .get()
+        .await
+        .context("Failed to get database connection")?;
+    let mut deleted = 0;
+    loop {
+        let batch = delete_activities_before(&mut conn, marketplace, cutoff, batch_size)
+            .await
+            .context("Failed to delete expired activities")?;
+        if batch == 0 {
+            return Ok(deleted);
+        }
+        deleted += batch;
+    }
+}
+
+/// Prunes activities every [`ACTIVITY_RETENTION_INTERVAL`], starting right away, until the
+/// processor exits.
+pub async fn run_activity_retention(
+    db_pool: ArcDbPool,
+    marketplace: String,
+    retention_days: u32,
+    batch_size: i64,
+) {
+    let mut prune_interval = tokio::time::interval(ACTIVITY_RETENTION_INTERVAL);
+    loop {
+        prune_interval.tick().await;
+        match prune_activities(db_pool.clone(), &marketplace, retention_days, batch_size).await {
+            Ok(deleted) => info!(
+                deleted,
+                retention_days, "Pruned activities past the retention window"
+            ),
+            Err(e) => error!("{e:#}"),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Discovery mode: counts the event types a marketplace config has no remapping for, so that
+//! onboarding a marketplace doesn't mean digging its event types out of the logs. Event types
+//! that weren't seen for [`DISCOVERY_TTL`] are pruned, so the table doesn't grow without bound.
+
+use crate::{
+    models::EventModel,
+    postgres::postgres_utils::{execute_in_chunks, greatest, ArcDbPool},
+    schema::discovered_event_types,
+};
+use anyhow::{Context, Result};
+use chrono::{TimeDelta, Utc};
+use diesel::{
+    pg::{upsert::excluded, Pg},
+    query_builder::QueryFragment,
+    ExpressionMethods, Insertable,
+};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{error, info};
+
+/// How often the counts are written to `discovered_event_types`.
+pub const DISCOVERY_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+const DISCOVERY_CHUNK_SIZE: usize = 200;
+/// Event types that weren't seen for this long are deleted on the next flush.
+pub const DISCOVERY_TTL: TimeDelta = TimeDelta::days(30);
+
+#[derive(Clone, Debug, Deserialize, Insertable, PartialEq, Serialize)]
+#[diesel(table_name = discovered_event_types)]
+pub struct DiscoveredEventType {
+    pub marketplace: String,
+    pub contract_address: String,
+    pub event_type: String,
+    /// Occurrences since the last flush, added to the stored count on insert.
+    pub occurrences: i64,
+    pub last_seen_version: i64,
+}
+
+#[derive(Default)]
+struct Discovered {
+    occurrences: i64,
+    last_seen_version: i64,
+}
+
+/// Occurrences of unmapped event types, keyed by contract address and event type, that
+/// haven't been written yet.
+pub struct DiscoveredEventTypes {
+    marketplace: String,
+    discovered: Mutex<HashMap<(String, String), Discovered>>,
+}
+
+impl DiscoveredEventTypes {
+    pub fn new(marketplace: String) -> Self {
+        Self {
+            marketplace,
+            discovered: Mutex::default(),
+        }
+    }
+
+    /// Counts `event`. Framework events, e.g. coin deposits, show up in nearly every
+    /// transaction and are never a marketplace's own, so they're left out.
+    pub fn record(&self, event: &EventModel) {
+        if event.event_type.is_framework_event() {
+            return;
+        }
+        let key = (
+            event.event_type.get_address().to_string(),
+            event.event_type.to_string(),
+        );
+        let mut discovered = self.discovered.lock().unwrap();
+        let entry = discovered.entry(key).or_default();
+        entry.occurrences += 1;
+        entry.last_seen_version = entry.last_seen_version.max(event.transaction_version);
+    }
+
+    /// Returns the counts recorded since the last call, sorted so concurrent flushes lock the
+    /// rows in the same order.
+    pub fn take(&self) -> Vec<DiscoveredEventType> {
+        let discovered = std::mem::take(&mut *self.discovered.lock().unwrap());
+        let mut rows: Vec<DiscoveredEventType> = discovered
+            .into_iter()
+            .map(
+                |((contract_address, event_type), discovered)| DiscoveredEventType {
+                    marketplace: self.marketplace.clone(),
+                    contract_address,
+                    event_type,
+                    occurrences: discovered.occurrences,
+                    last_seen_version: discovered.last_seen_version,
+                },
+            )
+            .collect();
+        rows.sort_by(|a, b| {
+            (&a.contract_address, &a.event_type).cmp(&(&b.contract_address, &b.event_type))
+        });
+        rows
+    }
+
+    /// Writes the counts recorded since the last flush, adding them to the stored ones, and
+    /// prunes the event types that weren't seen for [`DISCOVERY_TTL`].
+    pub async fn flush(&self, db_pool: ArcDbPool) {
+        let rows = self.take();
+        if !rows.is_empty() {
+            match execute_in_chunks(
+                db_pool.clone(),
+                insert_discovered_event_types,
+                &rows,
+                DISCOVERY_CHUNK_SIZE,
+            )
+            .await
+            {
+                Ok(()) => info!("Recorded {} discovered event types", rows.len()),
+                Err(e) => error!("Failed to write discovered event types: {e:?}"),
+            }
+        }
+
+        match prune_discovered_event_types(db_pool, &self.marketplace).await {
+            Ok(0) => {},
+            Ok(deleted) => info!(
+                "Pruned {deleted} discovered event types not seen for {} days",
+                DISCOVERY_TTL.num_days()
+            ),
+            Err(e) => error!("Failed to prune discovered event types: {e:?}"),
+        }
+    }
+}
+
+/// Flushes `discovered` every [`DISCOVERY_FLUSH_INTERVAL`] until the processor exits.
+pub async fn run_discovery_flusher(discovered: Arc<DiscoveredEventTypes>, db_pool: ArcDbPool) {
+    let mut flush_interval = tokio::time::interval(DISCOVERY_FLUSH_INTERVAL);
+    // The first tick completes immediately, before anything was recorded
+    flush_interval.tick().await;
+    loop {
+        flush_interval.tick().await;
+        discovered.flush(db_pool.clone()).await;
+    }
+}
+
+pub fn insert_discovered_event_types(
+    items_to_insert: Vec<DiscoveredEventType>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::discovered_event_types::dsl::*;
+
+    diesel::insert_into(discovered_event_types::table)
+        .values(items_to_insert)
+        .on_conflict((marketplace, contract_address, event_type))
+        .do_update()
+        .set((
+            occurrences.eq(occurrences + excluded(occurrences)),
+            last_seen_version.eq(greatest(last_seen_version, excluded(last_seen_version))),
+            last_updated.eq(diesel::dsl::now),
+        ))
+}
+
+/// Deletes the event types of `marketplace` that weren't seen for [`DISCOVERY_TTL`], and
+/// returns how many were deleted.
+pub async fn prune_discovered_event_types(db_pool: ArcDbPool, marketplace: &str) -> Result<usize> {
+    let cutoff = Utc::now().naive_utc() - DISCOVERY_TTL;
+    let mut conn = db_pool
+        .get()
+        .await
+        .context("Failed to get database connection")?;
+    diesel::delete(discovered_event_types::table)
+        .filter(discovered_event_types::marketplace.eq(marketplace))
+        .filter(discovered_event_types::last_updated.lt(cutoff))
+        .execute(&mut conn)
+        .await
+        .context("Failed to delete stale discovered event types")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::marketplace_config::EventType;
+    use chrono::NaiveDateTime;
+
+    fn build_event(event_type: &str, transaction_version: i64) -> EventModel {
+        EventModel {
+            sequence_number: 0,
+            creation_number: 0,
+            account_address: "0x0".to_string(),
+            transaction_version,
+            transaction_block_height: 0,
+            event_type: EventType::try_from(event_type).unwrap(),
+            data: serde_json::Value::Null,
+            event_index: 0,
+            block_timestamp: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_counts_unmapped_event_types_per_contract() {
+        let discovered = DiscoveredEventTypes::new("wapal".to_string());
+        discovered.record(&build_event("0xabc::events::ListingPlacedEvent", 10));
+        discovered.record(&build_event("0xabc::events::ListingPlacedEvent", 12));
+        discovered.record(&build_event("0xdef::market::Bought", 11));
+        discovered.record(&build_event("0x1::coin::CoinDeposit", 11));
+
+        let rows = discovered.take();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].event_type.ends_with("::events::ListingPlacedEvent"));
+        assert_eq!(rows[0].occurrences, 2);
+        assert_eq!(rows[0].last_seen_version, 12);
+        assert!(rows[1].event_type.ends_with("::market::Bought"));
+        assert_eq!(rows[1].occurrences, 1);
+        assert!(rows.iter().all(|row| row.marketplace == "wapal"));
+
+        // Flushed counts aren't written again
+        assert!(discovered.take().is_empty());
+    }
+}
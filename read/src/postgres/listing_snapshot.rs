@@ -0,0 +1,98 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Point-in-time lookups over listings, rebuilt from `nft_marketplace_activities` instead of a
+//! separate snapshot table.
+
+use crate::{
+    config::marketplace_config::MarketplaceEventType,
+    models::nft_models::{CurrentNFTMarketplaceListing, NftMarketplaceActivity},
+    postgres::postgres_utils::DbPoolConnection,
+    schema::nft_marketplace_activities,
+    steps::reduction_step::NFTAccumulator,
+};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+
+const LISTING_EVENT_TYPES: [MarketplaceEventType; 4] = [
+    MarketplaceEventType::PlaceListing,
+    MarketplaceEventType::CancelListing,
+    MarketplaceEventType::FillListing,
+    MarketplaceEventType::UpdateListing,
+];
+
+/// Returns the listing of `token_data_id` on `marketplace` as it was right after `version`.
+/// A listing that was cancelled or filled by then is returned with `is_deleted` set.
+pub async fn get_listing_at_version(
+    conn: &mut DbPoolConnection<'_>,
+    token_data_id: &str,
+    marketplace: &str,
+    version: i64,
+) -> diesel::QueryResult<Option<CurrentNFTMarketplaceListing>> {
+    let listing_event_types: Vec<String> =
+        LISTING_EVENT_TYPES.iter().map(|t| t.to_string()).collect();
+    let activities = nft_marketplace_activities::table
+        .filter(nft_marketplace_activities::token_data_id.eq(token_data_id))
+        .filter(nft_marketplace_activities::marketplace.eq(marketplace))
+        .filter(nft_marketplace_activities::txn_version.le(version))
+        .filter(nft_marketplace_activities::standard_event_type.eq_any(listing_event_types))
+        .order_by((
+            nft_marketplace_activities::txn_version,
+            nft_marketplace_activities::index,
+        ))
+        .load::<NftMarketplaceActivity>(conn)
+        .await?;
+
+    Ok(fold_listing_activities(activities))
+}
+
+/// Folds listing activities, ordered by version, into the listing state they lead to.
+pub fn fold_listing_activities(
+    activities: impl IntoIterator<Item = NftMarketplaceActivity>,
+) -> Option<CurrentNFTMarketplaceListing> {
+    let mut accumulator = NFTAccumulator::default();
+    for activity in activities {
+        if let Some(listing) = CurrentNFTMarketplaceListing::from_activity(&activity) {
+            accumulator.fold_listing(listing);
+        }
+    }
+    accumulator.drain().listings.into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+
+    fn build_activity(
+        txn_version: i64,
+        event_type: MarketplaceEventType,
+        price: i64,
+    ) -> NftMarketplaceActivity {
+        NftMarketplaceActivity {
+            txn_version,
+            standard_event_type: event_type.to_string(),
+            token_data_id: Some("0x1".to_string()),
+            price: BigDecimal::from(price),
+            marketplace: "test_marketplace".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fold_listing_activities_uses_latest_state() {
+        let placed = vec![build_activity(1, MarketplaceEventType::PlaceListing, 100)];
+        let listing = fold_listing_activities(placed.clone()).unwrap();
+        assert!(!listing.is_deleted);
+        assert_eq!(listing.price, BigDecimal::from(100));
+        assert_eq!(listing.last_transaction_version, 1);
+
+        let mut filled = placed;
+        filled.push(build_activity(2, MarketplaceEventType::FillListing, 100));
+        let listing = fold_listing_activities(filled).unwrap();
+        assert!(listing.is_deleted);
+        assert_eq!(listing.last_transaction_version, 2);
+
+        assert!(fold_listing_activities(vec![]).is_none());
+    }
+}
@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nft_marketplace_activities
+DROP COLUMN IF EXISTS price_raw,
+DROP COLUMN IF EXISTS price_display;
+
+ALTER TABLE current_nft_marketplace_listings
+DROP COLUMN IF EXISTS price_raw,
+DROP COLUMN IF EXISTS price_display;
+
+ALTER TABLE current_nft_marketplace_token_offers
+DROP COLUMN IF EXISTS price_raw,
+DROP COLUMN IF EXISTS price_display;
+
+ALTER TABLE current_nft_marketplace_collection_offers
+DROP COLUMN IF EXISTS price_raw,
+DROP COLUMN IF EXISTS price_display;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ADD COLUMN IF NOT EXISTS price_raw NUMERIC,
+ADD COLUMN IF NOT EXISTS price_display NUMERIC;
+
+ALTER TABLE current_nft_marketplace_listings
+ADD COLUMN IF NOT EXISTS price_raw NUMERIC,
+ADD COLUMN IF NOT EXISTS price_display NUMERIC;
+
+ALTER TABLE current_nft_marketplace_token_offers
+ADD COLUMN IF NOT EXISTS price_raw NUMERIC,
+ADD COLUMN IF NOT EXISTS price_display NUMERIC;
+
+ALTER TABLE current_nft_marketplace_collection_offers
+ADD COLUMN IF NOT EXISTS price_raw NUMERIC,
+ADD COLUMN IF NOT EXISTS price_display NUMERIC;
@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nft_marketplace_activities
+ALTER COLUMN price TYPE BIGINT,
+ALTER COLUMN token_amount TYPE BIGINT;
+
+ALTER TABLE current_nft_marketplace_listings
+ALTER COLUMN price TYPE BIGINT,
+ALTER COLUMN token_amount TYPE BIGINT;
+
+ALTER TABLE current_nft_marketplace_token_offers
+ALTER COLUMN price TYPE BIGINT,
+ALTER COLUMN token_amount TYPE BIGINT;
+
+ALTER TABLE current_nft_marketplace_collection_offers
+ALTER COLUMN price TYPE BIGINT,
+ALTER COLUMN remaining_token_amount TYPE BIGINT;
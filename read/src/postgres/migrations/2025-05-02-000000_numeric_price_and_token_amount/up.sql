@@ -0,0 +1,16 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ALTER COLUMN price TYPE NUMERIC,
+ALTER COLUMN token_amount TYPE NUMERIC;
+
+ALTER TABLE current_nft_marketplace_listings
+ALTER COLUMN price TYPE NUMERIC,
+ALTER COLUMN token_amount TYPE NUMERIC;
+
+ALTER TABLE current_nft_marketplace_token_offers
+ALTER COLUMN price TYPE NUMERIC,
+ALTER COLUMN token_amount TYPE NUMERIC;
+
+ALTER TABLE current_nft_marketplace_collection_offers
+ALTER COLUMN price TYPE NUMERIC,
+ALTER COLUMN remaining_token_amount TYPE NUMERIC;
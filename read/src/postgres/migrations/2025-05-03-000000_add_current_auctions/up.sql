@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS current_nft_marketplace_auctions (
+    token_data_id VARCHAR(66) NOT NULL,
+    marketplace VARCHAR NOT NULL,
+    auction_id VARCHAR(128),
+    collection_id VARCHAR(66),
+    token_name VARCHAR,
+    seller VARCHAR(66),
+    current_bidder VARCHAR(66),
+    starting_price NUMERIC,
+    ending_price NUMERIC,
+    current_bid NUMERIC,
+    start_time TIMESTAMP,
+    end_time TIMESTAMP,
+    is_deleted BOOLEAN NOT NULL,
+    contract_address VARCHAR NOT NULL,
+    last_transaction_version BIGINT NOT NULL,
+    last_transaction_timestamp TIMESTAMP NOT NULL,
+    standard_event_type VARCHAR NOT NULL,
+    PRIMARY KEY (token_data_id, marketplace)
+);
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ADD COLUMN IF NOT EXISTS payment_token VARCHAR NOT NULL DEFAULT '0x1::aptos_coin::AptosCoin';
+
+ALTER TABLE current_nft_marketplace_listings
+ADD COLUMN IF NOT EXISTS payment_token VARCHAR NOT NULL DEFAULT '0x1::aptos_coin::AptosCoin';
+
+ALTER TABLE current_nft_marketplace_token_offers
+ADD COLUMN IF NOT EXISTS payment_token VARCHAR NOT NULL DEFAULT '0x1::aptos_coin::AptosCoin';
+
+ALTER TABLE current_nft_marketplace_collection_offers
+ADD COLUMN IF NOT EXISTS payment_token VARCHAR NOT NULL DEFAULT '0x1::aptos_coin::AptosCoin';
+
+ALTER TABLE current_nft_marketplace_auctions
+ADD COLUMN IF NOT EXISTS payment_token VARCHAR NOT NULL DEFAULT '0x1::aptos_coin::AptosCoin';
@@ -0,0 +1,12 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE current_nft_marketplace_listings
+DROP COLUMN IF EXISTS creator_address,
+DROP COLUMN IF EXISTS collection_name;
+
+ALTER TABLE current_nft_marketplace_token_offers
+DROP COLUMN IF EXISTS creator_address,
+DROP COLUMN IF EXISTS collection_name;
+
+ALTER TABLE current_nft_marketplace_collection_offers
+DROP COLUMN IF EXISTS creator_address,
+DROP COLUMN IF EXISTS collection_name;
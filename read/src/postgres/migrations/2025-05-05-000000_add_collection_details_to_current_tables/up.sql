@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE current_nft_marketplace_listings
+ADD COLUMN IF NOT EXISTS creator_address VARCHAR(66),
+ADD COLUMN IF NOT EXISTS collection_name VARCHAR;
+
+ALTER TABLE current_nft_marketplace_token_offers
+ADD COLUMN IF NOT EXISTS creator_address VARCHAR(66),
+ADD COLUMN IF NOT EXISTS collection_name VARCHAR;
+
+ALTER TABLE current_nft_marketplace_collection_offers
+ADD COLUMN IF NOT EXISTS creator_address VARCHAR(66),
+ADD COLUMN IF NOT EXISTS collection_name VARCHAR;
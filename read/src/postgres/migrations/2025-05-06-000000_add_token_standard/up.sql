@@ -0,0 +1,15 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ADD COLUMN IF NOT EXISTS token_standard VARCHAR(10);
+
+ALTER TABLE current_nft_marketplace_listings
+ADD COLUMN IF NOT EXISTS token_standard VARCHAR(10);
+
+ALTER TABLE current_nft_marketplace_token_offers
+ADD COLUMN IF NOT EXISTS token_standard VARCHAR(10);
+
+ALTER TABLE current_nft_marketplace_collection_offers
+ADD COLUMN IF NOT EXISTS token_standard VARCHAR(10);
+
+ALTER TABLE current_nft_marketplace_auctions
+ADD COLUMN IF NOT EXISTS token_standard VARCHAR(10);
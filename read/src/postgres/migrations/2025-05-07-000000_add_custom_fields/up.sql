@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ADD COLUMN IF NOT EXISTS custom_fields JSONB;
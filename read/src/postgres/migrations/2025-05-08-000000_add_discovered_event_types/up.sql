@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS discovered_event_types (
+    marketplace VARCHAR NOT NULL,
+    contract_address VARCHAR(66) NOT NULL,
+    event_type VARCHAR NOT NULL,
+    occurrences BIGINT NOT NULL,
+    last_seen_version BIGINT NOT NULL,
+    last_updated TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (marketplace, contract_address, event_type)
+);
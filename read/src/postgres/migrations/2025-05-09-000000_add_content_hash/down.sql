@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nft_marketplace_activities
+DROP COLUMN IF EXISTS content_hash;
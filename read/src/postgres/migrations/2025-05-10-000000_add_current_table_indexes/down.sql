@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_current_nft_marketplace_listings_collection_id;
+DROP INDEX IF EXISTS idx_current_nft_marketplace_listings_seller;
+DROP INDEX IF EXISTS idx_current_nft_marketplace_token_offers_buyer;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+CREATE INDEX IF NOT EXISTS idx_current_nft_marketplace_listings_collection_id ON current_nft_marketplace_listings (collection_id);
+CREATE INDEX IF NOT EXISTS idx_current_nft_marketplace_listings_seller ON current_nft_marketplace_listings (seller);
+CREATE INDEX IF NOT EXISTS idx_current_nft_marketplace_token_offers_buyer ON current_nft_marketplace_token_offers (buyer);
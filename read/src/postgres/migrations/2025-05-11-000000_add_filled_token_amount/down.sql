@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE current_nft_marketplace_listings
+DROP COLUMN IF EXISTS filled_token_amount;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE current_nft_marketplace_listings
+ADD COLUMN IF NOT EXISTS filled_token_amount NUMERIC;
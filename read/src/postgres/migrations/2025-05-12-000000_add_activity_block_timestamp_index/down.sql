@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_nft_marketplace_activities_marketplace_block_timestamp;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+CREATE INDEX IF NOT EXISTS idx_nft_marketplace_activities_marketplace_block_timestamp ON nft_marketplace_activities (marketplace, block_timestamp);
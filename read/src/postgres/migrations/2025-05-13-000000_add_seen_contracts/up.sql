@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS seen_contracts (
+    marketplace VARCHAR NOT NULL,
+    contract_address VARCHAR(66) NOT NULL,
+    first_seen_version BIGINT NOT NULL,
+    inserted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (marketplace, contract_address)
+);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS collection_offer_fills (
+    txn_version BIGINT NOT NULL,
+    index BIGINT NOT NULL,
+    marketplace VARCHAR NOT NULL,
+    collection_offer_id VARCHAR(128) NOT NULL,
+    token_data_id VARCHAR(66) NOT NULL,
+    buyer VARCHAR(66),
+    seller VARCHAR(66),
+    price NUMERIC NOT NULL,
+    block_timestamp TIMESTAMP NOT NULL,
+    PRIMARY KEY (txn_version, index, marketplace)
+);
+CREATE INDEX IF NOT EXISTS idx_collection_offer_fills_collection_offer_id ON collection_offer_fills (collection_offer_id, marketplace);
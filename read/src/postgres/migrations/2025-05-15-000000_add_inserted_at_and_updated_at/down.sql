@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nft_marketplace_activities
+DROP COLUMN IF EXISTS inserted_at,
+DROP COLUMN IF EXISTS updated_at;
+
+ALTER TABLE current_nft_marketplace_listings
+DROP COLUMN IF EXISTS inserted_at,
+DROP COLUMN IF EXISTS updated_at;
+
+ALTER TABLE current_nft_marketplace_token_offers
+DROP COLUMN IF EXISTS inserted_at,
+DROP COLUMN IF EXISTS updated_at;
+
+ALTER TABLE current_nft_marketplace_collection_offers
+DROP COLUMN IF EXISTS inserted_at,
+DROP COLUMN IF EXISTS updated_at;
+
+ALTER TABLE current_nft_marketplace_auctions
+DROP COLUMN IF EXISTS inserted_at,
+DROP COLUMN IF EXISTS updated_at;
@@ -0,0 +1,19 @@
+-- This file should undo anything in `up.sql`
+-- Keeps the latest offer of each buyer, as the old primary key would have
+DELETE FROM current_nft_marketplace_token_offers
+WHERE (token_data_id, buyer, marketplace, offer_key) NOT IN (
+    SELECT DISTINCT ON (token_data_id, buyer, marketplace)
+        token_data_id, buyer, marketplace, offer_key
+    FROM current_nft_marketplace_token_offers
+    ORDER BY token_data_id, buyer, marketplace, last_transaction_version DESC
+);
+
+ALTER TABLE current_nft_marketplace_token_offers
+DROP CONSTRAINT IF EXISTS current_nft_marketplace_token_offers_pkey;
+
+ALTER TABLE current_nft_marketplace_token_offers
+ADD CONSTRAINT current_nft_marketplace_token_offers_pkey
+PRIMARY KEY (token_data_id, buyer, marketplace);
+
+ALTER TABLE current_nft_marketplace_token_offers
+DROP COLUMN IF EXISTS offer_key;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Empty unless the marketplace keys its token offers by offer id, in which case it holds the
+-- offer id so that a buyer's offers on the same token get a row each
+ALTER TABLE current_nft_marketplace_token_offers
+ADD COLUMN IF NOT EXISTS offer_key VARCHAR(128) NOT NULL DEFAULT '';
+
+ALTER TABLE current_nft_marketplace_token_offers
+DROP CONSTRAINT IF EXISTS current_nft_marketplace_token_offers_pkey;
+
+ALTER TABLE current_nft_marketplace_token_offers
+ADD CONSTRAINT current_nft_marketplace_token_offers_pkey
+PRIMARY KEY (token_data_id, buyer, marketplace, offer_key);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nft_marketplace_activities
+DROP COLUMN IF EXISTS marketplace_fee,
+DROP COLUMN IF EXISTS royalty_fee;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ADD COLUMN IF NOT EXISTS marketplace_fee NUMERIC,
+ADD COLUMN IF NOT EXISTS royalty_fee NUMERIC;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE current_nft_marketplace_listings
+DROP COLUMN IF EXISTS owner,
+DROP COLUMN IF EXISTS is_stale;
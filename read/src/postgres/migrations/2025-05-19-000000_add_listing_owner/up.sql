@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE current_nft_marketplace_listings
+ADD COLUMN IF NOT EXISTS owner VARCHAR(66),
+ADD COLUMN IF NOT EXISTS is_stale BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ADD COLUMN IF NOT EXISTS is_self_trade BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE nft_marketplace_activities
+SET is_self_trade = TRUE
+WHERE buyer = seller;
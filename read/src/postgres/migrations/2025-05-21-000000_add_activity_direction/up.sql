@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE nft_marketplace_activities
+ADD COLUMN IF NOT EXISTS direction VARCHAR(20);
+
+UPDATE nft_marketplace_activities
+SET direction = CASE
+    WHEN standard_event_type IN ('fill_listing', 'fill_token_offer', 'fill_collection_offer', 'settle_auction') THEN 'to_seller'
+    WHEN standard_event_type IN ('cancel_token_offer', 'cancel_collection_offer') THEN 'to_buyer'
+    WHEN standard_event_type IN ('place_token_offer', 'place_collection_offer', 'bid_auction') THEN 'to_escrow'
+END;
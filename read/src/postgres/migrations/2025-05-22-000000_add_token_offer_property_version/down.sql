@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE current_nft_marketplace_token_offers
+DROP COLUMN IF EXISTS property_version;
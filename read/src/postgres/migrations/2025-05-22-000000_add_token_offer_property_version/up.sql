@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE current_nft_marketplace_token_offers
+ADD COLUMN IF NOT EXISTS property_version NUMERIC;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+-- The processor_metadata tables and backfill_processor_status are left to
+-- 2025-03-27-050413_move-processor-status, which `public` ran
+DROP TABLE IF EXISTS ledger_infos;
@@ -0,0 +1,33 @@
+-- Your SQL goes here
+-- Schemas set with the `schema` option skip 2025-03-27-050413_move-processor-status, which
+-- copies the processor status out of `public`, so the tables it creates are created here
+CREATE SCHEMA IF NOT EXISTS processor_metadata;
+
+CREATE TABLE IF NOT EXISTS processor_metadata.processor_status (
+  processor VARCHAR(100) UNIQUE PRIMARY KEY NOT NULL,
+  last_success_version BIGINT NOT NULL,
+  last_updated TIMESTAMP NOT NULL DEFAULT NOW(),
+  last_transaction_timestamp TIMESTAMP NULL
+);
+
+CREATE TABLE IF NOT EXISTS processor_metadata.ledger_infos (chain_id BIGINT UNIQUE PRIMARY KEY NOT NULL);
+
+CREATE TABLE IF NOT EXISTS backfill_processor_status (
+    backfill_alias VARCHAR(100) NOT NULL,
+    backfill_status VARCHAR(50) NOT NULL,
+    last_success_version BIGINT NOT NULL,
+    last_updated TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_transaction_timestamp TIMESTAMP NULL,
+    backfill_start_version BIGINT NOT NULL,
+    backfill_end_version BIGINT NULL,
+    PRIMARY KEY (backfill_alias)
+);
+
+-- Chain id of the data in this schema. `processor_metadata.ledger_infos` is shared by every
+-- schema, so only `public`, whose chain id it holds so far, takes it over.
+CREATE TABLE IF NOT EXISTS ledger_infos (chain_id BIGINT UNIQUE PRIMARY KEY NOT NULL);
+
+INSERT INTO ledger_infos
+SELECT chain_id FROM processor_metadata.ledger_infos
+WHERE current_schema() = 'public'
+ON CONFLICT DO NOTHING;
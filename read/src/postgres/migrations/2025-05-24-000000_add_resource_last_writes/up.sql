@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- The last write of each resource in a marketplace's resource_event_model_mapping, NULL once
+-- the resource is deleted
+CREATE TABLE IF NOT EXISTS resource_last_writes (
+    marketplace VARCHAR NOT NULL,
+    resource_type VARCHAR NOT NULL,
+    address VARCHAR(66) NOT NULL,
+    data JSONB,
+    last_transaction_version BIGINT NOT NULL,
+    PRIMARY KEY (marketplace, resource_type, address)
+);
@@ -1,3 +1,10 @@
+pub mod activity_retention;
 pub mod postgres_utils;
 // pub mod processor_status;
 pub mod backfill_processor_status;
+pub mod discovered_event_types;
+pub mod listing_snapshot;
+pub mod queries;
+pub mod resource_last_writes;
+pub mod seen_contracts;
+pub mod token_owners;
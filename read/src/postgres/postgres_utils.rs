@@ -4,20 +4,28 @@
 //! Database-related functions
 #![allow(clippy::extra_unused_lifetimes)]
 
+use crate::config::PostgresConfig;
 use ahash::AHashMap;
 use aptos_indexer_processor_sdk::utils::{convert::remove_null_bytes, errors::ProcessorError};
-use diesel::{query_builder::QueryFragment, ConnectionResult, QueryResult};
+use diesel::{
+    define_sql_function,
+    migration::{Migration, MigrationSource, MigrationVersion},
+    query_builder::QueryFragment,
+    sql_types::{SingleValue, SqlType},
+    ConnectionError, ConnectionResult, QueryResult,
+};
 use diesel_async::{
     pooled_connection::{
         bb8::{Pool, PooledConnection},
         AsyncDieselConnectionManager, ManagerConfig, PoolError,
     },
-    AsyncPgConnection, RunQueryDsl,
+    scoped_futures::ScopedFutureExt,
+    AsyncConnection, AsyncPgConnection, RunQueryDsl,
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use futures_util::{future::BoxFuture, FutureExt};
-use std::sync::Arc;
-use tracing::{info, warn};
+use std::{sync::Arc, time::Duration};
+use tracing::{error, info, warn};
 
 pub type Backend = diesel::pg::Pg;
 
@@ -33,6 +41,22 @@ pub const DEFAULT_MAX_POOL_SIZE: u32 = 150;
 // the max is actually u16::MAX but we see that when the size is too big we get an overflow error so reducing it a bit
 pub const MAX_DIESEL_PARAM_SIZE: usize = (u16::MAX / 2) as usize;
 
+define_sql_function! {
+    /// Postgres' `GREATEST`, e.g. to keep an upsert from moving a stored value backwards.
+    fn greatest<T: SqlType + SingleValue>(x: T, y: T) -> T;
+}
+
+/// Timeouts of the pool and its connections. Unset ones keep bb8's and Postgres' defaults.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolTimeouts {
+    /// How long getting a connection from the pool waits for one to be free.
+    pub connection_timeout: Option<Duration>,
+    /// How long a connection stays open without being used.
+    pub idle_timeout: Option<Duration>,
+    /// The `statement_timeout` set on every connection the pool opens.
+    pub statement_timeout: Option<Duration>,
+}
+
 /// This function will clean the data for postgres. Currently it has support for removing
 /// null bytes from strings but in the future we will add more functionality.
 pub fn clean_data_for_db<T: serde::Serialize + for<'de> serde::Deserialize<'de>>(
@@ -67,7 +91,7 @@ fn establish_connection(database_url: &str) -> BoxFuture<ConnectionResult<AsyncP
             .expect("Could not connect to database");
         tokio::spawn(async move {
             if let Err(e) = connection.await {
-                eprintln!("connection error: {e}");
+                error!("Postgres connection error: {e}");
             }
         });
         AsyncPgConnection::try_from(client).await
@@ -95,23 +119,68 @@ fn parse_and_clean_db_url(url: &str) -> (String, Option<String>) {
 pub async fn new_db_pool(
     database_url: &str,
     max_pool_size: Option<u32>,
+) -> Result<ArcDbPool, PoolError> {
+    new_db_pool_with_timeouts(database_url, max_pool_size, PoolTimeouts::default()).await
+}
+
+pub async fn new_db_pool_with_timeouts(
+    database_url: &str,
+    max_pool_size: Option<u32>,
+    timeouts: PoolTimeouts,
 ) -> Result<ArcDbPool, PoolError> {
     let (_url, cert_path) = parse_and_clean_db_url(database_url);
+    let use_tls = cert_path.is_some();
+    let statement_timeout = timeouts.statement_timeout;
 
-    let config = if cert_path.is_some() {
-        let mut config = ManagerConfig::<MyDbConnection>::default();
-        config.custom_setup = Box::new(|conn| Box::pin(establish_connection(conn)));
-        AsyncDieselConnectionManager::<MyDbConnection>::new_with_config(database_url, config)
-    } else {
-        AsyncDieselConnectionManager::<MyDbConnection>::new(database_url)
-    };
-    let pool = Pool::builder()
-        .max_size(max_pool_size.unwrap_or(DEFAULT_MAX_POOL_SIZE))
-        .build(config)
-        .await?;
+    let mut config = ManagerConfig::<MyDbConnection>::default();
+    config.custom_setup = Box::new(move |url| {
+        Box::pin(async move {
+            let mut conn = if use_tls {
+                establish_connection(url).await?
+            } else {
+                AsyncPgConnection::establish(url).await?
+            };
+            if let Some(statement_timeout) = statement_timeout {
+                diesel::sql_query(format!(
+                    "SET statement_timeout = {}",
+                    statement_timeout.as_millis()
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(ConnectionError::CouldntSetupConfiguration)?;
+            }
+            Ok::<_, ConnectionError>(conn)
+        })
+    });
+    let config =
+        AsyncDieselConnectionManager::<MyDbConnection>::new_with_config(database_url, config);
+
+    let mut builder = Pool::builder().max_size(max_pool_size.unwrap_or(DEFAULT_MAX_POOL_SIZE));
+    if let Some(connection_timeout) = timeouts.connection_timeout {
+        builder = builder.connection_timeout(connection_timeout);
+    }
+    if let Some(idle_timeout) = timeouts.idle_timeout {
+        builder = builder.idle_timeout(idle_timeout);
+    }
+    let pool = builder.build(config).await?;
     Ok(Arc::new(pool))
 }
 
+/// Creates `schema` unless it exists, so that the migrations can create the tables in it.
+/// The name is expected to be validated already, see
+/// [`crate::config::PostgresConfig::connection_string_with_schema`].
+pub async fn create_schema(pool: &ArcDbPool, schema: &str) -> anyhow::Result<()> {
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get database connection: {e:?}"))?;
+    diesel::sql_query(format!("CREATE SCHEMA IF NOT EXISTS {schema}"))
+        .execute(&mut conn)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to create schema {schema}: {e}"))?;
+    Ok(())
+}
+
 pub async fn execute_in_chunks<U, T>(
     conn: ArcDbPool,
     build_query: fn(Vec<T>) -> U,
@@ -144,6 +213,52 @@ where
     Ok(())
 }
 
+/// Like [`execute_in_chunks`], but runs the chunks one after the other on `conn`, so they can
+/// share the caller's transaction. Each chunk runs in a savepoint: a chunk that fails is rolled
+/// back on its own and retried with cleaned data, without aborting the enclosing transaction.
+pub async fn execute_in_chunks_on_conn<U, T>(
+    conn: &mut MyDbConnection,
+    build_query: fn(Vec<T>) -> U,
+    items_to_insert: &[T],
+    chunk_size: usize,
+) -> QueryResult<()>
+where
+    U: QueryFragment<Backend> + diesel::query_builder::QueryId + Send,
+    T: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone + Send,
+{
+    for chunk in items_to_insert.chunks(chunk_size) {
+        let items = chunk.to_vec();
+        let query = build_query(items.clone());
+        let result: QueryResult<usize> = conn
+            .transaction(|conn| execute_with_better_error_conn(conn, query).scope_boxed())
+            .await;
+        if result.is_err() {
+            let cleaned_query = build_query(clean_data_for_db(items, true));
+            conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                execute_with_better_error_conn(conn, cleaned_query).scope_boxed()
+            })
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `e` may go away when the statement is retried: a dropped connection or a
+/// serialization failure between concurrent transactions. Errors about the data itself, such
+/// as constraint violations, fail the same way every time.
+pub fn is_transient_error(e: &diesel::result::Error) -> bool {
+    use diesel::result::{DatabaseErrorKind, Error};
+
+    match e {
+        Error::DatabaseError(kind, _) => matches!(
+            kind,
+            DatabaseErrorKind::ClosedConnection | DatabaseErrorKind::SerializationFailure
+        ),
+        Error::BrokenTransactionManager => true,
+        _ => false,
+    }
+}
+
 /// Returns the entry for the config hashmap, or the default field count for the insert.
 ///
 /// Given diesel has a limit of how many parameters can be inserted in a single operation (u16::MAX),
@@ -208,7 +323,7 @@ async fn execute_or_retry_cleaned<U, T>(
 ) -> Result<(), ProcessorError>
 where
     U: QueryFragment<Backend> + diesel::query_builder::QueryId + Send,
-    T: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone,
+    T: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone + Send,
 {
     match execute_with_better_error(conn.clone(), query).await {
         Ok(_) => {},
@@ -226,23 +341,66 @@ where
     Ok(())
 }
 
-pub fn run_pending_migrations<DB: diesel::backend::Backend>(conn: &mut impl MigrationHarness<DB>) {
-    conn.run_pending_migrations(MIGRATIONS)
+/// Version of `2025-03-27-050413_move-processor-status`, which copies the processor status out
+/// of `public.processor_status`.
+const MOVE_PROCESSOR_STATUS_VERSION: &str = "20250327050413";
+
+/// [`MIGRATIONS`] as they run in a schema set with [`PostgresConfig::schema`].
+///
+/// Such a schema never held the `processor_status` that `move-processor-status` copies out of
+/// `public`, and copying `public`'s again would conflict with the rows it already moved, so the
+/// schema skips that migration. `add_schema_processor_metadata` creates the tables it would
+/// have created.
+pub struct SchemaMigrations;
+
+impl<DB: diesel::backend::Backend> MigrationSource<DB> for SchemaMigrations {
+    fn migrations(&self) -> diesel::migration::Result<Vec<Box<dyn Migration<DB>>>> {
+        let mut migrations = MigrationSource::<DB>::migrations(&MIGRATIONS)?;
+        migrations.retain(|migration| {
+            migration.name().version() != MigrationVersion::from(MOVE_PROCESSOR_STATUS_VERSION)
+        });
+        Ok(migrations)
+    }
+}
+
+pub fn run_pending_migrations<DB: diesel::backend::Backend>(
+    conn: &mut impl MigrationHarness<DB>,
+    migrations: impl MigrationSource<DB>,
+) {
+    conn.run_pending_migrations(migrations)
         .expect("[Parser] Migrations failed!");
 }
 
+/// Runs the migrations in the schema of `postgres_config`, see [`SchemaMigrations`].
+pub async fn run_schema_migrations(
+    postgres_config: &PostgresConfig,
+    conn_pool: ArcDbPool,
+) -> anyhow::Result<()> {
+    let connection_string = postgres_config.connection_string_with_schema()?;
+    if postgres_config.schema.is_some() {
+        run_migrations(connection_string, conn_pool, SchemaMigrations).await;
+    } else {
+        run_migrations(connection_string, conn_pool, MIGRATIONS).await;
+    }
+    Ok(())
+}
+
 // For the normal processor build we just use standard Diesel with the postgres
 // feature enabled (which uses libpq under the hood, hence why we named the feature
 // this way).
 #[cfg(feature = "libpq")]
-pub async fn run_migrations(postgres_connection_string: String, _conn_pool: ArcDbPool) {
+pub async fn run_migrations(
+    postgres_connection_string: String,
+    _conn_pool: ArcDbPool,
+    migrations: impl MigrationSource<Backend> + Send + 'static,
+) {
     use diesel::{Connection, PgConnection};
 
     info!("Running migrations: {:?}", postgres_connection_string);
     let migration_time = std::time::Instant::now();
     let mut conn =
         PgConnection::establish(&postgres_connection_string).expect("migrations failed!");
-    run_pending_migrations(&mut conn);
+    run_pending_migrations(&mut conn, migrations);
     info!(
         duration_in_secs = migration_time.elapsed().as_secs_f64(),
         "[Parser] Finished migrations"
@@ -252,7 +410,11 @@ pub async fn run_migrations(postgres_connection_string: String, _conn_pool: ArcD
 // If the libpq feature isn't enabled, we use diesel async instead. This is used by
 // the CLI for the local testnet, where we cannot tolerate the libpq dependency.
 #[cfg(not(feature = "libpq"))]
-pub async fn run_migrations(postgres_connection_string: String, conn_pool: ArcDbPool) {
+pub async fn run_migrations(
+    postgres_connection_string: String,
+    conn_pool: ArcDbPool,
+    migrations: impl MigrationSource<Backend> + Send + 'static,
+) {
     use diesel_async::async_connection_wrapper::AsyncConnectionWrapper;
 
     info!("Running migrations: {:?}", postgres_connection_string);
@@ -268,7 +430,7 @@ pub async fn run_migrations(postgres_connection_string: String, conn_pool: ArcDb
         // https://docs.rs/diesel-async/latest/diesel_async/async_connection_wrapper/type.AsyncConnectionWrapper.html
         let mut conn: AsyncConnectionWrapper<diesel_async::AsyncPgConnection> =
             AsyncConnectionWrapper::from(conn);
-        run_pending_migrations(&mut conn);
+        run_pending_migrations(&mut conn, migrations);
     })
     .await
     .expect("[Parser] Failed to run migrations");
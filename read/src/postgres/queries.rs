@@ -0,0 +1,61 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Typed reads over the current state tables for serving queries. Each lookup is backed by an
+//! index on the column it filters on.
+
+use crate::{
+    models::nft_models::{CurrentNFTMarketplaceListing, CurrentNFTMarketplaceTokenOffer},
+    postgres::postgres_utils::DbPoolConnection,
+    schema::{current_nft_marketplace_listings, current_nft_marketplace_token_offers},
+};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+
+/// Returns the open listings of a collection across marketplaces, cheapest first.
+pub async fn active_listings_for_collection(
+    conn: &mut DbPoolConnection<'_>,
+    collection_id: &str,
+) -> diesel::QueryResult<Vec<CurrentNFTMarketplaceListing>> {
+    current_nft_marketplace_listings::table
+        .filter(current_nft_marketplace_listings::collection_id.eq(collection_id))
+        .filter(current_nft_marketplace_listings::is_deleted.eq(false))
+        .order_by((
+            current_nft_marketplace_listings::price,
+            current_nft_marketplace_listings::token_data_id,
+        ))
+        .load::<CurrentNFTMarketplaceListing>(conn)
+        .await
+}
+
+/// Returns the open listings of a seller across marketplaces, most recent first.
+pub async fn active_listings_for_seller(
+    conn: &mut DbPoolConnection<'_>,
+    seller: &str,
+) -> diesel::QueryResult<Vec<CurrentNFTMarketplaceListing>> {
+    current_nft_marketplace_listings::table
+        .filter(current_nft_marketplace_listings::seller.eq(seller))
+        .filter(current_nft_marketplace_listings::is_deleted.eq(false))
+        .order_by((
+            current_nft_marketplace_listings::last_transaction_version.desc(),
+            current_nft_marketplace_listings::token_data_id,
+        ))
+        .load::<CurrentNFTMarketplaceListing>(conn)
+        .await
+}
+
+/// Returns the open token offers made by a buyer across marketplaces, most recent first.
+pub async fn active_token_offers_for_buyer(
+    conn: &mut DbPoolConnection<'_>,
+    buyer: &str,
+) -> diesel::QueryResult<Vec<CurrentNFTMarketplaceTokenOffer>> {
+    current_nft_marketplace_token_offers::table
+        .filter(current_nft_marketplace_token_offers::buyer.eq(buyer))
+        .filter(current_nft_marketplace_token_offers::is_deleted.eq(false))
+        .order_by((
+            current_nft_marketplace_token_offers::last_transaction_version.desc(),
+            current_nft_marketplace_token_offers::token_data_id,
+        ))
+        .load::<CurrentNFTMarketplaceTokenOffer>(conn)
+        .await
+}
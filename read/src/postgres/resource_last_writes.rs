@@ -0,0 +1,178 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Keeps the last write of the resources in `resource_event_model_mapping`, e.g. the `Listing`
+//! objects of a marketplace that emits no events. A write set doesn't tell a new resource from
+//! an updated one, and a deletion carries no data, so both are told apart and remapped with the
+//! resource's last write. The last writes are stored with the batch of their version, so that a
+//! restarted processor still knows the resources written before it stopped.
+
+use crate::{postgres::postgres_utils::ArcDbPool, schema::resource_last_writes};
+use anyhow::{Context, Result};
+use diesel::{
+    pg::{upsert::excluded, Pg},
+    query_builder::QueryFragment,
+    ExpressionMethods, Insertable, QueryDsl,
+};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, mem, sync::Mutex};
+
+#[derive(Clone, Debug, Deserialize, Insertable, PartialEq, Serialize)]
+#[diesel(table_name = resource_last_writes)]
+pub struct ResourceLastWrite {
+    pub marketplace: String,
+    pub resource_type: String,
+    pub address: String,
+    /// `None` once the resource is deleted.
+    pub data: Option<Value>,
+    pub last_transaction_version: i64,
+}
+
+#[derive(Default)]
+struct LastWrites {
+    /// Data of the last write of each resource that exists, by type and address.
+    resources: HashMap<(String, String), Value>,
+    /// Changes waiting for the batch of their version to be written, `None` for deletions.
+    pending: HashMap<(String, String), (Option<Value>, i64)>,
+}
+
+/// The last writes of a marketplace's resources, as of the transactions remapped so far.
+pub struct ResourceLastWrites {
+    marketplace: String,
+    last_writes: Mutex<LastWrites>,
+}
+
+impl ResourceLastWrites {
+    pub fn new(marketplace: String) -> Self {
+        Self {
+            marketplace,
+            last_writes: Mutex::default(),
+        }
+    }
+
+    /// Records a write of the resource, returning whether the resource is new.
+    pub fn write(&self, resource_type: &str, address: &str, data: Value, txn_version: i64) -> bool {
+        let key = (resource_type.to_string(), address.to_string());
+        let mut last_writes = self.last_writes.lock().unwrap();
+        last_writes
+            .pending
+            .insert(key.clone(), (Some(data.clone()), txn_version));
+        last_writes.resources.insert(key, data).is_none()
+    }
+
+    /// Records the deletion of the resource, returning the data of its last write, or `None`
+    /// when no write was seen.
+    pub fn delete(&self, resource_type: &str, address: &str, txn_version: i64) -> Option<Value> {
+        let key = (resource_type.to_string(), address.to_string());
+        let mut last_writes = self.last_writes.lock().unwrap();
+        last_writes.pending.insert(key.clone(), (None, txn_version));
+        last_writes.resources.remove(&key)
+    }
+
+    /// Removes and returns the changes recorded at or before `end_version`. The remapping step
+    /// can run batches ahead of the one being written, and their changes must wait until their
+    /// own batch is stored.
+    pub fn take_up_to(&self, end_version: i64) -> Vec<ResourceLastWrite> {
+        let mut last_writes = self.last_writes.lock().unwrap();
+        let (taken, pending) = mem::take(&mut last_writes.pending)
+            .into_iter()
+            .partition::<HashMap<_, _>, _>(|(_, (_, txn_version))| *txn_version <= end_version);
+        last_writes.pending = pending;
+        let mut taken: Vec<ResourceLastWrite> = taken
+            .into_iter()
+            .map(
+                |((resource_type, address), (data, txn_version))| ResourceLastWrite {
+                    marketplace: self.marketplace.clone(),
+                    resource_type,
+                    address,
+                    data,
+                    last_transaction_version: txn_version,
+                },
+            )
+            .collect();
+        taken.sort_by(|a, b| (&a.resource_type, &a.address).cmp(&(&b.resource_type, &b.address)));
+        taken
+    }
+
+    /// Replaces the resources with the ones stored for the marketplace and drops the pending
+    /// changes, so that the stream can resume from the last written batch.
+    pub async fn load(&self, db_pool: &ArcDbPool) -> Result<()> {
+        let mut conn = db_pool
+            .get()
+            .await
+            .context("Failed to get database connection")?;
+        let stored: Vec<(String, String, Option<Value>)> = resource_last_writes::table
+            .select((
+                resource_last_writes::resource_type,
+                resource_last_writes::address,
+                resource_last_writes::data,
+            ))
+            .filter(resource_last_writes::marketplace.eq(&self.marketplace))
+            .filter(resource_last_writes::data.is_not_null())
+            .load(&mut conn)
+            .await
+            .context("Failed to load resource_last_writes")?;
+
+        let mut last_writes = self.last_writes.lock().unwrap();
+        last_writes.pending.clear();
+        last_writes.resources = stored
+            .into_iter()
+            .filter_map(|(resource_type, address, data)| Some(((resource_type, address), data?)))
+            .collect();
+        Ok(())
+    }
+}
+
+pub fn insert_resource_last_writes(
+    items_to_insert: Vec<ResourceLastWrite>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::resource_last_writes::dsl::*;
+
+    diesel::insert_into(crate::schema::resource_last_writes::table)
+        .values(items_to_insert)
+        .on_conflict((marketplace, resource_type, address))
+        .do_update()
+        .set((
+            data.eq(excluded(data)),
+            last_transaction_version.eq(excluded(last_transaction_version)),
+        ))
+        .filter(last_transaction_version.le(excluded(last_transaction_version)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tells_new_resources_from_updates() {
+        let last_writes = ResourceLastWrites::new("wapal".to_string());
+        assert!(last_writes.write("0x1::m::Listing", "0xa", Value::from(1), 10));
+        assert!(!last_writes.write("0x1::m::Listing", "0xa", Value::from(2), 11));
+        assert_eq!(
+            last_writes.delete("0x1::m::Listing", "0xa", 12),
+            Some(Value::from(2))
+        );
+        assert_eq!(last_writes.delete("0x1::m::Listing", "0xa", 13), None);
+        assert!(last_writes.write("0x1::m::Listing", "0xa", Value::from(3), 14));
+    }
+
+    #[test]
+    fn test_take_up_to_keeps_later_changes() {
+        let last_writes = ResourceLastWrites::new("wapal".to_string());
+        last_writes.write("0x1::m::Listing", "0xa", Value::from(1), 10);
+        last_writes.delete("0x1::m::Listing", "0xa", 12);
+        last_writes.write("0x1::m::Listing", "0xb", Value::from(2), 21);
+
+        assert_eq!(last_writes.take_up_to(20), vec![ResourceLastWrite {
+            marketplace: "wapal".to_string(),
+            resource_type: "0x1::m::Listing".to_string(),
+            address: "0xa".to_string(),
+            data: None,
+            last_transaction_version: 12,
+        }]);
+        assert!(last_writes.take_up_to(20).is_empty());
+        assert_eq!(last_writes.take_up_to(30).len(), 1);
+    }
+}
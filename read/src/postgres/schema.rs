@@ -14,6 +14,56 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    collection_offer_fills (txn_version, index, marketplace) {
+        txn_version -> Int8,
+        index -> Int8,
+        marketplace -> Varchar,
+        #[max_length = 128]
+        collection_offer_id -> Varchar,
+        #[max_length = 66]
+        token_data_id -> Varchar,
+        #[max_length = 66]
+        buyer -> Nullable<Varchar>,
+        #[max_length = 66]
+        seller -> Nullable<Varchar>,
+        price -> Numeric,
+        block_timestamp -> Timestamp,
+    }
+}
+
+diesel::table! {
+    current_nft_marketplace_auctions (token_data_id, marketplace) {
+        #[max_length = 66]
+        token_data_id -> Varchar,
+        marketplace -> Varchar,
+        #[max_length = 128]
+        auction_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        collection_id -> Nullable<Varchar>,
+        token_name -> Nullable<Varchar>,
+        #[max_length = 66]
+        seller -> Nullable<Varchar>,
+        #[max_length = 66]
+        current_bidder -> Nullable<Varchar>,
+        starting_price -> Nullable<Numeric>,
+        ending_price -> Nullable<Numeric>,
+        current_bid -> Nullable<Numeric>,
+        start_time -> Nullable<Timestamp>,
+        end_time -> Nullable<Timestamp>,
+        is_deleted -> Bool,
+        contract_address -> Varchar,
+        last_transaction_version -> Int8,
+        last_transaction_timestamp -> Timestamp,
+        standard_event_type -> Varchar,
+        payment_token -> Varchar,
+        #[max_length = 10]
+        token_standard -> Nullable<Varchar>,
+        inserted_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     current_nft_marketplace_collection_offers (collection_offer_id, marketplace) {
         #[max_length = 128]
@@ -22,8 +72,8 @@ diesel::table! {
         collection_id -> Nullable<Varchar>,
         #[max_length = 66]
         buyer -> Varchar,
-        price -> Int8,
-        remaining_token_amount -> Nullable<Int8>,
+        price -> Numeric,
+        remaining_token_amount -> Nullable<Numeric>,
         is_deleted -> Bool,
         marketplace -> Varchar,
         contract_address -> Varchar,
@@ -34,6 +84,16 @@ diesel::table! {
         token_data_id -> Nullable<Varchar>,
         expiration_time -> Nullable<Timestamp>,
         bid_key -> Nullable<Int8>,
+        price_raw -> Nullable<Numeric>,
+        price_display -> Nullable<Numeric>,
+        payment_token -> Varchar,
+        #[max_length = 66]
+        creator_address -> Nullable<Varchar>,
+        collection_name -> Nullable<Varchar>,
+        #[max_length = 10]
+        token_standard -> Nullable<Varchar>,
+        inserted_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -47,8 +107,8 @@ diesel::table! {
         collection_id -> Nullable<Varchar>,
         #[max_length = 66]
         seller -> Nullable<Varchar>,
-        price -> Int8,
-        token_amount -> Nullable<Int8>,
+        price -> Numeric,
+        token_amount -> Nullable<Numeric>,
         token_name -> Nullable<Varchar>,
         is_deleted -> Bool,
         marketplace -> Varchar,
@@ -56,11 +116,25 @@ diesel::table! {
         last_transaction_version -> Int8,
         last_transaction_timestamp -> Timestamp,
         standard_event_type -> Varchar,
+        price_raw -> Nullable<Numeric>,
+        price_display -> Nullable<Numeric>,
+        payment_token -> Varchar,
+        #[max_length = 66]
+        creator_address -> Nullable<Varchar>,
+        collection_name -> Nullable<Varchar>,
+        #[max_length = 10]
+        token_standard -> Nullable<Varchar>,
+        filled_token_amount -> Nullable<Numeric>,
+        inserted_at -> Timestamp,
+        updated_at -> Timestamp,
+        #[max_length = 66]
+        owner -> Nullable<Varchar>,
+        is_stale -> Bool,
     }
 }
 
 diesel::table! {
-    current_nft_marketplace_token_offers (token_data_id, buyer, marketplace) {
+    current_nft_marketplace_token_offers (token_data_id, buyer, marketplace, offer_key) {
         #[max_length = 66]
         token_data_id -> Varchar,
         #[max_length = 128]
@@ -70,8 +144,8 @@ diesel::table! {
         collection_id -> Nullable<Varchar>,
         #[max_length = 66]
         buyer -> Varchar,
-        price -> Int8,
-        token_amount -> Nullable<Int8>,
+        price -> Numeric,
+        token_amount -> Nullable<Numeric>,
         token_name -> Nullable<Varchar>,
         is_deleted -> Bool,
         contract_address -> Varchar,
@@ -80,6 +154,31 @@ diesel::table! {
         standard_event_type -> Varchar,
         expiration_time -> Nullable<Timestamp>,
         bid_key -> Nullable<Int8>,
+        price_raw -> Nullable<Numeric>,
+        price_display -> Nullable<Numeric>,
+        payment_token -> Varchar,
+        #[max_length = 66]
+        creator_address -> Nullable<Varchar>,
+        collection_name -> Nullable<Varchar>,
+        #[max_length = 10]
+        token_standard -> Nullable<Varchar>,
+        inserted_at -> Timestamp,
+        updated_at -> Timestamp,
+        #[max_length = 128]
+        offer_key -> Varchar,
+        property_version -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    discovered_event_types (marketplace, contract_address, event_type) {
+        marketplace -> Varchar,
+        #[max_length = 66]
+        contract_address -> Varchar,
+        event_type -> Varchar,
+        occurrences -> Int8,
+        last_seen_version -> Int8,
+        last_updated -> Timestamp,
     }
 }
 
@@ -97,8 +196,8 @@ diesel::table! {
         #[max_length = 66]
         token_data_id -> Nullable<Varchar>,
         token_name -> Nullable<Varchar>,
-        price -> Int8,
-        token_amount -> Nullable<Int8>,
+        price -> Numeric,
+        token_amount -> Nullable<Numeric>,
         #[max_length = 66]
         buyer -> Nullable<Varchar>,
         #[max_length = 66]
@@ -113,6 +212,27 @@ diesel::table! {
         block_timestamp -> Timestamp,
         expiration_time -> Nullable<Timestamp>,
         bid_key -> Nullable<Int8>,
+        price_raw -> Nullable<Numeric>,
+        price_display -> Nullable<Numeric>,
+        payment_token -> Varchar,
+        #[max_length = 10]
+        token_standard -> Nullable<Varchar>,
+        custom_fields -> Nullable<Jsonb>,
+        #[max_length = 64]
+        content_hash -> Nullable<Varchar>,
+        inserted_at -> Timestamp,
+        updated_at -> Timestamp,
+        marketplace_fee -> Nullable<Numeric>,
+        royalty_fee -> Nullable<Numeric>,
+        is_self_trade -> Bool,
+        #[max_length = 20]
+        direction -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    ledger_infos (chain_id) {
+        chain_id -> Int8,
     }
 }
 
@@ -126,11 +246,38 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    resource_last_writes (marketplace, resource_type, address) {
+        marketplace -> Varchar,
+        resource_type -> Varchar,
+        #[max_length = 66]
+        address -> Varchar,
+        data -> Nullable<Jsonb>,
+        last_transaction_version -> Int8,
+    }
+}
+
+diesel::table! {
+    seen_contracts (marketplace, contract_address) {
+        marketplace -> Varchar,
+        #[max_length = 66]
+        contract_address -> Varchar,
+        first_seen_version -> Int8,
+        inserted_at -> Timestamp,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
     backfill_processor_status,
+    collection_offer_fills,
+    current_nft_marketplace_auctions,
     current_nft_marketplace_collection_offers,
     current_nft_marketplace_listings,
     current_nft_marketplace_token_offers,
+    discovered_event_types,
+    ledger_infos,
     nft_marketplace_activities,
     processor_status,
+    resource_last_writes,
+    seen_contracts,
 );
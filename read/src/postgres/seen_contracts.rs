@@ -0,0 +1,168 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Records the contract addresses each marketplace's activities come from. The first activity
+//! from an address that isn't recorded yet is logged, which catches a marketplace redeploying
+//! its contract before its config is updated.
+
+use crate::{
+    metrics::NFT_CONTRACTS_FIRST_SEEN, models::nft_models::NftMarketplaceActivity,
+    postgres::postgres_utils::ArcDbPool, schema::seen_contracts,
+};
+use anyhow::{Context, Result};
+use diesel::{Insertable, Queryable};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+use tracing::info;
+
+#[derive(Clone, Debug, Deserialize, Insertable, PartialEq, Queryable, Serialize)]
+#[diesel(table_name = seen_contracts)]
+pub struct SeenContract {
+    pub marketplace: String,
+    pub contract_address: String,
+    pub first_seen_version: i64,
+}
+
+/// The `(marketplace, contract_address)` pairs already recorded, so that only new pairs go
+/// to the database.
+#[derive(Default)]
+pub struct SeenContracts {
+    seen: Mutex<HashSet<(String, String)>>,
+}
+
+impl SeenContracts {
+    /// Returns the pairs of `activities` this process hasn't recorded yet, each with the first
+    /// version it appears at.
+    pub fn unseen(&self, activities: &[NftMarketplaceActivity]) -> Vec<SeenContract> {
+        let mut first_seen_versions: HashMap<(&str, &str), i64> = HashMap::new();
+        for activity in activities {
+            let key = (
+                activity.marketplace.as_str(),
+                activity.contract_address.as_str(),
+            );
+            let first_seen_version = first_seen_versions
+                .entry(key)
+                .or_insert(activity.txn_version);
+            *first_seen_version = (*first_seen_version).min(activity.txn_version);
+        }
+        let seen = self.seen.lock().unwrap();
+        let mut unseen: Vec<SeenContract> = first_seen_versions
+            .into_iter()
+            .map(
+                |((marketplace, contract_address), first_seen_version)| SeenContract {
+                    marketplace: marketplace.to_string(),
+                    contract_address: contract_address.to_string(),
+                    first_seen_version,
+                },
+            )
+            .filter(|contract| {
+                !seen.contains(&(
+                    contract.marketplace.clone(),
+                    contract.contract_address.clone(),
+                ))
+            })
+            .collect();
+        unseen.sort_by(|a, b| {
+            (&a.marketplace, &a.contract_address).cmp(&(&b.marketplace, &b.contract_address))
+        });
+        unseen
+    }
+
+    pub fn mark_seen(&self, contracts: &[SeenContract]) {
+        let mut seen = self.seen.lock().unwrap();
+        for contract in contracts {
+            seen.insert((
+                contract.marketplace.clone(),
+                contract.contract_address.clone(),
+            ));
+        }
+    }
+
+    /// Stores the pairs of `activities` that weren't recorded yet and returns the ones that are
+    /// new to the database, logging and counting each of them. Pairs a previous run recorded
+    /// are only stored in memory.
+    pub async fn record(
+        &self,
+        db_pool: ArcDbPool,
+        activities: &[NftMarketplaceActivity],
+    ) -> Result<Vec<SeenContract>> {
+        let unseen = self.unseen(activities);
+        if unseen.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = db_pool
+            .get()
+            .await
+            .context("Failed to get database connection")?;
+        let first_seen: Vec<SeenContract> = diesel::insert_into(seen_contracts::table)
+            .values(&unseen)
+            .on_conflict_do_nothing()
+            .returning((
+                seen_contracts::marketplace,
+                seen_contracts::contract_address,
+                seen_contracts::first_seen_version,
+            ))
+            .get_results(&mut conn)
+            .await
+            .context("Failed to record seen contracts")?;
+        self.mark_seen(&unseen);
+
+        for contract in &first_seen {
+            info!(
+                marketplace = %contract.marketplace,
+                contract_address = %contract.contract_address,
+                first_seen_version = contract.first_seen_version,
+                "First activity from a new marketplace contract"
+            );
+            NFT_CONTRACTS_FIRST_SEEN
+                .with_label_values(&[contract.marketplace.as_str()])
+                .inc();
+        }
+        Ok(first_seen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_activity(txn_version: i64, contract_address: &str) -> NftMarketplaceActivity {
+        NftMarketplaceActivity {
+            txn_version,
+            marketplace: "wapal".to_string(),
+            contract_address: contract_address.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_detects_first_seen_contracts() {
+        let seen_contracts = SeenContracts::default();
+        let unseen = seen_contracts.unseen(&[
+            build_activity(12, "0x1"),
+            build_activity(10, "0x1"),
+            build_activity(11, "0x2"),
+        ]);
+        assert_eq!(unseen, vec![
+            SeenContract {
+                marketplace: "wapal".to_string(),
+                contract_address: "0x1".to_string(),
+                first_seen_version: 10,
+            },
+            SeenContract {
+                marketplace: "wapal".to_string(),
+                contract_address: "0x2".to_string(),
+                first_seen_version: 11,
+            },
+        ]);
+
+        seen_contracts.mark_seen(&unseen);
+        let unseen = seen_contracts.unseen(&[build_activity(13, "0x1"), build_activity(14, "0x3")]);
+        assert_eq!(unseen.len(), 1);
+        assert_eq!(unseen[0].contract_address, "0x3");
+    }
+}
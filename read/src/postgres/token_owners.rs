@@ -0,0 +1,145 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tracks who owns listed v2 tokens. A token listed without escrow stays in the seller's
+//! wallet and can be transferred away while the listing is still open, which no marketplace
+//! event reports. The `0x1::object::ObjectCore` write of the transfer does.
+
+use crate::postgres::postgres_utils::MyDbConnection;
+use diesel::{
+    sql_types::{Array, BigInt, Text},
+    QueryResult,
+};
+use diesel_async::RunQueryDsl;
+use std::{collections::HashMap, sync::Mutex};
+
+pub const OBJECT_CORE_TYPE: &str = "0x1::object::ObjectCore";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenOwner {
+    /// Address of the token's object, which is the `token_data_id` of v2 tokens.
+    pub token_data_id: String,
+    pub owner: String,
+    pub txn_version: i64,
+}
+
+/// The latest owner of each object seen by the remapping step, waiting for the batch of its
+/// version to be written.
+pub struct TokenOwners {
+    marketplace: String,
+    owners: Mutex<HashMap<String, (String, i64)>>,
+}
+
+impl TokenOwners {
+    pub fn new(marketplace: String) -> Self {
+        Self {
+            marketplace,
+            owners: Mutex::default(),
+        }
+    }
+
+    pub fn marketplace(&self) -> &str {
+        &self.marketplace
+    }
+
+    pub fn record(&self, token_data_id: String, owner: String, txn_version: i64) {
+        let mut owners = self.owners.lock().unwrap();
+        let entry = owners
+            .entry(token_data_id)
+            .or_insert_with(|| (owner.clone(), txn_version));
+        if txn_version >= entry.1 {
+            *entry = (owner, txn_version);
+        }
+    }
+
+    /// Removes and returns the owners recorded at or before `end_version`. The remapping step
+    /// can run batches ahead of the one being written, and their owners must wait until the
+    /// listings of their own batch are stored.
+    pub fn take_up_to(&self, end_version: i64) -> Vec<TokenOwner> {
+        let mut owners = self.owners.lock().unwrap();
+        let token_data_ids: Vec<String> = owners
+            .iter()
+            .filter(|(_, (_, txn_version))| *txn_version <= end_version)
+            .map(|(token_data_id, _)| token_data_id.clone())
+            .collect();
+        let mut taken: Vec<TokenOwner> = token_data_ids
+            .into_iter()
+            .filter_map(|token_data_id| {
+                let (owner, txn_version) = owners.remove(&token_data_id)?;
+                Some(TokenOwner {
+                    token_data_id,
+                    owner,
+                    txn_version,
+                })
+            })
+            .collect();
+        taken.sort_by(|a, b| a.token_data_id.cmp(&b.token_data_id));
+        taken
+    }
+}
+
+/// Stores the owner of the open listings among `owners`' tokens, and flags the listings
+/// whose token went to anyone other than the seller or the listing object as stale. Owners
+/// from the listing's own last transaction, e.g. the escrow of the token when it was listed,
+/// are ignored.
+///
+/// Runs a single statement per batch, joining the owners against the open listings, so the
+/// many objects that change owners without being listed cost nothing but the join. `owners`
+/// holds one owner per token, see [`TokenOwners::take_up_to`].
+pub async fn update_listing_owners(
+    conn: &mut MyDbConnection,
+    marketplace: &str,
+    owners: &[TokenOwner],
+) -> QueryResult<()> {
+    if owners.is_empty() {
+        return Ok(());
+    }
+    let token_data_ids: Vec<&str> = owners
+        .iter()
+        .map(|owner| owner.token_data_id.as_str())
+        .collect();
+    let new_owners: Vec<&str> = owners.iter().map(|owner| owner.owner.as_str()).collect();
+    let txn_versions: Vec<i64> = owners.iter().map(|owner| owner.txn_version).collect();
+
+    diesel::sql_query(
+        "UPDATE current_nft_marketplace_listings AS listings \
+         SET owner = owners.owner, \
+             is_stale = listings.seller IS DISTINCT FROM owners.owner \
+                 AND listings.listing_id IS DISTINCT FROM owners.owner, \
+             updated_at = NOW() \
+         FROM unnest($2::text[], $3::text[], $4::bigint[]) \
+             AS owners(token_data_id, owner, txn_version) \
+         WHERE listings.token_data_id = owners.token_data_id \
+             AND listings.marketplace = $1 \
+             AND listings.is_deleted = false \
+             AND listings.last_transaction_version < owners.txn_version",
+    )
+    .bind::<Text, _>(marketplace)
+    .bind::<Array<Text>, _>(token_data_ids)
+    .bind::<Array<Text>, _>(new_owners)
+    .bind::<Array<BigInt>, _>(txn_versions)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_up_to_keeps_later_owners() {
+        let token_owners = TokenOwners::new("wapal".to_string());
+        token_owners.record("0x1".to_string(), "0xa".to_string(), 12);
+        token_owners.record("0x1".to_string(), "0xb".to_string(), 10);
+        token_owners.record("0x2".to_string(), "0xc".to_string(), 21);
+
+        assert_eq!(token_owners.take_up_to(20), vec![TokenOwner {
+            token_data_id: "0x1".to_string(),
+            owner: "0xa".to_string(),
+            txn_version: 12,
+        }]);
+        assert!(token_owners.take_up_to(20).is_empty());
+        assert_eq!(token_owners.take_up_to(30).len(), 1);
+    }
+}
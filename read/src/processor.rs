@@ -1,14 +1,43 @@
 use crate::{
     config::{DbConfig, IndexerProcessorConfig},
+    health::run_health_server,
+    metrics::NFT_DB_ROWS_WRITTEN,
+    models::nft_models::{
+        COLLECTION_OFFER_FILLS_TABLE_NAME, CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME, NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME,
+    },
+    postgres::{
+        activity_retention::{run_activity_retention, DEFAULT_ACTIVITY_RETENTION_BATCH_SIZE},
+        discovered_event_types::run_discovery_flusher,
+        postgres_utils::{create_schema, new_db_pool_with_timeouts, run_schema_migrations},
+    },
     steps::{
+        auth_token_step::{
+            auth_token_from_file, AuthTokenSource, ConnectStream, RefreshAuthTokenStep,
+        },
+        batch_size_step::CapBatchSizeStep,
+        bigquery_step::BigQueryStep,
+        channel_depth_step::ChannelDepthStep,
+        clickhouse_step::ClickHouseStep,
         db_writing_step::DBWritingStep,
+        kafka_step::KafkaStep,
         processor_status_saver_step::{
-            get_end_version, get_starting_version, PostgresProcessorStatusSaver,
+            get_end_version, get_last_success_version, get_starting_version,
+            PostgresProcessorStatusSaver,
         },
         reduction_step::NFTReductionStep,
         remapper_step::ProcessStep,
+        remappers::plugins::PluginRegistry,
+        shutdown_step::StopOnShutdownStep,
+        webhook_step::WebhookStep,
+    },
+    utils::{
+        chain_id::{check_or_update_chain_id, get_chain_id_with_retries},
+        reconnect::{run_with_reconnects, StreamEnd},
+        shutdown::spawn_shutdown_listener,
     },
-    MIGRATIONS,
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
@@ -17,27 +46,26 @@ use aptos_indexer_processor_sdk::{
     common_steps::{
         TransactionStreamStep, VersionTrackerStep, DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
     },
-    postgres::utils::{
-        checkpoint::PostgresChainIdChecker,
-        database::{new_db_pool, run_migrations, ArcDbPool},
-    },
+    postgres::utils::database::ArcDbPool,
     traits::{processor_trait::ProcessorTrait, IntoRunnableStep},
-    utils::chain_id_check::check_or_update_chain_id,
 };
-use tracing::{debug, info};
+use tokio::sync::watch;
+use tracing::{debug, error, info};
 
 pub struct Processor {
     pub config: IndexerProcessorConfig,
     pub db_pool: ArcDbPool,
+    pub plugins: PluginRegistry,
 }
 
 impl Processor {
     pub async fn new(config: IndexerProcessorConfig) -> Result<Self> {
         match config.db_config {
             DbConfig::PostgresConfig(ref postgres_config) => {
-                let conn_pool = new_db_pool(
-                    &postgres_config.connection_string,
+                let conn_pool = new_db_pool_with_timeouts(
+                    &postgres_config.connection_string_with_schema()?,
                     Some(postgres_config.db_pool_size),
+                    postgres_config.pool_timeouts()?,
                 )
                 .await
                 .map_err(|e| {
@@ -46,76 +74,147 @@ impl Processor {
                         e
                     )
                 })?;
+                if let Some(schema) = &postgres_config.schema {
+                    create_schema(&conn_pool, schema).await?;
+                }
 
                 Ok(Self {
                     config,
                     db_pool: conn_pool,
+                    plugins: PluginRegistry::default(),
                 })
             },
         }
     }
-}
 
-#[async_trait::async_trait]
-impl ProcessorTrait for Processor {
-    fn name(&self) -> &'static str {
-        self.config.nft_marketplace_config.get_name()
+    /// Remaps the marketplace with its plugin in `plugins` instead of its event remappings, for
+    /// binaries that embed the processor with their own plugins.
+    pub fn with_plugins(mut self, plugins: PluginRegistry) -> Self {
+        self.plugins = plugins;
+        self
     }
 
-    async fn run_processor(&self) -> Result<()> {
-        // Run migrations
-        let DbConfig::PostgresConfig(ref postgres_config) = self.config.db_config;
-        run_migrations(
-            postgres_config.connection_string.clone(),
-            self.db_pool.clone(),
-            MIGRATIONS,
-        )
-        .await;
-
-        // Merge the starting version from config and the latest processed version from the DB
-        let (starting_version, ending_version) = (
-            get_starting_version(&self.config, self.db_pool.clone()).await?,
-            get_end_version(&self.config, self.db_pool.clone()).await?,
-        );
+    /// The stream config for the version range, with the token from `auth_token_path` if one is
+    /// set, and the source to refresh that token from.
+    fn transaction_stream_config(
+        &self,
+        starting_version: Option<u64>,
+        ending_version: Option<u64>,
+    ) -> Result<(TransactionStreamConfig, Option<AuthTokenSource>)> {
+        let auth_token = self
+            .config
+            .auth_token_path
+            .clone()
+            .map(auth_token_from_file);
+        let mut transaction_stream_config = TransactionStreamConfig {
+            starting_version,
+            request_ending_version: ending_version,
+            ..self.config.transaction_stream_config.clone()
+        };
+        if let Some(auth_token) = &auth_token {
+            transaction_stream_config.auth_token = auth_token()?;
+        }
+        Ok((transaction_stream_config, auth_token))
+    }
 
-        // Check and update the ledger chain id to ensure we're indexing the correct chain
-        check_or_update_chain_id(
-            &self.config.transaction_stream_config,
-            &PostgresChainIdChecker::new(self.db_pool.clone()),
-        )
-        .await?;
+    /// Connects to the transaction stream from `starting_version` and runs the pipeline until
+    /// the stream ends, returning how it ended.
+    async fn run_pipeline(
+        &self,
+        process: ProcessStep,
+        starting_version: Option<u64>,
+        ending_version: Option<u64>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<StreamEnd> {
+        let DbConfig::PostgresConfig(ref postgres_config) = self.config.db_config;
+        let (transaction_stream_config, auth_token) =
+            self.transaction_stream_config(starting_version, ending_version)?;
 
         let channel_size = 100;
 
-        // Define processor steps
-        let transaction_stream = TransactionStreamStep::new(TransactionStreamConfig {
-            starting_version,
-            request_ending_version: ending_version,
-            ..self.config.transaction_stream_config.clone()
-        })
-        .await?;
+        // The stream resumes from the last stored batch, so the remapper resumes from its writes
+        if let Some(resource_last_writes) = process.resource_last_writes() {
+            resource_last_writes.load(&self.db_pool).await?;
+        }
 
-        let nft_marketplace_config = self.config.nft_marketplace_config.clone();
+        // Define processor steps
+        let transaction_stream =
+            match TransactionStreamStep::new(transaction_stream_config.clone()).await {
+                Ok(transaction_stream) => transaction_stream,
+                Err(e) => {
+                    // Counts as a drop, so that a stream that can't reconnect is retried too
+                    error!("Failed to connect to the transaction stream: {e:?}");
+                    return Ok(StreamEnd::Dropped { last_version: None });
+                },
+            };
+        let connect: ConnectStream<TransactionStreamStep> =
+            Box::new(|config| Box::pin(TransactionStreamStep::new(config)));
+        let transaction_stream = RefreshAuthTokenStep::new(
+            transaction_stream,
+            transaction_stream_config,
+            auth_token,
+            connect,
+        );
+        let transaction_stream = CapBatchSizeStep::new(
+            transaction_stream,
+            self.config.max_batch_size.unwrap_or(usize::MAX),
+        );
+        let transaction_stream = StopOnShutdownStep::new(transaction_stream, shutdown.clone());
 
-        let process = ProcessStep::new(nft_marketplace_config.clone())?;
         let reduction_step = NFTReductionStep::new();
-        let db_writing = DBWritingStep::new(self.db_pool.clone());
+        let bigquery = BigQueryStep::new(self.config.bigquery_config.clone())?;
+        let db_writing = DBWritingStep::new(
+            self.db_pool.clone(),
+            postgres_config.db_chunk_size(),
+            self.config.query_retry_config.clone(),
+        )
+        .with_token_owners(process.token_owners())
+        .with_resource_last_writes(process.resource_last_writes());
+        let clickhouse = ClickHouseStep::new(
+            self.config.clickhouse_config.clone(),
+            &self.config.query_retry_config,
+        );
+        let kafka = KafkaStep::new(self.config.kafka_config.clone())?;
+        let webhook = WebhookStep::new(self.config.webhook_config.clone());
         let version_tracker = VersionTrackerStep::new(
             PostgresProcessorStatusSaver::new(self.config.clone(), self.db_pool.clone()),
             DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
         );
 
+        // Count the batches in each stage into `nft_channel_depth`, the sinks being one stage
+        let into_process = ChannelDepthStep::new(channel_size).before("process");
+        let into_reduction = ChannelDepthStep::new(channel_size)
+            .after("process")
+            .before("reduction");
+        let into_db_writing = ChannelDepthStep::new(channel_size)
+            .after("reduction")
+            .before("db_writing");
+        let into_sinks = ChannelDepthStep::new(channel_size)
+            .after("db_writing")
+            .before("sinks");
+        let out_of_sinks = ChannelDepthStep::new(channel_size).after("sinks");
+
         // Connect processor steps together
         let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
             transaction_stream.into_runnable_step(),
         )
+        .connect_to(into_process.into_runnable_step(), channel_size)
         .connect_to(process.into_runnable_step(), channel_size)
+        .connect_to(into_reduction.into_runnable_step(), channel_size)
         .connect_to(reduction_step.into_runnable_step(), channel_size)
+        .connect_to(into_db_writing.into_runnable_step(), channel_size)
         .connect_to(db_writing.into_runnable_step(), channel_size)
+        .connect_to(into_sinks.into_runnable_step(), channel_size)
+        .connect_to(bigquery.into_runnable_step(), channel_size)
+        .connect_to(clickhouse.into_runnable_step(), channel_size)
+        .connect_to(kafka.into_runnable_step(), channel_size)
+        .connect_to(webhook.into_runnable_step(), channel_size)
+        .connect_to(out_of_sinks.into_runnable_step(), channel_size)
         .connect_to(version_tracker.into_runnable_step(), channel_size)
         .end_and_return_output_receiver(channel_size);
 
         // (Optional) Parse the results
+        let mut last_version = None;
         loop {
             match buffer_receiver.recv().await {
                 Ok(txn_context) => {
@@ -123,12 +222,163 @@ impl ProcessorTrait for Processor {
                         "Finished processing events from versions [{:?}, {:?}]",
                         txn_context.metadata.start_version, txn_context.metadata.end_version,
                     );
+                    last_version = Some(txn_context.metadata.end_version);
                 },
                 Err(e) => {
                     info!("No more transactions in channel: {:?}", e);
-                    break Ok(());
+                    break;
                 },
             }
         }
+
+        if *shutdown.borrow() {
+            return Ok(StreamEnd::Shutdown);
+        }
+        let reached_end = ending_version
+            .zip(last_version)
+            .is_some_and(|(ending_version, last_version)| last_version >= ending_version);
+        Ok(if reached_end {
+            StreamEnd::Finished
+        } else {
+            StreamEnd::Dropped { last_version }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ProcessorTrait for Processor {
+    fn name(&self) -> &'static str {
+        self.config.nft_marketplace_config.get_name()
+    }
+
+    async fn run_processor(&self) -> Result<()> {
+        // Run migrations
+        let DbConfig::PostgresConfig(ref postgres_config) = self.config.db_config;
+        run_schema_migrations(postgres_config, self.db_pool.clone()).await?;
+
+        // Merge the starting version from config and the latest processed version from the DB,
+        // unless the range was overridden on the command line
+        let (starting_version, ending_version) = self.config.version_override.apply(
+            get_starting_version(&self.config, self.db_pool.clone()).await?,
+            get_end_version(&self.config, self.db_pool.clone()).await?,
+        )?;
+        info!(
+            ?starting_version,
+            ?ending_version,
+            "Processing transactions in version range"
+        );
+
+        // Check and update the ledger chain id to ensure we're indexing the correct chain
+        let (transaction_stream_config, _) =
+            self.transaction_stream_config(starting_version, ending_version)?;
+        let grpc_chain_id =
+            get_chain_id_with_retries(&transaction_stream_config, &self.config.query_retry_config)
+                .await?;
+        check_or_update_chain_id(
+            grpc_chain_id,
+            self.config.nft_marketplace_config.network,
+            self.db_pool.clone(),
+        )
+        .await?;
+
+        // Stop streaming on SIGTERM or Ctrl-C and let the batches in flight drain
+        let shutdown = spawn_shutdown_listener();
+
+        let nft_marketplace_config = self.config.nft_marketplace_config.clone();
+
+        // Built once and cloned for every connection, so the health server and the discovery
+        // flusher keep following it across reconnects
+        let process = ProcessStep::with_plugins(nft_marketplace_config.clone(), &self.plugins)?;
+        if let Some(health_config) = self.config.health_config.clone() {
+            let processor_name = self.config.processor_status_name();
+            let db_pool = self.db_pool.clone();
+            let stream_progress = process.stream_progress();
+            tokio::spawn(async move {
+                if let Err(e) =
+                    run_health_server(health_config, processor_name, db_pool, stream_progress).await
+                {
+                    error!("{e:#}");
+                }
+            });
+        }
+        let discovered_event_types = process.discovered_event_types();
+        if let Some(discovered_event_types) = discovered_event_types.clone() {
+            tokio::spawn(run_discovery_flusher(
+                discovered_event_types,
+                self.db_pool.clone(),
+            ));
+        }
+        if let Some(retention_days) = self.config.activity_retention_days {
+            tokio::spawn(run_activity_retention(
+                self.db_pool.clone(),
+                nft_marketplace_config.marketplace().to_string(),
+                retention_days,
+                self.config
+                    .activity_retention_batch_size
+                    .unwrap_or(DEFAULT_ACTIVITY_RETENTION_BATCH_SIZE),
+            ));
+        }
+
+        // A stream that drops resumes from the last saved version instead of ending the processor
+        let (process, shutdown_receiver) = (&process, &shutdown);
+        let run = move |starting_version| {
+            self.run_pipeline(
+                process.clone(),
+                starting_version,
+                ending_version,
+                shutdown_receiver.clone(),
+            )
+        };
+        let resume_version = move || async move {
+            Ok(get_starting_version(&self.config, self.db_pool.clone()).await?)
+        };
+        let result = run_with_reconnects(
+            &self.config.stream_reconnect_config,
+            starting_version,
+            run,
+            resume_version,
+        )
+        .await;
+
+        // Write what was discovered since the last periodic flush
+        if let Some(discovered_event_types) = discovered_event_types {
+            discovered_event_types.flush(self.db_pool.clone()).await;
+        }
+        result?;
+
+        if *shutdown.borrow() {
+            let last_success_version =
+                get_last_success_version(&self.config, self.db_pool.clone()).await?;
+            info!(
+                ?last_success_version,
+                "Shut down after draining the batches in flight"
+            );
+        }
+        if let Some(max_versions) = self.config.version_override.max_versions {
+            log_rows_written(max_versions, starting_version, ending_version);
+        }
+        Ok(())
+    }
+}
+
+/// Summarizes a `--max-versions` run. The counters cover the whole process, which is this one
+/// run.
+fn log_rows_written(max_versions: u64, starting_version: Option<u64>, ending_version: Option<u64>) {
+    info!(
+        max_versions,
+        ?starting_version,
+        ?ending_version,
+        "Stopped after the maximum number of versions"
+    );
+    for table in [
+        NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+        COLLECTION_OFFER_FILLS_TABLE_NAME,
+    ] {
+        let rows = NFT_DB_ROWS_WRITTEN.with_label_values(&[table]).get();
+        info!(table, rows, "Rows written");
     }
 }
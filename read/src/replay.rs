@@ -0,0 +1,106 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs transactions exported to JSON through the remapping steps without a transaction stream,
+//! e.g. to reproduce a reported data issue from the versions involved.
+
+use crate::{
+    config::{marketplace_config::NFTMarketplaceConfig, DbConfig, IndexerProcessorConfig},
+    postgres::postgres_utils::run_schema_migrations,
+    processor::Processor,
+    steps::{
+        db_writing_step::DBWritingStep, reduction_step::NFTReductionStep,
+        remapper_step::ProcessStep, remappers::RemapOutput,
+    },
+};
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::Transaction,
+    traits::Processable,
+    types::transaction_context::{TransactionContext, TransactionMetadata},
+};
+use std::{fs, path::Path};
+
+/// Parses a transaction exported to JSON, in the format of the `aptos-indexer-test-transactions`
+/// files the integration tests load.
+pub fn parse_transaction(json: &[u8]) -> Result<Transaction> {
+    serde_json::from_slice(json).context("Failed to parse transaction JSON")
+}
+
+/// Reads the transaction in the JSON file at `path`, or the ones in every `*.json` file of the
+/// directory at `path`.
+pub fn load_transactions(path: impl AsRef<Path>) -> Result<Vec<Transaction>> {
+    let path = path.as_ref();
+    let paths = if path.is_dir() {
+        let mut paths = fs::read_dir(path)
+            .with_context(|| format!("Failed to read transaction dir {}", path.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+        paths
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    paths
+        .iter()
+        .map(|path| {
+            let json =
+                fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            parse_transaction(&json).with_context(|| format!("Failed to load {}", path.display()))
+        })
+        .collect()
+}
+
+/// Remaps `transactions` with the processor's process and reduction steps, as a single batch in
+/// version order. The output holds the rows as the processor would write them.
+pub async fn replay(
+    config: &NFTMarketplaceConfig,
+    mut transactions: Vec<Transaction>,
+) -> Result<RemapOutput> {
+    transactions.sort_by_key(|transaction| transaction.version);
+    let metadata = TransactionMetadata {
+        start_version: transactions.first().map_or(0, |txn| txn.version),
+        end_version: transactions.last().map_or(0, |txn| txn.version),
+        ..Default::default()
+    };
+
+    let mut process = ProcessStep::new(config.clone())?;
+    let mut reduction = NFTReductionStep::new();
+    let Some(remapped) = process
+        .process(TransactionContext {
+            data: transactions,
+            metadata,
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to remap transactions: {e:?}"))?
+    else {
+        return Ok(RemapOutput::default());
+    };
+    let reduced = reduction
+        .process(remapped)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to reduce remapped models: {e:?}"))?;
+    Ok(reduced.map(|reduced| reduced.data).unwrap_or_default())
+}
+
+/// Writes a replay's rows to the configured database with the processor's DB writing step.
+pub async fn write_output(config: &IndexerProcessorConfig, output: RemapOutput) -> Result<()> {
+    let processor = Processor::new(config.clone()).await?;
+    let DbConfig::PostgresConfig(ref postgres_config) = config.db_config;
+    run_schema_migrations(postgres_config, processor.db_pool.clone()).await?;
+
+    DBWritingStep::new(
+        processor.db_pool.clone(),
+        postgres_config.db_chunk_size(),
+        config.query_retry_config.clone(),
+    )
+    .process(TransactionContext {
+        data: output,
+        metadata: TransactionMetadata::default(),
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to write replayed rows: {e:?}"))?;
+    Ok(())
+}
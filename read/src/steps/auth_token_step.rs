@@ -0,0 +1,265 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_indexer_transaction_stream::TransactionStreamConfig,
+    traits::{
+        pollable_async_step::PollableAsyncRunType, NamedStep, PollableAsyncStep, Processable,
+    },
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use futures::future::BoxFuture;
+use std::{fs, path::PathBuf};
+use tonic::async_trait;
+use tracing::{error, info};
+
+/// Reads the current gRPC auth token.
+pub type AuthTokenSource = Box<dyn Fn() -> Result<String> + Send + Sync>;
+
+/// Opens a new stream with the given config, e.g. with `TransactionStreamStep::new`.
+pub type ConnectStream<S> =
+    Box<dyn Fn(TransactionStreamConfig) -> BoxFuture<'static, Result<S>> + Send + Sync>;
+
+/// Reads the auth token from `path`, e.g. a file a secret manager rotates. Surrounding
+/// whitespace is ignored.
+pub fn auth_token_from_file(path: impl Into<PathBuf>) -> AuthTokenSource {
+    let path = path.into();
+    Box::new(move || {
+        let auth_token = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read auth token from {}", path.display()))?;
+        Ok(auth_token.trim().to_string())
+    })
+}
+
+/// Wraps the `TransactionStreamStep` so that a rotated auth token doesn't end the stream.
+///
+/// The stream reconnects on its own when the connection drops, but with the token it was
+/// created with. Once the token expires, every reconnect fails. Whenever a poll doesn't return
+/// a batch, the token is read again, and if it changed the stream is replaced by one using the
+/// new token that starts after the last version received.
+pub struct RefreshAuthTokenStep<S> {
+    inner: S,
+    config: TransactionStreamConfig,
+    auth_token: Option<AuthTokenSource>,
+    connect: ConnectStream<S>,
+}
+
+impl<S> RefreshAuthTokenStep<S> {
+    /// `inner` was connected with `config`. Without an `auth_token` source the token is never
+    /// refreshed and the step only forwards to `inner`.
+    pub fn new(
+        inner: S,
+        config: TransactionStreamConfig,
+        auth_token: Option<AuthTokenSource>,
+        connect: ConnectStream<S>,
+    ) -> Self {
+        Self {
+            inner,
+            config,
+            auth_token,
+            connect,
+        }
+    }
+
+    /// Reconnects if the auth token changed. Returns whether it did.
+    async fn refresh_auth_token(&mut self) -> Result<bool> {
+        let Some(auth_token) = &self.auth_token else {
+            return Ok(false);
+        };
+        let auth_token = auth_token()?;
+        if auth_token == self.config.auth_token {
+            return Ok(false);
+        }
+        let mut config = self.config.clone();
+        config.auth_token = auth_token;
+        self.inner = (self.connect)(config.clone()).await?;
+        self.config = config;
+        info!(
+            starting_version = ?self.config.starting_version,
+            "Reconnected to the transaction stream with a refreshed auth token"
+        );
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl<S> Processable for RefreshAuthTokenStep<S>
+where
+    S: PollableAsyncStep + Send + Sync + 'static,
+{
+    type Input = S::Input;
+    type Output = S::Output;
+    type RunType = PollableAsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<S::Input>,
+    ) -> Result<Option<TransactionContext<S::Output>>, ProcessorError> {
+        self.inner.process(input).await
+    }
+}
+
+#[async_trait]
+impl<S> PollableAsyncStep for RefreshAuthTokenStep<S>
+where
+    S: PollableAsyncStep + Send + Sync + 'static,
+{
+    fn poll_interval(&self) -> std::time::Duration {
+        self.inner.poll_interval()
+    }
+
+    async fn poll(&mut self) -> Result<Option<Vec<TransactionContext<S::Output>>>, ProcessorError> {
+        let result = self.inner.poll().await;
+        if let Ok(Some(batches)) = &result {
+            // A reconnect resumes after the last version received
+            if let Some(batch) = batches.last() {
+                self.config.starting_version = Some(batch.metadata.end_version + 1);
+            }
+            return result;
+        }
+        match self.refresh_auth_token().await {
+            // The next poll reads from the new stream
+            Ok(true) => Ok(None),
+            Ok(false) => result,
+            Err(e) => {
+                error!("Failed to refresh the auth token: {e:#}");
+                result
+            },
+        }
+    }
+
+    async fn should_continue_polling(&mut self) -> bool {
+        self.inner.should_continue_polling().await
+    }
+}
+
+impl<S: NamedStep> NamedStep for RefreshAuthTokenStep<S> {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::types::transaction_context::TransactionMetadata;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    /// Returns one version per poll while its token is the one `valid_token` accepts.
+    struct TestStream {
+        auth_token: String,
+        valid_token: Arc<Mutex<String>>,
+        next_version: u64,
+    }
+
+    #[async_trait]
+    impl Processable for TestStream {
+        type Input = ();
+        type Output = Vec<u64>;
+        type RunType = PollableAsyncRunType;
+
+        async fn process(
+            &mut self,
+            _input: TransactionContext<()>,
+        ) -> Result<Option<TransactionContext<Vec<u64>>>, ProcessorError> {
+            Ok(None)
+        }
+    }
+
+    #[async_trait]
+    impl PollableAsyncStep for TestStream {
+        fn poll_interval(&self) -> Duration {
+            Duration::from_millis(10)
+        }
+
+        async fn poll(
+            &mut self,
+        ) -> Result<Option<Vec<TransactionContext<Vec<u64>>>>, ProcessorError> {
+            if self.auth_token != *self.valid_token.lock().unwrap() {
+                return Err(ProcessorError::ProcessError {
+                    message: "Unauthenticated".to_string(),
+                });
+            }
+            let version = self.next_version;
+            self.next_version += 1;
+            Ok(Some(vec![TransactionContext {
+                data: vec![version],
+                metadata: TransactionMetadata {
+                    start_version: version,
+                    end_version: version,
+                    ..Default::default()
+                },
+            }]))
+        }
+    }
+
+    impl NamedStep for TestStream {
+        fn name(&self) -> String {
+            "TestStream".to_string()
+        }
+    }
+
+    fn polled_versions(
+        result: Result<Option<Vec<TransactionContext<Vec<u64>>>>, ProcessorError>,
+    ) -> Vec<u64> {
+        result
+            .unwrap()
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|batch| batch.data)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_with_rotated_auth_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let token_path = dir.path().join("auth_token");
+        fs::write(&token_path, "first\n").unwrap();
+        let valid_token = Arc::new(Mutex::new("first".to_string()));
+
+        let config: TransactionStreamConfig = serde_yaml::from_str(
+            r#"
+            indexer_grpc_data_service_address: "https://grpc.mainnet.aptoslabs.com:443"
+            starting_version: 10
+            auth_token: "first"
+            request_name_header: "nft-aggregator"
+            "#,
+        )
+        .unwrap();
+        let connect_valid_token = valid_token.clone();
+        let connect: ConnectStream<TestStream> = Box::new(move |config| {
+            let valid_token = connect_valid_token.clone();
+            Box::pin(async move {
+                Ok(TestStream {
+                    auth_token: config.auth_token,
+                    valid_token,
+                    next_version: config.starting_version.unwrap_or_default(),
+                })
+            })
+        });
+        let inner = connect(config.clone()).await.unwrap();
+        let mut step = RefreshAuthTokenStep::new(
+            inner,
+            config,
+            Some(auth_token_from_file(&token_path)),
+            connect,
+        );
+        assert_eq!(polled_versions(step.poll().await), vec![10]);
+        assert_eq!(polled_versions(step.poll().await), vec![11]);
+
+        // The token expired but the file wasn't rotated yet
+        *valid_token.lock().unwrap() = "second".to_string();
+        assert!(step.poll().await.is_err());
+
+        // Once it is, the stream reconnects and resumes where it stopped
+        fs::write(&token_path, "second\n").unwrap();
+        assert!(polled_versions(step.poll().await).is_empty());
+        assert_eq!(polled_versions(step.poll().await), vec![12]);
+        assert_eq!(step.config.auth_token, "second");
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::Transaction,
+    traits::{
+        pollable_async_step::PollableAsyncRunType, NamedStep, PollableAsyncStep, Processable,
+    },
+    types::transaction_context::{TransactionContext, TransactionMetadata},
+    utils::errors::ProcessorError,
+};
+use tonic::async_trait;
+
+/// Wraps the `TransactionStreamStep` and splits the batches it polls into batches of at most
+/// `max_batch_size` transactions, so that every step downstream holds a bounded batch, e.g.
+/// the reduction step while it folds the current models of a large backfill batch.
+///
+/// The split batches cover the versions of the polled batch without gaps, so the
+/// `VersionTrackerStep` sees the same versions as without the cap. Their timestamps are those
+/// of their first and last transactions, and the polled batch's size is split between them by
+/// their number of transactions.
+///
+/// The cap counts transactions, not the models they remap to, so a batch of a few very large
+/// transactions can still hold many models.
+pub struct CapBatchSizeStep<S> {
+    inner: S,
+    max_batch_size: usize,
+}
+
+impl<S> CapBatchSizeStep<S> {
+    pub fn new(inner: S, max_batch_size: usize) -> Self {
+        Self {
+            inner,
+            max_batch_size: max_batch_size.max(1),
+        }
+    }
+}
+
+/// Splits `batch` into batches of at most `max_batch_size` transactions.
+fn split_batch(
+    batch: TransactionContext<Vec<Transaction>>,
+    max_batch_size: usize,
+) -> Vec<TransactionContext<Vec<Transaction>>> {
+    if batch.data.len() <= max_batch_size {
+        return vec![batch];
+    }
+    let TransactionContext { data, metadata } = batch;
+    let num_transactions = data.len() as u64;
+    let mut start_version = metadata.start_version;
+    let mut split_size = 0;
+    let mut chunks = data.chunks(max_batch_size).peekable();
+    let mut batches = Vec::new();
+    while let Some(chunk) = chunks.next() {
+        let is_last = chunks.peek().is_none();
+        // The last batch ends where the polled one does, and takes what the rounding left of
+        // the size, so the sizes add up to the polled one
+        let (end_version, total_size_in_bytes) = if is_last {
+            (
+                metadata.end_version,
+                metadata.total_size_in_bytes - split_size,
+            )
+        } else {
+            (
+                chunk.last().map_or(start_version, |txn| txn.version),
+                metadata.total_size_in_bytes * chunk.len() as u64 / num_transactions,
+            )
+        };
+        split_size += total_size_in_bytes;
+        batches.push(TransactionContext {
+            data: chunk.to_vec(),
+            metadata: TransactionMetadata {
+                start_version,
+                end_version,
+                start_transaction_timestamp: chunk.first().and_then(|txn| txn.timestamp.clone()),
+                end_transaction_timestamp: chunk.last().and_then(|txn| txn.timestamp.clone()),
+                total_size_in_bytes,
+                ..metadata.clone()
+            },
+        });
+        start_version = end_version + 1;
+    }
+    batches
+}
+
+#[async_trait]
+impl<S> Processable for CapBatchSizeStep<S>
+where
+    S: PollableAsyncStep<Output = Vec<Transaction>> + Send + Sync + 'static,
+{
+    type Input = S::Input;
+    type Output = Vec<Transaction>;
+    type RunType = PollableAsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<S::Input>,
+    ) -> Result<Option<TransactionContext<Vec<Transaction>>>, ProcessorError> {
+        self.inner.process(input).await
+    }
+}
+
+#[async_trait]
+impl<S> PollableAsyncStep for CapBatchSizeStep<S>
+where
+    S: PollableAsyncStep<Output = Vec<Transaction>> + Send + Sync + 'static,
+{
+    fn poll_interval(&self) -> std::time::Duration {
+        self.inner.poll_interval()
+    }
+
+    async fn poll(
+        &mut self,
+    ) -> Result<Option<Vec<TransactionContext<Vec<Transaction>>>>, ProcessorError> {
+        let max_batch_size = self.max_batch_size;
+        Ok(self.inner.poll().await?.map(|batches| {
+            batches
+                .into_iter()
+                .flat_map(|batch| split_batch(batch, max_batch_size))
+                .collect()
+        }))
+    }
+
+    async fn should_continue_polling(&mut self) -> bool {
+        self.inner.should_continue_polling().await
+    }
+}
+
+impl<S: NamedStep> NamedStep for CapBatchSizeStep<S> {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::aptos_protos::util::timestamp::Timestamp;
+
+    fn timestamp(version: u64) -> Option<Timestamp> {
+        Some(Timestamp {
+            seconds: version as i64,
+            nanos: 0,
+        })
+    }
+
+    fn batch(versions: std::ops::RangeInclusive<u64>) -> TransactionContext<Vec<Transaction>> {
+        TransactionContext {
+            data: versions
+                .clone()
+                .map(|version| Transaction {
+                    version,
+                    timestamp: timestamp(version),
+                    ..Default::default()
+                })
+                .collect(),
+            metadata: TransactionMetadata {
+                start_version: *versions.start(),
+                end_version: *versions.end(),
+                start_transaction_timestamp: timestamp(*versions.start()),
+                end_transaction_timestamp: timestamp(*versions.end()),
+                total_size_in_bytes: 1000,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_split_batch_covers_all_versions() {
+        let batches = split_batch(batch(10..=14), 2);
+        let versions: Vec<(u64, u64, usize)> = batches
+            .iter()
+            .map(|batch| {
+                (
+                    batch.metadata.start_version,
+                    batch.metadata.end_version,
+                    batch.data.len(),
+                )
+            })
+            .collect();
+        assert_eq!(versions, vec![(10, 11, 2), (12, 13, 2), (14, 14, 1)]);
+    }
+
+    #[test]
+    fn test_split_batch_splits_timestamps_and_size() {
+        let batches = split_batch(batch(10..=14), 2);
+        let timestamps: Vec<_> = batches
+            .iter()
+            .map(|batch| {
+                (
+                    batch.metadata.start_transaction_timestamp.clone(),
+                    batch.metadata.end_transaction_timestamp.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(timestamps, vec![
+            (timestamp(10), timestamp(11)),
+            (timestamp(12), timestamp(13)),
+            (timestamp(14), timestamp(14)),
+        ]);
+        let sizes: Vec<u64> = batches
+            .iter()
+            .map(|batch| batch.metadata.total_size_in_bytes)
+            .collect();
+        assert_eq!(sizes, vec![400, 400, 200]);
+    }
+
+    #[test]
+    fn test_split_batch_keeps_small_batches() {
+        let batches = split_batch(batch(10..=14), 5);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].data.len(), 5);
+    }
+}
@@ -0,0 +1,586 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::{
+        DEFAULT_BIGQUERY_ACTIVITIES_TABLE, DEFAULT_BIGQUERY_BATCH_SIZE,
+        DEFAULT_BIGQUERY_FLUSH_INTERVAL_MS,
+    },
+    models::nft_models::{
+        NftMarketplaceActivity, CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME,
+    },
+    steps::remappers::RemapOutput,
+    utils::retry::{retry_with_backoff, QueryRetryConfig},
+};
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use base64::Engine;
+use ring::{
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tonic::async_trait;
+use tracing::{error, warn};
+
+/// Number of row batches waiting for the sink before new ones are dropped.
+const BIGQUERY_CHANNEL_SIZE: usize = 100;
+const BIGQUERY_API_URL: &str = "https://bigquery.googleapis.com/bigquery/v2";
+const BIGQUERY_INSERT_SCOPE: &str = "https://www.googleapis.com/auth/bigquery.insertdata";
+const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+/// Access tokens are refreshed this long before they expire, so none expires mid-insert.
+const ACCESS_TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Where to stream activities to, through BigQuery's `insertAll` API.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BigQueryConfig {
+    /// Service account key file, as downloaded from the Google Cloud console.
+    pub service_account_key_path: PathBuf,
+    /// Project of the dataset. Defaults to the service account's project.
+    #[serde(default)]
+    pub project_id: Option<String>,
+    pub dataset: String,
+    #[serde(default = "BigQueryConfig::default_activities_table")]
+    pub activities_table: String,
+    /// Also streams the rows written to the current tables, as a snapshot per change, into
+    /// tables of the dataset named like the Postgres ones.
+    #[serde(default)]
+    pub export_current_state: bool,
+    /// Maximum number of rows sent in a single insert.
+    #[serde(default = "BigQueryConfig::default_batch_size")]
+    pub batch_size: usize,
+    /// Rows buffered for less than a full batch are inserted after this long.
+    #[serde(default = "BigQueryConfig::default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Retries for failed inserts, with exponential backoff.
+    #[serde(default)]
+    pub retry_config: QueryRetryConfig,
+}
+
+impl BigQueryConfig {
+    pub fn default_activities_table() -> String {
+        DEFAULT_BIGQUERY_ACTIVITIES_TABLE.to_string()
+    }
+
+    pub const fn default_batch_size() -> usize {
+        DEFAULT_BIGQUERY_BATCH_SIZE
+    }
+
+    pub const fn default_flush_interval_ms() -> u64 {
+        DEFAULT_BIGQUERY_FLUSH_INTERVAL_MS
+    }
+}
+
+/// The fields of a service account key file the sink needs.
+#[derive(Clone, Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+    project_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct BigQueryRow {
+    /// BigQuery drops rows with an id it has seen in the last minute or so, so a batch sent
+    /// again, e.g. by a retry, isn't duplicated.
+    #[serde(rename = "insertId")]
+    insert_id: String,
+    json: serde_json::Value,
+}
+
+/// Rows to insert, by table.
+type BigQueryRows = BTreeMap<String, Vec<BigQueryRow>>;
+
+/// Streams the rows `DBWritingStep` wrote to BigQuery, and passes their activities on to the
+/// activity exports.
+///
+/// Like the ClickHouse export, the inserts run on a background task and are best effort: rows
+/// that still fail after their retries, or that arrive while the sink is too far behind, are
+/// logged and dropped. Rows are only exported once their batch is written to Postgres, but a
+/// batch the processor reprocesses after a restart is streamed again.
+///
+/// Timestamps need no clamping of their own: the chain's and the ones parsed from event data
+/// are already clamped to [`crate::utils::MAX_TIMESTAMP_SECS`], BigQuery's latest timestamp.
+pub struct BigQueryStep {
+    sender: Option<mpsc::Sender<BigQueryRows>>,
+    activities_table: String,
+    export_current_state: bool,
+}
+
+impl BigQueryStep {
+    /// Reads the service account key and spawns the sink task if BigQuery is configured. Must
+    /// be called within a tokio runtime.
+    pub fn new(config: Option<BigQueryConfig>) -> Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self {
+                sender: None,
+                activities_table: String::new(),
+                export_current_state: false,
+            });
+        };
+        let key: ServiceAccountKey = serde_json::from_str(
+            &fs::read_to_string(&config.service_account_key_path).with_context(|| {
+                format!(
+                    "Failed to read service account key {}",
+                    config.service_account_key_path.display()
+                )
+            })?,
+        )
+        .context("Failed to parse service account key")?;
+        let authenticator = Authenticator::new(reqwest::Client::new(), key)?;
+
+        let (sender, receiver) = mpsc::channel(BIGQUERY_CHANNEL_SIZE);
+        let step = Self {
+            sender: Some(sender),
+            activities_table: config.activities_table.clone(),
+            export_current_state: config.export_current_state,
+        };
+        tokio::spawn(run_sink(authenticator, config, receiver));
+        Ok(step)
+    }
+
+    /// Converts the rows of `tables` this step exports, keyed so that each change of a row has
+    /// its own insert id.
+    fn to_rows(&self, tables: &RemapOutput) -> Result<BigQueryRows> {
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            auctions,
+        } = tables;
+        let mut rows = BigQueryRows::new();
+        for activity in activities {
+            push_row(
+                &mut rows,
+                &self.activities_table,
+                format!(
+                    "{}:{}:{}",
+                    activity.txn_version, activity.index, activity.marketplace
+                ),
+                activity,
+            )?;
+        }
+        if !self.export_current_state {
+            return Ok(rows);
+        }
+        for listing in listings {
+            push_row(
+                &mut rows,
+                CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+                format!(
+                    "{}:{}:{}",
+                    listing.marketplace, listing.token_data_id, listing.last_transaction_version
+                ),
+                listing,
+            )?;
+        }
+        for offer in token_offers {
+            push_row(
+                &mut rows,
+                CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME,
+                format!(
+                    "{}:{}:{}:{}:{}",
+                    offer.marketplace,
+                    offer.token_data_id,
+                    offer.buyer,
+                    offer.offer_key,
+                    offer.last_transaction_version
+                ),
+                offer,
+            )?;
+        }
+        for offer in collection_offers {
+            push_row(
+                &mut rows,
+                CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+                format!(
+                    "{}:{}:{}",
+                    offer.marketplace, offer.collection_offer_id, offer.last_transaction_version
+                ),
+                offer,
+            )?;
+        }
+        for auction in auctions {
+            push_row(
+                &mut rows,
+                CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+                format!(
+                    "{}:{}:{}",
+                    auction.marketplace, auction.token_data_id, auction.last_transaction_version
+                ),
+                auction,
+            )?;
+        }
+        Ok(rows)
+    }
+}
+
+/// Adds `model` to the rows of `table`. `insertAll` takes `JSON` columns as strings, so nested
+/// values such as `json_data` are sent serialized.
+fn push_row(
+    rows: &mut BigQueryRows,
+    table: &str,
+    insert_id: String,
+    model: &impl Serialize,
+) -> Result<()> {
+    let mut json = serde_json::to_value(model)?;
+    if let Some(fields) = json.as_object_mut() {
+        for value in fields.values_mut() {
+            if value.is_object() || value.is_array() {
+                *value = serde_json::Value::String(value.to_string());
+            }
+        }
+    }
+    rows.entry(table.to_string())
+        .or_default()
+        .push(BigQueryRow { insert_id, json });
+    Ok(())
+}
+
+#[async_trait]
+impl Processable for BigQueryStep {
+    type Input = RemapOutput;
+    type Output = Vec<NftMarketplaceActivity>;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<RemapOutput>,
+    ) -> Result<Option<TransactionContext<Vec<NftMarketplaceActivity>>>, ProcessorError> {
+        if let Some(sender) = &self.sender {
+            match self.to_rows(&input.data) {
+                Ok(rows) if rows.is_empty() => {},
+                Ok(rows) => {
+                    if let Err(e) = sender.try_send(rows) {
+                        warn!(
+                            start_version = input.metadata.start_version,
+                            end_version = input.metadata.end_version,
+                            "Dropping rows for BigQuery: {e}"
+                        );
+                    }
+                },
+                Err(e) => warn!(
+                    start_version = input.metadata.start_version,
+                    end_version = input.metadata.end_version,
+                    "Failed to convert rows for BigQuery: {e:#}"
+                ),
+            }
+        }
+
+        Ok(Some(TransactionContext {
+            data: input.data.activities,
+            metadata: input.metadata,
+        }))
+    }
+}
+
+impl AsyncStep for BigQueryStep {}
+
+impl NamedStep for BigQueryStep {
+    fn name(&self) -> String {
+        "BigQueryStep".to_string()
+    }
+}
+
+#[derive(Serialize)]
+struct TokenClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Exchanges a JWT signed with the service account's key for an OAuth access token, and
+/// reuses it until it is about to expire.
+struct Authenticator {
+    client: reqwest::Client,
+    key: ServiceAccountKey,
+    key_pair: RsaKeyPair,
+    access_token: Option<(String, Instant)>,
+}
+
+impl Authenticator {
+    fn new(client: reqwest::Client, key: ServiceAccountKey) -> Result<Self> {
+        // Service account keys are PKCS#8 PEM
+        let der: String = key
+            .private_key
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(der.trim())
+            .context("Service account private key isn't valid PEM")?;
+        let key_pair = RsaKeyPair::from_pkcs8(&der)
+            .map_err(|e| anyhow::anyhow!("Invalid service account private key: {e}"))?;
+        Ok(Self {
+            client,
+            key,
+            key_pair,
+            access_token: None,
+        })
+    }
+
+    fn sign_jwt(&self, claims: &TokenClaims) -> Result<String> {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let message = format!(
+            "{}.{}",
+            engine.encode(r#"{"alg":"RS256","typ":"JWT"}"#),
+            engine.encode(serde_json::to_vec(claims)?)
+        );
+        let mut signature = vec![0; self.key_pair.public().modulus_len()];
+        self.key_pair
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to sign the access token request"))?;
+        Ok(format!("{message}.{}", engine.encode(signature)))
+    }
+
+    async fn access_token(&mut self) -> Result<String> {
+        if let Some((access_token, refresh_at)) = &self.access_token {
+            if Instant::now() < *refresh_at {
+                return Ok(access_token.clone());
+            }
+        }
+        let now = chrono::Utc::now().timestamp();
+        let assertion = self.sign_jwt(&TokenClaims {
+            iss: &self.key.client_email,
+            scope: BIGQUERY_INSERT_SCOPE,
+            aud: &self.key.token_uri,
+            iat: now,
+            exp: now + 3600,
+        })?;
+        let response: TokenResponse = self
+            .client
+            .post(&self.key.token_uri)
+            .form(&[
+                ("grant_type", JWT_BEARER_GRANT_TYPE),
+                ("assertion", &assertion),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Failed to get a BigQuery access token")?;
+        let refresh_at = Instant::now()
+            + Duration::from_secs(response.expires_in).saturating_sub(ACCESS_TOKEN_REFRESH_MARGIN);
+        self.access_token = Some((response.access_token.clone(), refresh_at));
+        Ok(response.access_token)
+    }
+}
+
+/// Buffers rows until a batch is full or the flush interval passes, then inserts them.
+/// Flushes what's left once the step is dropped.
+async fn run_sink(
+    mut authenticator: Authenticator,
+    config: BigQueryConfig,
+    mut receiver: mpsc::Receiver<BigQueryRows>,
+) {
+    let batch_size = config.batch_size.max(1);
+    let mut buffer = BigQueryRows::new();
+    let mut flush_interval =
+        tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(1)));
+    loop {
+        tokio::select! {
+            rows = receiver.recv() => match rows {
+                Some(rows) => {
+                    for (table, rows) in rows {
+                        buffer.entry(table).or_default().extend(rows);
+                    }
+                    if buffer.values().map(Vec::len).sum::<usize>() >= batch_size {
+                        flush(&mut authenticator, &config, &mut buffer).await;
+                    }
+                },
+                None => {
+                    flush(&mut authenticator, &config, &mut buffer).await;
+                    return;
+                },
+            },
+            _ = flush_interval.tick() => flush(&mut authenticator, &config, &mut buffer).await,
+        }
+    }
+}
+
+/// Splits the buffered rows into inserts of at most `batch_size` rows, a table at a time.
+fn into_batches(buffer: BigQueryRows, batch_size: usize) -> Vec<(String, Vec<BigQueryRow>)> {
+    let batch_size = batch_size.max(1);
+    let mut batches = Vec::new();
+    for (table, rows) in buffer {
+        let mut rows = rows.into_iter().peekable();
+        while rows.peek().is_some() {
+            let batch = rows.by_ref().take(batch_size).collect();
+            batches.push((table.clone(), batch));
+        }
+    }
+    batches
+}
+
+async fn flush(
+    authenticator: &mut Authenticator,
+    config: &BigQueryConfig,
+    buffer: &mut BigQueryRows,
+) {
+    for (table, rows) in into_batches(std::mem::take(buffer), config.batch_size) {
+        if let Err(e) = insert_rows(authenticator, config, &table, &rows).await {
+            error!(
+                %table,
+                rows = rows.len(),
+                "Dropping rows for BigQuery: {e:#}"
+            );
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct InsertAllResponse {
+    #[serde(default, rename = "insertErrors")]
+    insert_errors: Vec<serde_json::Value>,
+}
+
+async fn insert_rows(
+    authenticator: &mut Authenticator,
+    config: &BigQueryConfig,
+    table: &str,
+    rows: &[BigQueryRow],
+) -> Result<()> {
+    let access_token = authenticator.access_token().await?;
+    let project_id = config
+        .project_id
+        .as_deref()
+        .unwrap_or(&authenticator.key.project_id);
+    let url = format!(
+        "{BIGQUERY_API_URL}/projects/{project_id}/datasets/{}/tables/{table}/insertAll",
+        config.dataset
+    );
+    let body = serde_json::json!({ "rows": rows });
+    let client = &authenticator.client;
+
+    retry_with_backoff("insert_bigquery_rows", &config.retry_config, || {
+        let request = client.post(&url).bearer_auth(&access_token).json(&body);
+        async move {
+            let response: InsertAllResponse =
+                request.send().await?.error_for_status()?.json().await?;
+            // Rejected rows don't fail the request
+            if let Some(insert_error) = response.insert_errors.first() {
+                anyhow::bail!(
+                    "{} rows were rejected, e.g. {insert_error}",
+                    response.insert_errors.len()
+                );
+            }
+            Ok(())
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nft_models::CurrentNFTMarketplaceListing;
+
+    fn build_step(export_current_state: bool) -> BigQueryStep {
+        BigQueryStep {
+            sender: None,
+            activities_table: "activities".to_string(),
+            export_current_state,
+        }
+    }
+
+    #[test]
+    fn test_parse_bigquery_config() {
+        let config: BigQueryConfig = serde_yaml::from_str(
+            r#"
+            service_account_key_path: /secrets/bigquery.json
+            dataset: nft
+            batch_size: 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.service_account_key_path,
+            PathBuf::from("/secrets/bigquery.json")
+        );
+        assert_eq!(config.project_id, None);
+        assert_eq!(config.activities_table, DEFAULT_BIGQUERY_ACTIVITIES_TABLE);
+        assert!(!config.export_current_state);
+        assert_eq!(config.batch_size, 100);
+        assert_eq!(config.flush_interval_ms, DEFAULT_BIGQUERY_FLUSH_INTERVAL_MS);
+
+        assert!(serde_yaml::from_str::<BigQueryConfig>("dataset: nft").is_err());
+    }
+
+    #[test]
+    fn test_batches_rows_per_table() {
+        let activities: Vec<_> = (0..5)
+            .map(|index| NftMarketplaceActivity {
+                txn_version: 1,
+                index,
+                marketplace: "wapal".to_string(),
+                json_data: serde_json::json!({ "price": "100" }),
+                ..Default::default()
+            })
+            .collect();
+        let listings = vec![CurrentNFTMarketplaceListing {
+            token_data_id: "0x3".to_string(),
+            marketplace: "wapal".to_string(),
+            last_transaction_version: 1,
+            ..Default::default()
+        }];
+        let tables = RemapOutput {
+            activities,
+            listings,
+            ..Default::default()
+        };
+
+        // Current tables are only exported when configured
+        let rows = build_step(false).to_rows(&tables).unwrap();
+        assert_eq!(rows.keys().collect::<Vec<_>>(), vec!["activities"]);
+
+        let rows = build_step(true).to_rows(&tables).unwrap();
+        let activity = &rows["activities"][1];
+        assert_eq!(activity.insert_id, "1:1:wapal");
+        assert_eq!(activity.json["json_data"], r#"{"price":"100"}"#);
+
+        let batches = into_batches(rows, 2);
+        assert_eq!(
+            batches
+                .iter()
+                .map(|(table, rows)| (table.as_str(), rows.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("activities", 2),
+                ("activities", 2),
+                ("activities", 1),
+                (CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME, 1),
+            ]
+        );
+        assert_eq!(batches[3].1[0].insert_id, "wapal:0x3:1");
+    }
+}
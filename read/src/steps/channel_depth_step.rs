@@ -0,0 +1,134 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::metrics::NFT_CHANNEL_DEPTH;
+use aptos_indexer_processor_sdk::{
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use std::marker::PhantomData;
+use tonic::async_trait;
+use tracing::warn;
+
+/// Number of consecutive batches a stage has to find its input channel full before warning
+/// about it.
+const SATURATED_BATCHES_BEFORE_WARNING: usize = 100;
+
+/// Passes batches along unchanged and counts them into `nft_channel_depth`. The channels
+/// between the builder's steps are created inside the SDK, so the depth of a stage, i.e. the
+/// steps between two `ChannelDepthStep`s, is the number of batches that entered it through the
+/// first and haven't left it through the second yet. That's the batches waiting in the stage's
+/// input channel plus the ones its steps are working on.
+pub struct ChannelDepthStep<T> {
+    /// The stage the batches leave, whose depth drops as they pass.
+    after: Option<&'static str>,
+    /// The stage the batches enter, whose depth grows as they pass.
+    before: Option<&'static str>,
+    channel_size: usize,
+    saturated_batches: usize,
+    _batch: PhantomData<fn(T) -> T>,
+}
+
+impl<T> ChannelDepthStep<T> {
+    pub fn new(channel_size: usize) -> Self {
+        Self {
+            after: None,
+            before: None,
+            channel_size,
+            saturated_batches: 0,
+            _batch: PhantomData,
+        }
+    }
+
+    /// Counts the batches out of `stage`, which the step follows.
+    pub fn after(mut self, stage: &'static str) -> Self {
+        self.after = Some(stage);
+        self
+    }
+
+    /// Counts the batches into `stage`, which the step precedes. The stage starts empty, since
+    /// a new pipeline is built after the stream reconnects.
+    pub fn before(mut self, stage: &'static str) -> Self {
+        NFT_CHANNEL_DEPTH.with_label_values(&[stage]).set(0);
+        self.before = Some(stage);
+        self
+    }
+}
+
+#[async_trait]
+impl<T> Processable for ChannelDepthStep<T>
+where
+    T: Send + 'static,
+{
+    type Input = T;
+    type Output = T;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<T>,
+    ) -> Result<Option<TransactionContext<T>>, ProcessorError> {
+        if let Some(stage) = self.after {
+            NFT_CHANNEL_DEPTH.with_label_values(&[stage]).dec();
+        }
+        if let Some(stage) = self.before {
+            let gauge = NFT_CHANNEL_DEPTH.with_label_values(&[stage]);
+            // The stage's input channel was full before this batch was passed on
+            let depth = gauge.get() as usize;
+            if depth >= self.channel_size {
+                self.saturated_batches += 1;
+                if self.saturated_batches % SATURATED_BATCHES_BEFORE_WARNING == 0 {
+                    warn!(
+                        stage,
+                        depth,
+                        channel_size = self.channel_size,
+                        saturated_batches = self.saturated_batches,
+                        "Input channel of {stage} has stayed full, batches reach it faster than \
+                         it processes them"
+                    );
+                }
+            } else {
+                self.saturated_batches = 0;
+            }
+            gauge.inc();
+        }
+        Ok(Some(input))
+    }
+}
+
+impl<T> AsyncStep for ChannelDepthStep<T> where T: Send + 'static {}
+
+impl<T> NamedStep for ChannelDepthStep<T> {
+    fn name(&self) -> String {
+        match self.before {
+            Some(stage) => format!("ChannelDepthStep({stage})"),
+            None => "ChannelDepthStep".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_counts_batches_between_steps() {
+        let mut into_stage = ChannelDepthStep::<()>::new(2).before("test_stage");
+        let mut out_of_stage = ChannelDepthStep::<()>::new(2).after("test_stage");
+        let gauge = NFT_CHANNEL_DEPTH.with_label_values(&["test_stage"]);
+        let batch = || TransactionContext {
+            data: (),
+            metadata: Default::default(),
+        };
+
+        for _ in 0..3 {
+            into_stage.process(batch()).await.unwrap();
+        }
+        assert_eq!(gauge.get(), 3);
+        assert_eq!(into_stage.saturated_batches, 1);
+
+        out_of_stage.process(batch()).await.unwrap();
+        assert_eq!(gauge.get(), 2);
+    }
+}
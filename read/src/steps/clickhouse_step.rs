@@ -0,0 +1,340 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::{
+        DEFAULT_CLICKHOUSE_BATCH_SIZE, DEFAULT_CLICKHOUSE_FLUSH_INTERVAL_MS,
+        DEFAULT_CLICKHOUSE_TABLE,
+    },
+    models::nft_models::NftMarketplaceActivity,
+    utils::retry::{retry_with_backoff, QueryRetryConfig},
+};
+use anyhow::{bail, Result};
+use aptos_indexer_processor_sdk::{
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tonic::async_trait;
+use tracing::{error, warn};
+use url::{Host, Url};
+
+/// Number of activity batches waiting for the sink before new ones are dropped.
+const CLICKHOUSE_CHANNEL_SIZE: usize = 100;
+
+/// Where to export activities to, through ClickHouse's HTTP interface.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClickHouseConfig {
+    /// HTTP interface of the server, e.g. `http://localhost:8123`.
+    pub url: Url,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Table to insert into, optionally qualified with its database.
+    #[serde(default = "ClickHouseConfig::default_table")]
+    pub table: String,
+    /// Maximum number of rows sent in a single insert.
+    #[serde(default = "ClickHouseConfig::default_batch_size")]
+    pub batch_size: usize,
+    /// Rows buffered for less than a full batch are inserted after this long.
+    #[serde(default = "ClickHouseConfig::default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Retries for failed inserts, with exponential backoff. The processor's
+    /// `query_retry_config` when unset.
+    #[serde(default)]
+    pub retry_config: Option<QueryRetryConfig>,
+}
+
+impl ClickHouseConfig {
+    pub fn default_table() -> String {
+        DEFAULT_CLICKHOUSE_TABLE.to_string()
+    }
+
+    pub const fn default_batch_size() -> usize {
+        DEFAULT_CLICKHOUSE_BATCH_SIZE
+    }
+
+    pub const fn default_flush_interval_ms() -> u64 {
+        DEFAULT_CLICKHOUSE_FLUSH_INTERVAL_MS
+    }
+
+    /// Errors on a password that would be sent in the clear, over `http` to another host than
+    /// this one.
+    pub fn validate(&self) -> Result<()> {
+        let is_loopback = match self.url.host() {
+            Some(Host::Domain(domain)) => domain == "localhost",
+            Some(Host::Ipv4(ip)) => ip.is_loopback(),
+            Some(Host::Ipv6(ip)) => ip.is_loopback(),
+            None => false,
+        };
+        if self.password.is_some() && self.url.scheme() != "https" && !is_loopback {
+            bail!(
+                "clickhouse_config has a password but its url {} isn't https",
+                self.url
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Exports the activities written by `DBWritingStep` to ClickHouse as `JSONEachRow` inserts.
+///
+/// The inserts run on a background task, so a slow or unavailable ClickHouse never holds up the
+/// Postgres writes. The flip side is that the export is best effort: rows that still fail after
+/// their retries, or that arrive while the sink is too far behind, are logged and dropped.
+pub struct ClickHouseStep {
+    sender: Option<mpsc::Sender<Vec<NftMarketplaceActivity>>>,
+}
+
+impl ClickHouseStep {
+    /// Spawns the sink task if ClickHouse is configured. Must be called within a tokio runtime.
+    /// Inserts are retried with `query_retry_config` unless the config sets its own retries.
+    pub fn new(config: Option<ClickHouseConfig>, query_retry_config: &QueryRetryConfig) -> Self {
+        let sender = config.map(|config| {
+            let retry_config = config
+                .retry_config
+                .clone()
+                .unwrap_or_else(|| query_retry_config.clone());
+            let (sender, receiver) = mpsc::channel(CLICKHOUSE_CHANNEL_SIZE);
+            tokio::spawn(run_sink(
+                reqwest::Client::new(),
+                config,
+                retry_config,
+                receiver,
+            ));
+            sender
+        });
+        Self { sender }
+    }
+}
+
+#[async_trait]
+impl Processable for ClickHouseStep {
+    type Input = Vec<NftMarketplaceActivity>;
+    type Output = Vec<NftMarketplaceActivity>;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<Vec<NftMarketplaceActivity>>,
+    ) -> Result<Option<TransactionContext<Vec<NftMarketplaceActivity>>>, ProcessorError> {
+        if let Some(sender) = &self.sender {
+            if !input.data.is_empty() {
+                if let Err(e) = sender.try_send(input.data.clone()) {
+                    warn!(
+                        start_version = input.metadata.start_version,
+                        end_version = input.metadata.end_version,
+                        "Dropping activities for ClickHouse: {e}"
+                    );
+                }
+            }
+        }
+
+        Ok(Some(input))
+    }
+}
+
+impl AsyncStep for ClickHouseStep {}
+
+impl NamedStep for ClickHouseStep {
+    fn name(&self) -> String {
+        "ClickHouseStep".to_string()
+    }
+}
+
+/// Buffers activities until a batch is full or the flush interval passes, then inserts them.
+/// Flushes what's left once the step is dropped.
+async fn run_sink(
+    client: reqwest::Client,
+    config: ClickHouseConfig,
+    retry_config: QueryRetryConfig,
+    mut receiver: mpsc::Receiver<Vec<NftMarketplaceActivity>>,
+) {
+    let batch_size = config.batch_size.max(1);
+    let mut buffer: Vec<NftMarketplaceActivity> = Vec::new();
+    let mut flush_interval =
+        tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(1)));
+    loop {
+        tokio::select! {
+            activities = receiver.recv() => match activities {
+                Some(activities) => {
+                    buffer.extend(activities);
+                    if buffer.len() >= batch_size {
+                        flush(&client, &config, &retry_config, &mut buffer).await;
+                    }
+                },
+                None => {
+                    flush(&client, &config, &retry_config, &mut buffer).await;
+                    return;
+                },
+            },
+            _ = flush_interval.tick() => flush(&client, &config, &retry_config, &mut buffer).await,
+        }
+    }
+}
+
+async fn flush(
+    client: &reqwest::Client,
+    config: &ClickHouseConfig,
+    retry_config: &QueryRetryConfig,
+    buffer: &mut Vec<NftMarketplaceActivity>,
+) {
+    for batch in std::mem::take(buffer).chunks(config.batch_size.max(1)) {
+        if let Err(e) = insert_activities(client, config, retry_config, batch).await {
+            error!(
+                rows = batch.len(),
+                "Dropping activities for ClickHouse: {e:#}"
+            );
+        }
+    }
+}
+
+async fn insert_activities(
+    client: &reqwest::Client,
+    config: &ClickHouseConfig,
+    retry_config: &QueryRetryConfig,
+    activities: &[NftMarketplaceActivity],
+) -> Result<()> {
+    let mut url = config.url.clone();
+    url.query_pairs_mut().append_pair(
+        "query",
+        &format!("INSERT INTO {} FORMAT JSONEachRow", config.table),
+    );
+    let mut body = String::new();
+    for activity in activities {
+        body.push_str(&serde_json::to_string(activity)?);
+        body.push('\n');
+    }
+
+    retry_with_backoff("insert_clickhouse_activities", retry_config, || {
+        let mut request = client.post(url.clone()).body(body.clone());
+        if let Some(user) = &config.user {
+            request = request.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &config.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+        async move {
+            request.send().await?.error_for_status()?;
+            Ok(())
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::types::transaction_context::TransactionMetadata;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Accepts a single request and returns its request line and body.
+    async fn receive_insert(listener: TcpListener) -> (String, String) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "Connection closed before the request was complete");
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                let content_length: usize = headers
+                    .to_lowercase()
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|len| len.trim().parse().unwrap())
+                    .unwrap_or_default();
+                if body.len() >= content_length {
+                    stream
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let request_line = headers.lines().next().unwrap().to_string();
+                    return (request_line, body.to_string());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_password_requires_https_except_on_loopback() {
+        let build_config = |url: &str, password: Option<&str>| ClickHouseConfig {
+            url: Url::parse(url).unwrap(),
+            user: Some("default".to_string()),
+            password: password.map(str::to_string),
+            table: ClickHouseConfig::default_table(),
+            batch_size: ClickHouseConfig::default_batch_size(),
+            flush_interval_ms: ClickHouseConfig::default_flush_interval_ms(),
+            retry_config: None,
+        };
+
+        assert!(build_config("http://clickhouse:8123", Some("secret"))
+            .validate()
+            .is_err());
+        assert!(build_config("https://clickhouse:8443", Some("secret"))
+            .validate()
+            .is_ok());
+        assert!(build_config("http://127.0.0.1:8123", Some("secret"))
+            .validate()
+            .is_ok());
+        assert!(build_config("http://clickhouse:8123", None)
+            .validate()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_inserts_activities_as_json_each_row_without_blocking() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let mut step = ClickHouseStep::new(
+            Some(ClickHouseConfig {
+                url,
+                user: None,
+                password: None,
+                table: "nft.activities".to_string(),
+                batch_size: 2,
+                flush_interval_ms: 60_000,
+                retry_config: None,
+            }),
+            &QueryRetryConfig::default(),
+        );
+
+        let activities: Vec<_> = (0..2)
+            .map(|index| NftMarketplaceActivity {
+                txn_version: 1,
+                index,
+                marketplace: "wapal".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        // The step returns before anything was inserted, the server only accepts afterwards
+        let output = step
+            .process(TransactionContext {
+                data: activities,
+                metadata: TransactionMetadata::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(output.data.len(), 2);
+
+        let (request_line, body) = receive_insert(listener).await;
+        assert!(
+            request_line.starts_with("POST /?query=INSERT+INTO+nft.activities+FORMAT+JSONEachRow")
+        );
+        let rows: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["index"], 1);
+    }
+}
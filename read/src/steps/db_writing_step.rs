@@ -1,10 +1,29 @@
 use crate::{
+    config::marketplace_config::MarketplaceEventType,
+    metrics::NFT_DB_ROWS_WRITTEN,
     models::nft_models::{
+        CollectionOfferFill, CurrentMarketplaceModel, CurrentNFTMarketplaceAuction,
         CurrentNFTMarketplaceCollectionOffer, CurrentNFTMarketplaceListing,
-        CurrentNFTMarketplaceTokenOffer, NftMarketplaceActivity,
+        CurrentNFTMarketplaceTokenOffer, MarketplaceModel, NftMarketplaceActivity,
+        COLLECTION_OFFER_FILLS_TABLE_NAME, CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+        CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME, NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME,
+    },
+    postgres::{
+        postgres_utils::{
+            execute_in_chunks_on_conn, greatest, is_transient_error, ArcDbPool, MyDbConnection,
+        },
+        resource_last_writes::{insert_resource_last_writes, ResourceLastWrites},
+        seen_contracts::SeenContracts,
+        token_owners::{update_listing_owners, TokenOwners},
     },
-    postgres::postgres_utils::{execute_in_chunks, ArcDbPool},
     schema,
+    steps::{
+        reduction_step::{drain_sorted, fold_latest},
+        remappers::RemapOutput,
+    },
+    utils::retry::{retry_with_backoff_if, QueryRetryConfig},
 };
 use ahash::HashMap;
 use aptos_indexer_processor_sdk::{
@@ -12,168 +31,242 @@ use aptos_indexer_processor_sdk::{
     types::transaction_context::TransactionContext,
     utils::errors::ProcessorError,
 };
+use bigdecimal::{BigDecimal, Zero};
 use diesel::{
+    define_sql_function,
+    dsl::{case_when, now},
     pg::{upsert::excluded, Pg},
     query_builder::QueryFragment,
     query_dsl::methods::FilterDsl,
-    ExpressionMethods,
+    sql_types::{Nullable, SingleValue, SqlType},
+    BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, PgExpressionMethods,
 };
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection};
+use std::{future::Future, sync::Arc};
 use tonic::async_trait;
+use tracing::warn;
 
 pub struct DBWritingStep {
     pub db_pool: ArcDbPool,
+    pub chunk_size: usize,
+    /// Retries for batches that fail with a transient error, see [`retry_transient_errors`].
+    pub retry_config: QueryRetryConfig,
+    seen_contracts: SeenContracts,
+    token_owners: Option<Arc<TokenOwners>>,
+    resource_last_writes: Option<Arc<ResourceLastWrites>>,
 }
 
 impl DBWritingStep {
-    pub fn new(db_pool: ArcDbPool) -> Self {
-        Self { db_pool }
+    pub fn new(db_pool: ArcDbPool, chunk_size: usize, retry_config: QueryRetryConfig) -> Self {
+        Self {
+            db_pool,
+            chunk_size,
+            retry_config,
+            seen_contracts: SeenContracts::default(),
+            token_owners: None,
+            resource_last_writes: None,
+        }
+    }
+
+    /// Applies the token owners the remapping step records to the listings, after each batch's
+    /// listings are written.
+    pub fn with_token_owners(mut self, token_owners: Option<Arc<TokenOwners>>) -> Self {
+        self.token_owners = token_owners;
+        self
+    }
+
+    /// Stores the resource last writes the remapping step records with each batch, so that a
+    /// restarted processor still remaps the resources written before it stopped.
+    pub fn with_resource_last_writes(
+        mut self,
+        resource_last_writes: Option<Arc<ResourceLastWrites>>,
+    ) -> Self {
+        self.resource_last_writes = resource_last_writes;
+        self
     }
 }
 
 #[async_trait]
 impl Processable for DBWritingStep {
-    type Input = (
-        Vec<NftMarketplaceActivity>,
-        Vec<CurrentNFTMarketplaceListing>,
-        Vec<CurrentNFTMarketplaceTokenOffer>,
-        Vec<CurrentNFTMarketplaceCollectionOffer>,
-    );
-    type Output = ();
+    type Input = RemapOutput;
+    type Output = RemapOutput;
     type RunType = AsyncRunType;
 
     async fn process(
         &mut self,
-        input: TransactionContext<(
-            Vec<NftMarketplaceActivity>,
-            Vec<CurrentNFTMarketplaceListing>,
-            Vec<CurrentNFTMarketplaceTokenOffer>,
-            Vec<CurrentNFTMarketplaceCollectionOffer>,
-        )>,
-    ) -> Result<Option<TransactionContext<()>>, ProcessorError> {
-        let (activities, listings, token_offers, collection_offers) = input.data;
-
-        let mut deduped_activities: Vec<NftMarketplaceActivity> = activities
-            .into_iter()
-            .map(|activity| {
-                (
-                    (
-                        activity.txn_version,
-                        activity.index,
-                        activity.marketplace.clone(),
-                    ),
-                    activity,
-                )
-            })
-            .collect::<HashMap<_, _>>()
-            .into_values()
-            .collect();
+        input: TransactionContext<RemapOutput>,
+    ) -> Result<Option<TransactionContext<RemapOutput>>, ProcessorError> {
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            auctions,
+        } = input.data;
 
-        deduped_activities.sort_by(|a, b| {
-            a.txn_version
-                .cmp(&b.txn_version)
-                .then(a.index.cmp(&b.index))
-        });
-
-        let mut deduped_listings: Vec<CurrentNFTMarketplaceListing> = listings
-            .into_iter()
-            .map(|listing| {
-                let key = (listing.token_data_id.clone(), listing.marketplace.clone());
-                (key, listing)
-            })
-            .collect::<HashMap<_, _>>()
-            .into_values()
-            .collect();
-        deduped_listings.sort_by(|a, b| a.token_data_id.cmp(&b.token_data_id));
-
-        let mut deduped_token_offers: Vec<CurrentNFTMarketplaceTokenOffer> = token_offers
-            .into_iter()
-            .map(|offer| {
-                let key = (
-                    offer.token_data_id.clone(),
-                    offer.buyer.clone(),
-                    offer.marketplace.clone(),
-                );
-                (key, offer)
-            })
-            .collect::<HashMap<_, _>>()
-            .into_values()
+        let mut deduped_activities = dedup_activities(activities);
+        for activity in deduped_activities.iter_mut() {
+            activity.content_hash = Some(activity.compute_content_hash());
+        }
+
+        // Deduplicated on their primary keys, and written in their order
+        let deduped_listings = dedup_latest(listings);
+        let deduped_token_offers = dedup_latest(token_offers);
+        let deduped_collection_offers = dedup_latest(collection_offers);
+        let deduped_auctions = dedup_latest(auctions);
+
+        // Each token sold against a collection offer, which the offer's current state doesn't keep
+        let collection_offer_fills: Vec<CollectionOfferFill> = deduped_activities
+            .iter()
+            .filter_map(CollectionOfferFill::from_activity)
             .collect();
 
-        deduped_token_offers.sort_by(|a, b: &CurrentNFTMarketplaceTokenOffer| {
-            let key_a = (&a.token_data_id, &a.buyer);
-            let key_b = (&b.token_data_id, &b.buyer);
-            key_a.cmp(&key_b)
-        });
-
-        // Deduplicate collection offers using offer_id
-        let mut deduped_collection_offers: Vec<CurrentNFTMarketplaceCollectionOffer> =
-            collection_offers
-                .into_iter()
-                .map(|offer| {
-                    let key = (offer.collection_offer_id.clone(), offer.marketplace.clone());
-                    (key, offer)
-                })
-                .collect::<HashMap<_, _>>()
-                .into_values()
-                .collect();
-
-        deduped_collection_offers.sort_by(|a, b| a.collection_offer_id.cmp(&b.collection_offer_id));
-
-        // Execute DB operations with sorted, deduplicated data
-        let activities_result = execute_in_chunks(
-            self.db_pool.clone(),
-            insert_nft_marketplace_activities,
-            &deduped_activities,
-            200,
-        );
+        let (marketplace, owners) = match &self.token_owners {
+            Some(token_owners) => (
+                token_owners.marketplace(),
+                token_owners.take_up_to(input.metadata.end_version as i64),
+            ),
+            None => ("", Vec::new()),
+        };
+        let last_writes = self
+            .resource_last_writes
+            .as_ref()
+            .map(|last_writes| last_writes.take_up_to(input.metadata.end_version as i64))
+            .unwrap_or_default();
 
-        let listings_result = execute_in_chunks(
-            self.db_pool.clone(),
-            insert_current_nft_marketplace_listings,
+        // Write all tables in one transaction, so a failed batch leaves none of its rows behind
+        // and is retried as a whole
+        let chunk_size = self.chunk_size;
+        let db_pool = &self.db_pool;
+        let (activities, listings, token_offers, collection_offers, auctions, fills) = (
+            &deduped_activities,
             &deduped_listings,
-            200,
-        );
-
-        let token_offers_result = execute_in_chunks(
-            self.db_pool.clone(),
-            insert_current_nft_marketplace_token_offers,
             &deduped_token_offers,
-            200,
-        );
-
-        let collection_offers_result = execute_in_chunks(
-            self.db_pool.clone(),
-            insert_current_nft_marketplace_collection_offers,
             &deduped_collection_offers,
-            200,
-        );
-
-        let (activities_result, listings_result, token_offers_result, collection_offers_result) = tokio::join!(
-            activities_result,
-            listings_result,
-            token_offers_result,
-            collection_offers_result
+            &deduped_auctions,
+            &collection_offer_fills,
         );
+        let (owners, last_writes) = (&owners, &last_writes);
+        retry_transient_errors(&self.retry_config, || async move {
+            let mut conn = db_pool
+                .get()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to get database connection: {e:?}"))?;
+            let conn: &mut MyDbConnection = &mut conn;
+            conn.transaction(|conn| {
+                async move {
+                    execute_in_chunks_on_conn(
+                        conn,
+                        insert_nft_marketplace_activities,
+                        activities,
+                        chunk_size,
+                    )
+                    .await?;
+                    execute_in_chunks_on_conn(
+                        conn,
+                        insert_current_nft_marketplace_listings,
+                        listings,
+                        chunk_size,
+                    )
+                    .await?;
+                    execute_in_chunks_on_conn(
+                        conn,
+                        insert_current_nft_marketplace_token_offers,
+                        token_offers,
+                        chunk_size,
+                    )
+                    .await?;
+                    execute_in_chunks_on_conn(
+                        conn,
+                        insert_current_nft_marketplace_collection_offers,
+                        collection_offers,
+                        chunk_size,
+                    )
+                    .await?;
+                    execute_in_chunks_on_conn(
+                        conn,
+                        insert_current_nft_marketplace_auctions,
+                        auctions,
+                        chunk_size,
+                    )
+                    .await?;
+                    execute_in_chunks_on_conn(
+                        conn,
+                        insert_collection_offer_fills,
+                        fills,
+                        chunk_size,
+                    )
+                    .await?;
+                    update_listing_owners(conn, marketplace, owners).await?;
+                    execute_in_chunks_on_conn(
+                        conn,
+                        insert_resource_last_writes,
+                        last_writes,
+                        chunk_size,
+                    )
+                    .await?;
+                    Ok::<(), diesel::result::Error>(())
+                }
+                .scope_boxed()
+            })
+            .await?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| ProcessorError::DBStoreError {
+            message: format!("Failed to store: {e:?}"),
+            query: None,
+        })?;
 
-        for result in [
-            activities_result,
-            listings_result,
-            token_offers_result,
-            collection_offers_result,
+        for (table_name, row_count) in [
+            (
+                NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME,
+                deduped_activities.len(),
+            ),
+            (
+                CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+                deduped_listings.len(),
+            ),
+            (
+                CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME,
+                deduped_token_offers.len(),
+            ),
+            (
+                CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+                deduped_collection_offers.len(),
+            ),
+            (
+                CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+                deduped_auctions.len(),
+            ),
+            (
+                COLLECTION_OFFER_FILLS_TABLE_NAME,
+                collection_offer_fills.len(),
+            ),
         ] {
-            match result {
-                Ok(_) => (),
-                Err(e) => {
-                    return Err(ProcessorError::DBStoreError {
-                        message: format!("Failed to store: {e:?}"),
-                        query: None,
-                    })
-                },
-            }
+            NFT_DB_ROWS_WRITTEN
+                .with_label_values(&[table_name])
+                .inc_by(row_count as u64);
+        }
+
+        // Only a signal for operators, so a failure doesn't fail the batch
+        if let Err(e) = self
+            .seen_contracts
+            .record(self.db_pool.clone(), &deduped_activities)
+            .await
+        {
+            warn!("{e:#}");
         }
 
+        // Pass the persisted rows on so they can be exported downstream
         Ok(Some(TransactionContext {
-            data: (),
+            data: RemapOutput {
+                activities: deduped_activities,
+                listings: deduped_listings,
+                token_offers: deduped_token_offers,
+                collection_offers: deduped_collection_offers,
+                auctions: deduped_auctions,
+            },
             metadata: input.metadata,
         }))
     }
@@ -187,15 +280,141 @@ impl NamedStep for DBWritingStep {
     }
 }
 
+/// Runs `write` again when it fails with a transient database error or without a connection,
+/// e.g. while the database fails over. Other errors, such as constraint violations, would fail
+/// the same way again and are returned right away.
+pub async fn retry_transient_errors<F, Fut>(
+    retry_config: &QueryRetryConfig,
+    write: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    retry_with_backoff_if(
+        "store_batch",
+        retry_config,
+        |e: &anyhow::Error| match e.downcast_ref::<diesel::result::Error>() {
+            Some(e) => is_transient_error(e),
+            // Getting a connection from the pool failed
+            None => true,
+        },
+        write,
+    )
+    .await
+}
+
+/// Deduplicates activities on their primary key `(txn_version, index, marketplace)`, so events
+/// from different marketplaces that share an index in the same transaction are all kept.
+pub fn dedup_activities(activities: Vec<NftMarketplaceActivity>) -> Vec<NftMarketplaceActivity> {
+    let mut deduped_activities: Vec<NftMarketplaceActivity> = activities
+        .into_iter()
+        .map(|activity| (activity.primary_key(), activity))
+        .collect::<HashMap<_, _>>()
+        .into_values()
+        .collect();
+
+    deduped_activities.sort_by(|a, b| {
+        a.txn_version
+            .cmp(&b.txn_version)
+            .then(a.index.cmp(&b.index))
+            .then_with(|| a.marketplace.cmp(&b.marketplace))
+    });
+    deduped_activities
+}
+
+/// Deduplicates current models on their primary key, keeping the one from the latest
+/// transaction rather than whichever one the map happened to end up with. The models are
+/// returned ordered by key, like [`crate::steps::reduction_step::NFTAccumulator::drain`] does.
+pub fn dedup_latest<T: CurrentMarketplaceModel>(models: Vec<T>) -> Vec<T> {
+    let mut latest = HashMap::default();
+    for model in models {
+        fold_latest(&mut latest, model.primary_key(), model);
+    }
+    drain_sorted(&mut latest)
+}
+
+define_sql_function! {
+    /// Lets the upserts keep a stored value the new row leaves unset, see
+    /// [`CurrentMarketplaceModel`].
+    fn coalesce<T: SqlType + SingleValue>(x: Nullable<T>, y: Nullable<T>) -> Nullable<T>;
+}
+
 pub fn insert_nft_marketplace_activities(
     items_to_insert: Vec<NftMarketplaceActivity>,
 ) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
     use crate::schema::nft_marketplace_activities::dsl::*;
 
+    // Reprocessed activities are only rewritten when their content changed, e.g. after a
+    // remapping fix. Rows written before content hashes existed have none and are rewritten.
     diesel::insert_into(schema::nft_marketplace_activities::table)
         .values(items_to_insert)
         .on_conflict((txn_version, index, marketplace))
-        .do_nothing()
+        .do_update()
+        .set((
+            raw_event_type.eq(excluded(raw_event_type)),
+            standard_event_type.eq(excluded(standard_event_type)),
+            creator_address.eq(excluded(creator_address)),
+            collection_id.eq(excluded(collection_id)),
+            collection_name.eq(excluded(collection_name)),
+            token_data_id.eq(excluded(token_data_id)),
+            token_name.eq(excluded(token_name)),
+            price.eq(excluded(price)),
+            token_amount.eq(excluded(token_amount)),
+            buyer.eq(excluded(buyer)),
+            seller.eq(excluded(seller)),
+            listing_id.eq(excluded(listing_id)),
+            offer_id.eq(excluded(offer_id)),
+            json_data.eq(excluded(json_data)),
+            contract_address.eq(excluded(contract_address)),
+            block_timestamp.eq(excluded(block_timestamp)),
+            expiration_time.eq(excluded(expiration_time)),
+            bid_key.eq(excluded(bid_key)),
+            price_raw.eq(excluded(price_raw)),
+            price_display.eq(excluded(price_display)),
+            payment_token.eq(excluded(payment_token)),
+            token_standard.eq(excluded(token_standard)),
+            custom_fields.eq(excluded(custom_fields)),
+            content_hash.eq(excluded(content_hash)),
+            marketplace_fee.eq(excluded(marketplace_fee)),
+            royalty_fee.eq(excluded(royalty_fee)),
+            is_self_trade.eq(excluded(is_self_trade)),
+            direction.eq(excluded(direction)),
+            updated_at.eq(now),
+        ))
+        .filter(content_hash.is_distinct_from(excluded(content_hash)))
+}
+
+/// Expands to the `SET` of a current table's upsert: `overwrite` columns take the new row's
+/// value, `coalesce` columns keep the stored one when the new row leaves them unset, see
+/// [`CurrentMarketplaceModel`], and `nonzero` columns keep it when the new value is zero. Both
+/// only keep stored values while `$keep_stored` holds, i.e. the new row doesn't place a new
+/// listing, offer or auction. Columns with values of their own are given by the upsert, after
+/// `$keep_stored`. A column the upsert leaves out keeps the value it was first inserted with,
+/// which `test_upsert_updates_every_column` catches.
+macro_rules! upsert_set {
+    (
+        $keep_stored:ident $(, $custom_column:ident => $custom_value:expr)* $(,)?;
+        overwrite: [$($overwrite:ident),* $(,)?],
+        coalesce: [$($coalesce:ident),* $(,)?],
+        nonzero: [$($nonzero:ident),* $(,)?] $(,)?
+    ) => {
+        (
+            $($overwrite.eq(excluded($overwrite)),)*
+            $($coalesce.eq(case_when(
+                $keep_stored.clone(),
+                coalesce(excluded($coalesce), $coalesce),
+            )
+            .otherwise(excluded($coalesce))),)*
+            $($nonzero.eq(case_when(
+                $keep_stored.clone().and(excluded($nonzero).eq(BigDecimal::zero())),
+                $nonzero,
+            )
+            .otherwise(excluded($nonzero))),)*
+            $($custom_column.eq($custom_value),)*
+            updated_at.eq(now),
+        )
+    };
 }
 
 pub fn insert_current_nft_marketplace_listings(
@@ -203,22 +422,72 @@ pub fn insert_current_nft_marketplace_listings(
 ) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
     use crate::schema::current_nft_marketplace_listings::dsl::*;
 
+    // Updates keep whether the listing was deleted
+    let update_event_type = MarketplaceEventType::UpdateListing.to_string();
+    // A new listing replaces what was stored for the token, e.g. the price of a canceled one
+    let keep_stored =
+        excluded(standard_event_type).ne(MarketplaceEventType::PlaceListing.to_string());
+    // A fill that reports how many tokens it took subtracts them from the stored amount, see
+    // `CurrentNFTMarketplaceListing::apply_partial_fill`. Reprocessing the fill stored last
+    // must not subtract them again.
+    let partial_fill = excluded(filled_token_amount)
+        .is_not_null()
+        .and(excluded(token_amount).is_null())
+        .and(token_amount.is_not_null());
+    let new_partial_fill = partial_fill
+        .clone()
+        .and(last_transaction_version.lt(excluded(last_transaction_version)));
+    let remaining_token_amount = token_amount - excluded(filled_token_amount);
     diesel::insert_into(schema::current_nft_marketplace_listings::table)
         .values(items_to_insert)
         .on_conflict((token_data_id, marketplace))
         .do_update()
-        .set((
-            listing_id.eq(excluded(listing_id)),
-            collection_id.eq(excluded(collection_id)),
-            seller.eq(excluded(seller)),
-            price.eq(excluded(price)),
-            token_amount.eq(excluded(token_amount)),
-            token_name.eq(excluded(token_name)),
-            is_deleted.eq(excluded(is_deleted)),
-            contract_address.eq(excluded(contract_address)),
-            last_transaction_timestamp.eq(excluded(last_transaction_timestamp)),
-            last_transaction_version.eq(excluded(last_transaction_version)),
-            standard_event_type.eq(excluded(standard_event_type)),
+        .set(upsert_set!(keep_stored,
+            token_amount => case_when(
+                new_partial_fill.clone(),
+                // Keeps the tokens left on a partially filled listing from going negative
+                greatest(remaining_token_amount.clone(), BigDecimal::zero()),
+            )
+            .when(keep_stored.clone(), coalesce(excluded(token_amount), token_amount))
+            .otherwise(excluded(token_amount)),
+            is_deleted => case_when(
+                excluded(standard_event_type).eq(update_event_type),
+                is_deleted,
+            )
+            .when(
+                new_partial_fill,
+                remaining_token_amount
+                    .le(BigDecimal::zero())
+                    .assume_not_null(),
+            )
+            .when(
+                partial_fill,
+                token_amount.le(BigDecimal::zero()).assume_not_null(),
+            )
+            .otherwise(excluded(is_deleted));
+            overwrite: [
+                contract_address,
+                last_transaction_timestamp,
+                last_transaction_version,
+                standard_event_type,
+                payment_token,
+                filled_token_amount,
+                // A newer event means the seller still controls the token. The owner is only
+                // ever written by `update_listing_owners`.
+                is_stale,
+            ],
+            coalesce: [
+                listing_id,
+                collection_id,
+                seller,
+                token_name,
+                price_raw,
+                price_display,
+                creator_address,
+                collection_name,
+                token_standard,
+            ],
+            nonzero: [price],
         ))
         .filter(last_transaction_version.le(excluded(last_transaction_version)))
 }
@@ -227,23 +496,45 @@ pub fn insert_current_nft_marketplace_token_offers(
     items_to_insert: Vec<CurrentNFTMarketplaceTokenOffer>,
 ) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
     use crate::schema::current_nft_marketplace_token_offers::dsl::*;
+
+    // Updates keep whether the offer was deleted
+    let update_event_type = MarketplaceEventType::UpdateTokenOffer.to_string();
+    // A new offer replaces what was stored for the key, e.g. the expiration of a canceled one
+    let keep_stored =
+        excluded(standard_event_type).ne(MarketplaceEventType::PlaceTokenOffer.to_string());
     diesel::insert_into(schema::current_nft_marketplace_token_offers::table)
         .values(items_to_insert)
-        .on_conflict((token_data_id, buyer, marketplace))
+        .on_conflict((token_data_id, buyer, marketplace, offer_key))
         .do_update()
-        .set((
-            offer_id.eq(excluded(offer_id)),
-            collection_id.eq(excluded(collection_id)),
-            buyer.eq(excluded(buyer)),
-            price.eq(excluded(price)),
-            token_amount.eq(excluded(token_amount)),
-            token_name.eq(excluded(token_name)),
-            is_deleted.eq(excluded(is_deleted)),
-            contract_address.eq(excluded(contract_address)),
-            last_transaction_version.eq(excluded(last_transaction_version)),
-            last_transaction_timestamp.eq(excluded(last_transaction_timestamp)),
-            standard_event_type.eq(excluded(standard_event_type)),
-            bid_key.eq(excluded(bid_key)),
+        .set(upsert_set!(keep_stored,
+            is_deleted => case_when(
+                excluded(standard_event_type).eq(update_event_type),
+                is_deleted,
+            )
+            .otherwise(excluded(is_deleted));
+            overwrite: [
+                buyer,
+                contract_address,
+                last_transaction_version,
+                last_transaction_timestamp,
+                standard_event_type,
+                payment_token,
+            ],
+            coalesce: [
+                offer_id,
+                collection_id,
+                token_amount,
+                token_name,
+                expiration_time,
+                bid_key,
+                price_raw,
+                price_display,
+                creator_address,
+                collection_name,
+                token_standard,
+                property_version,
+            ],
+            nonzero: [price],
         ))
         .filter(last_transaction_version.le(excluded(last_transaction_version)))
 }
@@ -253,22 +544,183 @@ pub fn insert_current_nft_marketplace_collection_offers(
 ) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
     use crate::schema::current_nft_marketplace_collection_offers::dsl::*;
 
+    let keep_stored =
+        excluded(standard_event_type).ne(MarketplaceEventType::PlaceCollectionOffer.to_string());
     diesel::insert_into(schema::current_nft_marketplace_collection_offers::table)
         .values(items_to_insert)
         .on_conflict((collection_offer_id, marketplace))
         .do_update()
+        .set(upsert_set!(keep_stored;
+            overwrite: [
+                buyer,
+                remaining_token_amount,
+                is_deleted,
+                contract_address,
+                last_transaction_version,
+                last_transaction_timestamp,
+                token_data_id,
+                standard_event_type,
+                payment_token,
+            ],
+            coalesce: [
+                collection_id,
+                expiration_time,
+                bid_key,
+                price_raw,
+                price_display,
+                creator_address,
+                collection_name,
+                token_standard,
+            ],
+            nonzero: [price],
+        ))
+        .filter(last_transaction_version.le(excluded(last_transaction_version)))
+}
+
+pub fn insert_current_nft_marketplace_auctions(
+    items_to_insert: Vec<CurrentNFTMarketplaceAuction>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::current_nft_marketplace_auctions::dsl::*;
+
+    let keep_stored =
+        excluded(standard_event_type).ne(MarketplaceEventType::PlaceAuction.to_string());
+    diesel::insert_into(schema::current_nft_marketplace_auctions::table)
+        .values(items_to_insert)
+        .on_conflict((token_data_id, marketplace))
+        .do_update()
+        .set(upsert_set!(keep_stored;
+            overwrite: [
+                current_bidder,
+                current_bid,
+                is_deleted,
+                contract_address,
+                last_transaction_version,
+                last_transaction_timestamp,
+                standard_event_type,
+                payment_token,
+            ],
+            coalesce: [
+                auction_id,
+                collection_id,
+                token_name,
+                seller,
+                starting_price,
+                ending_price,
+                start_time,
+                end_time,
+                token_standard,
+            ],
+            nonzero: [],
+        ))
+        .filter(last_transaction_version.le(excluded(last_transaction_version)))
+}
+
+pub fn insert_collection_offer_fills(
+    items_to_insert: Vec<CollectionOfferFill>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::collection_offer_fills::dsl::*;
+
+    // Reprocessing rewrites the fill, like its activity
+    diesel::insert_into(schema::collection_offer_fills::table)
+        .values(items_to_insert)
+        .on_conflict((txn_version, index, marketplace))
+        .do_update()
         .set((
-            collection_id.eq(excluded(collection_id)),
+            collection_offer_id.eq(excluded(collection_offer_id)),
+            token_data_id.eq(excluded(token_data_id)),
             buyer.eq(excluded(buyer)),
+            seller.eq(excluded(seller)),
             price.eq(excluded(price)),
-            remaining_token_amount.eq(excluded(remaining_token_amount)),
-            is_deleted.eq(excluded(is_deleted)),
-            contract_address.eq(excluded(contract_address)),
-            last_transaction_version.eq(excluded(last_transaction_version)),
-            last_transaction_timestamp.eq(excluded(last_transaction_timestamp)),
-            token_data_id.eq(excluded(token_data_id)),
-            standard_event_type.eq(excluded(standard_event_type)),
-            bid_key.eq(excluded(bid_key)),
+            block_timestamp.eq(excluded(block_timestamp)),
         ))
-        .filter(last_transaction_version.le(excluded(last_transaction_version)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::result::DatabaseErrorKind;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn build_activity(txn_version: i64, index: i64, marketplace: &str) -> NftMarketplaceActivity {
+        NftMarketplaceActivity {
+            txn_version,
+            index,
+            marketplace: marketplace.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dedup_activities_keeps_marketplaces_sharing_an_index() {
+        let deduped = dedup_activities(vec![
+            build_activity(1, 0, "wapal"),
+            build_activity(1, 0, "tradeport_v2"),
+            build_activity(1, 0, "wapal"),
+        ]);
+
+        let keys: Vec<_> = deduped
+            .iter()
+            .map(|a| (a.txn_version, a.index, a.marketplace.as_str()))
+            .collect();
+        assert_eq!(keys, vec![(1, 0, "tradeport_v2"), (1, 0, "wapal")]);
+    }
+
+    #[test]
+    fn test_dedup_latest_keeps_highest_version() {
+        let build_listing = |version: i64| CurrentNFTMarketplaceListing {
+            token_data_id: "0x1".to_string(),
+            marketplace: "wapal".to_string(),
+            last_transaction_version: version,
+            ..Default::default()
+        };
+        let deduped = dedup_latest(vec![build_listing(5), build_listing(3)]);
+        assert_eq!(deduped.len(), 1);
+        assert_eq!(deduped[0].last_transaction_version, 5);
+
+        let deduped = dedup_latest(vec![build_listing(3), build_listing(5)]);
+        assert_eq!(deduped.len(), 1);
+        assert_eq!(deduped[0].last_transaction_version, 5);
+    }
+
+    fn database_error(kind: DatabaseErrorKind) -> anyhow::Error {
+        diesel::result::Error::DatabaseError(kind, Box::new("test error".to_string())).into()
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_error() {
+        let retry_config = QueryRetryConfig {
+            retries: 3,
+            retry_delay_ms: 0,
+        };
+        let attempts = AtomicU32::new(0);
+        let counter = &attempts;
+        let result = retry_transient_errors(&retry_config, || async move {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(database_error(DatabaseErrorKind::SerializationFailure));
+            }
+            Ok(())
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_constraint_violation() {
+        let retry_config = QueryRetryConfig {
+            retries: 3,
+            retry_delay_ms: 0,
+        };
+        let attempts = AtomicU32::new(0);
+        let counter = &attempts;
+        let result = retry_transient_errors(&retry_config, || async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(database_error(DatabaseErrorKind::UniqueViolation))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
 }
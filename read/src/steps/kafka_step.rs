@@ -0,0 +1,373 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::DEFAULT_KAFKA_MESSAGE_TIMEOUT_MS,
+    models::nft_models::NftMarketplaceActivity,
+    utils::retry::{retry_with_backoff, QueryRetryConfig},
+};
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use futures::future::join_all;
+use rdkafka::{
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tonic::async_trait;
+use tracing::warn;
+
+/// Where to publish activities once they are persisted.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct KafkaConfig {
+    /// Bootstrap servers, e.g. `localhost:9092`.
+    pub brokers: Vec<String>,
+    pub topic: String,
+    #[serde(default)]
+    pub delivery: KafkaDelivery,
+    /// How long the producer keeps trying to deliver a message before reporting it as failed.
+    #[serde(default = "KafkaConfig::default_message_timeout_ms")]
+    pub message_timeout_ms: u64,
+    /// Retries for messages that failed to be delivered, with exponential backoff. Only used
+    /// with `at_least_once`.
+    #[serde(default)]
+    pub retry_config: QueryRetryConfig,
+}
+
+impl KafkaConfig {
+    pub const fn default_message_timeout_ms() -> u64 {
+        DEFAULT_KAFKA_MESSAGE_TIMEOUT_MS
+    }
+}
+
+/// What the processor does about messages that can't be delivered.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KafkaDelivery {
+    /// Waits for every in-sync replica to acknowledge each message, and fails the step when one
+    /// still isn't delivered after its retries. The batch is then processed again on restart,
+    /// so consumers can see an activity twice but never miss one.
+    #[default]
+    AtLeastOnce,
+    /// Waits for the partition leader only, and logs and drops messages that fail.
+    AtMostOnce,
+}
+
+/// Publishes a message to Kafka. Implemented for `rdkafka`'s producer, and by tests that
+/// record the messages instead.
+#[async_trait]
+pub trait ActivityProducer: Send + Sync {
+    /// Returns once the broker acknowledged the message.
+    async fn send(&self, topic: &str, key: Option<&str>, payload: &[u8]) -> Result<()>;
+}
+
+#[async_trait]
+impl ActivityProducer for FutureProducer {
+    async fn send(&self, topic: &str, key: Option<&str>, payload: &[u8]) -> Result<()> {
+        let mut record = FutureRecord::<str, [u8]>::to(topic).payload(payload);
+        if let Some(key) = key {
+            record = record.key(key);
+        }
+        FutureProducer::send(self, record, Duration::ZERO)
+            .await
+            .map_err(|(e, _)| anyhow::anyhow!("Failed to deliver message to {topic}: {e}"))?;
+        Ok(())
+    }
+}
+
+/// Publishes each activity written by `DBWritingStep` to a Kafka topic as JSON, keyed by its
+/// `token_data_id` so that the activities of a token land on the same partition, in order.
+/// Activities without a token, e.g. collection offers, are published without a key. Without
+/// Kafka configured the step only passes the batch along.
+pub struct KafkaStep {
+    sink: Option<(KafkaConfig, Arc<dyn ActivityProducer>)>,
+}
+
+impl KafkaStep {
+    pub fn new(config: Option<KafkaConfig>) -> Result<Self> {
+        let sink = match config {
+            Some(config) => {
+                let producer = build_producer(&config)?;
+                Some((config, Arc::new(producer) as Arc<dyn ActivityProducer>))
+            },
+            None => None,
+        };
+        Ok(Self { sink })
+    }
+
+    /// Publishes through `producer` instead of a producer connected to `config.brokers`.
+    pub fn with_producer(config: KafkaConfig, producer: Arc<dyn ActivityProducer>) -> Self {
+        Self {
+            sink: Some((config, producer)),
+        }
+    }
+}
+
+fn build_producer(config: &KafkaConfig) -> Result<FutureProducer> {
+    let acks = match config.delivery {
+        KafkaDelivery::AtLeastOnce => "all",
+        KafkaDelivery::AtMostOnce => "1",
+    };
+    ClientConfig::new()
+        .set("bootstrap.servers", config.brokers.join(","))
+        .set("message.timeout.ms", config.message_timeout_ms.to_string())
+        .set("acks", acks)
+        // Keeps the messages of a partition in order when the producer retries internally
+        .set(
+            "enable.idempotence",
+            (config.delivery == KafkaDelivery::AtLeastOnce).to_string(),
+        )
+        .create()
+        .context("Failed to create Kafka producer")
+}
+
+#[async_trait]
+impl Processable for KafkaStep {
+    type Input = Vec<NftMarketplaceActivity>;
+    type Output = Vec<NftMarketplaceActivity>;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<Vec<NftMarketplaceActivity>>,
+    ) -> Result<Option<TransactionContext<Vec<NftMarketplaceActivity>>>, ProcessorError> {
+        if let Some((config, producer)) = &self.sink {
+            publish_activities(producer.as_ref(), config, &input.data)
+                .await
+                .map_err(|e| ProcessorError::ProcessError {
+                    message: format!("{e:#}"),
+                })?;
+        }
+
+        Ok(Some(input))
+    }
+}
+
+impl AsyncStep for KafkaStep {}
+
+impl NamedStep for KafkaStep {
+    fn name(&self) -> String {
+        "KafkaStep".to_string()
+    }
+}
+
+/// Sends the messages of a batch concurrently across keys, but one at a time for each key, so
+/// a message that is retried still reaches its partition before the later ones for its key.
+/// Messages without a key have no order to keep and are each sent on their own.
+async fn publish_activities(
+    producer: &dyn ActivityProducer,
+    config: &KafkaConfig,
+    activities: &[NftMarketplaceActivity],
+) -> Result<()> {
+    let mut queues: Vec<(Option<&str>, Vec<Vec<u8>>)> = Vec::new();
+    let mut queue_of_key: HashMap<&str, usize> = HashMap::new();
+    for activity in activities {
+        let payload = serde_json::to_vec(activity)?;
+        match activity.token_data_id.as_deref() {
+            Some(key) => {
+                let queue = *queue_of_key.entry(key).or_insert_with(|| {
+                    queues.push((Some(key), Vec::new()));
+                    queues.len() - 1
+                });
+                queues[queue].1.push(payload);
+            },
+            None => queues.push((None, vec![payload])),
+        }
+    }
+
+    let results = join_all(queues.iter().map(|(key, payloads)| async move {
+        for payload in payloads {
+            let result = match config.delivery {
+                KafkaDelivery::AtLeastOnce => {
+                    retry_with_backoff("publish_activity", &config.retry_config, move || {
+                        producer.send(&config.topic, *key, payload)
+                    })
+                    .await
+                },
+                KafkaDelivery::AtMostOnce => producer.send(&config.topic, *key, payload).await,
+            };
+            match (config.delivery, result) {
+                (_, Ok(())) => {},
+                (KafkaDelivery::AtLeastOnce, Err(e)) => return Err(e),
+                (KafkaDelivery::AtMostOnce, Err(e)) => {
+                    warn!("Dropping activity for Kafka: {e:#}");
+                },
+            }
+        }
+        Ok(())
+    }))
+    .await;
+
+    results.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::types::transaction_context::TransactionMetadata;
+    use std::sync::Mutex;
+
+    /// Records the messages it's sent, failing the first `failures` of them.
+    #[derive(Default)]
+    struct MockProducer {
+        failures: Mutex<usize>,
+        messages: Mutex<Vec<(String, Option<String>, serde_json::Value)>>,
+    }
+
+    #[async_trait]
+    impl ActivityProducer for MockProducer {
+        async fn send(&self, topic: &str, key: Option<&str>, payload: &[u8]) -> Result<()> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                anyhow::bail!("Broker unavailable");
+            }
+            self.messages.lock().unwrap().push((
+                topic.to_string(),
+                key.map(str::to_string),
+                serde_json::from_slice(payload)?,
+            ));
+            Ok(())
+        }
+    }
+
+    fn build_config(delivery: KafkaDelivery) -> KafkaConfig {
+        KafkaConfig {
+            brokers: vec!["localhost:9092".to_string()],
+            topic: "nft_marketplace_activities".to_string(),
+            delivery,
+            message_timeout_ms: KafkaConfig::default_message_timeout_ms(),
+            retry_config: QueryRetryConfig {
+                retries: 2,
+                retry_delay_ms: 0,
+            },
+        }
+    }
+
+    fn build_activities() -> Vec<NftMarketplaceActivity> {
+        vec![
+            NftMarketplaceActivity {
+                txn_version: 1,
+                index: 0,
+                token_data_id: Some("0x1".to_string()),
+                ..Default::default()
+            },
+            NftMarketplaceActivity {
+                txn_version: 1,
+                index: 1,
+                token_data_id: Some("0x2".to_string()),
+                ..Default::default()
+            },
+            NftMarketplaceActivity {
+                txn_version: 2,
+                index: 0,
+                ..Default::default()
+            },
+        ]
+    }
+
+    async fn process(step: &mut KafkaStep) -> Result<(), ProcessorError> {
+        step.process(TransactionContext {
+            data: build_activities(),
+            metadata: TransactionMetadata::default(),
+        })
+        .await
+        .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_publishes_one_message_per_activity() {
+        let producer = Arc::new(MockProducer {
+            failures: Mutex::new(1),
+            ..Default::default()
+        });
+        let mut step =
+            KafkaStep::with_producer(build_config(KafkaDelivery::AtLeastOnce), producer.clone());
+
+        process(&mut step).await.unwrap();
+
+        let messages = producer.messages.lock().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages
+            .iter()
+            .all(|(topic, ..)| topic == "nft_marketplace_activities"));
+        let mut keys: Vec<_> = messages.iter().map(|(_, key, _)| key.clone()).collect();
+        keys.sort();
+        assert_eq!(keys, vec![
+            None,
+            Some("0x1".to_string()),
+            Some("0x2".to_string())
+        ]);
+        let payload = messages
+            .iter()
+            .find(|(_, key, _)| key.as_deref() == Some("0x2"))
+            .map(|(.., payload)| payload)
+            .unwrap();
+        assert_eq!(payload["index"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_at_least_once_fails_when_retries_are_exhausted() {
+        let producer = Arc::new(MockProducer {
+            failures: Mutex::new(usize::MAX),
+            ..Default::default()
+        });
+        let mut step = KafkaStep::with_producer(build_config(KafkaDelivery::AtLeastOnce), producer);
+
+        assert!(process(&mut step).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_at_most_once_drops_failed_messages() {
+        let producer = Arc::new(MockProducer {
+            failures: Mutex::new(1),
+            ..Default::default()
+        });
+        let mut step =
+            KafkaStep::with_producer(build_config(KafkaDelivery::AtMostOnce), producer.clone());
+
+        process(&mut step).await.unwrap();
+
+        assert_eq!(producer.messages.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_keep_per_key_order() {
+        let producer = Arc::new(MockProducer {
+            failures: Mutex::new(1),
+            ..Default::default()
+        });
+        let mut step =
+            KafkaStep::with_producer(build_config(KafkaDelivery::AtLeastOnce), producer.clone());
+
+        let activities = (0..3)
+            .map(|index| NftMarketplaceActivity {
+                txn_version: 1,
+                index,
+                token_data_id: Some("0x1".to_string()),
+                ..Default::default()
+            })
+            .collect();
+        step.process(TransactionContext {
+            data: activities,
+            metadata: TransactionMetadata::default(),
+        })
+        .await
+        .unwrap();
+
+        // The first message fails once and is retried before the others are sent
+        let indexes: Vec<_> = producer
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(.., payload)| payload["index"].clone())
+            .collect();
+        assert_eq!(indexes, vec![0, 1, 2]);
+    }
+}
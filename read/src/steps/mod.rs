@@ -5,11 +5,19 @@ use std::{
     str::FromStr,
 };
 
+pub mod auth_token_step;
+pub mod batch_size_step;
+pub mod bigquery_step;
+pub mod channel_depth_step;
+pub mod clickhouse_step;
 pub mod db_writing_step;
+pub mod kafka_step;
 pub mod processor_status_saver_step;
 pub mod reduction_step;
 pub mod remapper_step;
 pub mod remappers;
+pub mod shutdown_step;
+pub mod webhook_step;
 
 /// Extracts a string, ensuring proper handling of missing values
 pub fn extract_string(paths: &HashableJsonPath, from: &SerdeJsonValue) -> Option<String> {
@@ -19,23 +27,47 @@ pub fn extract_string(paths: &HashableJsonPath, from: &SerdeJsonValue) -> Option
         .and_then(|v| v.as_str().map(String::from))
 }
 
+/// Root of the paths that read the transaction an event was emitted in rather than the event,
+/// e.g. `$txn.sender`. See [`crate::models::TxnMetadata`] for the fields.
+pub const TXN_PATH_ROOT: &str = "$txn";
+
 /// A wrapper around JsonPath so that it can be hashed
 #[derive(Debug, Clone)]
 pub struct HashableJsonPath {
     json_path: JsonPath,
     /// The raw string representation of the JsonPath
     raw: String,
+    /// Whether the path starts at [`TXN_PATH_ROOT`]. `json_path` is then relative to `$`.
+    reads_txn: bool,
 }
 
 impl HashableJsonPath {
     pub fn new(raw: &str) -> anyhow::Result<Self> {
-        let json_path = JsonPath::from_str(raw)?;
+        let txn_path = raw
+            .strip_prefix(TXN_PATH_ROOT)
+            .filter(|rest| rest.starts_with('.') || rest.starts_with('['));
+        let json_path = match txn_path {
+            Some(rest) => JsonPath::from_str(&format!("${rest}"))?,
+            None => JsonPath::from_str(raw)?,
+        };
         Ok(Self {
             json_path,
             raw: raw.to_string(),
+            reads_txn: txn_path.is_some(),
         })
     }
 
+    /// The path as configured.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Whether the path reads the transaction's [`crate::models::TxnMetadata`] rather than the
+    /// event data.
+    pub fn reads_txn(&self) -> bool {
+        self.reads_txn
+    }
+
     /// Executes the JsonPath to extract the value from the provided serde_json::Value
     pub fn extract_from(&self, value: &SerdeJsonValue) -> anyhow::Result<SerdeJsonValue> {
         Ok(self
@@ -46,6 +78,17 @@ impl HashableJsonPath {
             .clone()
             .to_data())
     }
+
+    /// Executes the JsonPath and returns every match, e.g. each recipient for
+    /// `$.royalties[*].recipient`. Returns an empty vec if nothing matches.
+    pub fn extract_all_from(&self, value: &SerdeJsonValue) -> Vec<SerdeJsonValue> {
+        self.json_path
+            .find_slice(value)
+            .into_iter()
+            .map(|matched| matched.to_data())
+            .filter(|matched| !matched.is_null())
+            .collect()
+    }
 }
 
 impl Hash for HashableJsonPath {
@@ -22,6 +22,7 @@ use aptos_indexer_processor_sdk::{
 };
 use async_trait::async_trait;
 use diesel::{query_dsl::methods::FilterDsl, upsert::excluded, ExpressionMethods};
+use tracing::warn;
 
 /// A trait implementation of ProcessorStatusSaver for Postgres.
 pub struct PostgresProcessorStatusSaver {
@@ -42,7 +43,7 @@ impl ProcessorStatusSaver for PostgresProcessorStatusSaver {
         last_success_batch: &TransactionContext<()>,
     ) -> Result<(), ProcessorError> {
         save_processor_status(
-            &self.config.nft_marketplace_config.name,
+            &self.config.processor_status_name(),
             self.config.processor_mode.clone(),
             last_success_batch,
             self.db_pool.clone(),
@@ -161,7 +162,7 @@ pub async fn get_starting_version(
     config: &IndexerProcessorConfig,
     db_pool: ArcDbPool,
 ) -> Result<Option<u64>, ProcessorError> {
-    let processor_name = &config.nft_marketplace_config.name;
+    let processor_name = &config.processor_status_name();
     let mut conn = db_pool
         .get()
         .await
@@ -260,7 +261,7 @@ pub async fn get_starting_version(
                 // `backfill_config.initial_starting_version` is NOT respected.
                 // Return the last success version + 1.
                 let starting_version = status.last_success_version as u64 + 1;
-                log_ascii_warning(starting_version);
+                log_backfill_resume_warning(starting_version);
                 Ok(Some(starting_version))
             } else {
                 Ok(Some(*initial_starting_version))
@@ -280,7 +281,7 @@ pub async fn get_end_version(
     config: &IndexerProcessorConfig,
     db_pool: ArcDbPool,
 ) -> Result<Option<u64>, ProcessorError> {
-    let processor_name = &config.nft_marketplace_config.get_name();
+    let processor_name = &config.processor_status_name();
     let processor_mode = &config.processor_mode;
     match processor_mode {
         ProcessorMode::Default(_) => Ok(None),
@@ -315,19 +316,47 @@ pub async fn get_end_version(
     }
 }
 
-pub fn log_ascii_warning(version: u64) {
-    println!(
-        r#"
- ██╗    ██╗ █████╗ ██████╗ ███╗   ██╗██╗███╗   ██╗ ██████╗ ██╗
- ██║    ██║██╔══██╗██╔══██╗████╗  ██║██║████╗  ██║██╔════╝ ██║
- ██║ █╗ ██║███████║██████╔╝██╔██╗ ██║██║██╔██╗ ██║██║  ███╗██║
- ██║███╗██║██╔══██║██╔══██╗██║╚██╗██║██║██║╚██╗██║██║   ██║╚═╝
- ╚███╔███╔╝██║  ██║██║  ██║██║ ╚████║██║██║ ╚████║╚██████╔╝██╗
-  ╚══╝╚══╝ ╚═╝  ╚═╝╚═╝  ╚═╝╚═╝  ╚═══╝╚═╝╚═╝  ╚═══╝ ╚═════╝ ╚═╝
-                                                               
-=================================================================
-   This backfill job is resuming progress at version {version}
-=================================================================
-"#
+/// Returns the last version saved by the `PostgresProcessorStatusSaver`, if any. Testing mode
+/// never saves it.
+pub async fn get_last_success_version(
+    config: &IndexerProcessorConfig,
+    db_pool: ArcDbPool,
+) -> Result<Option<u64>, ProcessorError> {
+    let processor_name = &config.processor_status_name();
+    let mut conn = db_pool
+        .get()
+        .await
+        .map_err(|e| ProcessorError::ProcessError {
+            message: format!("Failed to get database connection. {e:?}"),
+        })?;
+
+    let last_success_version = match &config.processor_mode {
+        ProcessorMode::Default(_) => {
+            ProcessorStatusQuery::get_by_processor(processor_name, &mut conn)
+                .await
+                .map_err(|e| ProcessorError::ProcessError {
+                    message: format!("Failed to query processor_status table. {e:?}"),
+                })?
+                .map(|status| status.last_success_version)
+        },
+        ProcessorMode::Backfill(BackfillConfig { backfill_id, .. }) => {
+            BackfillProcessorStatusQuery::get_by_processor(processor_name, backfill_id, &mut conn)
+                .await
+                .map_err(|e| ProcessorError::ProcessError {
+                    message: format!("Failed to query backfill_processor_status table. {e:?}"),
+                })?
+                .map(|status| status.last_success_version)
+        },
+        ProcessorMode::Testing(_) => None,
+    };
+    Ok(last_success_version.map(|version| version as u64))
+}
+
+/// Once a backfill made progress its `initial_starting_version` is ignored, which is easy to
+/// miss when the intent was to start over.
+pub fn log_backfill_resume_warning(version: u64) {
+    warn!(
+        starting_version = version,
+        "This backfill job is resuming progress at version {version}"
     );
 }
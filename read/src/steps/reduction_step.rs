@@ -1,18 +1,28 @@
 use crate::{
     config::marketplace_config::MarketplaceEventType,
+    metrics::{NFT_EVENTS_SKIPPED, SKIP_REASON_INVALID_FIELD},
     models::nft_models::{
+        CurrentMarketplaceModel, CurrentNFTMarketplaceAuction,
         CurrentNFTMarketplaceCollectionOffer, CurrentNFTMarketplaceListing,
         CurrentNFTMarketplaceTokenOffer, MarketplaceField, MarketplaceModel,
         NftMarketplaceActivity,
     },
+    steps::remappers::{resource_remapper::ResourceUpdate, RemapOutput},
+    utils::convert::standardize_address,
 };
 use aptos_indexer_processor_sdk::{
     traits::{AsyncRunType, AsyncStep, NamedStep, Processable},
     types::transaction_context::TransactionContext,
     utils::errors::ProcessorError,
 };
-use log::debug;
-use std::{collections::HashMap, mem, str::FromStr};
+use bigdecimal::{BigDecimal, Zero};
+use log::{debug, warn};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::{BuildHasher, Hash},
+    mem,
+    str::FromStr,
+};
 
 #[derive(Clone, Debug, Default)]
 pub struct NFTAccumulator {
@@ -20,45 +30,79 @@ pub struct NFTAccumulator {
     listings: HashMap<String, CurrentNFTMarketplaceListing>,
     token_offers: HashMap<String, CurrentNFTMarketplaceTokenOffer>,
     collection_offers: HashMap<String, CurrentNFTMarketplaceCollectionOffer>,
+    auctions: HashMap<String, CurrentNFTMarketplaceAuction>,
 }
 
 impl NFTAccumulator {
     pub fn fold_listing(&mut self, listing: CurrentNFTMarketplaceListing) {
-        let key = format!("{}::{}", listing.marketplace, listing.token_data_id);
-        self.listings.insert(key, listing);
+        fold_latest(&mut self.listings, listing.primary_key(), listing);
     }
 
     pub fn fold_token_offer(&mut self, offer: CurrentNFTMarketplaceTokenOffer) {
-        let key = format!(
-            "{}::{}::{}",
-            offer.marketplace, offer.token_data_id, offer.buyer
-        );
-        self.token_offers.insert(key, offer);
+        fold_latest(&mut self.token_offers, offer.primary_key(), offer);
     }
 
     pub fn fold_collection_offer(&mut self, offer: CurrentNFTMarketplaceCollectionOffer) {
-        let key = format!("{}::{}", offer.marketplace, offer.collection_offer_id);
-        self.collection_offers.insert(key, offer);
+        fold_latest(&mut self.collection_offers, offer.primary_key(), offer);
+    }
+
+    pub fn fold_auction(&mut self, auction: CurrentNFTMarketplaceAuction) {
+        fold_latest(&mut self.auctions, auction.primary_key(), auction);
     }
 
     pub fn add_activity(&mut self, activity: NftMarketplaceActivity) {
         self.activities.push(activity);
     }
 
-    pub fn drain(
-        &mut self,
-    ) -> (
-        Vec<NftMarketplaceActivity>,
-        Vec<CurrentNFTMarketplaceListing>,
-        Vec<CurrentNFTMarketplaceTokenOffer>,
-        Vec<CurrentNFTMarketplaceCollectionOffer>,
-    ) {
-        (
-            mem::take(&mut self.activities),
-            self.listings.drain().map(|(_, v)| v).collect(),
-            self.token_offers.drain().map(|(_, v)| v).collect(),
-            self.collection_offers.drain().map(|(_, v)| v).collect(),
-        )
+    /// Takes everything folded so far. Activities and current models are ordered by their
+    /// primary keys, so the same transactions always produce the same
+    /// output, e.g. for the expected files generated by the integration tests.
+    pub fn drain(&mut self) -> RemapOutput {
+        let mut activities = mem::take(&mut self.activities);
+        activities.sort_by(|a, b| {
+            (a.txn_version, a.index, &a.marketplace).cmp(&(b.txn_version, b.index, &b.marketplace))
+        });
+        RemapOutput {
+            activities,
+            listings: drain_sorted(&mut self.listings),
+            token_offers: drain_sorted(&mut self.token_offers),
+            collection_offers: drain_sorted(&mut self.collection_offers),
+            auctions: drain_sorted(&mut self.auctions),
+        }
+    }
+}
+
+/// Takes the models out of `models` ordered by their key rather than the map's iteration
+/// order, which differs between runs.
+pub(crate) fn drain_sorted<K: Ord, T, S>(models: &mut HashMap<K, T, S>) -> Vec<T> {
+    let mut models: Vec<(K, T)> = models.drain().collect();
+    models.sort_by(|(a, _), (b, _)| a.cmp(b));
+    models.into_iter().map(|(_, model)| model).collect()
+}
+
+/// Keeps the model from the latest transaction under `key`, matching the
+/// `last_transaction_version <= excluded.last_transaction_version` filter of the upserts.
+///
+/// Within one transaction the last write wins: the current models don't carry an event index,
+/// so the model folded later replaces the earlier one. [`NFTReductionStep`] folds them ordered
+/// by `(txn_version, event_index)`, so e.g. a listing canceled and placed again in the same
+/// transaction ends up open. Fields the later model leaves unset are kept from the earlier one.
+pub(crate) fn fold_latest<K, T, S>(models: &mut HashMap<K, T, S>, key: K, mut model: T)
+where
+    K: Eq + Hash,
+    T: CurrentMarketplaceModel,
+    S: BuildHasher,
+{
+    match models.entry(key) {
+        Entry::Occupied(mut existing) => {
+            if model.get_txn_version() >= existing.get().get_txn_version() {
+                model.fill_missing_fields(existing.get());
+                existing.insert(model);
+            }
+        },
+        Entry::Vacant(entry) => {
+            entry.insert(model);
+        },
     }
 }
 
@@ -78,62 +122,53 @@ impl NFTReductionStep {
     }
 }
 
-pub type Tables = (
-    Vec<NftMarketplaceActivity>,
-    Vec<CurrentNFTMarketplaceListing>,
-    Vec<CurrentNFTMarketplaceTokenOffer>,
-    Vec<CurrentNFTMarketplaceCollectionOffer>,
-);
-
 #[async_trait::async_trait]
 impl Processable for NFTReductionStep {
-    type Input = (
-        HashMap<i64, Vec<NftMarketplaceActivity>>,
-        Vec<CurrentNFTMarketplaceListing>,
-        Vec<CurrentNFTMarketplaceTokenOffer>,
-        Vec<CurrentNFTMarketplaceCollectionOffer>,
-        HashMap<String, HashMap<String, String>>,
-    );
-    type Output = (
-        Vec<NftMarketplaceActivity>,
-        Vec<CurrentNFTMarketplaceListing>,
-        Vec<CurrentNFTMarketplaceTokenOffer>,
-        Vec<CurrentNFTMarketplaceCollectionOffer>,
-    );
+    type Input = (RemapOutput, HashMap<String, ResourceUpdate>);
+    type Output = RemapOutput;
     type RunType = AsyncRunType;
 
     async fn process(
         &mut self,
         transactions: TransactionContext<Self::Input>,
     ) -> Result<Option<TransactionContext<Self::Output>>, ProcessorError> {
-        let (
-            mut activities,
-            current_listings,
-            current_token_offers,
-            current_collection_offers,
-            resource_updates,
-        ) = transactions.data;
-
-        // Process listings with resource updates inline
-        for listing in current_listings {
-            if let Some(updates) = resource_updates.get(&listing.token_data_id) {
-                let mut listing = listing;
+        let (remapped, resource_updates) = transactions.data;
+        // Resource updates also fill in the activities of the model's transaction
+        let mut activities: HashMap<i64, Vec<NftMarketplaceActivity>> = HashMap::new();
+        for activity in remapped.activities {
+            activities
+                .entry(activity.txn_version)
+                .or_default()
+                .push(activity);
+        }
+        // The remapper emits each transaction's models by event index, and the stable sort keeps
+        // that order while ordering the transactions, see `fold_latest`
+        let current_listings = in_event_order(remapped.listings);
+        let current_token_offers = in_event_order(remapped.token_offers);
+        let current_collection_offers = in_event_order(remapped.collection_offers);
+        let current_auctions = in_event_order(remapped.auctions);
+
+        // Process listings with resource updates inline. Besides the token, listings that are
+        // objects themselves (e.g. `listing::Listing`) carry their state at the listing address.
+        for mut listing in current_listings {
+            for updates in matching_resource_updates(&resource_updates, [
+                Some(listing.token_data_id.as_str()),
+                listing.listing_id.as_deref(),
+            ]) {
                 merge_partial_update(&mut listing, updates, &mut activities);
-                self.accumulator.fold_listing(listing);
-            } else {
-                self.accumulator.fold_listing(listing);
             }
+            self.accumulator.fold_listing(listing);
         }
 
         // Process token offers with resource updates inline
-        for offer in current_token_offers {
-            if let Some(updates) = resource_updates.get(&offer.token_data_id) {
-                let mut offer = offer;
+        for mut offer in current_token_offers {
+            for updates in matching_resource_updates(&resource_updates, [
+                Some(offer.token_data_id.as_str()),
+                offer.offer_id.as_deref(),
+            ]) {
                 merge_partial_update(&mut offer, updates, &mut activities);
-                self.accumulator.fold_token_offer(offer);
-            } else {
-                self.accumulator.fold_token_offer(offer);
             }
+            self.accumulator.fold_token_offer(offer);
         }
 
         // Process collection offers with resource updates inline
@@ -161,6 +196,17 @@ impl Processable for NFTReductionStep {
             }
         }
 
+        // Process auctions with resource updates inline
+        for mut auction in current_auctions {
+            for updates in matching_resource_updates(&resource_updates, [
+                Some(auction.token_data_id.as_str()),
+                auction.auction_id.as_deref(),
+            ]) {
+                merge_partial_update(&mut auction, updates, &mut activities);
+            }
+            self.accumulator.fold_auction(auction);
+        }
+
         // process activities after all updates are applied
         for activities_vec_same_txn_version in activities.into_values() {
             for activity in activities_vec_same_txn_version {
@@ -185,20 +231,67 @@ impl NamedStep for NFTReductionStep {
     }
 }
 
+fn in_event_order<T: MarketplaceModel>(mut models: Vec<T>) -> Vec<T> {
+    models.sort_by_key(|model| model.get_txn_version());
+    models
+}
+
+/// Returns the resource updates keyed by any of `ids`, in order. Resource updates are keyed by
+/// the standardized address of the resource, so ids from events are standardized to match.
+fn matching_resource_updates<'a, const N: usize>(
+    resource_updates: &'a HashMap<String, ResourceUpdate>,
+    ids: [Option<&str>; N],
+) -> Vec<&'a ResourceUpdate> {
+    let mut keys: Vec<String> = Vec::with_capacity(N);
+    for id in ids.into_iter().flatten() {
+        let key = standardize_address(id).unwrap_or_else(|_| id.to_string());
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys.iter()
+        .filter_map(|key| resource_updates.get(key))
+        .collect()
+}
+
+/// Whether the event left `field` unset. Prices default to zero when the event doesn't carry
+/// one, so a zero price counts as unset.
+fn is_unset(field: &MarketplaceField, value: Option<String>) -> bool {
+    match value {
+        None => true,
+        Some(value) if value.is_empty() => true,
+        Some(value) => {
+            *field == MarketplaceField::Price
+                && BigDecimal::from_str(&value).is_ok_and(|price| price.is_zero())
+        },
+    }
+}
+
+/// Fills the fields `model` left unset from `partial_update`. Resources last written before the
+/// model's transaction describe an older state than its event, so they're ignored.
 fn merge_partial_update<T: MarketplaceModel>(
     model: &mut T,
-    partial_update: &HashMap<String, String>,
+    partial_update: &ResourceUpdate,
     activities: &mut HashMap<i64, Vec<NftMarketplaceActivity>>,
 ) {
-    for (column, value) in partial_update {
+    if partial_update.txn_version < model.get_txn_version() {
+        debug!(
+            "Skipping resource update from version {} older than the model's version {}",
+            partial_update.txn_version,
+            model.get_txn_version()
+        );
+        return;
+    }
+    for (column, value) in &partial_update.columns {
+        let Ok(field) = MarketplaceField::from_str(column) else {
+            warn!("Skipping resource update of unknown column '{}'", column);
+            NFT_EVENTS_SKIPPED
+                .with_label_values(&[SKIP_REASON_INVALID_FIELD])
+                .inc();
+            continue;
+        };
         // Only update if the field is not set in the event or is empty
-        if model
-            .get_field(MarketplaceField::from_str(column).unwrap())
-            .is_none()
-            || model
-                .get_field(MarketplaceField::from_str(column).unwrap())
-                .is_some_and(|v| v.is_empty())
-        {
+        if is_unset(&field, model.get_field(field.clone())) {
             if let Some(activities_vec) = activities.get_mut(&model.get_txn_version()) {
                 // Try to find matching activity based on token_data_id or collection_id
                 if let Some(matching_activity) = activities_vec.iter_mut().find(|activity| {
@@ -232,11 +325,332 @@ fn merge_partial_update<T: MarketplaceModel>(
                             .unwrap_or(false)
                     }
                 }) {
-                    matching_activity
-                        .set_field(MarketplaceField::from_str(column).unwrap(), value.clone());
+                    matching_activity.set_field(field.clone(), value.clone());
                 }
             }
-            model.set_field(MarketplaceField::from_str(column).unwrap(), value.clone());
+            model.set_field(field, value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+
+    fn build_listing(version: i64, price: i64) -> CurrentNFTMarketplaceListing {
+        CurrentNFTMarketplaceListing {
+            token_data_id: "0x1".to_string(),
+            marketplace: "test_marketplace".to_string(),
+            price: BigDecimal::from(price),
+            last_transaction_version: version,
+            ..Default::default()
         }
     }
+
+    fn build_resource_update(txn_version: i64, columns: &[(&str, &str)]) -> ResourceUpdate {
+        ResourceUpdate {
+            txn_version,
+            columns: columns
+                .iter()
+                .map(|(column, value)| (column.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_fold_and_db_dedup_use_the_same_keys() {
+        use crate::steps::db_writing_step::dedup_latest;
+
+        let build_offer =
+            |buyer: &str, offer_key: &str, version: i64| CurrentNFTMarketplaceTokenOffer {
+                token_data_id: "0x1".to_string(),
+                marketplace: "test_marketplace".to_string(),
+                buyer: buyer.to_string(),
+                offer_key: offer_key.to_string(),
+                last_transaction_version: version,
+                ..Default::default()
+            };
+        let offers = vec![
+            build_offer("0xa", "1", 1),
+            build_offer("0xa", "2", 2),
+            build_offer("0xb", "1", 3),
+            build_offer("0xa", "1", 4),
+        ];
+        let listings = vec![
+            build_listing(1, 100),
+            CurrentNFTMarketplaceListing {
+                marketplace: "other_marketplace".to_string(),
+                ..build_listing(2, 100)
+            },
+            build_listing(3, 100),
+        ];
+
+        let mut accumulator = NFTAccumulator::default();
+        for offer in offers.clone() {
+            accumulator.fold_token_offer(offer);
+        }
+        for listing in listings.clone() {
+            accumulator.fold_listing(listing);
+        }
+        let mut folded_keys: Vec<String> = accumulator
+            .token_offers
+            .keys()
+            .chain(accumulator.listings.keys())
+            .cloned()
+            .collect();
+        folded_keys.sort();
+        let RemapOutput {
+            listings: folded_listings,
+            token_offers: folded_offers,
+            ..
+        } = accumulator.drain();
+
+        let deduped_offers = dedup_latest(offers);
+        let deduped_listings = dedup_latest(listings);
+        let mut deduped_keys: Vec<String> = deduped_offers
+            .iter()
+            .map(MarketplaceModel::primary_key)
+            .chain(deduped_listings.iter().map(MarketplaceModel::primary_key))
+            .collect();
+        deduped_keys.sort();
+
+        assert_eq!(folded_keys, deduped_keys);
+        assert_eq!(folded_keys.len(), 5);
+        let versions = |offers: &[CurrentNFTMarketplaceTokenOffer]| -> Vec<i64> {
+            offers
+                .iter()
+                .map(|offer| offer.last_transaction_version)
+                .collect()
+        };
+        assert_eq!(versions(&folded_offers), versions(&deduped_offers));
+        assert_eq!(versions(&folded_offers), vec![4, 2, 3]);
+        assert_eq!(
+            folded_listings
+                .iter()
+                .map(|listing| listing.primary_key())
+                .collect::<Vec<_>>(),
+            deduped_listings
+                .iter()
+                .map(|listing| listing.primary_key())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_drain_is_deterministic() {
+        let build_output = |versions: &[i64]| {
+            let mut accumulator = NFTAccumulator::default();
+            for &version in versions {
+                accumulator.fold_listing(CurrentNFTMarketplaceListing {
+                    token_data_id: format!("0x{version}"),
+                    ..build_listing(version, version * 100)
+                });
+                accumulator.add_activity(NftMarketplaceActivity {
+                    txn_version: version,
+                    ..Default::default()
+                });
+            }
+            serde_json::to_vec(&accumulator.drain()).unwrap()
+        };
+
+        let versions: Vec<i64> = (1..=50).collect();
+        let reversed: Vec<i64> = versions.iter().rev().copied().collect();
+        assert_eq!(build_output(&versions), build_output(&versions));
+        assert_eq!(build_output(&versions), build_output(&reversed));
+    }
+
+    #[test]
+    fn test_fold_keeps_latest_version_when_out_of_order() {
+        let mut accumulator = NFTAccumulator::default();
+        accumulator.fold_listing(build_listing(2, 200));
+        accumulator.fold_listing(build_listing(1, 100));
+
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].last_transaction_version, 2);
+        assert_eq!(listings[0].price, BigDecimal::from(200));
+    }
+
+    #[test]
+    fn test_fold_same_version_takes_later_model() {
+        let mut accumulator = NFTAccumulator::default();
+        accumulator.fold_listing(build_listing(1, 100));
+        accumulator.fold_listing(build_listing(1, 150));
+
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert_eq!(listings[0].price, BigDecimal::from(150));
+    }
+
+    #[test]
+    fn test_fold_fill_keeps_fields_of_place() {
+        let mut accumulator = NFTAccumulator::default();
+        accumulator.fold_listing(CurrentNFTMarketplaceListing {
+            seller: Some("0x2".to_string()),
+            token_name: Some("Token #1".to_string()),
+            ..build_listing(1, 100)
+        });
+        accumulator.fold_listing(CurrentNFTMarketplaceListing {
+            is_deleted: true,
+            ..build_listing(2, 0)
+        });
+
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert!(listings[0].is_deleted);
+        assert_eq!(listings[0].last_transaction_version, 2);
+        assert_eq!(listings[0].seller.as_deref(), Some("0x2"));
+        assert_eq!(listings[0].token_name.as_deref(), Some("Token #1"));
+        assert_eq!(listings[0].price, BigDecimal::from(100));
+    }
+
+    #[test]
+    fn test_fold_place_replaces_fields_of_cancel() {
+        let mut accumulator = NFTAccumulator::default();
+        accumulator.fold_listing(CurrentNFTMarketplaceListing {
+            seller: Some("0x2".to_string()),
+            token_amount: Some(BigDecimal::from(5)),
+            is_deleted: true,
+            standard_event_type: "cancel_listing".to_string(),
+            ..build_listing(1, 100)
+        });
+        accumulator.fold_listing(CurrentNFTMarketplaceListing {
+            standard_event_type: "place_listing".to_string(),
+            ..build_listing(2, 0)
+        });
+
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert!(!listings[0].is_deleted);
+        assert_eq!(listings[0].seller, None);
+        assert_eq!(listings[0].token_amount, None);
+        assert_eq!(listings[0].price, BigDecimal::from(0));
+    }
+
+    #[test]
+    fn test_fold_update_keeps_deletion_state() {
+        let mut accumulator = NFTAccumulator::default();
+        accumulator.fold_listing(build_listing(1, 100));
+        accumulator.fold_listing(CurrentNFTMarketplaceListing {
+            standard_event_type: "update_listing".to_string(),
+            ..build_listing(2, 150)
+        });
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert!(!listings[0].is_deleted);
+        assert_eq!(listings[0].price, BigDecimal::from(150));
+
+        // A late update of a cancelled listing doesn't reopen it
+        let mut accumulator = NFTAccumulator::default();
+        accumulator.fold_listing(CurrentNFTMarketplaceListing {
+            is_deleted: true,
+            standard_event_type: "cancel_listing".to_string(),
+            ..build_listing(1, 100)
+        });
+        accumulator.fold_listing(CurrentNFTMarketplaceListing {
+            standard_event_type: "update_listing".to_string(),
+            ..build_listing(2, 150)
+        });
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert!(listings[0].is_deleted);
+    }
+
+    #[tokio::test]
+    async fn test_fill_takes_price_from_listing_resource() {
+        // The fill event only names the listing, its price lives in the listing object
+        let listing_address = "0xabc";
+        let listing = CurrentNFTMarketplaceListing {
+            listing_id: Some(listing_address.to_string()),
+            is_deleted: true,
+            standard_event_type: "fill_listing".to_string(),
+            ..build_listing(1, 0)
+        };
+        let activity = NftMarketplaceActivity {
+            txn_version: 1,
+            token_data_id: Some("0x1".to_string()),
+            listing_id: Some(listing_address.to_string()),
+            standard_event_type: "fill_listing".to_string(),
+            ..Default::default()
+        };
+        let resource_updates = HashMap::from([(
+            standardize_address(listing_address).unwrap(),
+            build_resource_update(1, &[("price", "500")]),
+        )]);
+
+        let mut step = NFTReductionStep::new();
+        let output = step
+            .process(TransactionContext {
+                data: (
+                    RemapOutput {
+                        activities: vec![activity],
+                        listings: vec![listing],
+                        ..Default::default()
+                    },
+                    resource_updates,
+                ),
+                metadata: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = output.data;
+        assert_eq!(listings[0].price, BigDecimal::from(500));
+        assert!(listings[0].is_deleted);
+        assert_eq!(activities[0].price, BigDecimal::from(500));
+    }
+
+    #[test]
+    fn test_resource_update_keeps_price_from_event() {
+        let mut listing = build_listing(1, 100);
+        let resource_update = build_resource_update(1, &[("price", "500")]);
+        merge_partial_update(&mut listing, &resource_update, &mut HashMap::new());
+        assert_eq!(listing.price, BigDecimal::from(100));
+    }
+
+    #[test]
+    fn test_resource_update_skips_unknown_columns() {
+        let mut listing = build_listing(1, 0);
+        let mut activities = HashMap::from([(1, vec![NftMarketplaceActivity {
+            txn_version: 1,
+            token_data_id: Some("0x1".to_string()),
+            ..Default::default()
+        }])]);
+        let resource_update = build_resource_update(1, &[("royalty_bps", "250"), ("price", "500")]);
+        merge_partial_update(&mut listing, &resource_update, &mut activities);
+
+        assert_eq!(listing.price, BigDecimal::from(500));
+        assert_eq!(activities[&1][0].price, BigDecimal::from(500));
+        // An unknown column isn't merged as a custom activity column either
+        assert_eq!(
+            activities[&1][0].get_field(MarketplaceField::Custom("royalty_bps".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_older_resource_update_is_not_merged() {
+        // The listing resource was last written at version 1, before the event at version 2
+        // left the price and amount unset
+        let mut listing = build_listing(2, 0);
+        let mut activities = HashMap::from([(2, vec![NftMarketplaceActivity {
+            txn_version: 2,
+            token_data_id: Some("0x1".to_string()),
+            ..Default::default()
+        }])]);
+        let resource_update = build_resource_update(1, &[("price", "500"), ("token_amount", "3")]);
+        merge_partial_update(&mut listing, &resource_update, &mut activities);
+
+        assert_eq!(listing.price, BigDecimal::from(0));
+        assert_eq!(listing.token_amount, None);
+        assert_eq!(activities[&2][0].price, BigDecimal::from(0));
+
+        // The same write at the event's version is merged
+        let resource_update = build_resource_update(2, &[("price", "500"), ("token_amount", "3")]);
+        merge_partial_update(&mut listing, &resource_update, &mut activities);
+        assert_eq!(listing.price, BigDecimal::from(500));
+        assert_eq!(listing.token_amount, Some(BigDecimal::from(3)));
+        assert_eq!(activities[&2][0].price, BigDecimal::from(500));
+    }
 }
@@ -1,11 +1,16 @@
-use super::remappers::resource_remapper::ResourceMapper;
+use super::remappers::resource_remapper::{ResourceMapper, ResourceUpdate};
 use crate::{
     config::marketplace_config::NFTMarketplaceConfig,
-    models::nft_models::{
-        CurrentNFTMarketplaceCollectionOffer, CurrentNFTMarketplaceListing,
-        CurrentNFTMarketplaceTokenOffer, NftMarketplaceActivity,
+    health::StreamProgress,
+    metrics::NFT_REMAP_ERRORS,
+    models::nft_models::NftMarketplaceActivity,
+    postgres::{
+        discovered_event_types::DiscoveredEventTypes, resource_last_writes::ResourceLastWrites,
+        token_owners::TokenOwners,
+    },
+    steps::remappers::{
+        error::RemapError, event_remapper::EventRemapper, plugins::PluginRegistry, RemapOutput,
     },
-    steps::remappers::event_remapper::EventRemapper,
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
@@ -14,7 +19,7 @@ use aptos_indexer_processor_sdk::{
     types::transaction_context::TransactionContext,
     utils::errors::ProcessorError,
 };
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::{collections::HashMap, sync::Arc};
 use tonic::async_trait;
 
@@ -23,122 +28,119 @@ pub struct RemapResult {
     pub errors: Vec<String>,
 }
 
+/// Clones share the remappers and the stream progress, so the pipeline can be rebuilt with a
+/// clone when the transaction stream reconnects.
+#[derive(Clone)]
 pub struct ProcessStep
 where
     Self: Sized + Send + 'static,
 {
     event_remapper: Arc<EventRemapper>,
     resource_remapper: Arc<ResourceMapper>,
+    stream_progress: Arc<StreamProgress>,
 }
 
 impl ProcessStep {
     pub fn new(config: NFTMarketplaceConfig) -> anyhow::Result<Self> {
-        let event_remapper: Arc<EventRemapper> = EventRemapper::new(&config)?;
+        Self::with_plugins(config, &PluginRegistry::default())
+    }
+
+    /// Like [`Self::new`], but remaps the marketplace's events with its plugin in `plugins`, if
+    /// one is registered.
+    pub fn with_plugins(
+        config: NFTMarketplaceConfig,
+        plugins: &PluginRegistry,
+    ) -> anyhow::Result<Self> {
+        let event_remapper: Arc<EventRemapper> = EventRemapper::with_plugins(&config, plugins)?;
         let resource_remapper: Arc<ResourceMapper> = ResourceMapper::new(&config)?;
         Ok(Self {
             event_remapper,
             resource_remapper,
+            stream_progress: Arc::default(),
         })
     }
+
+    /// Tracks the versions this step receives from the transaction stream.
+    pub fn stream_progress(&self) -> Arc<StreamProgress> {
+        self.stream_progress.clone()
+    }
+
+    /// Counts of the event types without a remapping, in discovery mode.
+    pub fn discovered_event_types(&self) -> Option<Arc<DiscoveredEventTypes>> {
+        self.event_remapper.discovered_event_types()
+    }
+
+    /// Owners of the objects the remapped transactions wrote, when listing owners are tracked.
+    pub fn token_owners(&self) -> Option<Arc<TokenOwners>> {
+        self.resource_remapper.token_owners()
+    }
+
+    /// Last writes of the resources the config maps to events, when it maps any.
+    pub fn resource_last_writes(&self) -> Option<Arc<ResourceLastWrites>> {
+        self.resource_remapper.resource_last_writes()
+    }
 }
 
 #[async_trait]
 impl Processable for ProcessStep {
     type Input = Vec<Transaction>;
-    type Output = (
-        HashMap<i64, Vec<NftMarketplaceActivity>>,
-        Vec<CurrentNFTMarketplaceListing>,
-        Vec<CurrentNFTMarketplaceTokenOffer>,
-        Vec<CurrentNFTMarketplaceCollectionOffer>,
-        HashMap<String, HashMap<String, String>>,
-    );
+    type Output = (RemapOutput, HashMap<String, ResourceUpdate>);
     type RunType = AsyncRunType;
 
     async fn process(
         &mut self,
         transactions: TransactionContext<Vec<Transaction>>,
-    ) -> Result<
-        Option<
-            TransactionContext<(
-                HashMap<i64, Vec<NftMarketplaceActivity>>,
-                Vec<CurrentNFTMarketplaceListing>,
-                Vec<CurrentNFTMarketplaceTokenOffer>,
-                Vec<CurrentNFTMarketplaceCollectionOffer>,
-                HashMap<String, HashMap<String, String>>,
-            )>,
-        >,
-        ProcessorError,
-    > {
+    ) -> Result<Option<TransactionContext<Self::Output>>, ProcessorError> {
+        self.stream_progress
+            .record(transactions.metadata.end_version);
+        // Resource deletions are remapped with the resource's last write, which may be in an
+        // earlier transaction of the batch, so these are derived in version order up front
+        let resource_events: Vec<_> = transactions
+            .data
+            .iter()
+            .map(|transaction| {
+                self.resource_remapper
+                    .map_events_from_resources(transaction)
+            })
+            .collect();
         let results = transactions
             .data
             .par_iter()
-            .map(|transaction| {
+            .zip(resource_events.par_iter())
+            .map(|(transaction, resource_events)| {
                 let event_remapper = self.event_remapper.clone();
                 let resource_remapper = self.resource_remapper.clone();
-                let (activities, listings, token_offers, collection_offers) =
-                    event_remapper.remap_events(transaction.clone())?;
+                let mut remapped = event_remapper.remap_events(transaction.clone())?;
+                if !resource_events.is_empty() {
+                    remapped.extend(
+                        event_remapper.remap_resource_events(transaction, resource_events)?,
+                    );
+                }
 
                 let resource_updates = resource_remapper.remap_resources(transaction.clone())?;
 
-                Ok((
-                    activities,
-                    listings,
-                    token_offers,
-                    collection_offers,
-                    resource_updates,
-                ))
+                Ok((remapped, resource_updates))
             })
-            .collect::<anyhow::Result<Vec<_>>>()
-            .map_err(|e| ProcessorError::ProcessError {
-                message: format!("{e:#}"),
+            .collect::<Result<Vec<_>, RemapError>>()
+            .map_err(|e| {
+                NFT_REMAP_ERRORS.with_label_values(&[e.reason()]).inc();
+                ProcessorError::from(e)
             })?;
 
-        let (
-            mut all_activities,
-            mut all_listings,
-            mut all_token_offers,
-            mut all_collection_offers,
-            mut all_resource_updates,
-        ) = (
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            HashMap::<String, HashMap<String, String>>::new(),
-        );
+        let mut all_remapped = RemapOutput::default();
+        let mut all_resource_updates = HashMap::<String, ResourceUpdate>::new();
+        for (remapped, resource_updates) in results {
+            all_remapped.extend(remapped);
 
-        for (activities, listings, token_offers, collection_offers, resource_updates) in results {
-            all_activities.extend(activities);
-            all_listings.extend(listings);
-            all_token_offers.extend(token_offers);
-            all_collection_offers.extend(collection_offers);
-
-            // Merge resource_updates by key
-            resource_updates.into_iter().for_each(|(key, value_map)| {
-                all_resource_updates
-                    .entry(key)
-                    .or_default()
-                    .extend(value_map);
+            // Merge resource_updates by key. The results are in version order, so later writes
+            // take precedence
+            resource_updates.into_iter().for_each(|(key, update)| {
+                all_resource_updates.entry(key).or_default().merge(update);
             });
         }
 
-        // iterate activities and crete a map of key txn_veesrion to activity, so it can be used later to be updated during reduction step
-        let mut activities_map: HashMap<i64, Vec<NftMarketplaceActivity>> = HashMap::new();
-        for activity in all_activities {
-            activities_map
-                .entry(activity.txn_version)
-                .or_default()
-                .push(activity);
-        }
-
         Ok(Some(TransactionContext {
-            data: (
-                activities_map,
-                all_listings,
-                all_token_offers,
-                all_collection_offers,
-                all_resource_updates,
-            ),
+            data: (all_remapped, all_resource_updates),
             metadata: transactions.metadata,
         }))
     }
@@ -151,3 +153,183 @@ impl NamedStep for ProcessStep {
         "ProcessStep".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::marketplace_config::{
+            DbColumn, EventRemapping, MarketplaceEventType, ResourceEventTypes,
+        },
+        utils::convert::standardize_address,
+    };
+    use aptos_indexer_processor_sdk::{
+        aptos_protos::{
+            transaction::v1::{
+                transaction::TxnData, write_set_change, BlockMetadataTransaction, DeleteResource,
+                TransactionInfo, UserTransaction, WriteResource, WriteSetChange,
+            },
+            util::timestamp::Timestamp,
+        },
+        types::transaction_context::TransactionMetadata,
+    };
+    use bigdecimal::BigDecimal;
+
+    #[tokio::test]
+    async fn test_process_tolerates_transactions_without_user_data() {
+        let mut step = ProcessStep::new(NFTMarketplaceConfig {
+            name: "test_marketplace".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let transactions = vec![
+            Transaction {
+                version: 1,
+                txn_data: None,
+                ..Default::default()
+            },
+            Transaction {
+                version: 2,
+                txn_data: Some(TxnData::BlockMetadata(BlockMetadataTransaction::default())),
+                ..Default::default()
+            },
+        ];
+
+        let output = step
+            .process(TransactionContext {
+                data: transactions,
+                metadata: TransactionMetadata::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (remapped, resource_updates) = output.data;
+        assert!(remapped.activities.is_empty());
+        assert!(remapped.listings.is_empty());
+        assert!(remapped.token_offers.is_empty());
+        assert!(remapped.collection_offers.is_empty());
+        assert!(remapped.auctions.is_empty());
+        assert!(resource_updates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resource_only_marketplace_places_and_cancels_listing() {
+        const LISTING_TYPE: &str = "0xcafe::legacy::Listing";
+        let column = |table: &str, column: &str| DbColumn {
+            table: table.to_string(),
+            column: column.to_string(),
+            ..Default::default()
+        };
+        let both_tables = |name: &str| {
+            vec![
+                column("nft_marketplace_activities", name),
+                column("current_nft_marketplace_listings", name),
+            ]
+        };
+        let mut step = ProcessStep::new(NFTMarketplaceConfig {
+            name: "test_marketplace".to_string(),
+            resource_event_model_mapping: HashMap::from([(
+                LISTING_TYPE.to_string(),
+                ResourceEventTypes {
+                    on_write: Some(MarketplaceEventType::PlaceListing),
+                    on_delete: Some(MarketplaceEventType::CancelListing),
+                    ..Default::default()
+                },
+            )]),
+            events: HashMap::from([(LISTING_TYPE.to_string(), EventRemapping {
+                event_fields: HashMap::from([
+                    ("$.address".to_string(), both_tables("listing_id")),
+                    ("$.data.token".to_string(), both_tables("token_data_id")),
+                    ("$.data.seller".to_string(), both_tables("seller")),
+                    ("$.data.price".to_string(), both_tables("price")),
+                ]),
+                ..Default::default()
+            })]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let build_txn = |version: u64, change: write_set_change::Change| Transaction {
+            version,
+            timestamp: Some(Timestamp {
+                seconds: 1_700_000_000 + version as i64,
+                nanos: 0,
+            }),
+            txn_data: Some(TxnData::User(UserTransaction::default())),
+            info: Some(TransactionInfo {
+                changes: vec![WriteSetChange {
+                    change: Some(change),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let build_write = |version: u64, price: &str| {
+            build_txn(
+                version,
+                write_set_change::Change::WriteResource(WriteResource {
+                    address: "0x10".to_string(),
+                    type_str: LISTING_TYPE.to_string(),
+                    data: serde_json::json!({
+                        "price": price,
+                        "seller": "0x2",
+                        "token": "0x3",
+                    })
+                    .to_string(),
+                    ..Default::default()
+                }),
+            )
+        };
+        // All changes land in one batch, so the deletion relies on the writes before it. Only
+        // the first write places the listing, and the deletion cancels it with the last write.
+        let transactions = vec![
+            build_write(1, "500"),
+            build_write(2, "600"),
+            build_txn(
+                3,
+                write_set_change::Change::DeleteResource(DeleteResource {
+                    address: "0x10".to_string(),
+                    type_str: LISTING_TYPE.to_string(),
+                    ..Default::default()
+                }),
+            ),
+        ];
+
+        let output = step
+            .process(TransactionContext {
+                data: transactions,
+                metadata: TransactionMetadata::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (
+            RemapOutput {
+                activities,
+                listings,
+                ..
+            },
+            _,
+        ) = output.data;
+        let listing_id = standardize_address("0x10").unwrap();
+        assert_eq!(activities.len(), 2);
+        assert_eq!(activities[0].txn_version, 1);
+        assert_eq!(activities[0].standard_event_type, "place_listing");
+        assert_eq!(
+            activities[0].listing_id.as_deref(),
+            Some(listing_id.as_str())
+        );
+        assert_eq!(activities[1].txn_version, 3);
+        assert_eq!(activities[1].standard_event_type, "cancel_listing");
+        assert_eq!(activities[1].token_data_id.as_deref(), Some("0x3"));
+        assert_eq!(activities[1].price, BigDecimal::from(600));
+        assert_eq!(listings.len(), 2);
+        assert!(!listings[0].is_deleted);
+        assert_eq!(listings[0].price, BigDecimal::from(500));
+        assert!(listings[1].is_deleted);
+        assert_eq!(listings[1].seller.as_deref(), Some("0x2"));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::metrics::{
+    SKIP_REASON_EXTRACTION_FAILED, SKIP_REASON_INVALID_PRICE, SKIP_REASON_UNKNOWN_TABLE,
+};
+use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
+use thiserror::Error;
+
+/// Errors of the remappers. The ones returned fail the transaction's batch, the others are
+/// only logged while the event or field is skipped, and all of them are counted by
+/// [`RemapError::reason`].
+#[derive(Debug, Error)]
+pub enum RemapError {
+    /// A user transaction without its info, so its write set can't be read.
+    #[error("Transaction info doesn't exist at version {txn_version}")]
+    MissingTxnInfo { txn_version: i64 },
+    /// Event data that can't be parsed or serialized as JSON.
+    #[error("Invalid JSON at version {txn_version}: {error:#}")]
+    InvalidJson {
+        txn_version: i64,
+        error: anyhow::Error,
+    },
+    /// A column mapped to a table that isn't one of the marketplace tables.
+    #[error("Unknown table: {0}")]
+    UnknownTable(String),
+    /// A configured JSON path that couldn't be extracted from the event data.
+    #[error("Failed to extract value for path {path}: {error:#}")]
+    PathExtractionFailed { path: String, error: anyhow::Error },
+    /// A value mapped to a price column that isn't a valid price. The event is skipped rather
+    /// than recorded as free.
+    #[error("Invalid price for path {path} for column {column}: {error:#}")]
+    InvalidPrice {
+        path: String,
+        column: String,
+        error: anyhow::Error,
+    },
+    /// The marketplace's plugin failed to remap the transaction.
+    #[error("Plugin failed to remap transaction: {0:#}")]
+    Plugin(anyhow::Error),
+}
+
+impl RemapError {
+    /// Label for the error's class, shared with the skip reasons of [`crate::metrics`].
+    pub fn reason(&self) -> &'static str {
+        match self {
+            RemapError::MissingTxnInfo { .. } => "missing_txn_info",
+            RemapError::InvalidJson { .. } => "invalid_json",
+            RemapError::UnknownTable(_) => SKIP_REASON_UNKNOWN_TABLE,
+            RemapError::PathExtractionFailed { .. } => SKIP_REASON_EXTRACTION_FAILED,
+            RemapError::InvalidPrice { .. } => SKIP_REASON_INVALID_PRICE,
+            RemapError::Plugin(_) => "plugin_failed",
+        }
+    }
+}
+
+impl From<RemapError> for ProcessorError {
+    fn from(e: RemapError) -> Self {
+        ProcessorError::ProcessError {
+            message: e.to_string(),
+        }
+    }
+}
@@ -1,40 +1,108 @@
 use crate::{
     config::marketplace_config::{
-        EventFieldRemappings, EventType, MarketplaceEventType, NFTMarketplaceConfig,
+        CollectionOfferKey, DbColumn, EventFieldRemappings, EventType, MarketplaceEventType,
+        NFTMarketplaceConfig, NameNormalization, TokenOfferKey,
+    },
+    metrics::{
+        NFT_EVENTS_REMAPPED, NFT_EVENTS_SKIPPED, NFT_REMAP_DURATION,
+        SKIP_REASON_DISABLED_EVENT_TYPE, SKIP_REASON_DISALLOWED_CONTRACT,
+        SKIP_REASON_INVALID_FIELD, SKIP_REASON_INVALID_MODEL, SKIP_REASON_NO_EVENT_MAPPING,
+        SKIP_REASON_TRANSFORM_FAILED, SKIP_REASON_UNKNOWN_EVENT_TYPE,
     },
     models::{
         nft_models::{
-            CurrentNFTMarketplaceCollectionOffer, CurrentNFTMarketplaceListing,
-            CurrentNFTMarketplaceTokenOffer, MarketplaceField, MarketplaceModel,
-            NftMarketplaceActivity,
+            to_display_price, CurrentNFTMarketplaceAuction, CurrentNFTMarketplaceCollectionOffer,
+            CurrentNFTMarketplaceListing, CurrentNFTMarketplaceTokenOffer, MarketplaceField,
+            MarketplaceModel, NftMarketplaceActivity, Price, DEFAULT_PAYMENT_TOKEN,
         },
-        EventModel,
+        EventModel, TxnMetadata,
     },
+    postgres::discovered_event_types::DiscoveredEventTypes,
     steps::{
-        remappers::{SecondaryModel, TableType},
+        remappers::{
+            error::RemapError,
+            plugins::{MarketplacePlugin, PluginRegistry},
+            resource_remapper::ResourceEvent,
+            RemapOutput, SecondaryModel, TableType,
+        },
         HashableJsonPath,
     },
+    utils::{
+        convert::standardize_address,
+        marketplace_resource_utils::{detect_token_standard, TokenStandard},
+    },
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
     aptos_indexer_transaction_stream::utils::time::parse_timestamp,
     aptos_protos::transaction::v1::{transaction::TxnData, Transaction},
-    utils::{convert::standardize_address, extract::hash_str},
+    utils::extract::hash_str,
+};
+use bigdecimal::Zero;
+use chrono::NaiveDateTime;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
 };
-use std::{collections::HashMap, str::FromStr, sync::Arc};
-use tracing::{debug, warn};
+use tracing::{debug, info_span, warn};
 
 pub struct EventRemapper {
     field_remappings: EventFieldRemappings,
     marketplace_name: String,
-    marketplace_event_type_mapping: HashMap<String, MarketplaceEventType>,
+    marketplace_event_type_mapping: HashMap<String, Vec<MarketplaceEventType>>,
+    /// Event types whose standard event types are all left out of `enabled_event_types`.
+    disabled_event_types: HashSet<String>,
+    price_decimals: Option<u32>,
+    fan_out_event_types: HashSet<EventType>,
+    token_standards: HashMap<EventType, TokenStandard>,
+    property_version_paths: HashMap<EventType, HashableJsonPath>,
+    collection_inner_paths: HashMap<EventType, HashableJsonPath>,
+    allowed_contracts: HashSet<String>,
+    parallel_remap: bool,
+    store_raw_json: bool,
+    name_normalization: NameNormalization,
+    token_offer_key: TokenOfferKey,
+    collection_offer_key: CollectionOfferKey,
+    keep_event_type_generics: bool,
+    default_buyer: Option<String>,
+    discovered_event_types: Option<Arc<DiscoveredEventTypes>>,
+    plugin: Option<Arc<dyn MarketplacePlugin>>,
 }
 
 impl EventRemapper {
     pub fn new(config: &NFTMarketplaceConfig) -> Result<Arc<Self>> {
+        Self::with_plugins(config, &PluginRegistry::default())
+    }
+
+    /// Like [`Self::new`], but remaps with the plugin registered for the marketplace in
+    /// `plugins` instead of the config's event remappings, if there is one.
+    pub fn with_plugins(
+        config: &NFTMarketplaceConfig,
+        plugins: &PluginRegistry,
+    ) -> Result<Arc<Self>> {
         let mut field_remappings: EventFieldRemappings = HashMap::new();
+        let mut fan_out_event_types = HashSet::new();
+        let mut token_standards = HashMap::new();
+        let mut property_version_paths = HashMap::new();
+        let mut collection_inner_paths = HashMap::new();
         for (event_type, event_remapping) in &config.events {
-            let event_type: EventType = event_type.as_str().try_into()?;
+            let event_type = config.parse_event_type(event_type)?;
+            if event_remapping.fan_out_arrays {
+                fan_out_event_types.insert(event_type.clone());
+            }
+            if let Some(token_standard) = &event_remapping.token_standard {
+                token_standards.insert(event_type.clone(), token_standard.clone());
+            }
+            if let Some(json_path) = &event_remapping.property_version_path {
+                property_version_paths
+                    .insert(event_type.clone(), HashableJsonPath::new(json_path)?);
+            }
+            if let Some(json_path) = &event_remapping.collection_inner_path {
+                collection_inner_paths
+                    .insert(event_type.clone(), HashableJsonPath::new(json_path)?);
+            }
             let mut db_mappings_for_event = HashMap::new();
 
             for (json_path, db_mappings) in &event_remapping.event_fields {
@@ -53,345 +121,954 @@ impl EventRemapper {
             field_remappings.insert(event_type, db_mappings_for_event);
         }
 
+        // Keyed the way events print their type, so generics in the config follow the events'
+        let mut marketplace_event_type_mapping: HashMap<String, Vec<MarketplaceEventType>> =
+            HashMap::new();
+        for (event_type, standard_event_type) in &config.event_model_mapping {
+            marketplace_event_type_mapping
+                .insert(config.parse_event_type(event_type)?.to_string(), vec![
+                    standard_event_type.clone(),
+                ]);
+        }
+        for (event_type, standard_event_types) in &config.combined_event_model_mapping {
+            marketplace_event_type_mapping.insert(
+                config.parse_event_type(event_type)?.to_string(),
+                standard_event_types.clone(),
+            );
+        }
+
+        // Combined events keep the standard event types that are enabled
+        let mut disabled_event_types = HashSet::new();
+        if !config.enabled_event_types.is_empty() {
+            for (event_type, standard_event_types) in marketplace_event_type_mapping.iter_mut() {
+                standard_event_types.retain(|standard_event_type| {
+                    config.enabled_event_types.contains(standard_event_type)
+                });
+                if standard_event_types.is_empty() {
+                    disabled_event_types.insert(event_type.clone());
+                }
+            }
+            marketplace_event_type_mapping
+                .retain(|event_type, _| !disabled_event_types.contains(event_type));
+        }
+
+        let allowed_contracts = config
+            .allowed_contracts
+            .iter()
+            .map(|contract| config.contract_address(contract))
+            .collect::<Result<HashSet<_>>>()?;
+
         Ok(Arc::new(Self {
             field_remappings,
-            marketplace_name: config.name.clone(),
-            marketplace_event_type_mapping: config.event_model_mapping.clone(),
+            marketplace_name: config.marketplace().to_string(),
+            marketplace_event_type_mapping,
+            disabled_event_types,
+            price_decimals: config.price_decimals,
+            fan_out_event_types,
+            token_standards,
+            property_version_paths,
+            collection_inner_paths,
+            allowed_contracts,
+            parallel_remap: config.parallel_remap,
+            store_raw_json: config.store_raw_json,
+            name_normalization: config.name_normalization.clone(),
+            token_offer_key: config.token_offer_key,
+            collection_offer_key: config.collection_offer_key,
+            keep_event_type_generics: config.keep_event_type_generics,
+            default_buyer: config.default_buyer.clone(),
+            discovered_event_types: config
+                .discovery_mode
+                .then(|| Arc::new(DiscoveredEventTypes::new(config.name.clone()))),
+            plugin: plugins.get(&config.name),
         }))
     }
 
-    /// Remaps events from a transaction into marketplace activities and current state models
+    /// Event types without a remapping seen so far, when the config enables discovery mode.
+    pub fn discovered_event_types(&self) -> Option<Arc<DiscoveredEventTypes>> {
+        self.discovered_event_types.clone()
+    }
+
+    /// Remaps events from a transaction into marketplace activities and current state models.
+    ///
+    /// This parses the events out of the transaction and hands them to [`Self::remap_event_models`],
+    /// unless a plugin handles the marketplace.
+    pub fn remap_events(&self, txn: Transaction) -> Result<RemapOutput, RemapError> {
+        if let Some(plugin) = &self.plugin {
+            let _timer = NFT_REMAP_DURATION
+                .with_label_values(&["event_remapper"])
+                .start_timer();
+            let mut output = plugin.remap(&txn).map_err(RemapError::Plugin)?;
+            self.drop_disallowed_contracts(&mut output);
+            for activity in output.activities.iter_mut() {
+                NFT_EVENTS_REMAPPED
+                    .with_label_values(&[
+                        self.marketplace_name.as_str(),
+                        activity.standard_event_type.as_str(),
+                    ])
+                    .inc();
+                if !self.store_raw_json {
+                    activity.json_data = serde_json::Value::Null;
+                }
+            }
+            return Ok(output);
+        }
+        let txn = Arc::new(txn);
+        let events = self.get_events(txn.clone());
+        // Pruned and non-user transactions carry no events to remap
+        if events.is_empty() {
+            return Ok(Default::default());
+        }
+        let txn_timestamp =
+            parse_timestamp(txn.timestamp.as_ref().unwrap(), txn.version as i64).naive_utc();
+        self.remap_transaction_event_models(
+            &events,
+            txn_timestamp,
+            &TxnMetadata::from_transaction(&txn),
+        )
+    }
+
+    /// Drops the models a plugin remapped from contracts outside `allowed_contracts`, which the
+    /// config-driven remapping skips event by event.
+    fn drop_disallowed_contracts(&self, output: &mut RemapOutput) {
+        if self.allowed_contracts.is_empty() {
+            return;
+        }
+        let allowed = |contract_address: &String| self.allowed_contracts.contains(contract_address);
+        output.activities.retain(|activity| {
+            if allowed(&activity.contract_address) {
+                return true;
+            }
+            warn!(
+                txn_version = activity.txn_version,
+                contract_address = %activity.contract_address,
+                "Skipping event from disallowed contract"
+            );
+            NFT_EVENTS_SKIPPED
+                .with_label_values(&[SKIP_REASON_DISALLOWED_CONTRACT])
+                .inc();
+            false
+        });
+        output
+            .listings
+            .retain(|listing| allowed(&listing.contract_address));
+        output
+            .token_offers
+            .retain(|token_offer| allowed(&token_offer.contract_address));
+        output
+            .collection_offers
+            .retain(|collection_offer| allowed(&collection_offer.contract_address));
+        output
+            .auctions
+            .retain(|auction| allowed(&auction.contract_address));
+    }
+
+    /// Remaps already decoded events into marketplace activities and current state models
     ///
     /// # Key responsibilities:
-    /// 1. Takes a transaction and extracts relevant NFT marketplace events
+    /// 1. Filters the events down to the configured NFT marketplace events
     /// 2. Maps event fields to database columns based on configured remappings
     /// 3. Creates marketplace activity for event
     /// 4. Updates current models (listings, token offers, collection offers)
     /// 5. Generate necessary id fields for models that don't have an id if possible
-    pub fn remap_events(
+    ///
+    /// With `parallel_remap` the events are remapped on the rayon thread pool.
+    ///
+    /// `$txn` paths resolve to nothing, use [`Self::remap_transaction_event_models`] to map
+    /// them.
+    pub fn remap_event_models(
         &self,
-        txn: Transaction,
-    ) -> Result<(
-        Vec<NftMarketplaceActivity>,
-        Vec<CurrentNFTMarketplaceListing>,
-        Vec<CurrentNFTMarketplaceTokenOffer>,
-        Vec<CurrentNFTMarketplaceCollectionOffer>,
-    )> {
-        let mut activities: Vec<NftMarketplaceActivity> = Vec::new();
-        let mut current_token_offers: Vec<CurrentNFTMarketplaceTokenOffer> = Vec::new();
-        let mut current_collection_offers: Vec<CurrentNFTMarketplaceCollectionOffer> = Vec::new();
-        let mut current_listings: Vec<CurrentNFTMarketplaceListing> = Vec::new();
+        events: &[EventModel],
+        txn_timestamp: NaiveDateTime,
+    ) -> Result<RemapOutput, RemapError> {
+        self.remap_transaction_event_models(events, txn_timestamp, &TxnMetadata::default())
+    }
 
-        let txn_timestamp =
-            parse_timestamp(txn.timestamp.as_ref().unwrap(), txn.version as i64).naive_utc();
+    /// Like [`Self::remap_event_models`] for events of a single transaction, whose `txn` the
+    /// `$txn` paths of the config are resolved against.
+    pub fn remap_transaction_event_models(
+        &self,
+        events: &[EventModel],
+        txn_timestamp: NaiveDateTime,
+        txn: &TxnMetadata,
+    ) -> Result<RemapOutput, RemapError> {
+        let events: Vec<_> = events.iter().map(|event| (event, None)).collect();
+        self.remap_events_as(&events, txn_timestamp, txn)
+    }
 
-        let events = self.get_events(Arc::new(txn))?;
+    /// Remaps the events derived from the resource changes of `txn`, each as its own standard
+    /// event type.
+    pub fn remap_resource_events(
+        &self,
+        txn: &Transaction,
+        resource_events: &[ResourceEvent],
+    ) -> Result<RemapOutput, RemapError> {
+        let Some(first) = resource_events.first() else {
+            return Ok(Default::default());
+        };
+        let events: Vec<_> = resource_events
+            .iter()
+            .map(|resource_event| {
+                (
+                    &resource_event.event,
+                    Some(std::slice::from_ref(&resource_event.standard_event_type)),
+                )
+            })
+            .collect();
+        self.remap_events_as(
+            &events,
+            first.event.block_timestamp,
+            &TxnMetadata::from_transaction(txn),
+        )
+    }
 
-        for event in events {
-            if let Some(remappings) = self.field_remappings.get(&event.event_type) {
-                let mut activity = NftMarketplaceActivity {
-                    txn_version: event.transaction_version,
-                    index: event.event_index,
-                    marketplace: self.marketplace_name.clone(),
-                    contract_address: event.account_address.clone(),
-                    block_timestamp: txn_timestamp,
-                    raw_event_type: event.event_type.to_string(),
-                    json_data: serde_json::to_value(&event).unwrap(),
-                    ..Default::default()
-                };
+    /// Remaps `events` of a single transaction. An event paired with standard event types is
+    /// remapped as those instead of the ones its type maps to.
+    fn remap_events_as(
+        &self,
+        events: &[(&EventModel, Option<&[MarketplaceEventType]>)],
+        txn_timestamp: NaiveDateTime,
+        txn: &TxnMetadata,
+    ) -> Result<RemapOutput, RemapError> {
+        let txn = txn.to_json();
+        let _timer = NFT_REMAP_DURATION
+            .with_label_values(&["event_remapper"])
+            .start_timer();
+        let remapped = if self.parallel_remap {
+            events
+                .par_iter()
+                .map(|(event, standard_event_types)| {
+                    self.remap_event_elements(event, *standard_event_types, txn_timestamp, &txn)
+                })
+                .collect::<Result<Vec<_>, RemapError>>()?
+        } else {
+            events
+                .iter()
+                .map(|(event, standard_event_types)| {
+                    self.remap_event_elements(event, *standard_event_types, txn_timestamp, &txn)
+                })
+                .collect::<Result<Vec<_>, RemapError>>()?
+        };
 
-                // Step 1: Create the appropriate second model based on event type
-                let event_type_str = event.event_type.to_string();
-
-                let mut secondary_model: Option<SecondaryModel> =
-                    match self.marketplace_event_type_mapping.get(&event_type_str) {
-                        Some(MarketplaceEventType::PlaceListing) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::PlaceListing.to_string();
-                            Some(SecondaryModel::Listing(
-                                CurrentNFTMarketplaceListing::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    false,
-                                    MarketplaceEventType::PlaceListing.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::CancelListing) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::CancelListing.to_string();
-                            Some(SecondaryModel::Listing(
-                                CurrentNFTMarketplaceListing::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    true,
-                                    MarketplaceEventType::CancelListing.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::FillListing) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::FillListing.to_string();
-                            Some(SecondaryModel::Listing(
-                                CurrentNFTMarketplaceListing::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    true,
-                                    MarketplaceEventType::FillListing.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::PlaceTokenOffer) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::PlaceTokenOffer.to_string();
-                            Some(SecondaryModel::TokenOffer(
-                                CurrentNFTMarketplaceTokenOffer::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    false,
-                                    MarketplaceEventType::PlaceTokenOffer.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::CancelTokenOffer) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::CancelTokenOffer.to_string();
-                            Some(SecondaryModel::TokenOffer(
-                                CurrentNFTMarketplaceTokenOffer::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    true,
-                                    MarketplaceEventType::CancelTokenOffer.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::FillTokenOffer) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::FillTokenOffer.to_string();
-                            Some(SecondaryModel::TokenOffer(
-                                CurrentNFTMarketplaceTokenOffer::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    true,
-                                    MarketplaceEventType::FillTokenOffer.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::PlaceCollectionOffer) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::PlaceCollectionOffer.to_string();
-                            Some(SecondaryModel::CollectionOffer(
-                                CurrentNFTMarketplaceCollectionOffer::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    false,
-                                    MarketplaceEventType::PlaceCollectionOffer.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::CancelCollectionOffer) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::CancelCollectionOffer.to_string();
-                            Some(SecondaryModel::CollectionOffer(
-                                CurrentNFTMarketplaceCollectionOffer::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    true,
-                                    MarketplaceEventType::CancelCollectionOffer.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::FillCollectionOffer) => {
-                            activity.standard_event_type =
-                                MarketplaceEventType::FillCollectionOffer.to_string();
-                            Some(SecondaryModel::CollectionOffer(
-                                CurrentNFTMarketplaceCollectionOffer::build_default(
-                                    self.marketplace_name.clone(),
-                                    &event,
-                                    true,
-                                    MarketplaceEventType::FillCollectionOffer.to_string(),
-                                ),
-                            ))
-                        },
-                        Some(MarketplaceEventType::Unknown) => {
-                            warn!("Skipping unrecognized event type '{}'", event_type_str);
-                            continue;
-                        },
-                        None => {
-                            warn!("No remappings found for event type '{}'", event_type_str);
-                            continue;
-                        },
-                    };
-
-                // Step 2: Build model structs from the values obtained by the JsonPaths
-                remappings.iter().try_for_each(|(json_path, db_mappings)| {
-                    db_mappings.iter().try_for_each(|db_mapping| {
-                        // Extract value, continue on error instead of failing
-                        let extracted_value = match json_path.extract_from(&event.data) {
-                            Ok(value) => value,
-                            Err(e) => {
-                                debug!("Failed to extract value for path {}: {}", json_path.raw, e);
-                                return Ok::<(), anyhow::Error>(());
-                            },
-                        };
+        let mut output = RemapOutput::default();
+        // The reduction folds the current models of a transaction in the order they're emitted
+        // here, so emit them by event index rather than in the order `events` came in
+        let mut remapped: Vec<_> = remapped.into_iter().flatten().collect();
+        remapped.sort_by_key(|(activity, _)| (activity.txn_version, activity.index));
+        for (activity, secondary_models) in remapped {
+            for model in secondary_models {
+                match model {
+                    SecondaryModel::Listing(listing) => output.listings.push(listing),
+                    SecondaryModel::TokenOffer(token_offer) => {
+                        output.token_offers.push(token_offer)
+                    },
+                    SecondaryModel::CollectionOffer(collection_offer) => {
+                        output.collection_offers.push(collection_offer)
+                    },
+                    SecondaryModel::Auction(auction) => output.auctions.push(auction),
+                }
+            }
+            output.activities.push(activity);
+        }
 
-                        let value = extracted_value
-                            .as_str()
-                            .map(|s| s.to_string())
-                            .or_else(|| extracted_value.as_u64().map(|n| n.to_string()))
-                            .unwrap_or_default();
+        Ok(output)
+    }
 
-                        if value.is_empty() {
-                            debug!(
-                                "Skipping empty value for path {} for column {}",
-                                json_path.raw, db_mapping.column
-                            );
-                            return Ok(());
-                        }
+    /// Remaps an event of the marketplace into its activity and the secondary models of all its
+    /// elements. Returns `None` for events that aren't remapped, e.g. unknown event types.
+    /// `standard_event_types` takes the place of the ones the event type maps to.
+    fn remap_event_elements(
+        &self,
+        event: &EventModel,
+        standard_event_types: Option<&[MarketplaceEventType]>,
+        txn_timestamp: NaiveDateTime,
+        txn: &serde_json::Value,
+    ) -> Result<Option<(NftMarketplaceActivity, Vec<SecondaryModel>)>, RemapError> {
+        let Some(remappings) = self.field_remappings.get(&event.event_type) else {
+            if let Some(discovered_event_types) = &self.discovered_event_types {
+                discovered_event_types.record(event);
+            }
+            return Ok(None);
+        };
+        // Everything logged while remapping the event carries these fields
+        let _span = info_span!(
+            "remap_event",
+            txn_version = event.transaction_version,
+            marketplace = %self.marketplace_name,
+            event_type = %event.event_type,
+        )
+        .entered();
+        if !self.allowed_contracts.is_empty()
+            && !self.allowed_contracts.contains(&event.account_address)
+        {
+            warn!(
+                contract_address = %event.account_address,
+                "Skipping event from disallowed contract"
+            );
+            NFT_EVENTS_SKIPPED
+                .with_label_values(&[SKIP_REASON_DISALLOWED_CONTRACT])
+                .inc();
+            return Ok(None);
+        }
 
-                        match TableType::from_str(db_mapping.table.as_str()) {
-                            Some(TableType::Activities) => {
-                                match MarketplaceField::from_str(db_mapping.column.as_str()) {
-                                    Ok(field) => {
-                                        activity.set_field(field, value);
-                                    },
-                                    Err(e) => {
-                                        warn!(
-                                            "Skipping invalid field {}: {}",
-                                            db_mapping.column, e
-                                        );
-                                    },
-                                }
-                            },
-                            Some(_) => {
-                                if let Some(model) = &mut secondary_model {
-                                    match MarketplaceField::from_str(db_mapping.column.as_str()) {
-                                        Ok(field) => {
-                                            model.set_field(field, value);
-                                        },
-                                        Err(e) => {
-                                            warn!(
-                                                "Skipping invalid field {}: {}",
-                                                db_mapping.column, e
-                                            );
-                                        },
-                                    }
+        let standard_event_types = match standard_event_types {
+            Some(standard_event_types) => standard_event_types,
+            None => match self.standard_event_types(event) {
+                Some(standard_event_types) => standard_event_types,
+                None => return Ok(None),
+            },
+        };
+
+        // Bundle events are remapped once per element of their array values
+        let elements: Vec<Option<usize>> = match self
+            .fan_out_event_types
+            .contains(&event.event_type)
+            .then(|| fan_out_len(remappings, &event.data))
+            .flatten()
+        {
+            Some(len) => (0..len).map(Some).collect(),
+            None => vec![None],
+        };
+
+        // Activities are keyed by event, so a bundle records a single activity built
+        // from its first element. The whole bundle is still kept in `json_data`.
+        let mut event_activity: Option<NftMarketplaceActivity> = None;
+        let mut event_models: Vec<SecondaryModel> = Vec::new();
+        for element in elements {
+            let Some((activity, secondary_models)) = self.remap_event(
+                event,
+                remappings,
+                standard_event_types,
+                txn_timestamp,
+                txn,
+                element,
+            )?
+            else {
+                continue;
+            };
+            event_models.extend(secondary_models);
+            event_activity.get_or_insert(activity);
+        }
+
+        Ok(event_activity.map(|activity| {
+            NFT_EVENTS_REMAPPED
+                .with_label_values(&[
+                    self.marketplace_name.as_str(),
+                    activity.standard_event_type.as_str(),
+                ])
+                .inc();
+            (activity, event_models)
+        }))
+    }
+
+    /// Standard event types the type of `event` maps to, or `None` if it's disabled or doesn't
+    /// map to any.
+    fn standard_event_types(&self, event: &EventModel) -> Option<&[MarketplaceEventType]> {
+        // Most event types map to a single standard event type, but combined events
+        // (e.g. an instant sale) produce one secondary model per standard event type.
+        let event_type_str = event.event_type.to_string();
+        if self.disabled_event_types.contains(&event_type_str) {
+            debug!("Skipping disabled event type");
+            NFT_EVENTS_SKIPPED
+                .with_label_values(&[SKIP_REASON_DISABLED_EVENT_TYPE])
+                .inc();
+            return None;
+        }
+
+        match self.marketplace_event_type_mapping.get(&event_type_str) {
+            Some(standard_event_types)
+                if !standard_event_types.is_empty()
+                    && !standard_event_types.contains(&MarketplaceEventType::Unknown) =>
+            {
+                Some(standard_event_types)
+            },
+            Some(_) => {
+                warn!("Skipping unrecognized event type");
+                NFT_EVENTS_SKIPPED
+                    .with_label_values(&[SKIP_REASON_UNKNOWN_EVENT_TYPE])
+                    .inc();
+                None
+            },
+            None => {
+                warn!("No remappings found for event type");
+                NFT_EVENTS_SKIPPED
+                    .with_label_values(&[SKIP_REASON_NO_EVENT_MAPPING])
+                    .inc();
+                None
+            },
+        }
+    }
+
+    /// Remaps a single event into its activity and valid secondary models. For bundle events,
+    /// `element` selects the entry of every array value to remap. Returns `None` when a mapped
+    /// price doesn't parse or none of the secondary models is valid.
+    fn remap_event(
+        &self,
+        event: &EventModel,
+        remappings: &HashMap<HashableJsonPath, Vec<DbColumn>>,
+        standard_event_types: &[MarketplaceEventType],
+        txn_timestamp: NaiveDateTime,
+        txn: &serde_json::Value,
+        element: Option<usize>,
+    ) -> Result<Option<(NftMarketplaceActivity, Vec<SecondaryModel>)>, RemapError> {
+        let json_data = if self.store_raw_json {
+            serde_json::to_value(event).map_err(|e| RemapError::InvalidJson {
+                txn_version: event.transaction_version,
+                error: e.into(),
+            })?
+        } else {
+            serde_json::Value::Null
+        };
+        let mut activity = NftMarketplaceActivity {
+            txn_version: event.transaction_version,
+            index: event.event_index,
+            marketplace: self.marketplace_name.clone(),
+            contract_address: event.account_address.clone(),
+            block_timestamp: txn_timestamp,
+            raw_event_type: event.event_type.to_string(),
+            json_data,
+            payment_token: DEFAULT_PAYMENT_TOKEN.to_string(),
+            ..Default::default()
+        };
+
+        // The activity records the final state of a combined event, e.g. the fill of an
+        // instant sale rather than its listing.
+        if let Some(last) = standard_event_types.last() {
+            activity.standard_event_type = last.to_string();
+        }
+        // Step 1: Create the appropriate secondary models based on event type
+        let mut secondary_models: Vec<SecondaryModel> = Vec::new();
+        for standard_event_type in standard_event_types {
+            if let Some(model) = self.build_secondary_model(standard_event_type, event) {
+                // A later standard event type supersedes an earlier one on the same table
+                secondary_models.retain(|existing| existing.table_name() != model.table_name());
+                secondary_models.push(model);
+            }
+        }
+
+        // Step 2: Build model structs from the values obtained by the JsonPaths
+        let mut invalid_price = false;
+        remappings.iter().try_for_each(|(json_path, db_mappings)| {
+            let data = if json_path.reads_txn() {
+                txn
+            } else {
+                &event.data
+            };
+            db_mappings.iter().try_for_each(|db_mapping| {
+                if db_mapping.all_matches {
+                    remap_all_matches(&mut activity, json_path, db_mapping, data);
+                    return Ok(());
+                }
+
+                // Extract value, continue on error instead of failing
+                let extracted_value = match json_path.extract_from(data) {
+                    Ok(value) => {
+                        // A bundle element only takes its own entry of an array value
+                        let value = match (element, value) {
+                            (Some(i), serde_json::Value::Array(mut values)) => {
+                                if i < values.len() {
+                                    values.swap_remove(i)
+                                } else {
+                                    serde_json::Value::Null
                                 }
                             },
-                            None => {
-                                warn!("Unknown table: {}", db_mapping.table);
-                                return Ok(());
-                            },
+                            (_, value) => value,
+                        };
+                        match &db_mapping.unwrap {
+                            Some(wrapper) => wrapper.unwrap_value(value),
+                            None => value,
                         }
+                    },
+                    Err(error) => {
+                        let e = RemapError::PathExtractionFailed {
+                            path: json_path.raw.clone(),
+                            error,
+                        };
+                        debug!("{e}");
+                        NFT_EVENTS_SKIPPED.with_label_values(&[e.reason()]).inc();
+                        return Ok::<(), RemapError>(());
+                    },
+                };
 
-                        Ok(())
-                    })
-                })?;
-
-                // After processing all field remappings, generate necessary id fields if needed for PK
-                if let Some(model) = &mut secondary_model {
-                    let creator_address = activity.creator_address.clone();
-                    let collection_name = activity.collection_name.clone();
-                    let token_name = activity.token_name.clone();
-
-                    match model {
-                        SecondaryModel::Listing(listing) => {
-                            self.generate_and_set_ids(
-                                listing,
-                                &mut activity,
-                                &creator_address,
-                                &collection_name,
-                                &token_name,
-                            );
+                let value = scalar_to_string(&extracted_value).unwrap_or_default();
+
+                let value = match db_mapping.convert(value) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        warn!(
+                            "Failed to transform value for path {} for column {}: {:#}",
+                            json_path.raw, db_mapping.column, e
+                        );
+                        NFT_EVENTS_SKIPPED
+                            .with_label_values(&[SKIP_REASON_TRANSFORM_FAILED])
+                            .inc();
+                        return Ok(());
+                    },
+                };
+
+                let field = MarketplaceField::for_column(&db_mapping.table, &db_mapping.column);
+                if matches!(&field, Ok(field) if field.is_price()) && !value.is_empty() {
+                    if let Err(error) = value.parse::<Price>() {
+                        let e = RemapError::InvalidPrice {
+                            path: json_path.raw.clone(),
+                            column: db_mapping.column.clone(),
+                            error,
+                        };
+                        warn!("{e}");
+                        NFT_EVENTS_SKIPPED.with_label_values(&[e.reason()]).inc();
+                        invalid_price = true;
+                    }
+                }
+
+                if value.is_empty() {
+                    debug!(
+                        "Skipping empty value for path {} for column {}",
+                        json_path.raw, db_mapping.column
+                    );
+                    return Ok(());
+                }
+
+                match TableType::from_str(db_mapping.table.as_str()) {
+                    Some(TableType::Activities) => match field {
+                        Ok(field) => {
+                            activity.set_field(field, value);
                         },
-                        SecondaryModel::TokenOffer(token_offer) => {
-                            self.generate_and_set_ids(
-                                token_offer,
-                                &mut activity,
-                                &creator_address,
-                                &collection_name,
-                                &token_name,
-                            );
+                        Err(e) => {
+                            warn!("Skipping invalid field: {e}");
+                            NFT_EVENTS_SKIPPED
+                                .with_label_values(&[SKIP_REASON_INVALID_FIELD])
+                                .inc();
                         },
-                        SecondaryModel::CollectionOffer(collection_offer) => {
-                            self.generate_and_set_ids(
-                                collection_offer,
-                                &mut activity,
-                                &creator_address,
-                                &collection_name,
-                                &token_name,
-                            );
-
-                            // Handle collection_offer_id separately since it's specific to collection offers
-                            if collection_offer.collection_offer_id.is_empty() {
-                                if let Some(generated_collection_offer_id) =
-                                    generate_collection_offer_id(
-                                        creator_address,
-                                        activity.buyer.clone(),
-                                    )
-                                {
-                                    collection_offer.collection_offer_id =
-                                        generated_collection_offer_id.clone();
-                                    activity.set_field(
-                                        MarketplaceField::CollectionOfferId,
-                                        generated_collection_offer_id,
-                                    );
-                                }
+                    },
+                    Some(_) => {
+                        // With several secondary models, only the ones backed by the
+                        // mapped table receive the value.
+                        let route_by_table = secondary_models.len() > 1;
+                        for model in secondary_models.iter_mut().filter(|model| {
+                            !route_by_table || model.table_name() == db_mapping.table
+                        }) {
+                            match &field {
+                                Ok(field) => {
+                                    model.set_field(field.clone(), value.clone());
+                                },
+                                Err(e) => {
+                                    warn!("Skipping invalid field: {e}");
+                                    NFT_EVENTS_SKIPPED
+                                        .with_label_values(&[SKIP_REASON_INVALID_FIELD])
+                                        .inc();
+                                },
                             }
+                        }
+                    },
+                    None => {
+                        let e = RemapError::UnknownTable(db_mapping.table.clone());
+                        warn!("{e}");
+                        NFT_EVENTS_SKIPPED.with_label_values(&[e.reason()]).inc();
+                        return Ok(());
+                    },
+                }
+
+                Ok(())
+            })
+        })?;
+        if invalid_price {
+            return Ok(None);
+        }
+
+        // Step 3: Resolve the token standard, preferring the config's explicit standard for the
+        // event type, then a mapped value, then detection from the event's shape
+        let token_standard = self
+            .token_standards
+            .get(&event.event_type)
+            .cloned()
+            .or_else(|| activity.token_standard.as_deref()?.parse().ok())
+            .or_else(|| detect_token_standard(&event.data));
+        if let Some(token_standard) = token_standard {
+            activity.set_field(MarketplaceField::TokenStandard, token_standard.to_string());
+            for model in secondary_models.iter_mut() {
+                model.set_field(MarketplaceField::TokenStandard, token_standard.to_string());
+            }
+        }
+
+        // After processing all field remappings, generate necessary id fields if needed for PK
+        let property_version = self.extract_property_version(event);
+        let collection_inner = self.extract_collection_inner(event);
+        for model in secondary_models.iter_mut() {
+            let creator_address = activity.creator_address.clone();
+            let collection_name = activity.collection_name.clone();
+            let token_name = activity.token_name.clone();
+
+            match model {
+                SecondaryModel::Listing(listing) => {
+                    self.generate_and_set_ids(
+                        listing,
+                        &mut activity,
+                        &creator_address,
+                        &collection_name,
+                        &token_name,
+                        collection_inner.as_deref(),
+                    );
+                    self.fall_back_to_v1_token_data_id(listing, &mut activity, event);
+                },
+                SecondaryModel::TokenOffer(token_offer) => {
+                    // The property version read from the event type's path is stored with
+                    // the offer, unless one was mapped to the offer
+                    if let Some(property_version) = &property_version {
+                        if token_offer.property_version.is_none() {
+                            token_offer.set_field(
+                                MarketplaceField::PropertyVersion,
+                                property_version.clone(),
+                            );
+                        }
+                    }
+                    self.generate_and_set_ids(
+                        token_offer,
+                        &mut activity,
+                        &creator_address,
+                        &collection_name,
+                        &token_name,
+                        collection_inner.as_deref(),
+                    );
+                    self.fall_back_to_v1_token_data_id(token_offer, &mut activity, event);
+                    match self.token_offer_key {
+                        // The property versions of a v1 token share its token data id, so
+                        // they tell a buyer's offers on them apart
+                        TokenOfferKey::Buyer => {
+                            token_offer.offer_key = token_offer
+                                .property_version
+                                .as_ref()
+                                .filter(|property_version| !property_version.is_zero())
+                                .map(|property_version| property_version.to_string())
+                                .unwrap_or_default();
+                        },
+                        TokenOfferKey::OfferId => {
+                            token_offer.offer_key =
+                                token_offer.offer_id.clone().unwrap_or_default();
+                        },
+                        TokenOfferKey::BidKey => {
+                            token_offer.offer_key = token_offer
+                                .bid_key
+                                .map(|bid_key| bid_key.to_string())
+                                .unwrap_or_default();
                         },
                     }
-                }
+                    self.set_default_buyer(&mut token_offer.buyer);
+                },
+                SecondaryModel::CollectionOffer(collection_offer) => {
+                    self.generate_and_set_ids(
+                        collection_offer,
+                        &mut activity,
+                        &creator_address,
+                        &collection_name,
+                        &token_name,
+                        collection_inner.as_deref(),
+                    );
 
-                // Pass only if secondary model is valid
-                if let Some(model) = secondary_model {
-                    if model.is_valid() {
-                        match model {
-                            SecondaryModel::Listing(listing) => {
-                                activities.push(activity);
-                                current_listings.push(listing);
-                            },
-                            SecondaryModel::TokenOffer(token_offer) => {
-                                activities.push(activity);
-                                current_token_offers.push(token_offer);
-                            },
-                            SecondaryModel::CollectionOffer(collection_offer) => {
-                                activities.push(activity);
-                                current_collection_offers.push(collection_offer);
-                            },
+                    // Handle collection_offer_id separately since it's specific to collection offers
+                    if collection_offer.collection_offer_id.is_empty() {
+                        let bid_key = match self.collection_offer_key {
+                            CollectionOfferKey::Buyer => None,
+                            CollectionOfferKey::BidKey => collection_offer.bid_key,
+                        };
+                        if let Some(generated_collection_offer_id) = generate_collection_offer_id(
+                            creator_address,
+                            collection_offer.collection_id.clone(),
+                            activity.buyer.clone(),
+                            bid_key,
+                        ) {
+                            collection_offer.collection_offer_id =
+                                generated_collection_offer_id.clone();
+                            activity.set_field(
+                                MarketplaceField::CollectionOfferId,
+                                generated_collection_offer_id,
+                            );
                         }
-                    } else {
-                        debug!("Secondary model validation failed, skipping: {:?}", model);
                     }
+
+                    self.set_default_buyer(&mut collection_offer.buyer);
+                    collection_offer.apply_remaining_token_amount();
+                },
+                SecondaryModel::Auction(auction) => {
+                    self.generate_and_set_ids(
+                        auction,
+                        &mut activity,
+                        &creator_address,
+                        &collection_name,
+                        &token_name,
+                        collection_inner.as_deref(),
+                    );
+                    self.fall_back_to_v1_token_data_id(auction, &mut activity, event);
+                },
+            }
+        }
+        self.normalize_names(&mut activity, &mut secondary_models);
+
+        activity.is_self_trade = activity.buyer_is_seller();
+        activity.direction = activity
+            .standard_event_type
+            .parse::<MarketplaceEventType>()
+            .ok()
+            .and_then(|event_type| event_type.funds_direction())
+            .map(|direction| direction.to_string());
+
+        // Store the price in both the smallest unit and the display unit if configured
+        if let Some(decimals) = self.price_decimals {
+            activity.price_raw = Some(activity.price.clone());
+            activity.price_display = Some(to_display_price(&activity.price, decimals));
+            for model in secondary_models.iter_mut() {
+                match model {
+                    SecondaryModel::Listing(listing) => {
+                        listing.price_raw = Some(listing.price.clone());
+                        listing.price_display = Some(to_display_price(&listing.price, decimals));
+                    },
+                    SecondaryModel::TokenOffer(token_offer) => {
+                        token_offer.price_raw = Some(token_offer.price.clone());
+                        token_offer.price_display =
+                            Some(to_display_price(&token_offer.price, decimals));
+                    },
+                    SecondaryModel::CollectionOffer(collection_offer) => {
+                        collection_offer.price_raw = Some(collection_offer.price.clone());
+                        collection_offer.price_display =
+                            Some(to_display_price(&collection_offer.price, decimals));
+                    },
+                    // Auction prices are stored as-is in their own columns
+                    SecondaryModel::Auction(_) => {},
                 }
             }
         }
 
-        Ok((
-            activities,
-            current_listings,
-            current_token_offers,
-            current_collection_offers,
-        ))
+        // Keep only the valid secondary models, and the activity if any of them is valid
+        secondary_models.retain(|model| {
+            let is_valid = model.is_valid();
+            if !is_valid {
+                debug!("Secondary model validation failed, skipping: {:?}", model);
+                NFT_EVENTS_SKIPPED
+                    .with_label_values(&[SKIP_REASON_INVALID_MODEL])
+                    .inc();
+            }
+            is_valid
+        });
+        if secondary_models.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((activity, secondary_models)))
+    }
+
+    /// Builds the default current state model for a standard event type. Cancel, fill and
+    /// settle events mark the model as deleted. Update events leave it open, and the reduction
+    /// and the upserts keep the deletion state the model already had.
+    fn build_secondary_model(
+        &self,
+        standard_event_type: &MarketplaceEventType,
+        event: &EventModel,
+    ) -> Option<SecondaryModel> {
+        let marketplace = self.marketplace_name.clone();
+        let event_type = standard_event_type.to_string();
+        match standard_event_type {
+            MarketplaceEventType::PlaceListing
+            | MarketplaceEventType::CancelListing
+            | MarketplaceEventType::FillListing
+            | MarketplaceEventType::UpdateListing => {
+                let is_deleted = !matches!(
+                    standard_event_type,
+                    MarketplaceEventType::PlaceListing | MarketplaceEventType::UpdateListing
+                );
+                Some(SecondaryModel::Listing(
+                    CurrentNFTMarketplaceListing::build_default(
+                        marketplace,
+                        event,
+                        is_deleted,
+                        event_type,
+                    ),
+                ))
+            },
+            MarketplaceEventType::PlaceTokenOffer
+            | MarketplaceEventType::CancelTokenOffer
+            | MarketplaceEventType::FillTokenOffer
+            | MarketplaceEventType::UpdateTokenOffer => {
+                let is_deleted = !matches!(
+                    standard_event_type,
+                    MarketplaceEventType::PlaceTokenOffer | MarketplaceEventType::UpdateTokenOffer
+                );
+                Some(SecondaryModel::TokenOffer(
+                    CurrentNFTMarketplaceTokenOffer::build_default(
+                        marketplace,
+                        event,
+                        is_deleted,
+                        event_type,
+                    ),
+                ))
+            },
+            MarketplaceEventType::PlaceCollectionOffer
+            | MarketplaceEventType::CancelCollectionOffer
+            | MarketplaceEventType::FillCollectionOffer => {
+                let is_deleted = *standard_event_type != MarketplaceEventType::PlaceCollectionOffer;
+                Some(SecondaryModel::CollectionOffer(
+                    CurrentNFTMarketplaceCollectionOffer::build_default(
+                        marketplace,
+                        event,
+                        is_deleted,
+                        event_type,
+                    ),
+                ))
+            },
+            MarketplaceEventType::PlaceAuction
+            | MarketplaceEventType::BidAuction
+            | MarketplaceEventType::SettleAuction => {
+                let is_settled = *standard_event_type == MarketplaceEventType::SettleAuction;
+                Some(SecondaryModel::Auction(
+                    CurrentNFTMarketplaceAuction::build_default(
+                        marketplace,
+                        event,
+                        is_settled,
+                        event_type,
+                    ),
+                ))
+            },
+            MarketplaceEventType::Unknown => None,
+        }
     }
 
-    fn get_events(&self, transaction: Arc<Transaction>) -> Result<Vec<EventModel>> {
+    fn get_events(&self, transaction: Arc<Transaction>) -> Vec<EventModel> {
         let txn_version = transaction.version as i64;
         let block_height = transaction.block_height as i64;
-        let txn_data = match transaction.txn_data.as_ref() {
-            Some(data) => data,
+        let raw_events = match transaction.txn_data.as_ref() {
+            Some(TxnData::User(tx_inner)) => tx_inner.events.as_slice(),
+            Some(_) => return vec![],
             None => {
                 debug!("No transaction data found for version {}", txn_version);
-                return Ok(vec![]);
+                return vec![];
             },
         };
         let txn_timestamp =
             parse_timestamp(transaction.timestamp.as_ref().unwrap(), txn_version).naive_utc();
-        let default = vec![];
-        let raw_events = match txn_data {
-            TxnData::User(tx_inner) => tx_inner.events.as_slice(),
-            _ => &default,
-        };
-        EventModel::from_events(raw_events, txn_version, block_height, txn_timestamp)
+        EventModel::from_events(
+            raw_events,
+            txn_version,
+            block_height,
+            txn_timestamp,
+            self.keep_event_type_generics,
+        )
+    }
+
+    /// Offers are keyed by their buyer, so one the event didn't carry is stored under the
+    /// configured default buyer. Without one the offer stays invalid and is skipped.
+    fn set_default_buyer(&self, buyer: &mut String) {
+        if buyer.is_empty() {
+            if let Some(default_buyer) = &self.default_buyer {
+                buyer.clone_from(default_buyer);
+            }
+        }
+    }
+
+    /// Property version of the event's v1 token, if the event type has a path for it.
+    fn extract_property_version(&self, event: &EventModel) -> Option<String> {
+        let value = self
+            .property_version_paths
+            .get(&event.event_type)?
+            .extract_from(&event.data)
+            .ok()?;
+        value
+            .as_str()
+            .map(|s| s.to_string())
+            .or_else(|| value.as_u64().map(|n| n.to_string()))
+    }
+
+    /// Address of the event's v2 collection, if the event type has a path for it and the event
+    /// carries one. v1 events leave the path unresolved, e.g. an empty `collection.vec`.
+    fn extract_collection_inner(&self, event: &EventModel) -> Option<String> {
+        let value = self
+            .collection_inner_paths
+            .get(&event.event_type)?
+            .extract_from(&event.data)
+            .ok()?;
+        match value.as_str() {
+            Some(address) if !address.is_empty() => {
+                standardize_id_address(address, "collection id")
+            },
+            _ => None,
+        }
+    }
+
+    /// v1 tokens serialize `token_metadata.token` as `{ "vec": [] }`, so a config that only maps
+    /// the token id from `token.vec[0].inner` leaves them without one when it doesn't map their
+    /// names too. Generates the v1 id from the names in the event's `token_metadata` for a model
+    /// still without a token_data_id, and logs when the event doesn't carry them either.
+    fn fall_back_to_v1_token_data_id(
+        &self,
+        model: &mut impl MarketplaceModel,
+        activity: &mut NftMarketplaceActivity,
+        event: &EventModel,
+    ) {
+        if !model
+            .get_field(MarketplaceField::TokenDataId)
+            .unwrap_or_default()
+            .is_empty()
+        {
+            return;
+        }
+
+        let token_metadata = &event.data["token_metadata"];
+        let name = |field: &str| token_metadata[field].as_str().map(str::to_string);
+        match generate_token_data_id(
+            token_metadata["creator_address"]
+                .as_str()
+                .map(str::to_string),
+            name("collection_name"),
+            name("token_name"),
+        ) {
+            Some(id) => {
+                debug!(
+                    event_type = %event.event_type,
+                    "Generated the v1 token_data_id from the event's token_metadata"
+                );
+                model.set_field(MarketplaceField::TokenDataId, id.clone());
+                if activity
+                    .token_data_id
+                    .as_deref()
+                    .unwrap_or_default()
+                    .is_empty()
+                {
+                    activity.set_field(MarketplaceField::TokenDataId, id);
+                }
+            },
+            None => warn!(
+                event_type = %event.event_type,
+                txn_version = event.transaction_version,
+                "No token_data_id mapped, and the event's token_metadata has no v1 token names \
+                 to generate one from"
+            ),
+        }
+    }
+
+    /// Normalizes the stored token and collection names. Runs after the ids are generated, which
+    /// hash the names as they are on chain.
+    fn normalize_names(
+        &self,
+        activity: &mut NftMarketplaceActivity,
+        secondary_models: &mut [SecondaryModel],
+    ) {
+        let mut names = vec![&mut activity.token_name, &mut activity.collection_name];
+        for model in secondary_models.iter_mut() {
+            match model {
+                SecondaryModel::Listing(listing) => {
+                    names.extend([&mut listing.token_name, &mut listing.collection_name])
+                },
+                SecondaryModel::TokenOffer(token_offer) => names.extend([
+                    &mut token_offer.token_name,
+                    &mut token_offer.collection_name,
+                ]),
+                SecondaryModel::CollectionOffer(collection_offer) => {
+                    names.push(&mut collection_offer.collection_name)
+                },
+                SecondaryModel::Auction(auction) => names.push(&mut auction.token_name),
+            }
+        }
+        for name in names {
+            *name = name
+                .as_deref()
+                .map(|name| self.name_normalization.apply(name))
+                .filter(|name| !name.is_empty());
+        }
     }
 
     // Helper function to generate and set IDs for a model
@@ -402,7 +1079,24 @@ impl EventRemapper {
         creator_address: &Option<String>,
         collection_name: &Option<String>,
         token_name: &Option<String>,
+        collection_inner: Option<&str>,
     ) {
+        // Fill in the collection details from the activity if they weren't mapped for the model
+        for (field, value) in [
+            (MarketplaceField::CreatorAddress, creator_address),
+            (MarketplaceField::CollectionName, collection_name),
+        ] {
+            if let Some(value) = value {
+                if model
+                    .get_field(field.clone())
+                    .unwrap_or_default()
+                    .is_empty()
+                {
+                    model.set_field(field, value.clone());
+                }
+            }
+        }
+
         // Generate token_data_id if needed
         if model
             .get_field(MarketplaceField::TokenDataId)
@@ -420,14 +1114,16 @@ impl EventRemapper {
             }
         }
 
-        // Generate collection_id if needed
+        // Take the v2 collection's address, or generate the v1 collection_id, if needed
         if model
             .get_field(MarketplaceField::CollectionId)
             .unwrap_or_default()
             .is_empty()
         {
             if let Some(generated_collection_id) =
-                generate_collection_id(creator_address.clone(), collection_name.clone())
+                collection_inner.map(str::to_string).or_else(|| {
+                    generate_collection_id(creator_address.clone(), collection_name.clone())
+                })
             {
                 model.set_field(
                     MarketplaceField::CollectionId,
@@ -439,6 +1135,96 @@ impl EventRemapper {
     }
 }
 
+/// Returns a string or unsigned integer JSON value as a string.
+fn scalar_to_string(value: &serde_json::Value) -> Option<String> {
+    value
+        .as_str()
+        .map(|s| s.to_string())
+        .or_else(|| value.as_u64().map(|n| n.to_string()))
+}
+
+/// Stores every match of an `all_matches` path as an array in the activity's custom column.
+/// Matches that can't be converted are left out.
+fn remap_all_matches(
+    activity: &mut NftMarketplaceActivity,
+    json_path: &HashableJsonPath,
+    db_mapping: &DbColumn,
+    data: &serde_json::Value,
+) {
+    let column = match MarketplaceField::for_column(&db_mapping.table, &db_mapping.column) {
+        Ok(MarketplaceField::Custom(column)) if db_mapping.stores_array() => column,
+        _ => {
+            warn!(
+                "Skipping invalid field {}.{}: all_matches is only stored in custom activity \
+                 columns",
+                db_mapping.table, db_mapping.column
+            );
+            NFT_EVENTS_SKIPPED
+                .with_label_values(&[SKIP_REASON_INVALID_FIELD])
+                .inc();
+            return;
+        },
+    };
+
+    let values: Vec<String> = json_path
+        .extract_all_from(data)
+        .into_iter()
+        .filter_map(|value| {
+            let value = match &db_mapping.unwrap {
+                Some(wrapper) => wrapper.unwrap_value(value),
+                None => value,
+            };
+            match db_mapping.convert(scalar_to_string(&value)?) {
+                Ok(value) if !value.is_empty() => Some(value),
+                Ok(_) => None,
+                Err(e) => {
+                    warn!(
+                        "Failed to transform value for path {} for column {}: {:#}",
+                        json_path.raw, db_mapping.column, e
+                    );
+                    NFT_EVENTS_SKIPPED
+                        .with_label_values(&[SKIP_REASON_TRANSFORM_FAILED])
+                        .inc();
+                    None
+                },
+            }
+        })
+        .collect();
+    if !values.is_empty() {
+        activity.set_custom_array(column, values);
+    }
+}
+
+/// Returns the length of the longest array among the values the event's paths resolve to, or
+/// `None` if no path resolves to an array.
+fn fan_out_len(
+    remappings: &HashMap<HashableJsonPath, Vec<DbColumn>>,
+    data: &serde_json::Value,
+) -> Option<usize> {
+    remappings
+        .keys()
+        .filter(|json_path| !json_path.reads_txn())
+        .filter_map(|json_path| match json_path.extract_from(data) {
+            Ok(serde_json::Value::Array(values)) => Some(values.len()),
+            _ => None,
+        })
+        .max()
+}
+
+/// Standardizes an address used to derive an id, logging and skipping the id if the address is
+/// malformed rather than letting an over-length value reach the database.
+fn standardize_id_address(address: &str, id_name: &str) -> Option<String> {
+    match standardize_address(address) {
+        Ok(address) => Some(address),
+        Err(e) => {
+            warn!("Invalid address for {id_name} generation - skipping: {e:#}");
+            None
+        },
+    }
+}
+
+/// Hashes `creator::collection::token` like aptos-core's v1 token data ids. The property
+/// version is part of a v1 token id but not of its token data id, so it isn't hashed.
 fn generate_token_data_id(
     creator_address: Option<String>,
     collection_name: Option<String>,
@@ -448,10 +1234,10 @@ fn generate_token_data_id(
         (Some(creator), Some(collection), Some(token))
             if !creator.is_empty() && !collection.is_empty() && !token.is_empty() =>
         {
-            let creator_address = standardize_address(&creator);
+            let creator_address = standardize_id_address(&creator, "token data id")?;
             let input = format!("{creator_address}::{collection}::{token}");
             let hash_str = hash_str(&input);
-            Some(standardize_address(&hash_str))
+            standardize_id_address(&hash_str, "token data id")
         },
         _ => {
             debug!("Missing required fields for token data id generation - skipping");
@@ -466,10 +1252,10 @@ fn generate_collection_id(
 ) -> Option<String> {
     match (creator_address, collection_name) {
         (Some(creator), Some(collection)) if !creator.is_empty() && !collection.is_empty() => {
-            let creator_address = standardize_address(&creator);
+            let creator_address = standardize_id_address(&creator, "collection id")?;
             let input = format!("{creator_address}::{collection}");
             let hash_str = hash_str(&input);
-            Some(standardize_address(&hash_str))
+            standardize_id_address(&hash_str, "collection id")
         },
         _ => {
             debug!("Missing required fields for collection id generation - skipping");
@@ -478,18 +1264,27 @@ fn generate_collection_id(
     }
 }
 
-#[allow(dead_code)]
+/// A buyer can hold offers on several collections of one creator at once, so the collection is
+/// part of the id. The `bid_key`, if given, tells apart several bids of a buyer on a collection.
 fn generate_collection_offer_id(
     creator_address: Option<String>,
+    collection_id: Option<String>,
     buyer: Option<String>,
+    bid_key: Option<i64>,
 ) -> Option<String> {
-    match (creator_address, buyer) {
-        (Some(creator), Some(buyer)) if !creator.is_empty() && !buyer.is_empty() => {
-            let creator_address = standardize_address(&creator);
-            let buyer_address = standardize_address(&buyer);
-            let input = format!("{creator_address}::{buyer_address}");
+    match (creator_address, collection_id, buyer) {
+        (Some(creator), Some(collection_id), Some(buyer))
+            if !creator.is_empty() && !collection_id.is_empty() && !buyer.is_empty() =>
+        {
+            let creator_address = standardize_id_address(&creator, "collection offer id")?;
+            let collection_id = standardize_id_address(&collection_id, "collection offer id")?;
+            let buyer_address = standardize_id_address(&buyer, "collection offer id")?;
+            let mut input = format!("{creator_address}::{collection_id}::{buyer_address}");
+            if let Some(bid_key) = bid_key {
+                input.push_str(&format!("::{bid_key}"));
+            }
             let hash_str = hash_str(&input);
-            Some(standardize_address(&hash_str))
+            standardize_id_address(&hash_str, "collection offer id")
         },
         _ => {
             debug!("Missing required fields for collection offer id generation - skipping");
@@ -501,16 +1296,25 @@ fn generate_collection_offer_id(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::marketplace_config::{DbColumn, EventRemapping};
+    use crate::{
+        config::marketplace_config::{DbColumn, EventRemapping, MoveWrapper, Network},
+        models::nft_models::DEFAULT_BUYER,
+        steps::reduction_step::NFTAccumulator,
+    };
     use aptos_indexer_processor_sdk::aptos_protos::{
-        transaction::v1::{Event, UserTransaction},
+        transaction::v1::{
+            transaction_payload::Payload, EntryFunctionPayload, Event, TransactionPayload,
+            UserTransaction, UserTransactionRequest,
+        },
         util::timestamp::Timestamp,
     };
+    use bigdecimal::BigDecimal;
 
     fn create_db_column(table: &str, column: &str) -> DbColumn {
         DbColumn {
             table: table.to_string(),
             column: column.to_string(),
+            ..Default::default()
         }
     }
 
@@ -525,6 +1329,7 @@ mod tests {
                 let mut map = HashMap::new();
                 map.insert(event_type.to_string(), EventRemapping {
                     event_fields: fields,
+                    ..Default::default()
                 });
                 map
             },
@@ -533,7 +1338,7 @@ mod tests {
                 map.insert(event_type.to_string(), event_model_type);
                 map
             },
-            resources: HashMap::new(),
+            ..Default::default()
         }
     }
 
@@ -593,10 +1398,10 @@ mod tests {
     }
 
     fn build_test_token_data_id(creator: &str, collection: &str, token: &str) -> String {
-        let creator_address = standardize_address(creator);
+        let creator_address = standardize_address(creator).unwrap();
         let input = format!("{creator_address}::{collection}::{token}");
         let hash_str = hash_str(&input);
-        standardize_address(&hash_str)
+        standardize_address(&hash_str).unwrap()
     }
 
     #[test]
@@ -641,8 +1446,13 @@ mod tests {
 
         let remapper = EventRemapper::new(&config)?;
         let transaction = create_transaction(event_type, event_data);
-        let (activities, listings, token_offers, collection_offers) =
-            remapper.remap_events(transaction)?;
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            ..
+        } = remapper.remap_events(transaction)?;
 
         // Verify results
         assert_eq!(activities.len(), 1, "Should have one activity");
@@ -656,7 +1466,7 @@ mod tests {
 
         // Verify listing details
         let listing = &listings[0];
-        assert_eq!(listing.price, 3400000000);
+        assert_eq!(listing.price, BigDecimal::from(3400000000i64));
         assert_eq!(
             listing.token_data_id,
             "0xc821b5c1712fca97553c85830b91dc212cd2fcdd2a2490b65f945ed901d9f126"
@@ -669,6 +1479,11 @@ mod tests {
             listing.listing_id.as_deref().unwrap(),
             "0x9d14c489b6f56ac55e8707022400c23bb83bd0b0cd486c862defccf6241a219e"
         );
+        assert_eq!(
+            listing.creator_address.as_deref().unwrap(),
+            "0xf54f8f7ffc2b779d81b721b3d42fe9a53f96e1d3459a8001934307783d493725"
+        );
+        assert_eq!(listing.collection_name.as_deref().unwrap(), "The Loonies");
         assert_eq!(listing.marketplace, "test_marketplace");
         assert!(!listing.is_deleted);
 
@@ -753,8 +1568,13 @@ mod tests {
 
         let remapper = EventRemapper::new(&config)?;
         let transaction = create_transaction(event_type, event_data);
-        let (activities, listings, token_offers, collection_offers) =
-            remapper.remap_events(transaction)?;
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            ..
+        } = remapper.remap_events(transaction)?;
 
         // Verify results
         assert_eq!(activities.len(), 1, "Should have one activity");
@@ -768,7 +1588,7 @@ mod tests {
 
         // Verify activity details
         let activity = &activities[0];
-        assert_eq!(activity.price, 398000000);
+        assert_eq!(activity.price, BigDecimal::from(398000000i64));
         assert_eq!(
             activity.buyer.as_deref().unwrap(),
             "0x735507953f702ddad6dbf5a98de6fd3f57f50b89da9c68672414d8431f103726"
@@ -788,11 +1608,16 @@ mod tests {
 
         // Verify listing details
         let listing = &listings[0];
-        assert_eq!(listing.price, 398000000);
+        assert_eq!(listing.price, BigDecimal::from(398000000i64));
         assert_eq!(
             listing.seller,
             Some("0x8c557bb0a12d47c1eda90dd4883b44674111b915fa39ff862e6a0a39140dcd4".to_string())
         );
+        assert_eq!(
+            listing.creator_address.as_deref().unwrap(),
+            "0x43ec2cb158e3569842d537740fd53403e992b9e7349cc5d3dfaa5aff8faaef2"
+        );
+        assert_eq!(listing.collection_name.as_deref().unwrap(), "Bruh Bears");
         assert_eq!(listing.marketplace, "test_marketplace");
         assert!(listing.is_deleted);
 
@@ -800,16 +1625,307 @@ mod tests {
     }
 
     #[test]
-    fn test_listing_canceled_event_v2_token() -> Result<()> {
-        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingCanceledEvent";
-        let event_data = serde_json::json!({
-            "listing": "0x560197dcdc27af1cadc1cc75b51d9f0e3a0f40d7a761397c13bfdb4097924c1f",
-            "price": "3200000000",
-            "seller": "0xecd896bfa7eae31fb5085dca8e2f3c88ea3577bd54fafeaeb4ad6ede1e13e81e",
-            "token_metadata": {
-                "collection": {
-                    "vec": [
-                        {
+    fn test_v1_token_without_mapped_names_falls_back_to_token_metadata() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let creator = "0xf54f8f7ffc2b779d81b721b3d42fe9a53f96e1d3459a8001934307783d493725";
+        let mut fields = HashMap::new();
+        fields.insert("$.price".to_string(), vec![create_db_column(
+            "current_nft_marketplace_listings",
+            "price",
+        )]);
+        fields.insert("$.token_metadata.token.vec[0].inner".to_string(), vec![
+            create_db_column("current_nft_marketplace_listings", "token_data_id"),
+            create_db_column("nft_marketplace_activities", "token_data_id"),
+        ]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        let remapper = EventRemapper::new(&config)?;
+        let token_metadata = |token_name: Option<&str>| {
+            serde_json::json!({
+                "collection": { "vec": [] },
+                "collection_name": "The Loonies",
+                "creator_address": creator,
+                "property_version": { "vec": ["0"] },
+                "token": { "vec": [] },
+                "token_name": token_name,
+            })
+        };
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(
+            event_type,
+            serde_json::json!({
+                "price": "3400000000",
+                "token_metadata": token_metadata(Some("The Loonies #399")),
+            }),
+        ))?;
+        let expected_id = build_test_token_data_id(creator, "The Loonies", "The Loonies #399");
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].token_data_id, expected_id);
+        assert_eq!(
+            activities[0].token_data_id.as_deref(),
+            Some(expected_id.as_str())
+        );
+
+        // Without the names there is nothing to generate the id from, so the listing is dropped
+        let RemapOutput { listings, .. } = remapper.remap_events(create_transaction(
+            event_type,
+            serde_json::json!({
+                "price": "3400000000",
+                "token_metadata": token_metadata(None),
+            }),
+        ))?;
+        assert!(listings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_numeric_price_skips_event() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let config = create_marketplace_config(
+            event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let remapper = EventRemapper::new(&config)?;
+
+        let RemapOutput { activities, listings, .. } = remapper.remap_events(create_transaction(
+            event_type,
+            serde_json::json!({
+                "price": "not a price",
+                "seller": "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14",
+                "token_metadata": {
+                    "token": {
+                        "vec": [{
+                            "inner": "0xc821b5c1712fca97553c85830b91dc212cd2fcdd2a2490b65f945ed901d9f126"
+                        }]
+                    }
+                }
+            }),
+        ))?;
+
+        assert!(activities.is_empty());
+        assert!(listings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel_and_place_in_one_transaction_keeps_the_place() -> Result<()> {
+        let place_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let cancel_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingCanceledEvent";
+        let mut config = create_marketplace_config(
+            place_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let cancel_config = create_marketplace_config(
+            cancel_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::CancelListing,
+        );
+        config.events.extend(cancel_config.events);
+        config
+            .event_model_mapping
+            .extend(cancel_config.event_model_mapping);
+
+        let txn_timestamp = NaiveDateTime::default();
+        let build_event = |event_type: &str, price: &str, event_index: i64| -> Result<EventModel> {
+            Ok(EventModel {
+                sequence_number: 0,
+                creation_number: 0,
+                account_address: standardize_address("0x1")?,
+                transaction_version: 42,
+                transaction_block_height: 7,
+                event_type: EventType::try_from(event_type)?,
+                data: serde_json::json!({
+                    "price": price,
+                    "seller": "0x2",
+                    "token_metadata": { "token": { "vec": [{ "inner": "0x3" }] } }
+                }),
+                event_index,
+                block_timestamp: txn_timestamp,
+            })
+        };
+
+        // A re-list: the old listing is canceled, then placed again at a new price. The events
+        // come in out of order, the remapper orders them by event index.
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_event_models(
+            &[
+                build_event(place_type, "200", 1)?,
+                build_event(cancel_type, "100", 0)?,
+            ],
+            txn_timestamp,
+        )?;
+        assert_eq!(activities[0].standard_event_type, "cancel_listing");
+
+        let mut accumulator = NFTAccumulator::default();
+        for listing in listings {
+            accumulator.fold_listing(listing);
+        }
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert_eq!(listings.len(), 1);
+        assert!(!listings[0].is_deleted);
+        assert_eq!(listings[0].standard_event_type, "place_listing");
+        assert_eq!(listings[0].price, BigDecimal::from(200));
+        Ok(())
+    }
+
+    #[test]
+    fn test_listing_filled_event_fees() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingFilledEvent";
+        let event_data = serde_json::json!({
+            "commission": "51000000",
+            "price": "3400000000",
+            "purchaser": "0x2",
+            "royalties": "142800000",
+            "seller": "0x3",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x4" }] }
+            }
+        });
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.commission".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "marketplace_fee",
+        )]);
+        fields.insert("$.royalties".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "royalty_fee",
+        )]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::FillListing);
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput { activities, .. } =
+            remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        let activity = &activities[0];
+        assert_eq!(activity.price, BigDecimal::from(3400000000i64));
+        assert_eq!(activity.marketplace_fee, Some(BigDecimal::from(51000000)));
+        assert_eq!(activity.royalty_fee, Some(BigDecimal::from(142800000)));
+        assert_eq!(
+            activity.get_field(MarketplaceField::RoyaltyFee).as_deref(),
+            Some("142800000")
+        );
+        assert!(activity.custom_fields.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_self_trade_is_kept_and_flagged() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingFilledEvent";
+        let build_event_data = |purchaser: &str| {
+            serde_json::json!({
+                "price": "3400000000",
+                "purchaser": purchaser,
+                "seller": "0x2",
+                "token_metadata": {
+                    "token": { "vec": [{ "inner": "0x4" }] }
+                }
+            })
+        };
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.purchaser".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "buyer",
+        )]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::FillListing);
+        let remapper = EventRemapper::new(&config)?;
+
+        // The same address, written differently
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(
+            event_type,
+            build_event_data("0x0000000000000000000000000000000000000000000000000000000000000002"),
+        ))?;
+        assert_eq!(activities.len(), 1);
+        assert!(activities[0].is_self_trade);
+        assert_eq!(activities[0].seller.as_deref(), Some("0x2"));
+        assert_eq!(listings.len(), 1);
+        assert!(listings[0].is_deleted);
+
+        let RemapOutput { activities, .. } =
+            remapper.remap_events(create_transaction(event_type, build_event_data("0x3")))?;
+        assert!(!activities[0].is_self_trade);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_direction_of_place_cancel_and_fill_token_offers() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::TokenOfferEvent";
+        let table = "current_nft_marketplace_token_offers";
+        let fields = HashMap::from([
+            ("$.token".to_string(), vec![create_db_column(
+                table,
+                "token_data_id",
+            )]),
+            ("$.buyer".to_string(), vec![create_db_column(
+                table, "buyer",
+            )]),
+            ("$.price".to_string(), vec![create_db_column(
+                table, "price",
+            )]),
+        ]);
+        let event_data = serde_json::json!({ "token": "0x3", "buyer": "0x4", "price": "100" });
+
+        for (event_model_type, direction) in [
+            (MarketplaceEventType::PlaceTokenOffer, "to_escrow"),
+            (MarketplaceEventType::CancelTokenOffer, "to_buyer"),
+            (MarketplaceEventType::FillTokenOffer, "to_seller"),
+        ] {
+            let config = create_marketplace_config(event_type, fields.clone(), event_model_type);
+            let remapper = EventRemapper::new(&config)?;
+            let RemapOutput { activities, .. } =
+                remapper.remap_events(create_transaction(event_type, event_data.clone()))?;
+            assert_eq!(activities.len(), 1);
+            assert_eq!(activities[0].direction.as_deref(), Some(direction));
+        }
+
+        // Listings move no funds
+        let config = create_marketplace_config(
+            event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let RemapOutput { activities, .. } =
+            EventRemapper::new(&config)?.remap_events(create_transaction(
+                event_type,
+                serde_json::json!({
+                    "price": "100",
+                    "seller": "0x2",
+                    "token_metadata": { "token": { "vec": [{ "inner": "0x4" }] } }
+                }),
+            ))?;
+        assert_eq!(activities[0].direction, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listing_canceled_event_v2_token() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingCanceledEvent";
+        let event_data = serde_json::json!({
+            "listing": "0x560197dcdc27af1cadc1cc75b51d9f0e3a0f40d7a761397c13bfdb4097924c1f",
+            "price": "3200000000",
+            "seller": "0xecd896bfa7eae31fb5085dca8e2f3c88ea3577bd54fafeaeb4ad6ede1e13e81e",
+            "token_metadata": {
+                "collection": {
+                    "vec": [
+                        {
                             "inner": "0xa2485c3b392d211770ed161e73a1097d21016c7dd41f53592434380b2aa14cba"
                         }
                     ]
@@ -867,8 +1983,13 @@ mod tests {
 
         let remapper = EventRemapper::new(&config)?;
         let transaction = create_transaction(event_type, event_data);
-        let (activities, listings, token_offers, collection_offers) =
-            remapper.remap_events(transaction)?;
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            ..
+        } = remapper.remap_events(transaction)?;
 
         // Verify results
         assert_eq!(activities.len(), 1, "Should have one activity");
@@ -882,7 +2003,7 @@ mod tests {
 
         // Verify activity details
         let activity = &activities[0];
-        assert_eq!(activity.price, 3200000000);
+        assert_eq!(activity.price, BigDecimal::from(3200000000i64));
         assert_eq!(
             activity.seller.as_deref().unwrap(),
             "0xecd896bfa7eae31fb5085dca8e2f3c88ea3577bd54fafeaeb4ad6ede1e13e81e"
@@ -902,7 +2023,7 @@ mod tests {
 
         // Verify listing details
         let listing = &listings[0];
-        assert_eq!(listing.price, 3200000000);
+        assert_eq!(listing.price, BigDecimal::from(3200000000i64));
         assert_eq!(
             listing.seller,
             Some("0xecd896bfa7eae31fb5085dca8e2f3c88ea3577bd54fafeaeb4ad6ede1e13e81e".to_string())
@@ -911,6 +2032,11 @@ mod tests {
             listing.listing_id.as_deref().unwrap(),
             "0x560197dcdc27af1cadc1cc75b51d9f0e3a0f40d7a761397c13bfdb4097924c1f"
         );
+        assert_eq!(
+            listing.creator_address.as_deref().unwrap(),
+            "0xf54f8f7ffc2b779d81b721b3d42fe9a53f96e1d3459a8001934307783d493725"
+        );
+        assert_eq!(listing.collection_name.as_deref().unwrap(), "The Loonies");
         assert_eq!(listing.marketplace, "test_marketplace");
         assert!(listing.is_deleted);
 
@@ -974,8 +2100,13 @@ mod tests {
 
         let remapper = EventRemapper::new(&config)?;
         let transaction = create_transaction(event_type, event_data);
-        let (activities, listings, token_offers, collection_offers) =
-            remapper.remap_events(transaction)?;
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            ..
+        } = remapper.remap_events(transaction)?;
 
         // Verify results
         assert_eq!(activities.len(), 1, "Should have one activity");
@@ -989,7 +2120,7 @@ mod tests {
 
         // Verify activity details
         let activity = &activities[0];
-        assert_eq!(activity.price, 25000000);
+        assert_eq!(activity.price, BigDecimal::from(25000000i64));
         assert_eq!(
             activity.buyer.as_deref().unwrap(),
             "0x62928b3712d452190346090807d5cfb40dabb54740cf1d2acfc5b4d3d9e0b370"
@@ -1015,7 +2146,7 @@ mod tests {
             "AptosDogs #1596",
         );
         assert_eq!(token_offer.token_data_id, expected_token_data_id);
-        assert_eq!(token_offer.price, 25000000);
+        assert_eq!(token_offer.price, BigDecimal::from(25000000i64));
         assert_eq!(
             token_offer.buyer,
             "0x62928b3712d452190346090807d5cfb40dabb54740cf1d2acfc5b4d3d9e0b370"
@@ -1109,8 +2240,13 @@ mod tests {
 
         let remapper = EventRemapper::new(&config)?;
         let transaction = create_transaction(event_type, event_data);
-        let (activities, listings, token_offers, collection_offers) =
-            remapper.remap_events(transaction)?;
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            ..
+        } = remapper.remap_events(transaction)?;
 
         // Verify results
         assert_eq!(activities.len(), 1, "Should have one activity");
@@ -1124,7 +2260,7 @@ mod tests {
 
         // Verify activity details
         let activity = &activities[0];
-        assert_eq!(activity.price, 3400000000);
+        assert_eq!(activity.price, BigDecimal::from(3400000000i64));
         assert_eq!(
             activity.buyer.as_deref().unwrap(),
             "0x22113f16f9b7c6761ef14df757c016b8736a9023e8881cd5e11579b0b98ef562"
@@ -1152,7 +2288,7 @@ mod tests {
 
         // Verify token offer details
         let token_offer = &token_offers[0];
-        assert_eq!(token_offer.price, 3400000000);
+        assert_eq!(token_offer.price, BigDecimal::from(3400000000i64));
         assert_eq!(
             token_offer.buyer,
             "0x22113f16f9b7c6761ef14df757c016b8736a9023e8881cd5e11579b0b98ef562"
@@ -1170,4 +2306,2130 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_offer_id_key_keeps_each_offer_of_a_buyer() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::TokenOfferPlacedEvent";
+        let table = "current_nft_marketplace_token_offers";
+        let fields = HashMap::from([
+            ("$.token".to_string(), vec![create_db_column(
+                table,
+                "token_data_id",
+            )]),
+            ("$.offer".to_string(), vec![create_db_column(
+                table, "offer_id",
+            )]),
+            ("$.buyer".to_string(), vec![create_db_column(
+                table, "buyer",
+            )]),
+            ("$.price".to_string(), vec![create_db_column(
+                table, "price",
+            )]),
+        ]);
+        let config = NFTMarketplaceConfig {
+            token_offer_key: TokenOfferKey::OfferId,
+            ..create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceTokenOffer)
+        };
+        let txn_timestamp = NaiveDateTime::default();
+        let build_event = |offer: &str, price: &str, event_index: i64| -> Result<EventModel> {
+            Ok(EventModel {
+                sequence_number: 0,
+                creation_number: 0,
+                account_address: standardize_address("0x1")?,
+                transaction_version: 42,
+                transaction_block_height: 7,
+                event_type: EventType::try_from(event_type)?,
+                data: serde_json::json!({
+                    "token": "0x3",
+                    "offer": offer,
+                    "buyer": "0x2",
+                    "price": price,
+                }),
+                event_index,
+                block_timestamp: txn_timestamp,
+            })
+        };
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput { token_offers, .. } = remapper.remap_event_models(
+            &[build_event("0x4", "100", 0)?, build_event("0x5", "200", 1)?],
+            txn_timestamp,
+        )?;
+        let mut accumulator = NFTAccumulator::default();
+        for token_offer in token_offers {
+            accumulator.fold_token_offer(token_offer);
+        }
+        let RemapOutput {
+            mut token_offers, ..
+        } = accumulator.drain();
+        token_offers.sort_by(|a, b| a.offer_key.cmp(&b.offer_key));
+
+        // Same buyer and token, but a row per offer
+        assert_eq!(token_offers.len(), 2);
+        for token_offer in &token_offers {
+            assert_eq!(token_offer.offer_id.as_ref(), Some(&token_offer.offer_key));
+        }
+        assert_eq!(token_offers[0].price, BigDecimal::from(100));
+        assert_eq!(token_offers[1].price, BigDecimal::from(200));
+        assert_eq!(token_offers[0].buyer, token_offers[1].buyer);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bid_key_keeps_each_offer_of_a_buyer() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::TokenOfferPlacedEvent";
+        let token_offers_table = "current_nft_marketplace_token_offers";
+        let collection_offers_table = "current_nft_marketplace_collection_offers";
+        let fields = HashMap::from([
+            ("$.token".to_string(), vec![create_db_column(
+                token_offers_table,
+                "token_data_id",
+            )]),
+            ("$.creator".to_string(), vec![create_db_column(
+                "nft_marketplace_activities",
+                "creator_address",
+            )]),
+            ("$.collection".to_string(), vec![create_db_column(
+                collection_offers_table,
+                "collection_id",
+            )]),
+            ("$.buyer".to_string(), vec![
+                create_db_column(token_offers_table, "buyer"),
+                create_db_column(collection_offers_table, "buyer"),
+                create_db_column("nft_marketplace_activities", "buyer"),
+            ]),
+            ("$.bid_key".to_string(), vec![
+                create_db_column(token_offers_table, "bid_key"),
+                create_db_column(collection_offers_table, "bid_key"),
+            ]),
+            ("$.price".to_string(), vec![
+                create_db_column(token_offers_table, "price"),
+                create_db_column(collection_offers_table, "price"),
+            ]),
+        ]);
+        let txn_timestamp = NaiveDateTime::default();
+        let build_event = |bid_key: &str, event_index: i64| -> Result<EventModel> {
+            Ok(EventModel {
+                sequence_number: 0,
+                creation_number: 0,
+                account_address: standardize_address("0x1")?,
+                transaction_version: 42,
+                transaction_block_height: 7,
+                event_type: EventType::try_from(event_type)?,
+                data: serde_json::json!({
+                    "token": "0x3",
+                    "creator": "0x4",
+                    "collection": "0x5",
+                    "buyer": "0x2",
+                    "bid_key": bid_key,
+                    "price": "100",
+                }),
+                event_index,
+                block_timestamp: txn_timestamp,
+            })
+        };
+        let events = [build_event("7", 0)?, build_event("8", 1)?];
+
+        let config = NFTMarketplaceConfig {
+            token_offer_key: TokenOfferKey::BidKey,
+            ..create_marketplace_config(
+                event_type,
+                fields.clone(),
+                MarketplaceEventType::PlaceTokenOffer,
+            )
+        };
+        let RemapOutput { token_offers, .. } =
+            EventRemapper::new(&config)?.remap_event_models(&events, txn_timestamp)?;
+        let mut accumulator = NFTAccumulator::default();
+        for token_offer in token_offers {
+            accumulator.fold_token_offer(token_offer);
+        }
+        let RemapOutput {
+            mut token_offers, ..
+        } = accumulator.drain();
+        token_offers.sort_by(|a, b| a.offer_key.cmp(&b.offer_key));
+
+        // Same buyer, token and price, but a row per bid key
+        assert_eq!(token_offers.len(), 2);
+        assert_eq!(token_offers[0].offer_key, "7");
+        assert_eq!(token_offers[1].offer_key, "8");
+
+        let config = NFTMarketplaceConfig {
+            collection_offer_key: CollectionOfferKey::BidKey,
+            ..create_marketplace_config(
+                event_type,
+                fields,
+                MarketplaceEventType::PlaceCollectionOffer,
+            )
+        };
+        let RemapOutput {
+            collection_offers, ..
+        } = EventRemapper::new(&config)?.remap_event_models(&events, txn_timestamp)?;
+        let mut accumulator = NFTAccumulator::default();
+        for collection_offer in collection_offers {
+            accumulator.fold_collection_offer(collection_offer);
+        }
+        let RemapOutput {
+            collection_offers, ..
+        } = accumulator.drain();
+
+        assert_eq!(collection_offers.len(), 2);
+        assert_ne!(
+            collection_offers[0].collection_offer_id,
+            collection_offers[1].collection_offer_id
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_offer_without_buyer_uses_default_buyer() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::TokenOfferPlacedEvent";
+        let table = "current_nft_marketplace_token_offers";
+        let fields = HashMap::from([
+            ("$.token".to_string(), vec![create_db_column(
+                table,
+                "token_data_id",
+            )]),
+            ("$.buyer".to_string(), vec![
+                create_db_column(table, "buyer"),
+                create_db_column("nft_marketplace_activities", "buyer"),
+            ]),
+            ("$.price".to_string(), vec![create_db_column(
+                table, "price",
+            )]),
+        ]);
+        let mut config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceTokenOffer);
+        let txn_timestamp = NaiveDateTime::default();
+        let event = EventModel {
+            sequence_number: 0,
+            creation_number: 0,
+            account_address: standardize_address("0x1")?,
+            transaction_version: 42,
+            transaction_block_height: 7,
+            event_type: EventType::try_from(event_type)?,
+            data: serde_json::json!({ "token": "0x3", "price": "100" }),
+            event_index: 0,
+            block_timestamp: txn_timestamp,
+        };
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            token_offers,
+            ..
+        } = remapper.remap_event_models(&[event.clone()], txn_timestamp)?;
+        assert_eq!(token_offers.len(), 1);
+        assert_eq!(token_offers[0].buyer, DEFAULT_BUYER);
+        assert_eq!(activities[0].buyer, None);
+
+        config.default_buyer = Some("0x0".to_string());
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput { token_offers, .. } =
+            remapper.remap_event_models(&[event.clone()], txn_timestamp)?;
+        assert_eq!(token_offers[0].buyer, "0x0");
+
+        // Without a default the offer can't be keyed and is skipped
+        config.default_buyer = None;
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            token_offers,
+            ..
+        } = remapper.remap_event_models(&[event], txn_timestamp)?;
+        assert!(activities.is_empty());
+        assert!(token_offers.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_optional_string_field_present_and_absent() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.token_metadata.token_name".to_string(), vec![
+            DbColumn {
+                unwrap: Some(MoveWrapper::Option),
+                ..create_db_column("nft_marketplace_activities", "token_name")
+            },
+            DbColumn {
+                unwrap: Some(MoveWrapper::Option),
+                ..create_db_column("current_nft_marketplace_listings", "token_name")
+            },
+        ]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        let remapper = EventRemapper::new(&config)?;
+
+        let build_event_data = |token_name: serde_json::Value| {
+            serde_json::json!({
+                "price": "100",
+                "seller": "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14",
+                "token_metadata": {
+                    "token": {
+                        "vec": [
+                            {
+                                "inner": "0xc821b5c1712fca97553c85830b91dc212cd2fcdd2a2490b65f945ed901d9f126"
+                            }
+                        ]
+                    },
+                    "token_name": token_name
+                }
+            })
+        };
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(
+            event_type,
+            build_event_data(serde_json::json!({ "vec": ["The Loonies #399"] })),
+        ))?;
+        assert_eq!(
+            activities[0].token_name.as_deref(),
+            Some("The Loonies #399")
+        );
+        assert_eq!(listings[0].token_name.as_deref(), Some("The Loonies #399"));
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(
+            event_type,
+            build_event_data(serde_json::json!({ "vec": [] })),
+        ))?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].token_name, None);
+        assert_eq!(listings[0].token_name, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_price_raw_and_display_for_8_decimal_coin() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let event_data = serde_json::json!({
+            "price": "3400000000",
+            "seller": "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14",
+            "token_metadata": {
+                "token": {
+                    "vec": [
+                        {
+                            "inner": "0xc821b5c1712fca97553c85830b91dc212cd2fcdd2a2490b65f945ed901d9f126"
+                        }
+                    ]
+                }
+            }
+        });
+        let config = NFTMarketplaceConfig {
+            price_decimals: Some(8),
+            ..create_marketplace_config(
+                event_type,
+                create_listing_field_mappings(),
+                MarketplaceEventType::PlaceListing,
+            )
+        };
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        let expected_raw = BigDecimal::from(3400000000i64);
+        let expected_display = BigDecimal::from(34);
+        assert_eq!(activities[0].price_raw, Some(expected_raw.clone()));
+        assert_eq!(activities[0].price_display, Some(expected_display.clone()));
+        assert_eq!(listings[0].price_raw, Some(expected_raw));
+        assert_eq!(listings[0].price_display, Some(expected_display));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalizes_token_and_collection_names() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14",
+            "token_metadata": {
+                "token": {
+                    "vec": [
+                        {
+                            "inner": "0xc821b5c1712fca97553c85830b91dc212cd2fcdd2a2490b65f945ed901d9f126"
+                        }
+                    ]
+                }
+            },
+            "token_name": format!("  {}", "x".repeat(300)),
+            // "Cafe" with a combining acute accent, NFC turns it into a precomposed "é"
+            "collection_name": " Cafe\u{301} ",
+        });
+
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.token_name".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "token_name"),
+            create_db_column("current_nft_marketplace_listings", "token_name"),
+        ]);
+        fields.insert("$.collection_name".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "collection_name",
+        )]);
+        let config = NFTMarketplaceConfig {
+            name_normalization: NameNormalization {
+                trim: true,
+                nfc: true,
+                max_length: 128,
+            },
+            ..create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing)
+        };
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        let expected_token_name = "x".repeat(128);
+        assert_eq!(activities[0].token_name, Some(expected_token_name.clone()));
+        assert_eq!(listings[0].token_name, Some(expected_token_name));
+        assert_eq!(activities[0].collection_name, Some("Caf\u{e9}".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_v1_ids_hash_names_before_normalization() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let creator = "0xee814d743d2c3b4b1b8b30f3e0c84c7017df3154bda84c31958785f1d5b70e61";
+        let collection_name = " Aptos Dogs ";
+        let token_name = format!("AptosDogs #1596 {}", "x".repeat(200));
+        let mut fields = HashMap::new();
+        for (path, column) in [
+            ("$.token_metadata.creator_address", "creator_address"),
+            ("$.token_metadata.collection_name", "collection_name"),
+            ("$.token_metadata.token_name", "token_name"),
+            ("$.seller", "seller"),
+            ("$.price", "price"),
+        ] {
+            fields.insert(path.to_string(), vec![
+                create_db_column("nft_marketplace_activities", column),
+                create_db_column("current_nft_marketplace_listings", column),
+            ]);
+        }
+        let config = NFTMarketplaceConfig {
+            name_normalization: NameNormalization {
+                trim: true,
+                nfc: true,
+                max_length: 128,
+            },
+            ..create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing)
+        };
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14",
+            "token_metadata": {
+                "collection": { "vec": [] },
+                "collection_name": collection_name,
+                "creator_address": creator,
+                "token": { "vec": [] },
+                "token_name": token_name
+            }
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        // The ids are the chain's, hashed from the names as the chain has them
+        let expected_token_data_id = generate_token_data_id(
+            Some(creator.to_string()),
+            Some(collection_name.to_string()),
+            Some(token_name.clone()),
+        );
+        let expected_collection_id =
+            generate_collection_id(Some(creator.to_string()), Some(collection_name.to_string()));
+        assert_eq!(activities[0].token_data_id, expected_token_data_id);
+        assert_eq!(
+            Some(listings[0].token_data_id.clone()),
+            expected_token_data_id
+        );
+        assert_eq!(activities[0].collection_id, expected_collection_id);
+        assert_eq!(listings[0].collection_id, expected_collection_id);
+
+        // Only the stored names are normalized
+        assert_eq!(
+            activities[0].collection_name,
+            Some("Aptos Dogs".to_string())
+        );
+        assert_eq!(listings[0].collection_name, Some("Aptos Dogs".to_string()));
+        let expected_token_name: String = token_name.chars().take(128).collect();
+        assert_eq!(activities[0].token_name, Some(expected_token_name.clone()));
+        assert_eq!(listings[0].token_name, Some(expected_token_name));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_offer_priced_in_fungible_asset() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::TokenOfferPlacedEvent";
+        let usdc = "0xbae207659db88bea0cbead6da0ed00aac12edcdda169e591cd41c94180b46f3b";
+        let event_data = serde_json::json!({
+            "price": "5000000",
+            "purchaser": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            },
+            "payment_metadata": { "inner": usdc }
+        });
+
+        let mut fields = HashMap::new();
+        fields.insert("$.price".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "price"),
+            create_db_column("current_nft_marketplace_token_offers", "price"),
+        ]);
+        fields.insert("$.purchaser".to_string(), vec![create_db_column(
+            "current_nft_marketplace_token_offers",
+            "buyer",
+        )]);
+        fields.insert("$.token_metadata.token.vec[0].inner".to_string(), vec![
+            create_db_column("current_nft_marketplace_token_offers", "token_data_id"),
+        ]);
+        fields.insert("$.payment_metadata.inner".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "payment_token"),
+            create_db_column("current_nft_marketplace_token_offers", "payment_token"),
+        ]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceTokenOffer);
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            token_offers,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data.clone()))?;
+        assert_eq!(activities[0].payment_token, usdc);
+        assert_eq!(token_offers[0].payment_token, usdc);
+        assert_eq!(token_offers[0].price, BigDecimal::from(5000000));
+
+        // Without a payment token mapping, prices default to APT
+        let mut config = config;
+        config
+            .events
+            .get_mut(event_type)
+            .unwrap()
+            .event_fields
+            .remove("$.payment_metadata.inner");
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            token_offers,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+        assert_eq!(activities[0].payment_token, DEFAULT_PAYMENT_TOKEN);
+        assert_eq!(token_offers[0].payment_token, DEFAULT_PAYMENT_TOKEN);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registered_plugin_replaces_config_remapping() -> Result<()> {
+        struct FixedPlugin;
+
+        impl MarketplacePlugin for FixedPlugin {
+            fn remap(&self, txn: &Transaction) -> Result<RemapOutput> {
+                let activity = NftMarketplaceActivity {
+                    txn_version: txn.version as i64,
+                    raw_event_type: "plugin".to_string(),
+                    ..Default::default()
+                };
+                Ok(RemapOutput {
+                    activities: vec![activity],
+                    ..Default::default()
+                })
+            }
+        }
+
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            }
+        });
+        let config = create_marketplace_config(
+            event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+
+        let mut plugins = PluginRegistry::default();
+        plugins.register("test_marketplace", Arc::new(FixedPlugin));
+        let remapper = EventRemapper::with_plugins(&config, &plugins)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data.clone()))?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].raw_event_type, "plugin");
+        assert!(listings.is_empty());
+
+        // Marketplaces without a plugin fall back to the config
+        let mut plugins = PluginRegistry::default();
+        plugins.register("other_marketplace", Arc::new(FixedPlugin));
+        let remapper = EventRemapper::with_plugins(&config, &plugins)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+        assert_eq!(activities[0].raw_event_type, event_type);
+        assert_eq!(listings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_output_from_disallowed_contracts_is_dropped() -> Result<()> {
+        struct TwoContractPlugin;
+
+        impl MarketplacePlugin for TwoContractPlugin {
+            fn remap(&self, txn: &Transaction) -> Result<RemapOutput> {
+                let mut output = RemapOutput::default();
+                for (index, contract) in ["0x1", "0x4"].into_iter().enumerate() {
+                    let contract_address = standardize_address(contract)?;
+                    output.activities.push(NftMarketplaceActivity {
+                        txn_version: txn.version as i64,
+                        index: index as i64,
+                        contract_address: contract_address.clone(),
+                        ..Default::default()
+                    });
+                    output.listings.push(CurrentNFTMarketplaceListing {
+                        token_data_id: format!("0x{index}"),
+                        contract_address,
+                        ..Default::default()
+                    });
+                }
+                Ok(output)
+            }
+        }
+
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let config = NFTMarketplaceConfig {
+            allowed_contracts: vec!["0x1".to_string()],
+            ..create_marketplace_config(
+                event_type,
+                create_listing_field_mappings(),
+                MarketplaceEventType::PlaceListing,
+            )
+        };
+        let mut plugins = PluginRegistry::default();
+        plugins.register("test_marketplace", Arc::new(TwoContractPlugin));
+
+        let remapper = EventRemapper::with_plugins(&config, &plugins)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, serde_json::json!({})))?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].contract_address, standardize_address("0x1")?);
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].token_data_id, "0x0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generic_event_type_matches_bare_config() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let generic_event_type = format!("{event_type}<0x1::aptos_coin::AptosCoin>");
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            }
+        });
+        let transaction = create_transaction(&generic_event_type, event_data);
+
+        let mut config = create_marketplace_config(
+            event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(transaction.clone())?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].standard_event_type, "place_listing");
+        assert_eq!(listings.len(), 1);
+
+        // Keeping generics, only a config naming them matches
+        config.keep_event_type_generics = true;
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput { activities, .. } = remapper.remap_events(transaction.clone())?;
+        assert!(activities.is_empty());
+
+        let mut config = create_marketplace_config(
+            &format!("{event_type}< 0x1::aptos_coin::AptosCoin >"),
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        config.keep_event_type_generics = true;
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput { activities, .. } = remapper.remap_events(transaction)?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].raw_event_type, generic_event_type);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remap_event_models_without_transaction() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let config = create_marketplace_config(
+            event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let txn_timestamp = NaiveDateTime::default();
+        let event = EventModel {
+            sequence_number: 0,
+            creation_number: 0,
+            account_address: "0x1".to_string(),
+            transaction_version: 42,
+            transaction_block_height: 7,
+            event_type: EventType::try_from(event_type)?,
+            data: serde_json::json!({
+                "price": "100",
+                "seller": "0x2",
+                "token_metadata": {
+                    "token": { "vec": [{ "inner": "0x3" }] }
+                }
+            }),
+            event_index: 0,
+            block_timestamp: txn_timestamp,
+        };
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            ..
+        } = remapper.remap_event_models(&[event], txn_timestamp)?;
+
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].txn_version, 42);
+        assert_eq!(activities[0].block_timestamp, txn_timestamp);
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].price, BigDecimal::from(100i64));
+        assert!(token_offers.is_empty());
+        assert!(collection_offers.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_remap_of_10k_events_matches_sequential() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut config = create_marketplace_config(
+            event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let txn_timestamp = NaiveDateTime::default();
+        // Newest first, so the output order can't just be the input order
+        let events = (0..10_000i64)
+            .rev()
+            .map(|i| {
+                Ok(EventModel {
+                    sequence_number: i,
+                    creation_number: 0,
+                    account_address: "0x1".to_string(),
+                    transaction_version: 1 + i / 100,
+                    transaction_block_height: 1,
+                    event_type: EventType::try_from(event_type)?,
+                    data: serde_json::json!({
+                        "price": (i + 1).to_string(),
+                        "seller": "0x2",
+                        "token_metadata": {
+                            "token": { "vec": [{ "inner": format!("{:#x}", i + 1) }] }
+                        }
+                    }),
+                    event_index: i % 100,
+                    block_timestamp: txn_timestamp,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let sequential = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities: sequential_activities,
+            listings: sequential_listings,
+            ..
+        } = sequential.remap_event_models(&events, txn_timestamp)?;
+
+        config.parallel_remap = true;
+        let parallel = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = parallel.remap_event_models(&events, txn_timestamp)?;
+
+        assert_eq!(activities.len(), 10_000);
+        assert!(activities.windows(2).all(
+            |pair| (pair[0].txn_version, pair[0].index) < (pair[1].txn_version, pair[1].index)
+        ));
+        let keys = |activities: &[NftMarketplaceActivity]| {
+            activities
+                .iter()
+                .map(|activity| (activity.txn_version, activity.index, activity.price.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&activities), keys(&sequential_activities));
+        // Current models keep the events' order, the reduction takes the last one per key
+        let token_data_ids = |listings: &[CurrentNFTMarketplaceListing]| {
+            listings
+                .iter()
+                .map(|listing| listing.token_data_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            token_data_ids(&listings),
+            token_data_ids(&sequential_listings)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_place_then_update_listing_changes_price_and_keeps_listing_open() -> Result<()> {
+        let place_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let update_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPriceUpdatedEvent";
+        let mut config = create_marketplace_config(
+            place_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let update_config = create_marketplace_config(
+            update_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::UpdateListing,
+        );
+        config.events.extend(update_config.events);
+        config
+            .event_model_mapping
+            .extend(update_config.event_model_mapping);
+        let remapper = EventRemapper::new(&config)?;
+
+        let mut accumulator = NFTAccumulator::default();
+        for (event_type, price) in [(place_type, "100"), (update_type, "150")] {
+            let event_data = serde_json::json!({
+                "price": price,
+                "seller": "0x2",
+                "token_metadata": {
+                    "token": { "vec": [{ "inner": "0x3" }] }
+                }
+            });
+            let RemapOutput {
+                activities,
+                listings,
+                ..
+            } = remapper.remap_events(create_transaction(event_type, event_data))?;
+            assert_eq!(activities.len(), 1);
+            for listing in listings {
+                accumulator.fold_listing(listing);
+            }
+        }
+
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].price, BigDecimal::from(150));
+        assert!(!listings[0].is_deleted);
+        assert_eq!(listings[0].standard_event_type, "update_listing");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_data_is_null_without_store_raw_json() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut config = create_marketplace_config(
+            event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            }
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput { activities, .. } =
+            remapper.remap_events(create_transaction(event_type, event_data.clone()))?;
+        assert_eq!(activities[0].json_data["data"], event_data);
+
+        config.store_raw_json = false;
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+        assert_eq!(activities[0].json_data, serde_json::Value::Null);
+        assert_eq!(activities[0].price, BigDecimal::from(100));
+        assert_eq!(listings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allowed_contracts_filters_events_from_other_contracts() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let config = NFTMarketplaceConfig {
+            allowed_contracts: vec!["0x1".to_string()],
+            ..create_marketplace_config(
+                event_type,
+                create_listing_field_mappings(),
+                MarketplaceEventType::PlaceListing,
+            )
+        };
+        let txn_timestamp = NaiveDateTime::default();
+        let build_event = |account_address: &str, event_index: i64| -> Result<EventModel> {
+            Ok(EventModel {
+                sequence_number: 0,
+                creation_number: 0,
+                account_address: standardize_address(account_address)?,
+                transaction_version: 42,
+                transaction_block_height: 7,
+                event_type: EventType::try_from(event_type)?,
+                data: serde_json::json!({
+                    "price": "100",
+                    "seller": "0x2",
+                    "token_metadata": {
+                        "token": { "vec": [{ "inner": "0x3" }] }
+                    }
+                }),
+                event_index,
+                block_timestamp: txn_timestamp,
+            })
+        };
+        let allowed_event = build_event("0x1", 0)?;
+        let disallowed_event = build_event("0x4", 1)?;
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_event_models(&[allowed_event, disallowed_event], txn_timestamp)?;
+
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].index, 0);
+        assert_eq!(activities[0].contract_address, standardize_address("0x1")?);
+        assert_eq!(listings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_network_selects_testnet_contract_addresses() -> Result<()> {
+        const MAINNET_CONTRACT: &str =
+            "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9";
+        const TESTNET_CONTRACT: &str = "0x77";
+        let config_event_type = format!("{MAINNET_CONTRACT}::events::ListingPlacedEvent");
+        let mut config = NFTMarketplaceConfig {
+            allowed_contracts: vec![MAINNET_CONTRACT.to_string()],
+            contract_address_overrides: HashMap::from([(
+                Network::Testnet,
+                HashMap::from([(MAINNET_CONTRACT.to_string(), TESTNET_CONTRACT.to_string())]),
+            )]),
+            ..create_marketplace_config(
+                &config_event_type,
+                create_listing_field_mappings(),
+                MarketplaceEventType::PlaceListing,
+            )
+        };
+        let txn_timestamp = NaiveDateTime::default();
+        let build_event = |contract: &str| -> Result<EventModel> {
+            Ok(EventModel {
+                sequence_number: 0,
+                creation_number: 0,
+                account_address: standardize_address(contract)?,
+                transaction_version: 42,
+                transaction_block_height: 7,
+                event_type: EventType::try_from(
+                    format!("{contract}::events::ListingPlacedEvent").as_str(),
+                )?,
+                data: serde_json::json!({
+                    "price": "100",
+                    "seller": "0x2",
+                    "token_metadata": {
+                        "token": { "vec": [{ "inner": "0x3" }] }
+                    }
+                }),
+                event_index: 0,
+                block_timestamp: txn_timestamp,
+            })
+        };
+        let events = [
+            build_event(MAINNET_CONTRACT)?,
+            build_event(TESTNET_CONTRACT)?,
+        ];
+
+        config.network = Some(Network::Testnet);
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = EventRemapper::new(&config)?.remap_event_models(&events[..1], txn_timestamp)?;
+        assert!(activities.is_empty());
+        assert!(listings.is_empty());
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = EventRemapper::new(&config)?.remap_event_models(&events[1..], txn_timestamp)?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(
+            activities[0].contract_address,
+            standardize_address(TESTNET_CONTRACT)?
+        );
+        assert_eq!(listings.len(), 1);
+
+        // The same file on mainnet keeps the addresses it's written with
+        config.network = Some(Network::Mainnet);
+        let RemapOutput { activities, .. } =
+            EventRemapper::new(&config)?.remap_event_models(&events[..1], txn_timestamp)?;
+        assert_eq!(activities.len(), 1);
+        let RemapOutput { activities, .. } =
+            EventRemapper::new(&config)?.remap_event_models(&events[1..], txn_timestamp)?;
+        assert!(activities.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redeployed_contract_maps_to_canonical_marketplace() -> Result<()> {
+        let v1_event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let v2_event_type = "0x684b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let build_config = |name: &str, event_type: &str| NFTMarketplaceConfig {
+            name: name.to_string(),
+            canonical_name: Some("wapal".to_string()),
+            ..create_marketplace_config(
+                event_type,
+                create_listing_field_mappings(),
+                MarketplaceEventType::PlaceListing,
+            )
+        };
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            }
+        });
+
+        let mut marketplaces = Vec::new();
+        for (config, event_type) in [
+            (build_config("wapal", v1_event_type), v1_event_type),
+            (build_config("wapal_v2", v2_event_type), v2_event_type),
+        ] {
+            let remapper = EventRemapper::new(&config)?;
+            let RemapOutput {
+                activities,
+                listings,
+                ..
+            } = remapper.remap_events(create_transaction(event_type, event_data.clone()))?;
+            assert_eq!(activities.len(), 1);
+            assert_eq!(listings.len(), 1);
+            marketplaces.push((
+                activities[0].marketplace.clone(),
+                listings[0].token_data_id.clone(),
+                listings[0].marketplace.clone(),
+            ));
+        }
+
+        // Both contracts' listings of the token share a primary key
+        assert_eq!(marketplaces[0], marketplaces[1]);
+        assert_eq!(marketplaces[0].0, "wapal");
+        assert_eq!(marketplaces[0].2, "wapal");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transform_is_applied_after_extraction() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.token_name_bytes".to_string(), vec![DbColumn {
+            transform: Some("bcs_string".parse()?),
+            ..create_db_column("nft_marketplace_activities", "token_name")
+        }]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            },
+            "token_name_bytes": "0x05546f6b656e"
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput { activities, .. } =
+            remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].token_name.as_deref(), Some("Token"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bcs_fields_are_decoded_by_move_type() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut fields = create_listing_field_mappings();
+        // The name and price come as BCS bytes, e.g. `vector<u8>` fields of the event
+        fields.insert("$.token_name_bcs".to_string(), vec![
+            DbColumn {
+                transform: Some("bcs:0x1::string::String".parse()?),
+                ..create_db_column("nft_marketplace_activities", "token_name")
+            },
+            DbColumn {
+                transform: Some("bcs:0x1::string::String".parse()?),
+                ..create_db_column("current_nft_marketplace_listings", "token_name")
+            },
+        ]);
+        fields.insert("$.price".to_string(), vec![
+            DbColumn {
+                transform: Some("bcs:u64".parse()?),
+                ..create_db_column("nft_marketplace_activities", "price")
+            },
+            DbColumn {
+                transform: Some("bcs:u64".parse()?),
+                ..create_db_column("current_nft_marketplace_listings", "price")
+            },
+        ]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        let event_data = serde_json::json!({
+            "price": "0x00e1f50500000000",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            },
+            "token_name_bcs": "0x0b546f6b656e2023f09f8c8a"
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        assert_eq!(activities[0].token_name.as_deref(), Some("Token #🌊"));
+        assert_eq!(activities[0].price, BigDecimal::from(100_000_000));
+        assert_eq!(listings[0].token_name.as_deref(), Some("Token #🌊"));
+        assert_eq!(listings[0].price, BigDecimal::from(100_000_000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_standardize_is_applied_per_column() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.seller".to_string(), vec![
+            DbColumn {
+                standardize: true,
+                ..create_db_column("current_nft_marketplace_listings", "seller")
+            },
+            create_db_column("nft_marketplace_activities", "seller"),
+        ]);
+        // A name that looks like hex is stored as it is
+        fields.insert("$.token_name".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "token_name",
+        )]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            },
+            "token_name": "0xcafe"
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        assert_eq!(listings[0].seller, Some(standardize_address("0x2")?));
+        assert_eq!(activities[0].seller.as_deref(), Some("0x2"));
+        assert_eq!(activities[0].token_name.as_deref(), Some("0xcafe"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_activity_column_is_stored_in_custom_fields() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.royalty_bps".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "royalty_bps"),
+            create_db_column("current_nft_marketplace_listings", "royalty_bps"),
+        ]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            },
+            "royalty_bps": 250
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        assert_eq!(activities.len(), 1);
+        assert_eq!(
+            activities[0].custom_fields,
+            Some(serde_json::json!({ "royalty_bps": "250" }))
+        );
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].price, BigDecimal::from(100));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_txn_paths_map_transaction_fields() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$txn.sender".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "buyer",
+        )]);
+        fields.insert("$txn.entry_function".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "entry_function",
+        )]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        config.validate_config()?;
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            }
+        });
+        let mut transaction = create_transaction(event_type, event_data);
+        if let Some(TxnData::User(user_txn)) = transaction.txn_data.as_mut() {
+            user_txn.request = Some(UserTransactionRequest {
+                sender: "0xa11ce".to_string(),
+                payload: Some(TransactionPayload {
+                    payload: Some(Payload::EntryFunctionPayload(EntryFunctionPayload {
+                        entry_function_id_str: "0x1::marketplace::list".to_string(),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        }
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(transaction)?;
+
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].buyer, Some(standardize_address("0xa11ce")?));
+        assert_eq!(
+            activities[0].custom_fields,
+            Some(serde_json::json!({ "entry_function": "0x1::marketplace::list" }))
+        );
+        assert_eq!(listings.len(), 1);
+
+        // Without the transaction the paths resolve to nothing
+        let event = EventModel {
+            sequence_number: 0,
+            creation_number: 0,
+            account_address: standardize_address("0x1")?,
+            transaction_version: 1,
+            transaction_block_height: 1,
+            event_type: EventType::try_from(event_type)?,
+            data: serde_json::json!({
+                "price": "100",
+                "seller": "0x2",
+                "token_metadata": {
+                    "token": { "vec": [{ "inner": "0x3" }] }
+                }
+            }),
+            event_index: 0,
+            block_timestamp: NaiveDateTime::default(),
+        };
+        let RemapOutput { activities, .. } =
+            remapper.remap_event_models(&[event], NaiveDateTime::default())?;
+        assert_eq!(activities[0].buyer, None);
+        assert!(activities[0].custom_fields.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_matches_stores_every_wildcard_match() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.royalties[*].recipient".to_string(), vec![DbColumn {
+            all_matches: true,
+            standardize: true,
+            ..create_db_column("nft_marketplace_activities", "royalty_recipients")
+        }]);
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        config.validate_config()?;
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0x2",
+            "token_metadata": {
+                "token": { "vec": [{ "inner": "0x3" }] }
+            },
+            "royalties": [
+                { "recipient": "0x4", "bps": 100 },
+                { "recipient": "0x5", "bps": 150 }
+            ]
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        assert_eq!(
+            activities[0].custom_fields,
+            Some(serde_json::json!({
+                "royalty_recipients": [standardize_address("0x4")?, standardize_address("0x5")?]
+            }))
+        );
+        // The listing still fans out on its own paths only
+        assert_eq!(listings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_standard_of_v1_event_with_token_metadata() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingFilledEvent";
+        let mut fields = create_listing_field_mappings();
+        for column in ["creator_address", "collection_name", "token_name"] {
+            fields.insert(format!("$.token_metadata.{column}"), vec![
+                create_db_column("nft_marketplace_activities", column),
+            ]);
+        }
+        let mut config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::FillListing);
+        // A v1 token: the token option is empty and the property version is set
+        let event_data = serde_json::json!({
+            "price": "397900000",
+            "seller": "0xc24b9ea285e953bbf7470309b879bc60d5a68e71e2f2c17be047c3f74664e82b",
+            "token_metadata": {
+                "collection": { "vec": [] },
+                "collection_name": "Bruh Bears",
+                "creator_address": "0x43ec2cb158e3569842d537740fd53403e992b9e7349cc5d3dfaa5aff8faaef2",
+                "property_version": { "vec": ["1"] },
+                "token": { "vec": [] },
+                "token_name": "Bruh Bear #4084"
+            }
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data.clone()))?;
+        assert_eq!(activities[0].token_standard.as_deref(), Some("v1"));
+        assert_eq!(listings[0].token_standard.as_deref(), Some("v1"));
+
+        // The standard set in config takes precedence over detection
+        config.events.get_mut(event_type).unwrap().token_standard = Some(TokenStandard::V2);
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+        assert_eq!(activities[0].token_standard.as_deref(), Some("v2"));
+        assert_eq!(listings[0].token_standard.as_deref(), Some("v2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_instant_sale_event_produces_fill_activity_and_deleted_listing() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::InstantSaleEvent";
+        let event_data = serde_json::json!({
+            "price": "3400000000",
+            "buyer": "0x22113f16f9b7c6761ef14df757c016b8736a9023e8881cd5e11579b0b98ef562",
+            "seller": "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14",
+            "token_metadata": {
+                "token": {
+                    "vec": [
+                        {
+                            "inner": "0xc821b5c1712fca97553c85830b91dc212cd2fcdd2a2490b65f945ed901d9f126"
+                        }
+                    ]
+                }
+            },
+            "token_offer": "0x9d14c489b6f56ac55e8707022400c23bb83bd0b0cd486c862defccf6241a219e"
+        });
+        let mut fields = create_listing_field_mappings();
+        fields.insert("$.buyer".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "buyer",
+        )]);
+        let config = NFTMarketplaceConfig {
+            event_model_mapping: HashMap::new(),
+            combined_event_model_mapping: HashMap::from([(event_type.to_string(), vec![
+                MarketplaceEventType::PlaceListing,
+                MarketplaceEventType::FillListing,
+            ])]),
+            ..create_marketplace_config(event_type, fields, MarketplaceEventType::Unknown)
+        };
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            token_offers,
+            collection_offers,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        assert_eq!(activities.len(), 1, "Should have one activity");
+        assert_eq!(
+            activities[0].standard_event_type,
+            MarketplaceEventType::FillListing.to_string()
+        );
+        assert_eq!(
+            activities[0].buyer.as_deref(),
+            Some("0x22113f16f9b7c6761ef14df757c016b8736a9023e8881cd5e11579b0b98ef562")
+        );
+        assert_eq!(listings.len(), 1, "Should have one listing");
+        assert!(listings[0].is_deleted);
+        assert_eq!(
+            listings[0].listing_id.as_deref(),
+            Some("0x9d14c489b6f56ac55e8707022400c23bb83bd0b0cd486c862defccf6241a219e")
+        );
+        assert!(token_offers.is_empty());
+        assert!(collection_offers.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_listing_event_fans_out_into_one_listing_per_token() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::BundleListingPlacedEvent";
+        let creator = "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14";
+        let event_data = serde_json::json!({
+            "seller": "0x22113f16f9b7c6761ef14df757c016b8736a9023e8881cd5e11579b0b98ef562",
+            "creator_address": creator,
+            "collection_name": "Bundle Collection",
+            "tokens": ["Token #1", "Token #2", "Token #3"],
+            "prices": ["100", "200", "300"]
+        });
+        let mut fields = HashMap::new();
+        fields.insert("$.seller".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "seller"),
+            create_db_column("current_nft_marketplace_listings", "seller"),
+        ]);
+        fields.insert("$.creator_address".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "creator_address",
+        )]);
+        fields.insert("$.collection_name".to_string(), vec![create_db_column(
+            "nft_marketplace_activities",
+            "collection_name",
+        )]);
+        fields.insert("$.tokens".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "token_name"),
+            create_db_column("current_nft_marketplace_listings", "token_name"),
+        ]);
+        fields.insert("$.prices".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "price"),
+            create_db_column("current_nft_marketplace_listings", "price"),
+        ]);
+        let mut config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceListing);
+        config.events.get_mut(event_type).unwrap().fan_out_arrays = true;
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+
+        assert_eq!(
+            activities.len(),
+            1,
+            "A bundle should record a single activity"
+        );
+        assert_eq!(listings.len(), 3, "Should have one listing per token");
+        for (i, listing) in listings.iter().enumerate() {
+            let token_name = format!("Token #{}", i + 1);
+            assert_eq!(
+                listing.token_data_id,
+                build_test_token_data_id(creator, "Bundle Collection", &token_name)
+            );
+            assert_eq!(listing.token_name.as_deref(), Some(token_name.as_str()));
+            assert_eq!(listing.price, BigDecimal::from((i as i64 + 1) * 100));
+            assert_eq!(
+                listing.seller.as_deref(),
+                Some("0x22113f16f9b7c6761ef14df757c016b8736a9023e8881cd5e11579b0b98ef562")
+            );
+        }
+        assert_eq!(
+            activities[0].token_data_id,
+            Some(listings[0].token_data_id.clone())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_offer_partial_fill_keeps_remaining_amount() -> Result<()> {
+        let place_event_type = "0x1::collection_offer::CollectionOfferPlacedEvent";
+        let fill_event_type = "0x1::collection_offer::CollectionOfferFilledEvent";
+        let mut fields = HashMap::new();
+        fields.insert("$.offer_id".to_string(), vec![
+            create_db_column("nft_marketplace_activities", "offer_id"),
+            create_db_column(
+                "current_nft_marketplace_collection_offers",
+                "collection_offer_id",
+            ),
+        ]);
+        fields.insert("$.buyer".to_string(), vec![create_db_column(
+            "current_nft_marketplace_collection_offers",
+            "buyer",
+        )]);
+        fields.insert("$.price".to_string(), vec![create_db_column(
+            "current_nft_marketplace_collection_offers",
+            "price",
+        )]);
+        fields.insert("$.remaining".to_string(), vec![create_db_column(
+            "current_nft_marketplace_collection_offers",
+            "remaining_token_amount",
+        )]);
+        let mut config = create_marketplace_config(
+            place_event_type,
+            fields.clone(),
+            MarketplaceEventType::PlaceCollectionOffer,
+        );
+        config
+            .events
+            .insert(fill_event_type.to_string(), EventRemapping {
+                event_fields: fields,
+                ..Default::default()
+            });
+        config.event_model_mapping.insert(
+            fill_event_type.to_string(),
+            MarketplaceEventType::FillCollectionOffer,
+        );
+        let remapper = EventRemapper::new(&config)?;
+        let build_event = |event_type: &str, version: i64, remaining: &str| -> Result<EventModel> {
+            Ok(EventModel {
+                sequence_number: 0,
+                creation_number: 0,
+                account_address: "0x1".to_string(),
+                transaction_version: version,
+                transaction_block_height: version,
+                event_type: EventType::try_from(event_type)?,
+                data: serde_json::json!({
+                    "offer_id": "0x5",
+                    "buyer": "0x2",
+                    "price": "100",
+                    "remaining": remaining,
+                }),
+                event_index: 0,
+                block_timestamp: NaiveDateTime::default(),
+            })
+        };
+
+        let mut accumulator = NFTAccumulator::default();
+        for event in [
+            build_event(place_event_type, 1, "5")?,
+            build_event(fill_event_type, 2, "3")?,
+        ] {
+            let RemapOutput {
+                collection_offers, ..
+            } = remapper.remap_event_models(&[event], NaiveDateTime::default())?;
+            collection_offers
+                .into_iter()
+                .for_each(|offer| accumulator.fold_collection_offer(offer));
+        }
+        let RemapOutput {
+            collection_offers, ..
+        } = accumulator.drain();
+        assert_eq!(collection_offers.len(), 1);
+        assert_eq!(collection_offers[0].last_transaction_version, 2);
+        assert_eq!(
+            collection_offers[0].remaining_token_amount,
+            Some(BigDecimal::from(3))
+        );
+        assert!(!collection_offers[0].is_deleted);
+
+        // Filling the last tokens closes the offer
+        let RemapOutput {
+            collection_offers, ..
+        } = remapper.remap_event_models(
+            &[build_event(fill_event_type, 3, "0")?],
+            NaiveDateTime::default(),
+        )?;
+        assert_eq!(
+            collection_offers[0].remaining_token_amount,
+            Some(BigDecimal::from(0))
+        );
+        assert!(collection_offers[0].is_deleted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_enabled_event_types_skip_other_events() -> Result<()> {
+        let place_event_type = "0x1::listing::ListingPlacedEvent";
+        let fill_event_type = "0x1::listing::ListingFilledEvent";
+        let mut config = create_marketplace_config(
+            place_event_type,
+            create_listing_field_mappings(),
+            MarketplaceEventType::PlaceListing,
+        );
+        config
+            .events
+            .insert(fill_event_type.to_string(), EventRemapping {
+                event_fields: create_listing_field_mappings(),
+                ..Default::default()
+            });
+        config.event_model_mapping.insert(
+            fill_event_type.to_string(),
+            MarketplaceEventType::FillListing,
+        );
+        config.enabled_event_types = vec![MarketplaceEventType::FillListing];
+        let remapper = EventRemapper::new(&config)?;
+        let event_data = serde_json::json!({
+            "price": "100",
+            "seller": "0xc60f124dc24f4ea97232bc5ead5f37252b7cbee47f48ef05932998050c414d14",
+            "token_metadata": {
+                "token": {
+                    "vec": [
+                        {
+                            "inner": "0xc821b5c1712fca97553c85830b91dc212cd2fcdd2a2490b65f945ed901d9f126"
+                        }
+                    ]
+                }
+            }
+        });
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(place_event_type, event_data.clone()))?;
+        assert!(activities.is_empty());
+        assert!(listings.is_empty());
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(fill_event_type, event_data))?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].standard_event_type, "fill_listing");
+        assert_eq!(listings.len(), 1);
+        assert!(listings[0].is_deleted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listing_partial_fill_keeps_remaining_amount() -> Result<()> {
+        let place_event_type = "0x1::listing::ListingPlacedEvent";
+        let fill_event_type = "0x1::listing::ListingFilledEvent";
+        let mut place_fields = HashMap::new();
+        place_fields.insert("$.token".to_string(), vec![create_db_column(
+            "current_nft_marketplace_listings",
+            "token_data_id",
+        )]);
+        place_fields.insert("$.price".to_string(), vec![create_db_column(
+            "current_nft_marketplace_listings",
+            "price",
+        )]);
+        let mut fill_fields = place_fields.clone();
+        place_fields.insert("$.amount".to_string(), vec![create_db_column(
+            "current_nft_marketplace_listings",
+            "token_amount",
+        )]);
+        fill_fields.insert("$.amount".to_string(), vec![create_db_column(
+            "current_nft_marketplace_listings",
+            "filled_token_amount",
+        )]);
+        let mut config = create_marketplace_config(
+            place_event_type,
+            place_fields,
+            MarketplaceEventType::PlaceListing,
+        );
+        config
+            .events
+            .insert(fill_event_type.to_string(), EventRemapping {
+                event_fields: fill_fields,
+                ..Default::default()
+            });
+        config.event_model_mapping.insert(
+            fill_event_type.to_string(),
+            MarketplaceEventType::FillListing,
+        );
+        let remapper = EventRemapper::new(&config)?;
+        let build_event = |event_type: &str, version: i64, amount: &str| -> Result<EventModel> {
+            Ok(EventModel {
+                sequence_number: 0,
+                creation_number: 0,
+                account_address: "0x1".to_string(),
+                transaction_version: version,
+                transaction_block_height: version,
+                event_type: EventType::try_from(event_type)?,
+                data: serde_json::json!({
+                    "token": "0x5",
+                    "price": "100",
+                    "amount": amount,
+                }),
+                event_index: 0,
+                block_timestamp: NaiveDateTime::default(),
+            })
+        };
+
+        let mut accumulator = NFTAccumulator::default();
+        for event in [
+            build_event(place_event_type, 1, "5")?,
+            build_event(fill_event_type, 2, "2")?,
+        ] {
+            let RemapOutput { listings, .. } =
+                remapper.remap_event_models(&[event], NaiveDateTime::default())?;
+            listings
+                .into_iter()
+                .for_each(|listing| accumulator.fold_listing(listing));
+        }
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].last_transaction_version, 2);
+        assert_eq!(listings[0].token_amount, Some(BigDecimal::from(3)));
+        assert_eq!(listings[0].filled_token_amount, Some(BigDecimal::from(2)));
+        assert!(!listings[0].is_deleted);
+
+        // Filling the rest closes the listing
+        accumulator.fold_listing(listings[0].clone());
+        let RemapOutput { listings, .. } = remapper.remap_event_models(
+            &[build_event(fill_event_type, 3, "3")?],
+            NaiveDateTime::default(),
+        )?;
+        listings
+            .into_iter()
+            .for_each(|listing| accumulator.fold_listing(listing));
+        let RemapOutput { listings, .. } = accumulator.drain();
+        assert_eq!(listings[0].token_amount, Some(BigDecimal::from(0)));
+        assert!(listings[0].is_deleted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_auction_bid_and_settle_events() -> Result<()> {
+        let bid_event_type = "0x1::auction::BidEvent";
+        let settle_event_type = "0x1::auction::SettleEvent";
+        let mut fields = HashMap::new();
+        fields.insert("$.token".to_string(), vec![
+            create_db_column("current_nft_marketplace_auctions", "token_data_id"),
+            create_db_column("nft_marketplace_activities", "token_data_id"),
+        ]);
+        fields.insert("$.starting_price".to_string(), vec![create_db_column(
+            "current_nft_marketplace_auctions",
+            "starting_price",
+        )]);
+        fields.insert("$.bid".to_string(), vec![create_db_column(
+            "current_nft_marketplace_auctions",
+            "current_bid",
+        )]);
+        fields.insert("$.bidder".to_string(), vec![create_db_column(
+            "current_nft_marketplace_auctions",
+            "buyer",
+        )]);
+        fields.insert("$.end_time".to_string(), vec![create_db_column(
+            "current_nft_marketplace_auctions",
+            "end_time",
+        )]);
+        let mut config = create_marketplace_config(
+            bid_event_type,
+            fields.clone(),
+            MarketplaceEventType::BidAuction,
+        );
+        config
+            .events
+            .insert(settle_event_type.to_string(), EventRemapping {
+                event_fields: fields,
+                ..Default::default()
+            });
+        config.event_model_mapping.insert(
+            settle_event_type.to_string(),
+            MarketplaceEventType::SettleAuction,
+        );
+        let remapper = EventRemapper::new(&config)?;
+
+        let event_data = serde_json::json!({
+            "token": "0x3",
+            "starting_price": "1000",
+            "bid": "750",
+            "bidder": "0x2",
+            "end_time": "1700000000"
+        });
+        let RemapOutput {
+            activities,
+            auctions,
+            ..
+        } = remapper.remap_events(create_transaction(bid_event_type, event_data.clone()))?;
+        assert_eq!(
+            activities[0].standard_event_type,
+            MarketplaceEventType::BidAuction.to_string()
+        );
+        assert_eq!(auctions.len(), 1);
+        let auction = &auctions[0];
+        assert_eq!(auction.token_data_id, "0x3");
+        assert_eq!(auction.starting_price, Some(BigDecimal::from(1000)));
+        assert_eq!(auction.current_bid, Some(BigDecimal::from(750)));
+        assert_eq!(auction.current_bidder.as_deref(), Some("0x2"));
+        assert_eq!(
+            auction.end_time.map(|ts| ts.and_utc().timestamp()),
+            Some(1700000000)
+        );
+        assert!(!auction.is_deleted);
+
+        let RemapOutput { auctions, .. } =
+            remapper.remap_events(create_transaction(settle_event_type, event_data))?;
+        assert_eq!(auctions.len(), 1);
+        assert!(auctions[0].is_deleted);
+        assert_eq!(
+            auctions[0].standard_event_type,
+            MarketplaceEventType::SettleAuction.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_collection_offer_id_differs_per_collection() {
+        let offer_id = |collection_id: &str| {
+            generate_collection_offer_id(
+                Some("0x1".to_string()),
+                Some(collection_id.to_string()),
+                Some("0x2".to_string()),
+                None,
+            )
+        };
+
+        let first_offer_id = offer_id("0xa").unwrap();
+        let second_offer_id = offer_id("0xb").unwrap();
+        assert_ne!(first_offer_id, second_offer_id);
+        assert_eq!(offer_id("0xa").unwrap(), first_offer_id);
+
+        assert!(generate_collection_offer_id(
+            Some("0x1".to_string()),
+            None,
+            Some("0x2".to_string()),
+            None,
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_generate_token_data_id_skips_over_length_creator() {
+        let creator = format!("0x{}", "a".repeat(65));
+        let token_data_id = generate_token_data_id(
+            Some(creator),
+            Some("collection".to_string()),
+            Some("token".to_string()),
+        );
+        assert!(token_data_id.is_none());
+
+        let token_data_id = generate_token_data_id(
+            Some("0x1".to_string()),
+            Some("collection".to_string()),
+            Some("token".to_string()),
+        );
+        assert_eq!(
+            token_data_id,
+            Some(build_test_token_data_id("0x1", "collection", "token"))
+        );
+    }
+
+    #[test]
+    fn test_token_data_id_ignores_property_version() -> Result<()> {
+        // Bruh Bear #4084, filled on Wapal at version 2382221134 with property version 1. Its
+        // token data id on chain hashes only the creator, collection and name.
+        let creator = "0x43ec2cb158e3569842d537740fd53403e992b9e7349cc5d3dfaa5aff8faaef2";
+        let on_chain_token_data_id =
+            "0x74c366102a0abbc452f44c5017c69d99d52acc8140f7c35f141b09051a4891fa";
+        let expected = Some(on_chain_token_data_id.to_string());
+        assert_eq!(
+            generate_token_data_id(
+                Some(creator.to_string()),
+                Some("Bruh Bears".to_string()),
+                Some("Bruh Bear #4084".to_string()),
+            ),
+            expected
+        );
+
+        // The property version read from the event type's path leaves the id alone
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingFilledEvent";
+        let mut fields = HashMap::new();
+        for (path, column) in [
+            ("$.token_metadata.creator_address", "creator_address"),
+            ("$.token_metadata.collection_name", "collection_name"),
+            ("$.token_metadata.token_name", "token_name"),
+        ] {
+            fields.insert(path.to_string(), vec![create_db_column(
+                "nft_marketplace_activities",
+                column,
+            )]);
+        }
+        let mut config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::FillListing);
+        let event_remapping = config.events.get_mut(event_type).unwrap();
+        event_remapping.property_version_path =
+            Some("$.token_metadata.property_version.vec[0]".to_string());
+        let event_data = serde_json::json!({
+            "token_metadata": {
+                "creator_address": creator,
+                "collection_name": "Bruh Bears",
+                "token_name": "Bruh Bear #4084",
+                "property_version": { "vec": ["1"] }
+            }
+        });
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, event_data))?;
+        assert_eq!(activities[0].token_data_id, expected);
+        assert_eq!(Some(listings[0].token_data_id.clone()), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_v1_token_offer_stores_property_version() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::TokenOfferPlacedEvent";
+        let creator = "0xee814d743d2c3b4b1b8b30f3e0c84c7017df3154bda84c31958785f1d5b70e61";
+        let mut fields = HashMap::new();
+        for (path, column) in [
+            ("$.token_metadata.creator_address", "creator_address"),
+            ("$.token_metadata.collection_name", "collection_name"),
+            ("$.token_metadata.token_name", "token_name"),
+            ("$.purchaser", "buyer"),
+            ("$.price", "price"),
+        ] {
+            fields.insert(path.to_string(), vec![
+                create_db_column("nft_marketplace_activities", column),
+                create_db_column("current_nft_marketplace_token_offers", column),
+            ]);
+        }
+        fields.insert(
+            "$.token_metadata.property_version.vec[0]".to_string(),
+            vec![create_db_column(
+                "current_nft_marketplace_token_offers",
+                "property_version",
+            )],
+        );
+        let config =
+            create_marketplace_config(event_type, fields, MarketplaceEventType::PlaceTokenOffer);
+        let build_event_data = |property_version: &str| {
+            serde_json::json!({
+                "price": "25000000",
+                "purchaser": "0x62928b3712d452190346090807d5cfb40dabb54740cf1d2acfc5b4d3d9e0b370",
+                "token_metadata": {
+                    "collection": { "vec": [] },
+                    "collection_name": "Aptos Dogs",
+                    "creator_address": creator,
+                    "property_version": { "vec": [property_version] },
+                    "token": { "vec": [] },
+                    "token_name": "AptosDogs #1596"
+                }
+            })
+        };
+
+        let remapper = EventRemapper::new(&config)?;
+        let RemapOutput {
+            activities,
+            token_offers,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, build_event_data("3")))?;
+        let expected = generate_token_data_id(
+            Some(creator.to_string()),
+            Some("Aptos Dogs".to_string()),
+            Some("AptosDogs #1596".to_string()),
+        );
+        assert_eq!(token_offers[0].property_version, Some(BigDecimal::from(3)));
+        assert_eq!(token_offers[0].offer_key, "3");
+        assert_eq!(Some(token_offers[0].token_data_id.clone()), expected);
+        assert_eq!(activities[0].token_data_id, expected);
+
+        // An offer on property version 0 of the same token data is a separate offer
+        let RemapOutput {
+            token_offers: first_edition_offers,
+            ..
+        } = remapper.remap_events(create_transaction(event_type, build_event_data("0")))?;
+        assert_eq!(
+            first_edition_offers[0].property_version,
+            Some(BigDecimal::from(0))
+        );
+        assert_eq!(
+            Some(first_edition_offers[0].token_data_id.clone()),
+            expected
+        );
+        assert_ne!(
+            first_edition_offers[0].primary_key(),
+            token_offers[0].primary_key()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_offer_takes_v2_collection_inner() -> Result<()> {
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::CollectionOfferPlacedEvent";
+        let collection = "0xa2485c3b392d211770ed161e73a1097d21016c7dd41f53592434380b2aa14cba";
+        let creator = "0xf54f8f7ffc2b779d81b721b3d42fe9a53f96e1d3459a8001934307783d493725";
+        let purchaser = "0x22113f16f9b7c6761ef14df757c016b8736a9023e8881cd5e11579b0b98ef562";
+        let build_event_data = |collection_inner: serde_json::Value| {
+            serde_json::json!({
+                "collection_metadata": {
+                    "collection": { "vec": collection_inner },
+                    "collection_name": "The Loonies",
+                    "creator_address": creator
+                },
+                "price": "3000000000",
+                "purchaser": purchaser,
+                "token_amount": "2"
+            })
+        };
+
+        let mut fields = HashMap::new();
+        for (path, column) in [
+            ("$.collection_metadata.creator_address", "creator_address"),
+            ("$.collection_metadata.collection_name", "collection_name"),
+            ("$.purchaser", "buyer"),
+            ("$.price", "price"),
+        ] {
+            fields.insert(path.to_string(), vec![
+                create_db_column("nft_marketplace_activities", column),
+                create_db_column("current_nft_marketplace_collection_offers", column),
+            ]);
+        }
+        let mut config = create_marketplace_config(
+            event_type,
+            fields,
+            MarketplaceEventType::PlaceCollectionOffer,
+        );
+        config
+            .events
+            .get_mut(event_type)
+            .unwrap()
+            .collection_inner_path =
+            Some("$.collection_metadata.collection.vec[0].inner".to_string());
+        let remapper = EventRemapper::new(&config)?;
+
+        let RemapOutput {
+            activities,
+            collection_offers,
+            ..
+        } = remapper.remap_events(create_transaction(
+            event_type,
+            build_event_data(serde_json::json!([{ "inner": collection }])),
+        ))?;
+        assert_eq!(collection_offers.len(), 1);
+        assert_eq!(
+            collection_offers[0].collection_id.as_deref(),
+            Some(collection)
+        );
+        assert_eq!(activities[0].collection_id.as_deref(), Some(collection));
+        // The offer id is derived from the collection's address too
+        let expected_offer_id = generate_collection_offer_id(
+            Some(creator.to_string()),
+            Some(collection.to_string()),
+            Some(purchaser.to_string()),
+            None,
+        );
+        assert_eq!(
+            Some(collection_offers[0].collection_offer_id.clone()),
+            expected_offer_id
+        );
+
+        // Without the v2 collection, the id is generated from the creator and name as for v1
+        let RemapOutput {
+            collection_offers, ..
+        } = remapper.remap_events(create_transaction(
+            event_type,
+            build_event_data(serde_json::json!([])),
+        ))?;
+        assert_eq!(
+            collection_offers[0].collection_id,
+            generate_collection_id(Some(creator.to_string()), Some("The Loonies".to_string()))
+        );
+        assert_ne!(
+            collection_offers[0].collection_id.as_deref(),
+            Some(collection)
+        );
+
+        Ok(())
+    }
 }
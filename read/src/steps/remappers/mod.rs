@@ -1,19 +1,47 @@
 use crate::models::nft_models::{
-    CurrentNFTMarketplaceCollectionOffer, CurrentNFTMarketplaceListing,
-    CurrentNFTMarketplaceTokenOffer, MarketplaceField, MarketplaceModel,
+    CurrentNFTMarketplaceAuction, CurrentNFTMarketplaceCollectionOffer,
+    CurrentNFTMarketplaceListing, CurrentNFTMarketplaceTokenOffer, MarketplaceField,
+    MarketplaceModel, NftMarketplaceActivity, CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
     CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
     CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME, CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME,
     NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME,
 };
+use serde::{Deserialize, Serialize};
 
+pub mod error;
 pub mod event_remapper;
+pub mod plugins;
 pub mod resource_remapper;
 
+/// Activities and current state models remapped from a transaction's events, and what the
+/// steps after the remapping pass along.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RemapOutput {
+    pub activities: Vec<NftMarketplaceActivity>,
+    pub listings: Vec<CurrentNFTMarketplaceListing>,
+    pub token_offers: Vec<CurrentNFTMarketplaceTokenOffer>,
+    pub collection_offers: Vec<CurrentNFTMarketplaceCollectionOffer>,
+    pub auctions: Vec<CurrentNFTMarketplaceAuction>,
+}
+
+impl RemapOutput {
+    /// Appends the models of `other` after the ones already here, e.g. the remapped
+    /// transactions of a batch in version order.
+    pub fn extend(&mut self, other: RemapOutput) {
+        self.activities.extend(other.activities);
+        self.listings.extend(other.listings);
+        self.token_offers.extend(other.token_offers);
+        self.collection_offers.extend(other.collection_offers);
+        self.auctions.extend(other.auctions);
+    }
+}
+
 #[derive(Debug)]
 enum SecondaryModel {
     Listing(CurrentNFTMarketplaceListing),
     TokenOffer(CurrentNFTMarketplaceTokenOffer),
     CollectionOffer(CurrentNFTMarketplaceCollectionOffer),
+    Auction(CurrentNFTMarketplaceAuction),
 }
 
 impl MarketplaceModel for SecondaryModel {
@@ -22,6 +50,7 @@ impl MarketplaceModel for SecondaryModel {
             SecondaryModel::Listing(l) => l.set_field(column, value),
             SecondaryModel::TokenOffer(t) => t.set_field(column, value),
             SecondaryModel::CollectionOffer(c) => c.set_field(column, value),
+            SecondaryModel::Auction(a) => a.set_field(column, value),
         }
     }
 
@@ -30,6 +59,7 @@ impl MarketplaceModel for SecondaryModel {
             SecondaryModel::Listing(l) => l.is_valid(),
             SecondaryModel::TokenOffer(t) => t.is_valid(),
             SecondaryModel::CollectionOffer(c) => c.is_valid(),
+            SecondaryModel::Auction(a) => a.is_valid(),
         }
     }
 
@@ -40,6 +70,16 @@ impl MarketplaceModel for SecondaryModel {
             SecondaryModel::CollectionOffer(_) => {
                 CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME
             },
+            SecondaryModel::Auction(_) => CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+        }
+    }
+
+    fn primary_key(&self) -> String {
+        match self {
+            SecondaryModel::Listing(l) => l.primary_key(),
+            SecondaryModel::TokenOffer(t) => t.primary_key(),
+            SecondaryModel::CollectionOffer(c) => c.primary_key(),
+            SecondaryModel::Auction(a) => a.primary_key(),
         }
     }
 
@@ -66,6 +106,7 @@ enum TableType {
     Listings,
     TokenOffers,
     CollectionOffers,
+    Auctions,
 }
 
 impl TableType {
@@ -77,7 +118,60 @@ impl TableType {
             CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME => {
                 Some(TableType::CollectionOffers)
             },
+            CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME => Some(TableType::Auctions),
             _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_output(txn_version: i64) -> RemapOutput {
+        RemapOutput {
+            activities: vec![NftMarketplaceActivity {
+                txn_version,
+                ..Default::default()
+            }],
+            listings: vec![CurrentNFTMarketplaceListing {
+                token_data_id: format!("0x{txn_version}"),
+                last_transaction_version: txn_version,
+                ..Default::default()
+            }],
+            token_offers: vec![CurrentNFTMarketplaceTokenOffer {
+                last_transaction_version: txn_version,
+                ..Default::default()
+            }],
+            collection_offers: vec![CurrentNFTMarketplaceCollectionOffer {
+                last_transaction_version: txn_version,
+                ..Default::default()
+            }],
+            auctions: vec![CurrentNFTMarketplaceAuction {
+                last_transaction_version: txn_version,
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_extend_keeps_order_and_round_trips() {
+        let mut output = build_output(1);
+        output.extend(build_output(2));
+        assert_eq!(
+            output
+                .activities
+                .iter()
+                .map(|activity| activity.txn_version)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(output.listings[1].token_data_id, "0x2");
+        assert_eq!(output.auctions.len(), 2);
+
+        let json = serde_json::to_value(&output).unwrap();
+        let round_tripped: RemapOutput = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&round_tripped).unwrap(), json);
+        assert_eq!(json["collection_offers"][1]["last_transaction_version"], 2);
+    }
+}
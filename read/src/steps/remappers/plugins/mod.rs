@@ -0,0 +1,34 @@
+//! Marketplaces whose events can't be described with JSON path remappings, e.g. because a
+//! listing is spread over several events or a field depends on which fields are present.
+//! A plugin registered under a marketplace's name replaces the config-driven event remapping
+//! for that marketplace.
+
+use super::RemapOutput;
+use anyhow::Result;
+use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::Transaction;
+use std::{collections::HashMap, sync::Arc};
+
+pub mod split_listing;
+
+pub trait MarketplacePlugin: Send + Sync {
+    /// Remaps all events of `txn` that belong to the marketplace. Transactions without any
+    /// should produce empty output rather than an error.
+    fn remap(&self, txn: &Transaction) -> Result<RemapOutput>;
+}
+
+/// Plugins keyed by the marketplace name they handle.
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    plugins: HashMap<String, Arc<dyn MarketplacePlugin>>,
+}
+
+impl PluginRegistry {
+    /// Registers `plugin` for `marketplace_name`, replacing any plugin registered before.
+    pub fn register(&mut self, marketplace_name: &str, plugin: Arc<dyn MarketplacePlugin>) {
+        self.plugins.insert(marketplace_name.to_string(), plugin);
+    }
+
+    pub fn get(&self, marketplace_name: &str) -> Option<Arc<dyn MarketplacePlugin>> {
+        self.plugins.get(marketplace_name).cloned()
+    }
+}
@@ -0,0 +1,297 @@
+//! Example plugin for a marketplace whose listing events can't be remapped from config:
+//!
+//! - `ListingCreated { listing_id, token, seller }` doesn't carry the price. It's emitted in
+//!   a separate `ListingPriceSet { listing_id, price }` event of the same transaction.
+//! - `ListingClosed { listing_id, token, seller, price, buyer }` is emitted for both sales
+//!   and cancellations. It's a sale if the `buyer` option is set.
+
+use super::MarketplacePlugin;
+use crate::{
+    config::marketplace_config::{EventType, MarketplaceEventType},
+    metrics::{NFT_EVENTS_SKIPPED, SKIP_REASON_EXTRACTION_FAILED},
+    models::{
+        nft_models::{CurrentNFTMarketplaceListing, NftMarketplaceActivity, DEFAULT_PAYMENT_TOKEN},
+        EventModel,
+    },
+    steps::remappers::RemapOutput,
+    utils::convert::standardize_address,
+};
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_indexer_transaction_stream::utils::time::parse_timestamp,
+    aptos_protos::transaction::v1::{transaction::TxnData, Transaction},
+};
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+use tracing::warn;
+
+const MODULE_NAME: &str = "listing";
+
+pub struct SplitListingPlugin {
+    marketplace_name: String,
+    listing_created: EventType,
+    listing_price_set: EventType,
+    listing_closed: EventType,
+}
+
+impl SplitListingPlugin {
+    pub fn new(marketplace_name: &str, contract_address: &str) -> Result<Self> {
+        let event_type = |name: &str| {
+            EventType::try_from(format!("{contract_address}::{MODULE_NAME}::{name}").as_str())
+        };
+        Ok(Self {
+            marketplace_name: marketplace_name.to_string(),
+            listing_created: event_type("ListingCreated")?,
+            listing_price_set: event_type("ListingPriceSet")?,
+            listing_closed: event_type("ListingClosed")?,
+        })
+    }
+
+    /// Returns `None` for events of other types and for events missing a required field.
+    fn remap_event(
+        &self,
+        event: &EventModel,
+        prices: &HashMap<&str, BigDecimal>,
+    ) -> Option<(NftMarketplaceActivity, CurrentNFTMarketplaceListing)> {
+        let buyer = event.data["buyer"]["vec"][0].as_str();
+        let standard_event_type = if event.event_type == self.listing_created {
+            MarketplaceEventType::PlaceListing
+        } else if event.event_type != self.listing_closed {
+            return None;
+        } else if buyer.is_some() {
+            MarketplaceEventType::FillListing
+        } else {
+            MarketplaceEventType::CancelListing
+        };
+
+        let listing_id = event.data["listing_id"].as_str()?;
+        let token_data_id = standardize_address(event.data["token"]["inner"].as_str()?).ok()?;
+        let seller = standardize_address(event.data["seller"].as_str()?).ok()?;
+        let buyer = buyer.map(standardize_address).transpose().ok()?;
+        let price = match standard_event_type {
+            MarketplaceEventType::PlaceListing => prices.get(listing_id)?.clone(),
+            _ => event.data["price"].as_str()?.parse().ok()?,
+        };
+
+        let activity = NftMarketplaceActivity {
+            txn_version: event.transaction_version,
+            index: event.event_index,
+            raw_event_type: event.event_type.to_string(),
+            standard_event_type: standard_event_type.to_string(),
+            token_data_id: Some(token_data_id.clone()),
+            price: price.clone(),
+            buyer,
+            seller: Some(seller.clone()),
+            listing_id: Some(listing_id.to_string()),
+            json_data: serde_json::to_value(event).ok()?,
+            marketplace: self.marketplace_name.clone(),
+            contract_address: event.account_address.clone(),
+            block_timestamp: event.block_timestamp,
+            payment_token: DEFAULT_PAYMENT_TOKEN.to_string(),
+            ..Default::default()
+        };
+        let listing = CurrentNFTMarketplaceListing {
+            token_data_id,
+            listing_id: Some(listing_id.to_string()),
+            seller: Some(seller),
+            price,
+            ..CurrentNFTMarketplaceListing::build_default(
+                self.marketplace_name.clone(),
+                event,
+                standard_event_type != MarketplaceEventType::PlaceListing,
+                standard_event_type.to_string(),
+            )
+        };
+        Some((activity, listing))
+    }
+}
+
+impl MarketplacePlugin for SplitListingPlugin {
+    fn remap(&self, txn: &Transaction) -> Result<RemapOutput> {
+        let Some(TxnData::User(user_txn)) = txn.txn_data.as_ref() else {
+            return Ok(Default::default());
+        };
+        let txn_version = txn.version as i64;
+        let txn_timestamp = parse_timestamp(
+            txn.timestamp
+                .as_ref()
+                .context("Transaction should have a timestamp")?,
+            txn_version,
+        )
+        .naive_utc();
+        let events = EventModel::from_events(
+            &user_txn.events,
+            txn_version,
+            txn.block_height as i64,
+            txn_timestamp,
+            false,
+        );
+
+        let prices: HashMap<&str, BigDecimal> = events
+            .iter()
+            .filter(|event| event.event_type == self.listing_price_set)
+            .filter_map(|event| {
+                let listing_id = event.data["listing_id"].as_str()?;
+                let price = event.data["price"].as_str()?.parse().ok()?;
+                Some((listing_id, price))
+            })
+            .collect();
+
+        let mut activities = Vec::new();
+        let mut listings = Vec::new();
+        for event in &events {
+            if event.event_type != self.listing_created && event.event_type != self.listing_closed {
+                continue;
+            }
+            let Some((activity, listing)) = self.remap_event(event, &prices) else {
+                warn!(
+                    "Skipping event '{}' at version {} with missing fields",
+                    event.event_type, txn_version
+                );
+                NFT_EVENTS_SKIPPED
+                    .with_label_values(&[SKIP_REASON_EXTRACTION_FAILED])
+                    .inc();
+                continue;
+            };
+            activities.push(activity);
+            listings.push(listing);
+        }
+
+        Ok(RemapOutput {
+            activities,
+            listings,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::aptos_protos::{
+        transaction::v1::{Event, UserTransaction},
+        util::timestamp::Timestamp,
+    };
+
+    const CONTRACT_ADDRESS: &str = "0xabc";
+
+    fn create_event(name: &str, data: serde_json::Value) -> Event {
+        Event {
+            key: Some(Default::default()),
+            sequence_number: 0,
+            r#type: Some(Default::default()),
+            type_str: format!("{CONTRACT_ADDRESS}::{MODULE_NAME}::{name}"),
+            data: data.to_string(),
+        }
+    }
+
+    fn create_transaction(events: Vec<Event>) -> Transaction {
+        Transaction {
+            version: 1,
+            block_height: 1,
+            txn_data: Some(TxnData::User(UserTransaction {
+                request: None,
+                events,
+            })),
+            timestamp: Some(Timestamp {
+                seconds: 1,
+                nanos: 0,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn closed_event(buyer: Option<&str>) -> Event {
+        create_event(
+            "ListingClosed",
+            serde_json::json!({
+                "listing_id": "7",
+                "token": { "inner": "0x3" },
+                "seller": "0x2",
+                "price": "250",
+                "buyer": { "vec": buyer.into_iter().collect::<Vec<_>>() }
+            }),
+        )
+    }
+
+    #[test]
+    fn test_listing_created_takes_price_from_separate_event() -> Result<()> {
+        let plugin = SplitListingPlugin::new("split_listing", CONTRACT_ADDRESS)?;
+        let txn = create_transaction(vec![
+            create_event(
+                "ListingCreated",
+                serde_json::json!({
+                    "listing_id": "7",
+                    "token": { "inner": "0x3" },
+                    "seller": "0x2"
+                }),
+            ),
+            create_event(
+                "ListingPriceSet",
+                serde_json::json!({ "listing_id": "7", "price": "250" }),
+            ),
+        ]);
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = plugin.remap(&txn)?;
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].standard_event_type, "place_listing");
+        assert_eq!(activities[0].price, BigDecimal::from(250));
+        assert_eq!(listings[0].price, BigDecimal::from(250));
+        assert_eq!(listings[0].token_data_id, standardize_address("0x3")?);
+        assert!(!listings[0].is_deleted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listing_closed_is_fill_or_cancel_depending_on_buyer() -> Result<()> {
+        let plugin = SplitListingPlugin::new("split_listing", CONTRACT_ADDRESS)?;
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = plugin.remap(&create_transaction(vec![closed_event(Some("0x4"))]))?;
+        assert_eq!(activities[0].standard_event_type, "fill_listing");
+        assert_eq!(activities[0].buyer, Some(standardize_address("0x4")?));
+        assert!(listings[0].is_deleted);
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = plugin.remap(&create_transaction(vec![closed_event(None)]))?;
+        assert_eq!(activities[0].standard_event_type, "cancel_listing");
+        assert_eq!(activities[0].buyer, None);
+        assert!(listings[0].is_deleted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listing_created_without_price_is_skipped() -> Result<()> {
+        let plugin = SplitListingPlugin::new("split_listing", CONTRACT_ADDRESS)?;
+        let txn = create_transaction(vec![create_event(
+            "ListingCreated",
+            serde_json::json!({
+                "listing_id": "7",
+                "token": { "inner": "0x3" },
+                "seller": "0x2"
+            }),
+        )]);
+
+        let RemapOutput {
+            activities,
+            listings,
+            ..
+        } = plugin.remap(&txn)?;
+        assert!(activities.is_empty());
+        assert!(listings.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,19 +1,64 @@
 use crate::{
-    config::marketplace_config::{NFTMarketplaceConfig, ResourceFieldRemappings},
-    steps::{extract_string, HashableJsonPath},
+    config::marketplace_config::{
+        EventType, MarketplaceEventType, NFTMarketplaceConfig, ResourceEventTypes,
+        ResourceFieldRemappings,
+    },
+    metrics::{
+        NFT_EVENTS_SKIPPED, NFT_REMAP_DURATION, NFT_RESOURCES_REMAPPED,
+        SKIP_REASON_UNKNOWN_DELETED_RESOURCE,
+    },
+    models::EventModel,
+    postgres::{
+        resource_last_writes::ResourceLastWrites,
+        token_owners::{TokenOwners, OBJECT_CORE_TYPE},
+    },
+    steps::{extract_string, remappers::error::RemapError, HashableJsonPath},
+    utils::convert::standardize_address,
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
+    aptos_indexer_transaction_stream::utils::time::parse_timestamp,
     aptos_protos::transaction::v1::{transaction::TxnData, write_set_change, Transaction},
-    utils::{convert::standardize_address, errors::ProcessorError},
 };
 use serde_json::Value;
 use std::{collections::HashMap, sync::Arc};
-use tracing::warn;
+use tracing::{debug, warn};
 pub const WRITE_SET_CHANGES: &str = "write_set_changes";
 
+/// A resource change of a marketplace that emits no events, standing in for the event the
+/// marketplace would have emitted.
+#[derive(Clone, Debug)]
+pub struct ResourceEvent {
+    /// Has the resource type as its event type, and `{"address", "data"}` of the resource as
+    /// its data. Indexed after the transaction's events, in write set order.
+    pub event: EventModel,
+    pub standard_event_type: MarketplaceEventType,
+}
+
+/// The columns the resources at an address set, for the models that reference the address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceUpdate {
+    /// Version of the last transaction that wrote the resources.
+    pub txn_version: i64,
+    pub columns: HashMap<String, String>,
+}
+
+impl ResourceUpdate {
+    /// Merges the update of a later transaction into this one, its columns taking precedence.
+    pub fn merge(&mut self, later: ResourceUpdate) {
+        self.txn_version = self.txn_version.max(later.txn_version);
+        self.columns.extend(later.columns);
+    }
+}
+
 pub struct ResourceMapper {
     field_remappings: ResourceFieldRemappings,
+    token_owners: Option<Arc<TokenOwners>>,
+    /// The enabled standard event types of `resource_event_model_mapping`.
+    resource_event_types: HashMap<EventType, ResourceEventTypes>,
+    keep_event_type_generics: bool,
+    /// Last writes of the resources of `resource_event_types`, when there are any.
+    last_writes: Option<Arc<ResourceLastWrites>>,
 }
 
 impl ResourceMapper {
@@ -32,31 +77,81 @@ impl ResourceMapper {
 
                 db_mappings_for_resource.insert(json_path, db_mappings);
             }
-            field_remappings.insert(resource_type.clone(), db_mappings_for_resource);
+            field_remappings.insert(
+                config.parse_event_type(resource_type)?,
+                db_mappings_for_resource,
+            );
         }
 
-        Ok(Arc::new(Self { field_remappings }))
+        let is_enabled = |standard_event_type: &Option<MarketplaceEventType>| {
+            standard_event_type.clone().filter(|standard_event_type| {
+                config.enabled_event_types.is_empty()
+                    || config.enabled_event_types.contains(standard_event_type)
+            })
+        };
+        let mut resource_event_types = HashMap::new();
+        for (resource_type, event_types) in &config.resource_event_model_mapping {
+            let event_types = ResourceEventTypes {
+                on_write: is_enabled(&event_types.on_write),
+                on_update: is_enabled(&event_types.on_update),
+                on_delete: is_enabled(&event_types.on_delete),
+            };
+            if event_types.on_write.is_some()
+                || event_types.on_update.is_some()
+                || event_types.on_delete.is_some()
+            {
+                resource_event_types.insert(config.parse_event_type(resource_type)?, event_types);
+            }
+        }
+
+        Ok(Arc::new(Self {
+            field_remappings,
+            token_owners: config
+                .track_listing_owners
+                .then(|| Arc::new(TokenOwners::new(config.marketplace().to_string()))),
+            last_writes: (!resource_event_types.is_empty())
+                .then(|| Arc::new(ResourceLastWrites::new(config.marketplace().to_string()))),
+            resource_event_types,
+            keep_event_type_generics: config.keep_event_type_generics,
+        }))
+    }
+
+    /// Owners of the objects written so far, when the config tracks listing owners.
+    pub fn token_owners(&self) -> Option<Arc<TokenOwners>> {
+        self.token_owners.clone()
+    }
+
+    /// Last writes of the resources in `resource_event_model_mapping`, when it maps any.
+    pub fn resource_last_writes(&self) -> Option<Arc<ResourceLastWrites>> {
+        self.last_writes.clone()
     }
 
     pub fn remap_resources(
         &self,
         txn: Transaction,
-    ) -> Result<HashMap<String, HashMap<String, String>>> {
-        let txn_data = txn
-            .txn_data
-            .as_ref()
-            .ok_or_else(|| ProcessorError::ProcessError {
-                message: "Transaction data is missing".to_string(),
-            })?;
+    ) -> Result<HashMap<String, ResourceUpdate>, RemapError> {
+        let _timer = NFT_REMAP_DURATION
+            .with_label_values(&["resource_remapper"])
+            .start_timer();
+        let txn_data = match txn.txn_data.as_ref() {
+            Some(data) => data,
+            None => {
+                debug!("No transaction data found for version {}", txn.version);
+                return Ok(HashMap::new());
+            },
+        };
 
-        let mut resource_updates: HashMap<String, HashMap<String, String>> = HashMap::new();
+        let mut resource_updates: HashMap<String, ResourceUpdate> = HashMap::new();
 
         if let TxnData::User(_) = txn_data {
             let transaction_info = match txn.info.as_ref() {
                 Some(info) => info,
                 None => {
-                    warn!("ERROR: Transaction info doesn't exist!");
-                    return Err(anyhow::anyhow!("Transaction info doesn't exist!"));
+                    let e = RemapError::MissingTxnInfo {
+                        txn_version: txn.version as i64,
+                    };
+                    warn!("{e}");
+                    return Err(e);
                 },
             };
 
@@ -67,18 +162,64 @@ impl ResourceMapper {
                 };
                 let data: Value = serde_json::from_str(&write_resource.data).unwrap_or(Value::Null);
 
-                let resource_address = standardize_address(&write_resource.address);
+                let resource_address = match standardize_address(&write_resource.address) {
+                    Ok(address) => address,
+                    Err(e) => {
+                        warn!("Skipping write resource with invalid address: {e:#}");
+                        continue;
+                    },
+                };
                 let resource_type = &write_resource.type_str;
-                if let Some(remappings) = self.field_remappings.get(resource_type) {
+                if let Some(token_owners) = &self.token_owners {
+                    if resource_type == OBJECT_CORE_TYPE {
+                        match extract_owner(&data) {
+                            Some(owner) => token_owners.record(
+                                resource_address.clone(),
+                                owner,
+                                txn.version as i64,
+                            ),
+                            None => warn!(
+                                txn_version = txn.version,
+                                address = %resource_address,
+                                "ObjectCore without a valid owner"
+                            ),
+                        }
+                    }
+                }
+                // Parsed like the config's resource types, whose addresses may be overridden
+                let remappings = EventType::parse(resource_type, self.keep_event_type_generics)
+                    .ok()
+                    .and_then(|resource_type| self.field_remappings.get(&resource_type));
+                if let Some(remappings) = remappings {
+                    NFT_RESOURCES_REMAPPED
+                        .with_label_values(&[resource_type.as_str()])
+                        .inc();
                     remappings.iter().try_for_each(|(json_path, db_mappings)| {
                         db_mappings.iter().try_for_each(|db_mapping| {
                             // TODO: handle types when move_type is supported
                             let value = extract_string(json_path, &data).unwrap_or_default();
+                            let value = match db_mapping.convert(value) {
+                                Ok(value) => value,
+                                Err(e) => {
+                                    warn!(
+                                        txn_version = txn.version,
+                                        resource_type = %resource_type,
+                                        path = %json_path.raw,
+                                        column = %db_mapping.column,
+                                        "Failed to transform resource value: {e:#}"
+                                    );
+                                    return Ok(());
+                                },
+                            };
                             resource_updates
                                 .entry(resource_address.clone()) // Use resource address as key
-                                .or_default()
+                                .or_insert_with(|| ResourceUpdate {
+                                    txn_version: txn.version as i64,
+                                    columns: HashMap::new(),
+                                })
+                                .columns
                                 .insert(db_mapping.column.clone(), value);
-                            anyhow::Ok(())
+                            Ok::<(), RemapError>(())
                         })
                     })?;
                 }
@@ -86,4 +227,212 @@ impl ResourceMapper {
         }
         Ok(resource_updates)
     }
+
+    /// Derives events from the writes and deletions of the resources in
+    /// `resource_event_model_mapping`. Whether a write is the resource's first, and the data a
+    /// deletion is remapped with, come from the resource's last write, so the transactions must
+    /// be passed in version order.
+    pub fn map_events_from_resources(&self, txn: &Transaction) -> Vec<ResourceEvent> {
+        let Some(last_writes) = &self.last_writes else {
+            return vec![];
+        };
+        let (Some(TxnData::User(user_txn)), Some(info), Some(timestamp)) = (
+            txn.txn_data.as_ref(),
+            txn.info.as_ref(),
+            txn.timestamp.as_ref(),
+        ) else {
+            return vec![];
+        };
+        let txn_version = txn.version as i64;
+        let block_timestamp = parse_timestamp(timestamp, txn_version).naive_utc();
+
+        let mut resource_events = Vec::new();
+        for (index, wsc) in info.changes.iter().enumerate() {
+            let (address, type_str, data) = match wsc.change.as_ref() {
+                Some(write_set_change::Change::WriteResource(wr)) => {
+                    (&wr.address, &wr.type_str, Some(&wr.data))
+                },
+                Some(write_set_change::Change::DeleteResource(dr)) => {
+                    (&dr.address, &dr.type_str, None)
+                },
+                _ => continue,
+            };
+            let Ok(resource_type) = EventType::parse(type_str, self.keep_event_type_generics)
+            else {
+                continue;
+            };
+            let Some(event_types) = self.resource_event_types.get(&resource_type) else {
+                continue;
+            };
+            let address = match standardize_address(address) {
+                Ok(address) => address,
+                Err(e) => {
+                    warn!("Skipping resource change with invalid address: {e:#}");
+                    continue;
+                },
+            };
+
+            let type_str = resource_type.to_string();
+            let (data, standard_event_type) = match data {
+                Some(data) => {
+                    let data: Value = serde_json::from_str(data).unwrap_or(Value::Null);
+                    let is_new = last_writes.write(&type_str, &address, data.clone(), txn_version);
+                    let standard_event_type = if is_new {
+                        event_types.on_write.clone()
+                    } else {
+                        event_types.on_update.clone()
+                    };
+                    (data, standard_event_type)
+                },
+                None => match last_writes.delete(&type_str, &address, txn_version) {
+                    Some(data) => (data, event_types.on_delete.clone()),
+                    None if event_types.on_delete.is_some() => {
+                        warn!(
+                            txn_version,
+                            resource_type = %resource_type,
+                            address = %address,
+                            "Skipping deletion of a resource whose last write wasn't seen"
+                        );
+                        NFT_EVENTS_SKIPPED
+                            .with_label_values(&[SKIP_REASON_UNKNOWN_DELETED_RESOURCE])
+                            .inc();
+                        continue;
+                    },
+                    None => continue,
+                },
+            };
+            let Some(standard_event_type) = standard_event_type else {
+                continue;
+            };
+            resource_events.push(ResourceEvent {
+                event: EventModel {
+                    sequence_number: 0,
+                    creation_number: 0,
+                    account_address: resource_type.get_address().to_string(),
+                    transaction_version: txn_version,
+                    transaction_block_height: txn.block_height as i64,
+                    event_type: resource_type,
+                    data: serde_json::json!({ "address": address, "data": data }),
+                    event_index: (user_txn.events.len() + index) as i64,
+                    block_timestamp,
+                },
+                standard_event_type,
+            });
+        }
+        resource_events
+    }
+}
+
+fn extract_owner(object_core: &Value) -> Option<String> {
+    standardize_address(object_core.get("owner")?.as_str()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::marketplace_config::{DbColumn, Network, ResourceRemapping};
+    use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::{
+        TransactionInfo, UserTransaction, WriteResource, WriteSetChange,
+    };
+
+    #[test]
+    fn test_user_transaction_without_info_is_missing_txn_info() {
+        let remapper = ResourceMapper::new(&NFTMarketplaceConfig::default()).unwrap();
+        let txn = Transaction {
+            version: 5,
+            txn_data: Some(TxnData::User(UserTransaction::default())),
+            info: None,
+            ..Default::default()
+        };
+
+        let err = remapper.remap_resources(txn).unwrap_err();
+        assert!(matches!(err, RemapError::MissingTxnInfo { txn_version: 5 }));
+        assert_eq!(err.reason(), "missing_txn_info");
+    }
+
+    #[test]
+    fn test_object_core_write_records_owner() {
+        let remapper = ResourceMapper::new(&NFTMarketplaceConfig {
+            name: "test_marketplace".to_string(),
+            track_listing_owners: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let txn = Transaction {
+            version: 7,
+            txn_data: Some(TxnData::User(UserTransaction::default())),
+            info: Some(TransactionInfo {
+                changes: vec![WriteSetChange {
+                    change: Some(write_set_change::Change::WriteResource(WriteResource {
+                        address: "0x3".to_string(),
+                        type_str: OBJECT_CORE_TYPE.to_string(),
+                        data: serde_json::json!({
+                            "allow_ungated_transfer": true,
+                            "guid_creation_num": "1125899906842625",
+                            "owner": "0x5",
+                        })
+                        .to_string(),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        remapper.remap_resources(txn).unwrap();
+
+        let owners = remapper.token_owners().unwrap().take_up_to(7);
+        assert_eq!(owners.len(), 1);
+        assert_eq!(owners[0].token_data_id, standardize_address("0x3").unwrap());
+        assert_eq!(owners[0].owner, standardize_address("0x5").unwrap());
+        assert_eq!(owners[0].txn_version, 7);
+    }
+
+    #[test]
+    fn test_resources_match_overridden_contract_address() {
+        let remapper = ResourceMapper::new(&NFTMarketplaceConfig {
+            name: "test_marketplace".to_string(),
+            resources: HashMap::from([(
+                "0xcafe::listing::Listing".to_string(),
+                ResourceRemapping {
+                    resource_fields: HashMap::from([("$.seller".to_string(), vec![DbColumn {
+                        table: "current_nft_marketplace_listings".to_string(),
+                        column: "seller".to_string(),
+                        ..Default::default()
+                    }])]),
+                },
+            )]),
+            network: Some(Network::Testnet),
+            contract_address_overrides: HashMap::from([(
+                Network::Testnet,
+                HashMap::from([("0xcafe".to_string(), "0xbeef".to_string())]),
+            )]),
+            ..Default::default()
+        })
+        .unwrap();
+        let txn = Transaction {
+            version: 7,
+            txn_data: Some(TxnData::User(UserTransaction::default())),
+            info: Some(TransactionInfo {
+                changes: vec![WriteSetChange {
+                    change: Some(write_set_change::Change::WriteResource(WriteResource {
+                        address: "0x3".to_string(),
+                        type_str: "0xbeef::listing::Listing".to_string(),
+                        data: serde_json::json!({ "seller": "0x5" }).to_string(),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let updates = remapper.remap_resources(txn).unwrap();
+
+        let update = &updates[&standardize_address("0x3").unwrap()];
+        assert_eq!(update.columns["seller"], "0x5");
+    }
 }
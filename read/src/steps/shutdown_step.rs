@@ -0,0 +1,166 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_indexer_processor_sdk::{
+    traits::{
+        pollable_async_step::PollableAsyncRunType, NamedStep, PollableAsyncStep, Processable,
+    },
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use tokio::sync::watch;
+use tonic::async_trait;
+use tracing::info;
+
+/// Wraps the first step of the pipeline, usually the `TransactionStreamStep`, and stops polling
+/// it once `shutdown` turns `true`.
+///
+/// Nothing is aborted: the steps downstream see their input channel close once they've
+/// processed what was already in flight, and the `VersionTrackerStep` saves the processor
+/// status when its own input closes. A restart then resumes from the last written batch.
+pub struct StopOnShutdownStep<S> {
+    inner: S,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl<S> StopOnShutdownStep<S> {
+    pub fn new(inner: S, shutdown: watch::Receiver<bool>) -> Self {
+        Self { inner, shutdown }
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+}
+
+#[async_trait]
+impl<S> Processable for StopOnShutdownStep<S>
+where
+    S: PollableAsyncStep + Send + Sync + 'static,
+{
+    type Input = S::Input;
+    type Output = S::Output;
+    type RunType = PollableAsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<S::Input>,
+    ) -> Result<Option<TransactionContext<S::Output>>, ProcessorError> {
+        self.inner.process(input).await
+    }
+}
+
+#[async_trait]
+impl<S> PollableAsyncStep for StopOnShutdownStep<S>
+where
+    S: PollableAsyncStep + Send + Sync + 'static,
+{
+    fn poll_interval(&self) -> std::time::Duration {
+        self.inner.poll_interval()
+    }
+
+    /// Gives up on a poll that is still waiting for the stream when the shutdown starts. The
+    /// batch it would have returned was never processed, so nothing is lost.
+    async fn poll(&mut self) -> Result<Option<Vec<TransactionContext<S::Output>>>, ProcessorError> {
+        if self.is_shutting_down() {
+            return Ok(None);
+        }
+        let mut shutdown = self.shutdown.clone();
+        tokio::select! {
+            result = self.inner.poll() => result,
+            _ = shutdown.wait_for(|shutting_down| *shutting_down) => Ok(None),
+        }
+    }
+
+    async fn should_continue_polling(&mut self) -> bool {
+        if self.is_shutting_down() {
+            info!("Shutting down, stopped polling {}", self.inner.name());
+            return false;
+        }
+        self.inner.should_continue_polling().await
+    }
+}
+
+impl<S: NamedStep> NamedStep for StopOnShutdownStep<S> {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::types::transaction_context::TransactionMetadata;
+    use std::time::Duration;
+
+    /// Returns a batch per poll, or never returns when `blocking` is set.
+    struct TestStream {
+        blocking: bool,
+    }
+
+    #[async_trait]
+    impl Processable for TestStream {
+        type Input = ();
+        type Output = Vec<u64>;
+        type RunType = PollableAsyncRunType;
+
+        async fn process(
+            &mut self,
+            _input: TransactionContext<()>,
+        ) -> Result<Option<TransactionContext<Vec<u64>>>, ProcessorError> {
+            Ok(None)
+        }
+    }
+
+    #[async_trait]
+    impl PollableAsyncStep for TestStream {
+        fn poll_interval(&self) -> Duration {
+            Duration::from_millis(10)
+        }
+
+        async fn poll(
+            &mut self,
+        ) -> Result<Option<Vec<TransactionContext<Vec<u64>>>>, ProcessorError> {
+            if self.blocking {
+                std::future::pending::<()>().await;
+            }
+            Ok(Some(vec![TransactionContext {
+                data: vec![1],
+                metadata: TransactionMetadata::default(),
+            }]))
+        }
+    }
+
+    impl NamedStep for TestStream {
+        fn name(&self) -> String {
+            "TestStream".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stops_polling_on_shutdown() {
+        let (sender, receiver) = watch::channel(false);
+        let mut step = StopOnShutdownStep::new(TestStream { blocking: false }, receiver);
+        assert!(step.should_continue_polling().await);
+        assert!(step.poll().await.unwrap().is_some());
+
+        sender.send(true).unwrap();
+        assert!(!step.should_continue_polling().await);
+        assert!(step.poll().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_interrupts_pending_poll() {
+        let (sender, receiver) = watch::channel(false);
+        let mut step = StopOnShutdownStep::new(TestStream { blocking: true }, receiver);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            sender.send(true).unwrap();
+        });
+        let result = tokio::time::timeout(Duration::from_secs(5), step.poll())
+            .await
+            .expect("poll should return once the shutdown starts");
+        assert!(result.unwrap().is_none());
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::DEFAULT_WEBHOOK_BATCH_SIZE,
+    models::nft_models::NftMarketplaceActivity,
+    utils::retry::{retry_with_backoff, QueryRetryConfig},
+};
+use anyhow::Result;
+use aptos_indexer_processor_sdk::{
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use serde::{Deserialize, Serialize};
+use tonic::async_trait;
+use url::Url;
+
+/// Where to push activities once they are persisted.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookConfig {
+    pub url: Url,
+    /// Maximum number of activities sent in a single request.
+    #[serde(default = "WebhookConfig::default_batch_size")]
+    pub batch_size: usize,
+    /// Retries for failed requests, with exponential backoff.
+    #[serde(default)]
+    pub retry_config: QueryRetryConfig,
+}
+
+impl WebhookConfig {
+    pub const fn default_batch_size() -> usize {
+        DEFAULT_WEBHOOK_BATCH_SIZE
+    }
+}
+
+/// POSTs the activities written by `DBWritingStep` to a webhook as JSON arrays. Without a
+/// webhook configured the step only passes the batch along.
+///
+/// A request that still fails after its retries fails the step, so the version tracker doesn't
+/// move past activities that were never delivered.
+pub struct WebhookStep {
+    client: reqwest::Client,
+    config: Option<WebhookConfig>,
+}
+
+impl WebhookStep {
+    pub fn new(config: Option<WebhookConfig>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl Processable for WebhookStep {
+    type Input = Vec<NftMarketplaceActivity>;
+    type Output = ();
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        input: TransactionContext<Vec<NftMarketplaceActivity>>,
+    ) -> Result<Option<TransactionContext<()>>, ProcessorError> {
+        if let Some(config) = &self.config {
+            for batch in input.data.chunks(config.batch_size.max(1)) {
+                post_activities(&self.client, config, batch)
+                    .await
+                    .map_err(|e| ProcessorError::ProcessError {
+                        message: format!("{e:#}"),
+                    })?;
+            }
+        }
+
+        Ok(Some(TransactionContext {
+            data: (),
+            metadata: input.metadata,
+        }))
+    }
+}
+
+impl AsyncStep for WebhookStep {}
+
+impl NamedStep for WebhookStep {
+    fn name(&self) -> String {
+        "WebhookStep".to_string()
+    }
+}
+
+async fn post_activities(
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    activities: &[NftMarketplaceActivity],
+) -> Result<()> {
+    retry_with_backoff(
+        "post_activities",
+        &config.retry_config,
+        move || async move {
+            client
+                .post(config.url.clone())
+                .json(activities)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_processor_sdk::types::transaction_context::TransactionMetadata;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    /// Serves requests until the test ends, answering the first `failures` of them with a 500.
+    /// Returns the webhook URL and the number of activities received by successful requests.
+    async fn spawn_webhook_server(failures: usize) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let received = Arc::new(AtomicUsize::new(0));
+        let received_clone = received.clone();
+        tokio::spawn(async move {
+            let mut requests = 0;
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let body = read_request_body(&mut stream).await;
+                requests += 1;
+                let status = if requests <= failures {
+                    "500 Internal Server Error"
+                } else {
+                    let activities: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+                    received_clone.fetch_add(activities.len(), Ordering::SeqCst);
+                    "200 OK"
+                };
+                let response =
+                    format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    async fn read_request_body(stream: &mut TcpStream) -> Vec<u8> {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        let header_end = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "Connection closed before the request headers");
+            request.extend_from_slice(&buf[..n]);
+            if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+        let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+        let content_length: usize = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map(|len| len.trim().parse().unwrap())
+            .unwrap_or_default();
+        while request.len() < header_end + content_length {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "Connection closed before the request body");
+            request.extend_from_slice(&buf[..n]);
+        }
+        request[header_end..].to_vec()
+    }
+
+    fn build_activities(count: i64) -> Vec<NftMarketplaceActivity> {
+        (0..count)
+            .map(|index| NftMarketplaceActivity {
+                txn_version: 1,
+                index,
+                marketplace: "test_marketplace".to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn build_config(url: Url, batch_size: usize) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            batch_size,
+            retry_config: QueryRetryConfig {
+                retries: 2,
+                retry_delay_ms: 0,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_posts_activities_in_batches_after_retries() {
+        let (url, received) = spawn_webhook_server(1).await;
+        let mut step = WebhookStep::new(Some(build_config(url, 2)));
+
+        step.process(TransactionContext {
+            data: build_activities(3),
+            metadata: TransactionMetadata::default(),
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(received.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fails_when_retries_are_exhausted() {
+        let (url, received) = spawn_webhook_server(usize::MAX).await;
+        let mut step = WebhookStep::new(Some(build_config(url, 2)));
+
+        let result = step
+            .process(TransactionContext {
+                data: build_activities(1),
+                metadata: TransactionMetadata::default(),
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(received.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_passes_batches_through_without_webhook() {
+        let mut step = WebhookStep::new(None);
+
+        let output = step
+            .process(TransactionContext {
+                data: build_activities(1),
+                metadata: TransactionMetadata::default(),
+            })
+            .await
+            .unwrap();
+
+        assert!(output.is_some());
+    }
+}
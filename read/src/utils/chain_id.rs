@@ -0,0 +1,96 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::marketplace_config::Network,
+    schema::ledger_infos,
+    utils::retry::{retry_with_backoff, QueryRetryConfig},
+};
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_indexer_transaction_stream::{TransactionStream, TransactionStreamConfig},
+    postgres::utils::database::ArcDbPool,
+};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use tracing::info;
+
+/// Fetches the chain id served by the gRPC stream.
+pub async fn get_chain_id(transaction_stream_config: &TransactionStreamConfig) -> Result<u64> {
+    let mut transaction_stream = TransactionStream::new(transaction_stream_config.clone())
+        .await
+        .context("Failed to create transaction stream")?;
+    transaction_stream
+        .get_chain_id()
+        .await
+        .context("Failed to get chain id from gRPC")
+}
+
+/// Fetches the chain id served by the gRPC stream, retrying with exponential backoff so a
+/// briefly unavailable endpoint (e.g. during node upgrades) doesn't kill the processor.
+pub async fn get_chain_id_with_retries(
+    transaction_stream_config: &TransactionStreamConfig,
+    retry_config: &QueryRetryConfig,
+) -> Result<u64> {
+    retry_with_backoff("get_chain_id", retry_config, || {
+        get_chain_id(transaction_stream_config)
+    })
+    .await
+}
+
+/// Verifies the gRPC chain id against the configured `network` and the one stored in the
+/// schema's `ledger_infos`, so a processor pointed at the wrong network fails before writing
+/// anything. On first run the chain id is stored instead.
+pub async fn check_or_update_chain_id(
+    grpc_chain_id: u64,
+    network: Option<Network>,
+    db_pool: ArcDbPool,
+) -> Result<u64> {
+    if let Some(network) = network {
+        if network
+            .chain_id()
+            .is_some_and(|chain_id| chain_id != grpc_chain_id)
+        {
+            anyhow::bail!(
+                "Wrong chain id detected! The config is for {network} but gRPC serves chain {grpc_chain_id}"
+            );
+        }
+    }
+
+    let mut conn = db_pool
+        .get()
+        .await
+        .context("Failed to get database connection")?;
+
+    let stored_chain_id = ledger_infos::table
+        .select(ledger_infos::chain_id)
+        .first::<i64>(&mut conn)
+        .await
+        .optional()
+        .context("Failed to query ledger_infos table")?;
+
+    match stored_chain_id {
+        Some(stored_chain_id) => {
+            if stored_chain_id as u64 != grpc_chain_id {
+                anyhow::bail!(
+                    "Wrong chain id detected! Trying to index chain {grpc_chain_id} now but existing data is for chain {stored_chain_id}"
+                );
+            }
+            info!(
+                chain_id = stored_chain_id,
+                "Chain id matches the stored chain id"
+            );
+            Ok(grpc_chain_id)
+        },
+        None => {
+            info!(chain_id = grpc_chain_id, "Adding chain id to ledger_infos");
+            diesel::insert_into(ledger_infos::table)
+                .values(ledger_infos::chain_id.eq(grpc_chain_id as i64))
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await
+                .context("Failed to insert chain id into ledger_infos table")?;
+            Ok(grpc_chain_id)
+        },
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversions applied to values extracted from on-chain data. Address helpers reject malformed
+//! input instead of producing over-length strings that later fail the `VARCHAR(66)` columns on
+//! insert.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+
+/// Number of hex chars in a standardized address, without the `0x` prefix.
+const ADDRESS_HEX_LENGTH: usize = 64;
+/// Number of bytes in an account address.
+const ADDRESS_BYTES_LENGTH: usize = 32;
+
+/// Left-pads `address` to a `0x`-prefixed, 64 hex char address. Errors if the address is
+/// already longer than that.
+pub fn standardize_address(address: &str) -> Result<String> {
+    let trimmed = address.strip_prefix("0x").unwrap_or(address);
+    if trimmed.len() > ADDRESS_HEX_LENGTH {
+        anyhow::bail!(
+            "Address {address} is {} hex chars long, expected at most {ADDRESS_HEX_LENGTH}",
+            trimmed.len()
+        );
+    }
+    Ok(format!("0x{trimmed:0>64}"))
+}
+
+/// Hex encodes `bytes` into a standardized address. Errors if there are more than 32 bytes.
+pub fn standardize_address_from_bytes(bytes: &[u8]) -> Result<String> {
+    if bytes.len() > ADDRESS_BYTES_LENGTH {
+        anyhow::bail!(
+            "Address is {} bytes long, expected at most {ADDRESS_BYTES_LENGTH}",
+            bytes.len()
+        );
+    }
+    standardize_address(&hex::encode(bytes))
+}
+
+/// Cuts `value` down to at most `max_chars` characters, never splitting a character.
+pub fn truncate_str(value: &str, max_chars: usize) -> String {
+    match value.char_indices().nth(max_chars) {
+        Some((end, _)) => value[..end].to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Decodes a `0x`-prefixed hex string into the UTF-8 string its bytes spell out.
+pub fn hex_to_utf8(value: &str) -> Result<String> {
+    let bytes = hex::decode(value.strip_prefix("0x").unwrap_or(value))
+        .with_context(|| format!("Value {value} is not valid hex"))?;
+    String::from_utf8(bytes).with_context(|| format!("Value {value} is not valid UTF-8"))
+}
+
+/// Decodes a hex encoded, BCS serialized value. Errors if bytes are left over after the value.
+pub fn convert_bcs_hex<T: DeserializeOwned>(value: &str) -> Result<T> {
+    let bytes = hex::decode(value.strip_prefix("0x").unwrap_or(value))
+        .with_context(|| format!("Value {value} is not valid hex"))?;
+    Ok(bcs::from_bytes(&bytes)?)
+}
+
+/// Decodes a hex encoded, BCS serialized `0x1::string::String`, i.e. a length prefixed
+/// UTF-8 string.
+pub fn convert_bcs_hex_string(value: &str) -> Result<String> {
+    convert_bcs_hex(value).with_context(|| format!("Value {value} is not a BCS encoded string"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standardize_address_lengths() {
+        let address = standardize_address(&"a".repeat(63)).unwrap();
+        assert_eq!(address, format!("0x0{}", "a".repeat(63)));
+
+        let address = standardize_address(&format!("0x{}", "a".repeat(64))).unwrap();
+        assert_eq!(address, format!("0x{}", "a".repeat(64)));
+
+        assert!(standardize_address(&"a".repeat(65)).is_err());
+        assert!(standardize_address(&format!("0x{}", "a".repeat(65))).is_err());
+    }
+
+    #[test]
+    fn test_standardize_address_pads_short_addresses() {
+        assert_eq!(
+            standardize_address("0x1").unwrap(),
+            format!("0x{:0>64}", "1")
+        );
+    }
+
+    #[test]
+    fn test_standardize_address_from_bytes_lengths() {
+        let address = standardize_address_from_bytes(&[0xAB; 31]).unwrap();
+        assert_eq!(address, format!("0x00{}", "ab".repeat(31)));
+
+        let address = standardize_address_from_bytes(&[0xAB; 32]).unwrap();
+        assert_eq!(address, format!("0x{}", "ab".repeat(32)));
+
+        assert!(standardize_address_from_bytes(&[0xAB; 33]).is_err());
+    }
+
+    #[test]
+    fn test_truncate_str_keeps_whole_chars() {
+        assert_eq!(truncate_str("Token", 10), "Token");
+        assert_eq!(truncate_str("Token", 3), "Tok");
+        assert_eq!(truncate_str("Bär #1", 2), "Bä");
+    }
+
+    #[test]
+    fn test_hex_to_utf8() {
+        assert_eq!(hex_to_utf8("0x546f6b656e").unwrap(), "Token");
+        assert_eq!(hex_to_utf8("546f6b656e").unwrap(), "Token");
+        assert!(hex_to_utf8("0xzz").is_err());
+        assert!(hex_to_utf8("0xff").is_err());
+    }
+
+    #[test]
+    fn test_convert_bcs_hex_string() {
+        assert_eq!(convert_bcs_hex_string("0x05546f6b656e").unwrap(), "Token");
+        // Length prefix longer than the remaining bytes
+        assert!(convert_bcs_hex_string("0x06546f6b656e").is_err());
+    }
+
+    #[test]
+    fn test_convert_bcs_hex_rejects_trailing_bytes() {
+        assert_eq!(convert_bcs_hex::<u64>("0x6400000000000000").unwrap(), 100);
+        assert_eq!(convert_bcs_hex::<Vec<u8>>("0x02abcd").unwrap(), vec![
+            0xAB, 0xCD
+        ]);
+        assert!(convert_bcs_hex::<u64>("0x640000000000000000").is_err());
+        assert!(convert_bcs_hex::<bool>("0x02").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
+
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+/// Names the format when the config doesn't set one, e.g. `RUST_LOG_FORMAT=json` in the
+/// Dockerfile.
+pub const LOG_FORMAT_ENV: &str = "RUST_LOG_FORMAT";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line. The fields of a log call, e.g. `txn_version`, are top-level
+    /// keys, and the fields of the span it's logged in, e.g. the event being remapped, are
+    /// under `span`.
+    #[default]
+    Json,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LogConfig {
+    /// Filter directives, e.g. `info` or `warn,nft_aggregator=debug`. `RUST_LOG` takes
+    /// precedence when set, and [`DEFAULT_LOG_LEVEL`] is used without either.
+    #[serde(default)]
+    pub level: Option<String>,
+    /// Taken from [`LOG_FORMAT_ENV`] when unset, see [`LogConfig::format`].
+    #[serde(default)]
+    pub format: Option<LogFormat>,
+}
+
+impl LogConfig {
+    pub fn env_filter(&self) -> Result<EnvFilter> {
+        let directives = std::env::var(EnvFilter::DEFAULT_ENV)
+            .ok()
+            .filter(|directives| !directives.is_empty())
+            .or_else(|| self.level.clone())
+            .unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string());
+        EnvFilter::try_new(&directives).with_context(|| format!("Invalid log level {directives}"))
+    }
+
+    /// The configured format, else the one [`LOG_FORMAT_ENV`] names. JSON without either, or
+    /// when the variable names neither format.
+    pub fn format(&self) -> LogFormat {
+        self.format
+            .unwrap_or_else(|| match std::env::var(LOG_FORMAT_ENV) {
+                Ok(format) if format.trim().eq_ignore_ascii_case("text") => LogFormat::Text,
+                _ => LogFormat::default(),
+            })
+    }
+
+    /// Installs the global subscriber. `log` records, e.g. from dependencies, go through it too.
+    pub fn init(&self) -> Result<()> {
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(self.env_filter()?)
+            .with_thread_ids(true);
+        match self.format() {
+            LogFormat::Text => subscriber.try_init(),
+            LogFormat::Json => subscriber
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .try_init(),
+        }
+        .map_err(|e| anyhow::anyhow!("Failed to set up logging: {e}"))
+    }
+}
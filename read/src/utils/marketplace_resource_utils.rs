@@ -1,11 +1,13 @@
 #[allow(unused_imports)]
 #[allow(unused_variables)]
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::fmt::{self, Display};
 
 pub const MAX_NAME_LENGTH: usize = 128;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TokenStandard {
     V1,
     V2,
@@ -38,6 +40,27 @@ impl std::str::FromStr for TokenStandard {
     }
 }
 
+/// Guesses the token standard from the shape of an event when the config doesn't say.
+///
+/// v2 tokens are objects, so an event about one carries the token's object address
+/// (`token.inner`, or `token_metadata.token.vec[0].inner` for marketplaces that support both
+/// standards). v1 tokens are identified by a `token_id` or, in `token_metadata`, by a property
+/// version. Checking for a `token_metadata` object alone isn't enough, since v1 events of
+/// marketplaces supporting both standards carry one too, with an empty `token` option.
+pub fn detect_token_standard(data: &Value) -> Option<TokenStandard> {
+    let token_metadata = &data["token_metadata"];
+    if token_metadata["token"]["vec"][0]["inner"].is_string() || data["token"]["inner"].is_string()
+    {
+        Some(TokenStandard::V2)
+    } else if !token_metadata["property_version"]["vec"][0].is_null()
+        || data["token_id"]["token_data_id"].is_object()
+    {
+        Some(TokenStandard::V1)
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FixedPriceListing {
     pub price: i64,
@@ -124,3 +147,52 @@ pub struct CollectionMetadata {
     pub collection_name: String,
     pub token_standard: TokenStandard,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_token_standard_v1_event_with_token_metadata() {
+        // A v1 token sold on a marketplace supporting both standards: `token_metadata` is
+        // present, but the token option is empty and the property version is set.
+        let data = json!({
+            "price": "100",
+            "token_metadata": {
+                "collection": { "vec": [] },
+                "collection_name": "Bruh Bears",
+                "creator_address": "0x43ec2cb158e3569842d537740fd53403e992b9e7349cc5d3dfaa5aff8faaef2",
+                "property_version": { "vec": ["0"] },
+                "token": { "vec": [] },
+                "token_name": "Bruh Bear #3770"
+            }
+        });
+        assert_eq!(detect_token_standard(&data), Some(TokenStandard::V1));
+    }
+
+    #[test]
+    fn test_detect_token_standard() {
+        let v2 = json!({
+            "token_metadata": {
+                "property_version": { "vec": [] },
+                "token": { "vec": [{ "inner": "0x1" }] }
+            }
+        });
+        assert_eq!(detect_token_standard(&v2), Some(TokenStandard::V2));
+        assert_eq!(
+            detect_token_standard(&json!({ "token": { "inner": "0x1" } })),
+            Some(TokenStandard::V2)
+        );
+
+        let v1 = json!({
+            "token_id": {
+                "property_version": "1",
+                "token_data_id": { "collection": "c", "creator": "0x1", "name": "n" }
+            }
+        });
+        assert_eq!(detect_token_standard(&v1), Some(TokenStandard::V1));
+
+        assert_eq!(detect_token_standard(&json!({ "price": "100" })), None);
+    }
+}
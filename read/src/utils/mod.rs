@@ -1,10 +1,23 @@
 use aptos_protos::util::timestamp::Timestamp;
 
+pub mod chain_id;
+pub mod convert;
+pub mod logging;
 pub mod marketplace_resource_utils;
+pub mod reconnect;
+pub mod retry;
+pub mod shutdown;
 
 pub const MAX_TIMESTAMP_SECS: i64 = 253_402_300_799;
 
 pub fn parse_timestamp(ts: &Timestamp, version: i64) -> chrono::NaiveDateTime {
+    try_parse_timestamp(ts)
+        .unwrap_or_else(|| panic!("Could not parse timestamp {ts:?} for version {version}"))
+}
+
+/// Like [`parse_timestamp`], but returns `None` for timestamps chrono can't represent, e.g.
+/// negative nanos, instead of panicking. Seconds past [`MAX_TIMESTAMP_SECS`] are clamped to it.
+pub fn try_parse_timestamp(ts: &Timestamp) -> Option<chrono::NaiveDateTime> {
     let final_ts = if ts.seconds >= MAX_TIMESTAMP_SECS {
         Timestamp {
             seconds: MAX_TIMESTAMP_SECS,
@@ -13,7 +26,27 @@ pub fn parse_timestamp(ts: &Timestamp, version: i64) -> chrono::NaiveDateTime {
     } else {
         *ts
     };
-    #[allow(deprecated)]
-    chrono::NaiveDateTime::from_timestamp_opt(final_ts.seconds, final_ts.nanos as u32)
-        .unwrap_or_else(|| panic!("Could not parse timestamp {ts:?} for version {version}"))
+    let nanos = u32::try_from(final_ts.nanos).ok()?;
+    chrono::DateTime::from_timestamp(final_ts.seconds, nanos).map(|ts| ts.naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_parse_timestamp_out_of_range() {
+        let max = chrono::DateTime::from_timestamp(MAX_TIMESTAMP_SECS, 0)
+            .unwrap()
+            .naive_utc();
+        let parse = |seconds, nanos| try_parse_timestamp(&Timestamp { seconds, nanos });
+
+        assert_eq!(
+            parse(0, 0),
+            chrono::DateTime::from_timestamp(0, 0).map(|ts| ts.naive_utc())
+        );
+        assert_eq!(parse(i64::MAX, 0), Some(max));
+        assert_eq!(parse(0, -1), None);
+        assert_eq!(parse(i64::MIN, 0), None);
+    }
 }
@@ -0,0 +1,167 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reconnects to the transaction stream when it drops. The SDK's pipeline only sees its input
+//! channel close, so without this a dropped stream looks like a processor that finished.
+
+use crate::utils::retry::QueryRetryConfig;
+use anyhow::Result;
+use std::{future::Future, time::Duration};
+use tracing::{info, warn};
+
+/// How a run of the pipeline ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamEnd {
+    /// The processor was asked to shut down.
+    Shutdown,
+    /// The stream reached the requested ending version.
+    Finished,
+    /// The stream closed before the ending version. `last_version` is the end version of the
+    /// last batch that made it through the pipeline during the run, if any did.
+    Dropped { last_version: Option<u64> },
+}
+
+/// Calls `run` with `starting_version`, then again whenever the stream drops, starting from the
+/// version `resume_version` returns, i.e. the last one saved to the processor status.
+///
+/// Reconnects back off like [`crate::utils::retry::retry_with_backoff`]. The processor gives up
+/// once the stream drops more than `retry_config.retries` times in a row without any batch
+/// making it through. A run that made progress resets the count and the delay.
+pub async fn run_with_reconnects<R, RFut, V, VFut>(
+    retry_config: &QueryRetryConfig,
+    mut starting_version: Option<u64>,
+    mut run: R,
+    mut resume_version: V,
+) -> Result<()>
+where
+    R: FnMut(Option<u64>) -> RFut,
+    RFut: Future<Output = Result<StreamEnd>>,
+    V: FnMut() -> VFut,
+    VFut: Future<Output = Result<Option<u64>>>,
+{
+    let mut drops_without_progress = 0;
+    let mut delay_ms = retry_config.retry_delay_ms;
+    loop {
+        let last_version = match run(starting_version).await? {
+            StreamEnd::Shutdown | StreamEnd::Finished => return Ok(()),
+            StreamEnd::Dropped { last_version } => last_version,
+        };
+        if last_version.is_some() {
+            drops_without_progress = 0;
+            delay_ms = retry_config.retry_delay_ms;
+        } else if drops_without_progress >= retry_config.retries {
+            anyhow::bail!(
+                "Transaction stream dropped {} times in a row without progress, giving up",
+                drops_without_progress + 1
+            );
+        } else {
+            drops_without_progress += 1;
+        }
+        warn!(
+            ?last_version,
+            drops_without_progress,
+            max_reconnects = retry_config.retries,
+            delay_ms,
+            "Transaction stream dropped, reconnecting"
+        );
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        delay_ms = delay_ms.saturating_mul(2);
+
+        // A `--starting-version` past the saved one still applies
+        starting_version = starting_version.max(resume_version().await?);
+        info!(
+            ?starting_version,
+            "Reconnecting to the transaction stream from the last saved version"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn no_delay(retries: u32) -> QueryRetryConfig {
+        QueryRetryConfig {
+            retries,
+            retry_delay_ms: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resumes_from_saved_version_after_disconnect() {
+        // The stream drops after batches up to version 150, of which 120 were saved
+        let runs = &Mutex::new(Vec::new());
+        let saved_version = &Mutex::new(None);
+        let result = run_with_reconnects(
+            &no_delay(1),
+            Some(100),
+            move |starting_version| {
+                runs.lock().unwrap().push(starting_version);
+                let first_run = runs.lock().unwrap().len() == 1;
+                async move {
+                    if first_run {
+                        *saved_version.lock().unwrap() = Some(120);
+                        Ok(StreamEnd::Dropped {
+                            last_version: Some(150),
+                        })
+                    } else {
+                        Ok(StreamEnd::Finished)
+                    }
+                }
+            },
+            move || async move { Ok(*saved_version.lock().unwrap()) },
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(*runs.lock().unwrap(), vec![Some(100), Some(120)]);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_drops_without_progress() {
+        let runs = &Mutex::new(0);
+        let result = run_with_reconnects(
+            &no_delay(2),
+            Some(100),
+            move |_| {
+                *runs.lock().unwrap() += 1;
+                async { Ok(StreamEnd::Dropped { last_version: None }) }
+            },
+            || async { Ok(Some(100)) },
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(*runs.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_progress_resets_reconnects() {
+        // Every other run makes progress, so one reconnect is always enough
+        let runs = &Mutex::new(0u64);
+        let result = run_with_reconnects(
+            &no_delay(1),
+            None,
+            move |_| {
+                let mut runs = runs.lock().unwrap();
+                *runs += 1;
+                let run = *runs;
+                async move {
+                    Ok(match run {
+                        6 => StreamEnd::Shutdown,
+                        run if run % 2 == 1 => StreamEnd::Dropped {
+                            last_version: Some(run * 10),
+                        },
+                        _ => StreamEnd::Dropped { last_version: None },
+                    })
+                }
+            },
+            || async { Ok(None) },
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(*runs.lock().unwrap(), 6);
+    }
+}
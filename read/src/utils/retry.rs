@@ -0,0 +1,130 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{QUERY_DEFAULT_RETRIES, QUERY_DEFAULT_RETRY_DELAY_MS};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Debug, future::Future, time::Duration};
+use tracing::{debug, warn};
+
+/// How often and how patiently to retry a query against an external service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueryRetryConfig {
+    /// Number of retries after the first attempt.
+    #[serde(default = "QueryRetryConfig::default_retries")]
+    pub retries: u32,
+    /// Delay before the first retry, doubled after every failed retry.
+    #[serde(default = "QueryRetryConfig::default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+}
+
+impl QueryRetryConfig {
+    pub const fn default_retries() -> u32 {
+        QUERY_DEFAULT_RETRIES
+    }
+
+    pub const fn default_retry_delay_ms() -> u64 {
+        QUERY_DEFAULT_RETRY_DELAY_MS
+    }
+}
+
+impl Default for QueryRetryConfig {
+    fn default() -> Self {
+        Self {
+            retries: Self::default_retries(),
+            retry_delay_ms: Self::default_retry_delay_ms(),
+        }
+    }
+}
+
+/// Runs `operation` until it succeeds or the retries are exhausted, sleeping with exponential
+/// backoff between attempts. The error of the last attempt is returned.
+pub async fn retry_with_backoff<T, F, Fut>(
+    name: &str,
+    retry_config: &QueryRetryConfig,
+    operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let max_attempts = retry_config.retries + 1;
+    retry_with_backoff_if(name, retry_config, |_| true, operation)
+        .await
+        .map_err(|e| e.context(format!("{name} failed after {max_attempts} attempts")))
+}
+
+/// Like [`retry_with_backoff`], but only retries the errors `is_retryable` accepts. Any other
+/// error is returned right away.
+pub async fn retry_with_backoff_if<T, E, F, Fut>(
+    name: &str,
+    retry_config: &QueryRetryConfig,
+    is_retryable: impl Fn(&E) -> bool,
+    mut operation: F,
+) -> Result<T, E>
+where
+    E: Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let max_attempts = retry_config.retries + 1;
+    let mut delay_ms = retry_config.retry_delay_ms;
+    let mut attempt = 1;
+    loop {
+        // Every batch written to the database goes through here
+        debug!(attempt, max_attempts, "Running {name}");
+        match operation().await {
+            Ok(result) => return Ok(result),
+            Err(e) if attempt < max_attempts && is_retryable(&e) => {
+                warn!(attempt, max_attempts, delay_ms, error = ?e, "{name} failed, retrying");
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                delay_ms = delay_ms.saturating_mul(2);
+                attempt += 1;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn no_delay(retries: u32) -> QueryRetryConfig {
+        QueryRetryConfig {
+            retries,
+            retry_delay_ms: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_succeeds_after_failures() {
+        let attempts = AtomicU32::new(0);
+        let counter = &attempts;
+        let result = retry_with_backoff("test", &no_delay(3), move || async move {
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                anyhow::bail!("unavailable");
+            }
+            Ok(42)
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_max_attempts() {
+        let attempts = AtomicU32::new(0);
+        let counter = &attempts;
+        let result: Result<()> = retry_with_backoff("test", &no_delay(2), move || async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            anyhow::bail!("unavailable")
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,5 +1,4 @@
 use aptos_indexer_processor_sdk::{
-    postgres::subconfigs::postgres_config::PostgresConfig,
     testing_framework::{
         database::{PostgresTestDatabase, TestDatabase},
         sdk_test_context::{remove_inserted_at, SdkTestContext},
@@ -12,13 +11,15 @@ use nft_aggregator::{
     config::{
         marketplace_config::NFTMarketplaceConfig,
         processor_mode::{ProcessorMode, TestingConfig},
-        DbConfig, IndexerProcessorConfig,
+        DbConfig, IndexerProcessorConfig, PostgresConfig,
     },
     models::nft_models::{
         CurrentNFTMarketplaceCollectionOffer, CurrentNFTMarketplaceListing,
         CurrentNFTMarketplaceTokenOffer, NftMarketplaceActivity,
     },
     processor::Processor,
+    utils::retry::QueryRetryConfig,
+    RemapOutput,
 };
 use serde_json::Value;
 use std::{
@@ -73,6 +74,7 @@ fn load_data(conn: &mut PgConnection) -> anyhow::Result<HashMap<String, serde_js
                 current_nft_marketplace_token_offers::token_data_id,
                 current_nft_marketplace_token_offers::buyer,
                 current_nft_marketplace_token_offers::marketplace,
+                current_nft_marketplace_token_offers::offer_key,
             ))
             .load::<CurrentNFTMarketplaceTokenOffer>(conn)
             .map_err(|e| anyhow::anyhow!("Failed to load token offers: {}", e))?;
@@ -117,17 +119,37 @@ fn setup_nft_processor_config(
     let postgres_config = PostgresConfig {
         connection_string: db_url.to_string(),
         db_pool_size: 100,
+        db_chunk_size: None,
+        schema: None,
+        connection_timeout_ms: None,
+        idle_timeout_ms: None,
+        statement_timeout_ms: None,
     };
 
     let db_config = DbConfig::PostgresConfig(postgres_config);
     let processor_config = IndexerProcessorConfig {
         transaction_stream_config: transaction_stream_config.clone(),
+        auth_token_path: None,
         db_config,
         processor_mode: ProcessorMode::Testing(TestingConfig {
             override_starting_version: transaction_stream_config.starting_version.unwrap(),
             ending_version: transaction_stream_config.request_ending_version,
         }),
         nft_marketplace_config: build_test_nft_marketplace_config(marketplace_name),
+        nft_marketplace_config_dir: None,
+        query_retry_config: QueryRetryConfig::default(),
+        stream_reconnect_config: QueryRetryConfig::default(),
+        webhook_config: None,
+        clickhouse_config: None,
+        bigquery_config: None,
+        kafka_config: None,
+        health_config: None,
+        activity_retention_days: None,
+        activity_retention_batch_size: None,
+        max_batch_size: None,
+        strict: true,
+        log_config: Default::default(),
+        version_override: Default::default(),
     };
 
     let processor_name = processor_config.nft_marketplace_config.get_name();
@@ -176,6 +198,28 @@ pub fn remove_transaction_timestamp(value: &mut Value) {
     }
 }
 
+/// `updated_at` is set by the database when a row is written, so fixtures don't pin it.
+pub fn remove_updated_at(value: &mut Value) {
+    if let Some(array) = value.as_array_mut() {
+        for item in array.iter_mut() {
+            if let Some(obj) = item.as_object_mut() {
+                obj.remove("updated_at");
+            }
+        }
+    }
+}
+
+/// Content hashes change with any field, so fixtures don't pin them.
+pub fn remove_content_hash(value: &mut Value) {
+    if let Some(array) = value.as_array_mut() {
+        for item in array.iter_mut() {
+            if let Some(obj) = item.as_object_mut() {
+                obj.remove("content_hash");
+            }
+        }
+    }
+}
+
 pub fn validate_json(
     db_values: &mut HashMap<String, Value>,
     txn_version: u64,
@@ -207,8 +251,12 @@ pub fn validate_json(
 
         remove_inserted_at(db_value);
         remove_transaction_timestamp(db_value);
+        remove_content_hash(db_value);
+        remove_updated_at(db_value);
         remove_inserted_at(&mut expected_json);
         remove_transaction_timestamp(&mut expected_json);
+        remove_content_hash(&mut expected_json);
+        remove_updated_at(&mut expected_json);
         println!("Diffing table: {table_name}, diffing version: {txn_version}");
         assert_json_eq!(db_value, expected_json);
     }
@@ -319,7 +367,12 @@ async fn process_transactions(
 #[cfg(test)]
 mod nft_processor_tests {
     use super::*;
-    use aptos_indexer_processor_sdk::testing_framework::cli_parser::get_test_config;
+    use aptos_indexer_processor_sdk::{
+        testing_framework::cli_parser::get_test_config,
+        traits::Processable,
+        types::transaction_context::{TransactionContext, TransactionMetadata},
+        utils::errors::ProcessorError,
+    };
     use aptos_indexer_test_transactions::json_transactions::generated_transactions::{
         IMPORTED_MAINNET_TXNS_2277018899_TRADEPORT_V2_ACCEPT_TOKEN_DELIST_SAME_TOKEN_DATA_ID,
         IMPORTED_MAINNET_TXNS_2296098846_TRADEPORT_V2_ACCEPT_TOKEN_DELIST2,
@@ -343,6 +396,12 @@ mod nft_processor_tests {
         IMPORTED_MAINNET_TXNS_2386889884_TRADEPORT_V2_CANCEL_COLLECTION_OFFER,
         IMPORTED_MAINNET_TXNS_2386891051_TRADEPORT_V2_PLACE_COLLECTION_OFFER,
     };
+    use diesel_migrations::MigrationHarness;
+    use nft_aggregator::{
+        postgres::postgres_utils::{new_db_pool, ArcDbPool},
+        steps::db_writing_step::DBWritingStep,
+        MIGRATIONS,
+    };
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_wapal_place_offer() {
@@ -573,6 +632,1390 @@ mod nft_processor_tests {
         .await;
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_db_writing_step_with_chunk_size_one() {
+        use diesel::prelude::*;
+        use nft_aggregator::schema::nft_marketplace_activities;
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let activities: Vec<NftMarketplaceActivity> = (0..3)
+            .map(|index| NftMarketplaceActivity {
+                txn_version: 1,
+                index,
+                marketplace: "test_marketplace".to_string(),
+                contract_address: "0x1".to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut db_writing = DBWritingStep::new(db_pool, 1, QueryRetryConfig::default());
+        write_batch(&mut db_writing, RemapOutput {
+            activities,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let row_count: i64 = nft_marketplace_activities::table
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(row_count, 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_fill_keeps_fields_of_earlier_place() {
+        use bigdecimal::BigDecimal;
+        use diesel::prelude::*;
+        use nft_aggregator::schema::current_nft_marketplace_listings;
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let place = CurrentNFTMarketplaceListing {
+            token_data_id: "0x3".to_string(),
+            listing_id: Some("0x4".to_string()),
+            seller: Some("0x2".to_string()),
+            price: BigDecimal::from(100),
+            token_name: Some("Token #1".to_string()),
+            marketplace: "test_marketplace".to_string(),
+            contract_address: "0x1".to_string(),
+            last_transaction_version: 1,
+            standard_event_type: "place_listing".to_string(),
+            ..Default::default()
+        };
+        // The fill event only carries the token, like a fill of a listing placed before the
+        // backfill started
+        let fill = CurrentNFTMarketplaceListing {
+            token_data_id: "0x3".to_string(),
+            is_deleted: true,
+            marketplace: "test_marketplace".to_string(),
+            contract_address: "0x1".to_string(),
+            last_transaction_version: 2,
+            standard_event_type: "fill_listing".to_string(),
+            ..Default::default()
+        };
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        for listing in [place, fill] {
+            write_batch(&mut db_writing, RemapOutput {
+                listings: vec![listing],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let listing: CurrentNFTMarketplaceListing = current_nft_marketplace_listings::table
+            .first(&mut conn)
+            .unwrap();
+        assert!(listing.is_deleted);
+        assert_eq!(listing.last_transaction_version, 2);
+        assert_eq!(listing.standard_event_type, "fill_listing");
+        assert_eq!(listing.listing_id.as_deref(), Some("0x4"));
+        assert_eq!(listing.seller.as_deref(), Some("0x2"));
+        assert_eq!(listing.price, BigDecimal::from(100));
+        assert_eq!(listing.token_name.as_deref(), Some("Token #1"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_place_replaces_fields_of_canceled_offer() {
+        use bigdecimal::BigDecimal;
+        use chrono::DateTime;
+        use diesel::prelude::*;
+        use nft_aggregator::{
+            models::nft_models::CurrentNFTMarketplaceTokenOffer,
+            schema::current_nft_marketplace_token_offers,
+        };
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let build_offer = |version: i64, event_type: &str| CurrentNFTMarketplaceTokenOffer {
+            token_data_id: "0x3".to_string(),
+            buyer: "0x2".to_string(),
+            marketplace: "test_marketplace".to_string(),
+            contract_address: "0x1".to_string(),
+            last_transaction_version: version,
+            standard_event_type: event_type.to_string(),
+            ..Default::default()
+        };
+        let canceled = CurrentNFTMarketplaceTokenOffer {
+            price: BigDecimal::from(100),
+            expiration_time: DateTime::from_timestamp(1_700_000_000, 0).map(|t| t.naive_utc()),
+            bid_key: Some(7),
+            is_deleted: true,
+            ..build_offer(1, "cancel_token_offer")
+        };
+        // The new offer is free and never expires
+        let placed = build_offer(2, "place_token_offer");
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        for offer in [canceled, placed] {
+            write_batch(&mut db_writing, RemapOutput {
+                token_offers: vec![offer],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let offer: CurrentNFTMarketplaceTokenOffer = current_nft_marketplace_token_offers::table
+            .first(&mut conn)
+            .unwrap();
+        assert!(!offer.is_deleted);
+        assert_eq!(offer.standard_event_type, "place_token_offer");
+        assert_eq!(offer.price, BigDecimal::from(0));
+        assert_eq!(offer.expiration_time, None);
+        assert_eq!(offer.bid_key, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_update_changes_price_without_reopening() {
+        use bigdecimal::BigDecimal;
+        use diesel::prelude::*;
+        use nft_aggregator::schema::current_nft_marketplace_listings;
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let build_listing = |token_data_id: &str, version: i64, event_type: &str, price: i64| {
+            CurrentNFTMarketplaceListing {
+                token_data_id: token_data_id.to_string(),
+                seller: Some("0x2".to_string()),
+                price: BigDecimal::from(price),
+                is_deleted: event_type == "cancel_listing",
+                marketplace: "test_marketplace".to_string(),
+                contract_address: "0x1".to_string(),
+                last_transaction_version: version,
+                standard_event_type: event_type.to_string(),
+                ..Default::default()
+            }
+        };
+        // 0x3 is placed then repriced, 0x4 is cancelled before a late update
+        let batches = [
+            vec![
+                build_listing("0x3", 1, "place_listing", 100),
+                build_listing("0x4", 1, "cancel_listing", 100),
+            ],
+            vec![
+                build_listing("0x3", 2, "update_listing", 150),
+                build_listing("0x4", 2, "update_listing", 150),
+            ],
+        ];
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        for listings in batches {
+            write_batch(&mut db_writing, RemapOutput {
+                listings,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let listings: Vec<CurrentNFTMarketplaceListing> = current_nft_marketplace_listings::table
+            .order_by(current_nft_marketplace_listings::token_data_id)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(listings[0].price, BigDecimal::from(150));
+        assert!(!listings[0].is_deleted);
+        assert_eq!(listings[0].standard_event_type, "update_listing");
+        assert!(listings[1].is_deleted);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_reprocessing_rewrites_changed_activities_only() {
+        use aptos_indexer_processor_sdk::aptos_protos::{
+            transaction::v1::{transaction::TxnData, Event, Transaction, UserTransaction},
+            util::timestamp::Timestamp,
+        };
+        use diesel::prelude::*;
+        use nft_aggregator::{
+            schema::nft_marketplace_activities, steps::remappers::event_remapper::EventRemapper,
+        };
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let event_type = "0x584b50b999c78ade62f8359c91b5165ff390338d45f8e55969a04e65d76258c9::events::ListingPlacedEvent";
+        let txn = Transaction {
+            version: 1,
+            txn_data: Some(TxnData::User(UserTransaction {
+                request: None,
+                events: vec![Event {
+                    key: Some(Default::default()),
+                    type_str: event_type.to_string(),
+                    data: serde_json::json!({
+                        "price": "100",
+                        "token": "0x3",
+                        "token_name": "Token #1",
+                    })
+                    .to_string(),
+                    ..Default::default()
+                }],
+            })),
+            timestamp: Some(Timestamp {
+                seconds: 1,
+                nanos: 0,
+            }),
+            ..Default::default()
+        };
+        // The last config also maps the token name to the activities, like a fix to the
+        // remapping would
+        let build_config = |token_name_table: &str| -> NFTMarketplaceConfig {
+            serde_yaml::from_str(&format!(
+                r#"
+                name: test_marketplace
+                event_model_mapping:
+                  {event_type}: place_listing
+                events:
+                  {event_type}:
+                    event_fields:
+                      "$.price":
+                        - table: nft_marketplace_activities
+                          column: price
+                      "$.token":
+                        - table: nft_marketplace_activities
+                          column: token_data_id
+                      "$.token_name":
+                        - table: {token_name_table}
+                          column: token_name
+                "#
+            ))
+            .unwrap()
+        };
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let mut stored = Vec::new();
+        for config in [
+            build_config("current_nft_marketplace_listings"),
+            build_config("current_nft_marketplace_listings"),
+            build_config("nft_marketplace_activities"),
+        ] {
+            let RemapOutput { activities, .. } = EventRemapper::new(&config)
+                .unwrap()
+                .remap_events(txn.clone())
+                .unwrap();
+            write_batch(&mut db_writing, RemapOutput {
+                activities,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+            let activity: NftMarketplaceActivity =
+                nft_marketplace_activities::table.first(&mut conn).unwrap();
+            stored.push(activity);
+        }
+
+        assert!(stored[0].content_hash.is_some());
+        assert_eq!(stored[0].token_name, None);
+        assert_eq!(stored[1].content_hash, stored[0].content_hash);
+        assert_eq!(stored[2].token_name.as_deref(), Some("Token #1"));
+        assert_ne!(stored[2].content_hash, stored[0].content_hash);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_active_listing_and_offer_queries() {
+        use bigdecimal::BigDecimal;
+        use nft_aggregator::postgres::queries::{
+            active_listings_for_collection, active_listings_for_seller,
+            active_token_offers_for_buyer,
+        };
+
+        let (_db, _, db_pool) = setup_db_writing().await;
+
+        let build_listing =
+            |token_data_id: &str, price: i64, is_deleted: bool| CurrentNFTMarketplaceListing {
+                token_data_id: token_data_id.to_string(),
+                collection_id: Some("0xc".to_string()),
+                seller: Some("0x2".to_string()),
+                price: BigDecimal::from(price),
+                is_deleted,
+                marketplace: "test_marketplace".to_string(),
+                contract_address: "0x1".to_string(),
+                last_transaction_version: 1,
+                standard_event_type: "place_listing".to_string(),
+                ..Default::default()
+            };
+        let listings = vec![
+            build_listing("0x3", 200, false),
+            build_listing("0x4", 100, false),
+            build_listing("0x5", 50, true),
+        ];
+        let build_offer = |token_data_id: &str, is_deleted: bool| CurrentNFTMarketplaceTokenOffer {
+            token_data_id: token_data_id.to_string(),
+            buyer: "0x6".to_string(),
+            price: BigDecimal::from(10),
+            is_deleted,
+            marketplace: "test_marketplace".to_string(),
+            contract_address: "0x1".to_string(),
+            last_transaction_version: 1,
+            standard_event_type: "place_token_offer".to_string(),
+            ..Default::default()
+        };
+        let token_offers = vec![build_offer("0x3", false), build_offer("0x4", true)];
+
+        let mut db_writing = DBWritingStep::new(db_pool.clone(), 100, QueryRetryConfig::default());
+        write_batch(&mut db_writing, RemapOutput {
+            listings,
+            token_offers,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut conn = db_pool.get().await.unwrap();
+        let listings = active_listings_for_collection(&mut conn, "0xc")
+            .await
+            .unwrap();
+        let token_data_ids: Vec<&str> = listings.iter().map(|l| l.token_data_id.as_str()).collect();
+        assert_eq!(token_data_ids, vec!["0x4", "0x3"]);
+        assert!(active_listings_for_collection(&mut conn, "0xd")
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            active_listings_for_seller(&mut conn, "0x2")
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let offers = active_token_offers_for_buyer(&mut conn, "0x6")
+            .await
+            .unwrap();
+        assert_eq!(offers.len(), 1);
+        assert_eq!(offers[0].token_data_id, "0x3");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_partial_fills_decrement_stored_listing() {
+        use bigdecimal::BigDecimal;
+        use diesel::prelude::*;
+        use nft_aggregator::schema::current_nft_marketplace_listings;
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let build_listing =
+            |version: i64, standard_event_type: &str| CurrentNFTMarketplaceListing {
+                token_data_id: "0x3".to_string(),
+                price: BigDecimal::from(100),
+                marketplace: "test_marketplace".to_string(),
+                contract_address: "0x1".to_string(),
+                last_transaction_version: version,
+                standard_event_type: standard_event_type.to_string(),
+                ..Default::default()
+            };
+        let build_fill = |version: i64, filled: i64| CurrentNFTMarketplaceListing {
+            filled_token_amount: Some(BigDecimal::from(filled)),
+            is_deleted: true,
+            ..build_listing(version, "fill_listing")
+        };
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let mut stored = Vec::new();
+        for listing in [
+            CurrentNFTMarketplaceListing {
+                token_amount: Some(BigDecimal::from(5)),
+                ..build_listing(1, "place_listing")
+            },
+            build_fill(2, 2),
+            // Reprocessing the same fill doesn't take the tokens twice
+            build_fill(2, 2),
+            build_fill(3, 3),
+        ] {
+            write_batch(&mut db_writing, RemapOutput {
+                listings: vec![listing],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+            let listing: CurrentNFTMarketplaceListing = current_nft_marketplace_listings::table
+                .first(&mut conn)
+                .unwrap();
+            stored.push((listing.token_amount, listing.is_deleted));
+        }
+
+        assert_eq!(stored, vec![
+            (Some(BigDecimal::from(5)), false),
+            (Some(BigDecimal::from(3)), false),
+            (Some(BigDecimal::from(3)), false),
+            (Some(BigDecimal::from(0)), true),
+        ]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_activity_retention_prunes_old_activities_only() {
+        use chrono::{TimeDelta, Utc};
+        use diesel::prelude::*;
+        use nft_aggregator::{
+            postgres::activity_retention::prune_activities, schema::nft_marketplace_activities,
+        };
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let now = Utc::now().naive_utc();
+        let build_activity =
+            |txn_version: i64, marketplace: &str, age_days: i64| NftMarketplaceActivity {
+                txn_version,
+                marketplace: marketplace.to_string(),
+                contract_address: "0x1".to_string(),
+                standard_event_type: "place_listing".to_string(),
+                block_timestamp: now - TimeDelta::days(age_days),
+                ..Default::default()
+            };
+        let activities = vec![
+            build_activity(1, "test_marketplace", 120),
+            build_activity(2, "test_marketplace", 100),
+            build_activity(3, "test_marketplace", 95),
+            build_activity(4, "test_marketplace", 10),
+            // Other marketplaces' activities are left to their own processors
+            build_activity(1, "other_marketplace", 120),
+        ];
+
+        let mut db_writing = DBWritingStep::new(db_pool.clone(), 100, QueryRetryConfig::default());
+        write_batch(&mut db_writing, RemapOutput {
+            activities,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // A batch ending on version 2, then the rest
+        let deleted = prune_activities(db_pool, "test_marketplace", 90, 2)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 3);
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let remaining: Vec<(i64, String)> = nft_marketplace_activities::table
+            .select((
+                nft_marketplace_activities::txn_version,
+                nft_marketplace_activities::marketplace,
+            ))
+            .order_by(nft_marketplace_activities::marketplace)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(remaining, vec![
+            (1, "other_marketplace".to_string()),
+            (4, "test_marketplace".to_string()),
+        ]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_failed_batch_rolls_back_every_table() {
+        use diesel::prelude::*;
+        use nft_aggregator::schema::{
+            current_nft_marketplace_listings, nft_marketplace_activities,
+        };
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let activity = NftMarketplaceActivity {
+            txn_version: 1,
+            marketplace: "test_marketplace".to_string(),
+            contract_address: "0x1".to_string(),
+            standard_event_type: "place_listing".to_string(),
+            ..Default::default()
+        };
+        let listing = CurrentNFTMarketplaceListing {
+            token_data_id: "0x3".to_string(),
+            marketplace: "test_marketplace".to_string(),
+            last_transaction_version: 1,
+            ..Default::default()
+        };
+        // Too long for the column, so the insert fails even once the data is cleaned
+        let token_offer = CurrentNFTMarketplaceTokenOffer {
+            token_data_id: format!("0x{}", "3".repeat(80)),
+            buyer: "0x4".to_string(),
+            marketplace: "test_marketplace".to_string(),
+            last_transaction_version: 1,
+            ..Default::default()
+        };
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        let result = write_batch(&mut db_writing, RemapOutput {
+            activities: vec![activity],
+            listings: vec![listing],
+            token_offers: vec![token_offer],
+            ..Default::default()
+        })
+        .await;
+        assert!(result.is_err());
+
+        // The rows written before the failing insert were rolled back with it
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let activity_count: i64 = nft_marketplace_activities::table
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        let listing_count: i64 = current_nft_marketplace_listings::table
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!((activity_count, listing_count), (0, 0));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_collection_offer_fills_record_each_token() {
+        use bigdecimal::BigDecimal;
+        use diesel::prelude::*;
+        use nft_aggregator::{
+            models::nft_models::CollectionOfferFill,
+            schema::{collection_offer_fills, current_nft_marketplace_collection_offers},
+        };
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let build_fill = |txn_version: i64, token_data_id: &str, seller: &str| {
+            let activity = NftMarketplaceActivity {
+                txn_version,
+                marketplace: "test_marketplace".to_string(),
+                contract_address: "0x1".to_string(),
+                standard_event_type: "fill_collection_offer".to_string(),
+                offer_id: Some("0x2".to_string()),
+                token_data_id: Some(token_data_id.to_string()),
+                buyer: Some("0x3".to_string()),
+                seller: Some(seller.to_string()),
+                price: BigDecimal::from(100),
+                ..Default::default()
+            };
+            let offer = CurrentNFTMarketplaceCollectionOffer {
+                collection_offer_id: "0x2".to_string(),
+                buyer: "0x3".to_string(),
+                price: BigDecimal::from(100),
+                remaining_token_amount: Some(BigDecimal::from(3 - txn_version)),
+                marketplace: "test_marketplace".to_string(),
+                contract_address: "0x1".to_string(),
+                last_transaction_version: txn_version,
+                standard_event_type: "fill_collection_offer".to_string(),
+                token_data_id: Some(token_data_id.to_string()),
+                ..Default::default()
+            };
+            (activity, offer)
+        };
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        for (activity, offer) in [build_fill(1, "0x4", "0x5"), build_fill(2, "0x6", "0x7")] {
+            write_batch(&mut db_writing, RemapOutput {
+                activities: vec![activity],
+                collection_offers: vec![offer],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+
+        // The offer only keeps the token of its latest fill
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let offer: CurrentNFTMarketplaceCollectionOffer =
+            current_nft_marketplace_collection_offers::table
+                .first(&mut conn)
+                .unwrap();
+        assert_eq!(offer.token_data_id.as_deref(), Some("0x6"));
+
+        let fills: Vec<CollectionOfferFill> = collection_offer_fills::table
+            .order_by(collection_offer_fills::txn_version)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(
+            fills
+                .iter()
+                .map(|fill| (
+                    fill.collection_offer_id.as_str(),
+                    fill.token_data_id.as_str(),
+                    fill.seller.as_deref(),
+                    fill.txn_version
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("0x2", "0x4", Some("0x5"), 1),
+                ("0x2", "0x6", Some("0x7"), 2)
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_replay_remaps_exported_transactions() {
+        use nft_aggregator::replay::{load_transactions, replay};
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("2382251863.json"),
+            IMPORTED_MAINNET_TXNS_2382251863_WAPAL_PLACE_LISTING,
+        )
+        .unwrap();
+        fs::write(dir.path().join("README.md"), "Not a transaction").unwrap();
+
+        let transactions = load_transactions(dir.path()).unwrap();
+        assert_eq!(transactions.len(), 1);
+        let output = replay(&build_test_nft_marketplace_config("wapal"), transactions)
+            .await
+            .unwrap();
+
+        // The same rows the processor writes for this transaction
+        let expected_listings = read_and_parse_json(&format!(
+            "{DEFAULT_OUTPUT_FOLDER}/wapal/wapal_place_listing_test/current_nft_marketplace_listings.json"
+        ))
+        .unwrap();
+        let mut listings = output.listings;
+        listings.sort_by(|a, b| a.token_data_id.cmp(&b.token_data_id));
+        assert_eq!(
+            listings
+                .iter()
+                .map(|listing| listing.token_data_id.as_str())
+                .collect::<Vec<_>>(),
+            expected_listings
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|listing| listing["token_data_id"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert!(listings
+            .iter()
+            .all(|listing| listing.standard_event_type == "place_listing" && !listing.is_deleted));
+        assert_eq!(output.activities.len(), 2);
+        assert!(output.token_offers.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_upsert_refreshes_updated_at() {
+        use bigdecimal::BigDecimal;
+        use diesel::prelude::*;
+        use nft_aggregator::schema::current_nft_marketplace_listings;
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let build_listing = |version: i64, price: i64| CurrentNFTMarketplaceListing {
+            token_data_id: "0x3".to_string(),
+            price: BigDecimal::from(price),
+            marketplace: "test_marketplace".to_string(),
+            contract_address: "0x1".to_string(),
+            last_transaction_version: version,
+            standard_event_type: "place_listing".to_string(),
+            ..Default::default()
+        };
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let mut stored = Vec::new();
+        for listing in [build_listing(1, 100), build_listing(2, 200)] {
+            write_batch(&mut db_writing, RemapOutput {
+                listings: vec![listing],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+            let listing: CurrentNFTMarketplaceListing = current_nft_marketplace_listings::table
+                .first(&mut conn)
+                .unwrap();
+            stored.push(listing);
+        }
+
+        let (first, second) = (&stored[0], &stored[1]);
+        assert_eq!(second.price, BigDecimal::from(200));
+        assert_eq!(first.inserted_at, first.updated_at);
+        assert_eq!(second.inserted_at, first.inserted_at);
+        assert!(second.updated_at.unwrap() > first.updated_at.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_offer_id_key_stores_each_offer_of_a_buyer() {
+        use bigdecimal::BigDecimal;
+        use diesel::prelude::*;
+        use nft_aggregator::schema::current_nft_marketplace_token_offers;
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let build_offer = |offer_id: &str, price: i64| CurrentNFTMarketplaceTokenOffer {
+            token_data_id: "0x3".to_string(),
+            offer_id: Some(offer_id.to_string()),
+            buyer: "0x2".to_string(),
+            price: BigDecimal::from(price),
+            marketplace: "test_marketplace".to_string(),
+            contract_address: "0x1".to_string(),
+            last_transaction_version: 1,
+            standard_event_type: "place_token_offer".to_string(),
+            offer_key: offer_id.to_string(),
+            ..Default::default()
+        };
+        write_batch(
+            &mut DBWritingStep::new(db_pool, 100, QueryRetryConfig::default()),
+            RemapOutput {
+                token_offers: vec![build_offer("0x4", 100), build_offer("0x5", 200)],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let offers: Vec<CurrentNFTMarketplaceTokenOffer> =
+            current_nft_marketplace_token_offers::table
+                .order_by(current_nft_marketplace_token_offers::offer_key)
+                .load(&mut conn)
+                .unwrap();
+        assert_eq!(offers.len(), 2);
+        assert_eq!(offers[0].offer_id.as_deref(), Some("0x4"));
+        assert_eq!(offers[0].price, BigDecimal::from(100));
+        assert_eq!(offers[1].offer_id.as_deref(), Some("0x5"));
+        assert_eq!(offers[1].price, BigDecimal::from(200));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_transaction_touching_two_marketplaces() {
+        use aptos_indexer_processor_sdk::aptos_protos::{
+            transaction::v1::{transaction::TxnData, Event, Transaction, UserTransaction},
+            util::timestamp::Timestamp,
+        };
+        use diesel::prelude::*;
+        use nft_aggregator::{
+            config::marketplace_config::NFTMarketplaceConfigs,
+            replay::replay,
+            schema::{current_nft_marketplace_listings, nft_marketplace_activities},
+        };
+
+        // An aggregator contract routing one purchase to two marketplaces
+        let dir = tempfile::tempdir().unwrap();
+        let marketplaces = [("wapal", "0xa11ce"), ("tradeport", "0xb0b")];
+        for (name, contract) in marketplaces {
+            fs::write(
+                dir.path().join(format!("{name}.yaml")),
+                format!(
+                    r#"
+name: {name}
+event_model_mapping:
+  {contract}::market::ListingPlaced: place_listing
+events:
+  {contract}::market::ListingPlaced:
+    event_fields:
+      "$.price":
+        - table: nft_marketplace_activities
+          column: price
+        - table: current_nft_marketplace_listings
+          column: price
+      "$.seller":
+        - table: nft_marketplace_activities
+          column: seller
+        - table: current_nft_marketplace_listings
+          column: seller
+      "$.token":
+        - table: nft_marketplace_activities
+          column: token_data_id
+        - table: current_nft_marketplace_listings
+          column: token_data_id
+"#
+                ),
+            )
+            .unwrap();
+        }
+        let configs = NFTMarketplaceConfigs::load_dir(dir.path()).unwrap();
+        assert_eq!(configs.marketplaces.len(), 2);
+
+        let events = marketplaces
+            .iter()
+            .enumerate()
+            .map(|(i, (_, contract))| Event {
+                key: Some(Default::default()),
+                sequence_number: i as u64,
+                r#type: Some(Default::default()),
+                type_str: format!("{contract}::market::ListingPlaced"),
+                data: serde_json::json!({
+                    "price": (100 * (i + 1)).to_string(),
+                    "seller": "0x2",
+                    "token": "0x3",
+                })
+                .to_string(),
+            })
+            .collect();
+        let transaction = Transaction {
+            version: 1,
+            block_height: 1,
+            txn_data: Some(TxnData::User(UserTransaction {
+                request: None,
+                events,
+            })),
+            timestamp: Some(Timestamp {
+                seconds: 1,
+                nanos: 0,
+            }),
+            info: None,
+            epoch: 1,
+            r#type: 1,
+            size_info: None,
+        };
+
+        // Each marketplace runs its own processor over the same transaction
+        let mut output = RemapOutput::default();
+        for config in &configs.marketplaces {
+            let remapped = replay(config, vec![transaction.clone()]).await.unwrap();
+            assert_eq!(remapped.activities.len(), 1);
+            output.extend(remapped);
+        }
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+        write_batch(
+            &mut DBWritingStep::new(db_pool, 100, QueryRetryConfig::default()),
+            output,
+        )
+        .await
+        .unwrap();
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let activities: Vec<NftMarketplaceActivity> = nft_marketplace_activities::table
+            .order_by(nft_marketplace_activities::index)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(
+            activities
+                .iter()
+                .map(|activity| (activity.marketplace.as_str(), activity.index))
+                .collect::<Vec<_>>(),
+            vec![("wapal", 0), ("tradeport", 1)]
+        );
+
+        // The same token is listed once per marketplace
+        let listings: Vec<CurrentNFTMarketplaceListing> = current_nft_marketplace_listings::table
+            .order_by(current_nft_marketplace_listings::marketplace)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(
+            listings
+                .iter()
+                .map(|listing| (listing.marketplace.as_str(), listing.price.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("tradeport", "200".to_string()),
+                ("wapal", "100".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_transfer_invalidates_listing() {
+        use diesel::prelude::*;
+        use nft_aggregator::{
+            postgres::token_owners::TokenOwners, schema::current_nft_marketplace_listings,
+        };
+        use std::sync::Arc;
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+
+        let build_listing =
+            |token_data_id: &str, listing_id: &str, version: i64| CurrentNFTMarketplaceListing {
+                token_data_id: token_data_id.to_string(),
+                listing_id: Some(listing_id.to_string()),
+                seller: Some("0x2".to_string()),
+                marketplace: "test_marketplace".to_string(),
+                contract_address: "0x1".to_string(),
+                last_transaction_version: version,
+                standard_event_type: "place_listing".to_string(),
+                ..Default::default()
+            };
+        let metadata = |end_version: u64| TransactionMetadata {
+            end_version,
+            ..Default::default()
+        };
+
+        let token_owners = Arc::new(TokenOwners::new("test_marketplace".to_string()));
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default())
+            .with_token_owners(Some(token_owners.clone()));
+        db_writing
+            .process(TransactionContext {
+                data: RemapOutput {
+                    listings: vec![
+                        build_listing("0x3", "0x4", 1),
+                        build_listing("0x6", "0x7", 1),
+                    ],
+                    ..Default::default()
+                },
+                metadata: metadata(1),
+            })
+            .await
+            .unwrap();
+
+        // The seller transfers 0x3 away, while 0x6 moves into its listing object. The owner
+        // recorded for a later batch isn't applied yet.
+        token_owners.record("0x3".to_string(), "0x5".to_string(), 5);
+        token_owners.record("0x6".to_string(), "0x7".to_string(), 5);
+        token_owners.record("0x6".to_string(), "0x8".to_string(), 20);
+        db_writing
+            .process(TransactionContext {
+                data: RemapOutput::default(),
+                metadata: metadata(10),
+            })
+            .await
+            .unwrap();
+
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let load_listings = |conn: &mut PgConnection| {
+            current_nft_marketplace_listings::table
+                .order_by(current_nft_marketplace_listings::token_data_id)
+                .load::<CurrentNFTMarketplaceListing>(conn)
+                .unwrap()
+        };
+        let listings = load_listings(&mut conn);
+        assert_eq!(listings[0].owner.as_deref(), Some("0x5"));
+        assert!(listings[0].is_stale);
+        assert_eq!(listings[1].owner.as_deref(), Some("0x7"));
+        assert!(!listings[1].is_stale);
+
+        // Relisting the token clears the flag
+        db_writing
+            .process(TransactionContext {
+                data: RemapOutput {
+                    listings: vec![build_listing("0x3", "0x9", 11)],
+                    ..Default::default()
+                },
+                metadata: metadata(11),
+            })
+            .await
+            .unwrap();
+        let listings = load_listings(&mut conn);
+        assert!(!listings[0].is_stale);
+        assert_eq!(listings[0].listing_id.as_deref(), Some("0x9"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_processor_writes_to_configured_schema() {
+        use diesel::{prelude::*, sql_types::Bool};
+
+        let (db, mut test_context) =
+            setup_test_environment(&[IMPORTED_MAINNET_TXNS_2386809975_TRADEPORT_V2_PLACE_LISTING])
+                .await;
+        let db_url = db.get_db_url();
+        let (mut processor_config, _) =
+            setup_nft_processor_config(&test_context, &db_url, "tradeport_v2");
+        let DbConfig::PostgresConfig(ref mut postgres_config) = processor_config.db_config;
+        postgres_config.schema = Some("tenant_a".to_string());
+        let processor = Processor::new(processor_config).await.unwrap();
+
+        let db_values = run_processor_test(
+            &mut test_context,
+            processor,
+            |conn| {
+                diesel::sql_query("SET search_path TO tenant_a").execute(conn)?;
+                load_data(conn)
+            },
+            db_url.clone(),
+            false,
+            DEFAULT_OUTPUT_FOLDER.to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            db_values["current_nft_marketplace_listings"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+
+        #[derive(QueryableByName)]
+        struct TableExists {
+            #[diesel(sql_type = Bool)]
+            exists: bool,
+        }
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        let public_listings: TableExists = diesel::sql_query(
+            "SELECT to_regclass('public.current_nft_marketplace_listings') IS NOT NULL AS exists",
+        )
+        .get_result(&mut conn)
+        .unwrap();
+        assert!(!public_listings.exists);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_schema_migrations_run_next_to_public() {
+        use diesel::{prelude::*, sql_types::BigInt};
+        use nft_aggregator::{
+            postgres::postgres_utils::{create_schema, run_schema_migrations},
+            utils::chain_id::check_or_update_chain_id,
+        };
+
+        // `public` already moved its processor status to `processor_metadata`
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        diesel::sql_query(
+            "INSERT INTO processor_status (processor, last_success_version) VALUES ('wapal', 1)",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        diesel::sql_query(
+            "INSERT INTO processor_metadata.processor_status (processor, last_success_version) \
+             VALUES ('wapal', 1)",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        check_or_update_chain_id(1, None, db_pool.clone())
+            .await
+            .unwrap();
+
+        let postgres_config = PostgresConfig {
+            connection_string: db_url.clone(),
+            db_pool_size: 10,
+            db_chunk_size: None,
+            schema: Some("tenant_b".to_string()),
+            connection_timeout_ms: None,
+            idle_timeout_ms: None,
+            statement_timeout_ms: None,
+        };
+        let tenant_pool = new_db_pool(
+            &postgres_config.connection_string_with_schema().unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+        create_schema(&tenant_pool, "tenant_b").await.unwrap();
+        run_schema_migrations(&postgres_config, tenant_pool.clone())
+            .await
+            .unwrap();
+
+        // The schema keeps its own chain id
+        check_or_update_chain_id(2, None, tenant_pool)
+            .await
+            .unwrap();
+        #[derive(QueryableByName)]
+        struct ChainId {
+            #[diesel(sql_type = BigInt)]
+            chain_id: i64,
+        }
+        let chain_ids: Vec<i64> = diesel::sql_query(
+            "SELECT chain_id FROM public.ledger_infos \
+             UNION ALL SELECT chain_id FROM tenant_b.ledger_infos ORDER BY chain_id",
+        )
+        .load::<ChainId>(&mut conn)
+        .unwrap()
+        .into_iter()
+        .map(|row| row.chain_id)
+        .collect();
+        assert_eq!(chain_ids, vec![1, 2]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_max_versions_stops_at_the_limit() {
+        let (db, mut test_context) = setup_test_environment(&[
+            IMPORTED_MAINNET_TXNS_2296098846_TRADEPORT_V2_ACCEPT_TOKEN_DELIST2,
+            IMPORTED_MAINNET_TXNS_2296149225_TRADEPORT_V2_ACCEPT_TOKEN_DELIST,
+        ])
+        .await;
+        let db_url = db.get_db_url();
+        let (mut processor_config, _) =
+            setup_nft_processor_config(&test_context, &db_url, "tradeport_v2");
+        processor_config.version_override.max_versions = Some(1);
+        let processor = Processor::new(processor_config).await.unwrap();
+
+        let db_values = run_processor_test(
+            &mut test_context,
+            processor,
+            load_data,
+            db_url,
+            false,
+            DEFAULT_OUTPUT_FOLDER.to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let activities = db_values["nft_marketplace_activities"].as_array().unwrap();
+        assert!(!activities.is_empty());
+        assert!(activities
+            .iter()
+            .all(|activity| activity["txn_version"] == 2296098846i64));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_statement_timeout_aborts_slow_queries() {
+        use diesel_async::RunQueryDsl;
+        use nft_aggregator::postgres::postgres_utils::{new_db_pool_with_timeouts, PoolTimeouts};
+        use std::time::Duration;
+
+        let mut db = PostgresTestDatabase::new();
+        db.setup().await.unwrap();
+        let db_url = db.get_db_url();
+
+        let db_pool = new_db_pool_with_timeouts(&db_url, Some(1), PoolTimeouts {
+            connection_timeout: Some(Duration::from_secs(5)),
+            statement_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut conn = db_pool.get().await.unwrap();
+
+        let err = diesel::sql_query("SELECT pg_sleep(1)")
+            .execute(&mut conn)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("canceling statement due to statement timeout"),
+            "unexpected error: {err}"
+        );
+        // Quick statements still go through on the same connection
+        diesel::sql_query("SELECT 1")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_upsert_updates_every_column() {
+        use bigdecimal::BigDecimal;
+        use chrono::DateTime;
+        use diesel::prelude::*;
+        use nft_aggregator::{
+            models::nft_models::{
+                CurrentNFTMarketplaceAuction, CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+                CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+                CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+                CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME,
+            },
+            schema::{
+                current_nft_marketplace_auctions, current_nft_marketplace_collection_offers,
+                current_nft_marketplace_listings, current_nft_marketplace_token_offers,
+            },
+        };
+
+        let (_db, db_url, db_pool) = setup_db_writing().await;
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+
+        // Each version sets every column but the keys to a value of its own
+        let text = |name: &str, version: i64| format!("{name}_{version}");
+        let number = |version: i64| BigDecimal::from(version * 100);
+        let timestamp = |version: i64| {
+            DateTime::from_timestamp(version * 1000, 0)
+                .unwrap()
+                .naive_utc()
+        };
+        let build_listing = |version: i64| CurrentNFTMarketplaceListing {
+            token_data_id: "0x3".to_string(),
+            listing_id: Some(text("listing", version)),
+            collection_id: Some(text("collection", version)),
+            seller: Some(text("seller", version)),
+            price: number(version),
+            token_amount: Some(number(version)),
+            token_name: Some(text("token", version)),
+            is_deleted: version % 2 == 0,
+            marketplace: "test_marketplace".to_string(),
+            contract_address: text("contract", version),
+            last_transaction_version: version,
+            last_transaction_timestamp: timestamp(version),
+            standard_event_type: text("event", version),
+            price_raw: Some(number(version)),
+            price_display: Some(number(version)),
+            payment_token: text("coin", version),
+            creator_address: Some(text("creator", version)),
+            collection_name: Some(text("name", version)),
+            token_standard: Some(text("v", version)),
+            filled_token_amount: Some(number(version)),
+            is_stale: version % 2 == 0,
+            ..Default::default()
+        };
+        let build_token_offer = |version: i64| CurrentNFTMarketplaceTokenOffer {
+            token_data_id: "0x3".to_string(),
+            offer_id: Some(text("offer", version)),
+            marketplace: "test_marketplace".to_string(),
+            collection_id: Some(text("collection", version)),
+            buyer: "0xb".to_string(),
+            price: number(version),
+            token_amount: Some(number(version)),
+            token_name: Some(text("token", version)),
+            is_deleted: version % 2 == 0,
+            contract_address: text("contract", version),
+            last_transaction_version: version,
+            last_transaction_timestamp: timestamp(version),
+            standard_event_type: text("event", version),
+            expiration_time: Some(timestamp(version)),
+            bid_key: Some(version),
+            price_raw: Some(number(version)),
+            price_display: Some(number(version)),
+            payment_token: text("coin", version),
+            creator_address: Some(text("creator", version)),
+            collection_name: Some(text("name", version)),
+            token_standard: Some(text("v", version)),
+            offer_key: "0xb".to_string(),
+            property_version: Some(number(version)),
+            ..Default::default()
+        };
+        let build_collection_offer = |version: i64| CurrentNFTMarketplaceCollectionOffer {
+            collection_offer_id: "0x5".to_string(),
+            collection_id: Some(text("collection", version)),
+            buyer: text("buyer", version),
+            price: number(version),
+            remaining_token_amount: Some(number(version)),
+            is_deleted: version % 2 == 0,
+            marketplace: "test_marketplace".to_string(),
+            contract_address: text("contract", version),
+            last_transaction_version: version,
+            last_transaction_timestamp: timestamp(version),
+            standard_event_type: text("event", version),
+            token_data_id: Some(text("token", version)),
+            expiration_time: Some(timestamp(version)),
+            bid_key: Some(version),
+            price_raw: Some(number(version)),
+            price_display: Some(number(version)),
+            payment_token: text("coin", version),
+            creator_address: Some(text("creator", version)),
+            collection_name: Some(text("name", version)),
+            token_standard: Some(text("v", version)),
+            ..Default::default()
+        };
+        let build_auction = |version: i64| CurrentNFTMarketplaceAuction {
+            token_data_id: "0x3".to_string(),
+            marketplace: "test_marketplace".to_string(),
+            auction_id: Some(text("auction", version)),
+            collection_id: Some(text("collection", version)),
+            token_name: Some(text("token", version)),
+            seller: Some(text("seller", version)),
+            current_bidder: Some(text("bidder", version)),
+            starting_price: Some(number(version)),
+            ending_price: Some(number(version)),
+            current_bid: Some(number(version)),
+            start_time: Some(timestamp(version)),
+            end_time: Some(timestamp(version)),
+            is_deleted: version % 2 == 0,
+            contract_address: text("contract", version),
+            last_transaction_version: version,
+            last_transaction_timestamp: timestamp(version),
+            standard_event_type: text("event", version),
+            payment_token: text("coin", version),
+            token_standard: Some(text("v", version)),
+            ..Default::default()
+        };
+
+        let mut db_writing = DBWritingStep::new(db_pool, 100, QueryRetryConfig::default());
+        for version in [1, 2] {
+            write_batch(&mut db_writing, RemapOutput {
+                listings: vec![build_listing(version)],
+                token_offers: vec![build_token_offer(version)],
+                collection_offers: vec![build_collection_offer(version)],
+                auctions: vec![build_auction(version)],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+
+        let listing: CurrentNFTMarketplaceListing = current_nft_marketplace_listings::table
+            .first(&mut conn)
+            .unwrap();
+        let token_offer: CurrentNFTMarketplaceTokenOffer =
+            current_nft_marketplace_token_offers::table
+                .first(&mut conn)
+                .unwrap();
+        let collection_offer: CurrentNFTMarketplaceCollectionOffer =
+            current_nft_marketplace_collection_offers::table
+                .first(&mut conn)
+                .unwrap();
+        let auction: CurrentNFTMarketplaceAuction = current_nft_marketplace_auctions::table
+            .first(&mut conn)
+            .unwrap();
+        // Every column but the timestamps set by the database takes the later version's value.
+        // The keys and the listing's owner, which only `update_listing_owners` writes, are the
+        // same in both versions.
+        for (table, stored, expected) in [
+            (
+                CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME,
+                serde_json::to_value(listing).unwrap(),
+                serde_json::to_value(build_listing(2)).unwrap(),
+            ),
+            (
+                CURRENT_NFT_MARKETPLACE_TOKEN_OFFERS_TABLE_NAME,
+                serde_json::to_value(token_offer).unwrap(),
+                serde_json::to_value(build_token_offer(2)).unwrap(),
+            ),
+            (
+                CURRENT_NFT_MARKETPLACE_COLLECTION_OFFERS_TABLE_NAME,
+                serde_json::to_value(collection_offer).unwrap(),
+                serde_json::to_value(build_collection_offer(2)).unwrap(),
+            ),
+            (
+                CURRENT_NFT_MARKETPLACE_AUCTIONS_TABLE_NAME,
+                serde_json::to_value(auction).unwrap(),
+                serde_json::to_value(build_auction(2)).unwrap(),
+            ),
+        ] {
+            let serde_json::Value::Object(stored) = stored else {
+                panic!("{table} row isn't an object");
+            };
+            for (column, value) in stored {
+                if ["inserted_at", "updated_at"].contains(&column.as_str()) {
+                    continue;
+                }
+                assert_eq!(value, expected[&column], "{table}.{column}");
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_chain_id_mismatch_errors() {
+        use nft_aggregator::{
+            config::marketplace_config::Network, utils::chain_id::check_or_update_chain_id,
+        };
+
+        let (_db, _, db_pool) = setup_db_writing().await;
+
+        // The first run stores the chain id, later runs against the same chain pass
+        assert_eq!(
+            check_or_update_chain_id(1, None, db_pool.clone())
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            check_or_update_chain_id(1, Some(Network::Mainnet), db_pool.clone())
+                .await
+                .unwrap(),
+            1
+        );
+
+        let err = check_or_update_chain_id(2, None, db_pool.clone())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Wrong chain id detected"));
+
+        // A config for another network fails even before the stored chain id is looked at
+        let err = check_or_update_chain_id(1, Some(Network::Testnet), db_pool)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("The config is for testnet"));
+    }
+
+    /// Creates a test database with the migrations applied, and returns it with its url and a
+    /// pool to it. The database lives as long as the returned handle.
+    async fn setup_db_writing() -> (PostgresTestDatabase, String, ArcDbPool) {
+        let mut db = PostgresTestDatabase::new();
+        db.setup().await.unwrap();
+        let db_url = db.get_db_url();
+        PgConnection::establish(&db_url)
+            .unwrap()
+            .run_pending_migrations(MIGRATIONS)
+            .unwrap();
+        let db_pool = new_db_pool(&db_url, None).await.unwrap();
+        (db, db_url, db_pool)
+    }
+
+    /// Writes `data` as one batch, like the processor hands it to the step.
+    async fn write_batch(
+        db_writing: &mut DBWritingStep,
+        data: RemapOutput,
+    ) -> Result<Option<TransactionContext<RemapOutput>>, ProcessorError> {
+        db_writing
+            .process(TransactionContext {
+                data,
+                metadata: TransactionMetadata::default(),
+            })
+            .await
+    }
+
     async fn process_single_batch_txns(
         txns: &[&[u8]],
         test_case_name: Option<String>,